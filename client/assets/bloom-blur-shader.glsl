@@ -0,0 +1,39 @@
+#[description]
+Bloom pass: one direction of a separable gaussian blur
+
+#[vertex]
+#version 450 core
+
+layout (location = 0) in vec2 a_Position;
+layout (location = 1) in vec2 a_FrameCoord;
+
+out vec2 v_FrameCoord;
+
+void main() {
+    v_FrameCoord = a_FrameCoord;
+    gl_Position = vec4(a_Position, 0.0, 1.0);
+}
+
+#[fragment]
+#version 450 core
+
+in vec2 v_FrameCoord;
+
+uniform sampler2D u_Frame;
+uniform int u_Horizontal;
+
+layout (location = 0) out vec4 r_Color;
+
+const float weights[5] = float[](0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+
+void main() {
+    vec2 texel = 1.0 / textureSize(u_Frame, 0);
+    vec2 step = u_Horizontal != 0 ? vec2(texel.x, 0.0) : vec2(0.0, texel.y);
+
+    vec3 color = texture(u_Frame, v_FrameCoord).rgb * weights[0];
+    for (int i = 1; i < 5; i++) {
+        color += texture(u_Frame, v_FrameCoord + step * i).rgb * weights[i];
+        color += texture(u_Frame, v_FrameCoord - step * i).rgb * weights[i];
+    }
+    r_Color = vec4(color, 1.0);
+}
@@ -0,0 +1,30 @@
+#[description]
+Bloom pass: adds the blurred highlights back onto the frame
+
+#[vertex]
+#version 450 core
+
+layout (location = 0) in vec2 a_Position;
+layout (location = 1) in vec2 a_FrameCoord;
+
+out vec2 v_FrameCoord;
+
+void main() {
+    v_FrameCoord = a_FrameCoord;
+    gl_Position = vec4(a_Position, 0.0, 1.0);
+}
+
+#[fragment]
+#version 450 core
+
+in vec2 v_FrameCoord;
+
+uniform sampler2D u_Frame;
+uniform float u_Intensity;
+
+layout (location = 0) out vec4 r_Color;
+
+void main() {
+    // alpha of 0 keeps the destination untouched, so the bloom gets added on top
+    r_Color = vec4(texture(u_Frame, v_FrameCoord).rgb * u_Intensity, 0.0);
+}
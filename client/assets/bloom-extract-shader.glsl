@@ -0,0 +1,31 @@
+#[description]
+Bloom pass: keeps only the parts of the frame brighter than the threshold
+
+#[vertex]
+#version 450 core
+
+layout (location = 0) in vec2 a_Position;
+layout (location = 1) in vec2 a_FrameCoord;
+
+out vec2 v_FrameCoord;
+
+void main() {
+    v_FrameCoord = a_FrameCoord;
+    gl_Position = vec4(a_Position, 0.0, 1.0);
+}
+
+#[fragment]
+#version 450 core
+
+in vec2 v_FrameCoord;
+
+uniform sampler2D u_Frame;
+uniform float u_Threshold;
+
+layout (location = 0) out vec4 r_Color;
+
+void main() {
+    vec3 color = texture(u_Frame, v_FrameCoord).rgb;
+    float brightness = dot(color, vec3(0.2126, 0.7152, 0.0722));
+    r_Color = vec4(brightness > u_Threshold ? color : vec3(0.0), 1.0);
+}
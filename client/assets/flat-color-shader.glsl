@@ -1,18 +1,23 @@
 #[description]
-default shader for the FlatColor material
+default shader for the FlatColor and FeatheredColor materials
 
 #[vertex]
 #version 450 core
 
 layout (location = 0) in vec2 a_Position;
 layout (location = 1) in vec4 a_Color;
+// signed distance to the center of a stroke, -1 and 1 on its edges.
+// FlatColor doesn't provide this attribute, so it reads as 0 and no feathering is done.
+layout (location = 2) in float a_Edge;
 
 uniform mat4 u_ViewProjection;
 
 out vec4 v_Color;
+out float v_Edge;
 
 void main() {
     v_Color = a_Color;
+    v_Edge = a_Edge;
     gl_Position = u_ViewProjection * vec4(a_Position, 0.0, 1.0);
 }
 
@@ -20,9 +25,14 @@ void main() {
 #version 450 core
 
 in vec4 v_Color;
+in float v_Edge;
 
 layout (location = 0) out vec4 r_Color;
 
 void main() {
-    r_Color = v_Color;
+    float edge = abs(v_Edge);
+    // fade out over about one pixel towards the edge
+    float width = fwidth(edge);
+    float coverage = width > 0.0 ? clamp((1.0 - edge) / width, 0.0, 1.0) : 1.0;
+    r_Color = v_Color * coverage;
 }
@@ -0,0 +1,37 @@
+#[description]
+instanced shader for the FlatTexture material, every instance is a textured unit quad
+
+#[vertex]
+#version 450 core
+
+layout (location = 0) in vec2 a_Corner;
+layout (location = 1) in vec2 i_Position;
+layout (location = 2) in vec2 i_Size;
+layout (location = 3) in vec2 i_TexOffset;
+layout (location = 4) in vec2 i_TexSize;
+layout (location = 5) in int i_TexIdx;
+
+uniform mat4 u_ViewProjection;
+
+out vec2 v_TexCoord;
+flat out int v_TexIdx;
+
+void main() {
+    v_TexCoord = i_TexOffset + a_Corner * i_TexSize;
+    v_TexIdx = i_TexIdx;
+    gl_Position = u_ViewProjection * vec4(i_Position + a_Corner * i_Size, 0.0, 1.0);
+}
+
+#[fragment]
+#version 450 core
+
+in vec2 v_TexCoord;
+flat in int v_TexIdx;
+
+uniform sampler2D u_Textures[16];
+
+layout (location = 0) out vec4 r_Color;
+
+void main() {
+    r_Color = texture(u_Textures[v_TexIdx], v_TexCoord);
+}
@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+
+use aeonetica_engine::log;
+
+use crate::renderer::window::events::{Event, GamepadButton, KeyCode};
+
+/// Something the player does, triggered by the [`Input`]s bound to it in the [`ActionMap`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    ShakeCamera,
+    LightUp,
+    LightDown,
+    MoveLeft,
+    MoveRight,
+    Hover
+}
+
+impl Action {
+    pub const ALL: [Action; 6] = [Self::ShakeCamera, Self::LightUp, Self::LightDown, Self::MoveLeft, Self::MoveRight, Self::Hover];
+
+    /// Name used in [`ClientConfig::key_bindings`](crate::config::ClientConfig::key_bindings)
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::ShakeCamera => "shake_camera",
+            Self::LightUp => "light_up",
+            Self::LightDown => "light_down",
+            Self::MoveLeft => "move_left",
+            Self::MoveRight => "move_right",
+            Self::Hover => "hover"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|action| action.name() == name)
+    }
+
+    fn default_inputs(&self) -> Vec<Input> {
+        let key = match self {
+            Self::ShakeCamera => KeyCode::Enter,
+            Self::LightUp => KeyCode::M,
+            Self::LightDown => KeyCode::N,
+            Self::MoveLeft => KeyCode::A,
+            Self::MoveRight => KeyCode::D,
+            Self::Hover => KeyCode::Space
+        };
+        vec![Input::Key(key)]
+    }
+}
+
+/// Keys that can be bound by name
+const BINDABLE_KEYS: [KeyCode; 70] = [
+    KeyCode::A, KeyCode::B, KeyCode::C, KeyCode::D, KeyCode::E, KeyCode::F, KeyCode::G, KeyCode::H, KeyCode::I,
+    KeyCode::J, KeyCode::K, KeyCode::L, KeyCode::M, KeyCode::N, KeyCode::O, KeyCode::P, KeyCode::Q, KeyCode::R,
+    KeyCode::S, KeyCode::T, KeyCode::U, KeyCode::V, KeyCode::W, KeyCode::X, KeyCode::Y, KeyCode::Z,
+    KeyCode::Num0, KeyCode::Num1, KeyCode::Num2, KeyCode::Num3, KeyCode::Num4,
+    KeyCode::Num5, KeyCode::Num6, KeyCode::Num7, KeyCode::Num8, KeyCode::Num9,
+    KeyCode::F1, KeyCode::F2, KeyCode::F3, KeyCode::F4, KeyCode::F5, KeyCode::F6,
+    KeyCode::F7, KeyCode::F8, KeyCode::F9, KeyCode::F10, KeyCode::F11, KeyCode::F12,
+    KeyCode::Space, KeyCode::Enter, KeyCode::Escape, KeyCode::Tab, KeyCode::Backspace,
+    KeyCode::Left, KeyCode::Right, KeyCode::Up, KeyCode::Down,
+    KeyCode::LeftShift, KeyCode::RightShift, KeyCode::LeftControl, KeyCode::RightControl, KeyCode::LeftAlt, KeyCode::RightAlt,
+    KeyCode::Comma, KeyCode::Period, KeyCode::Minus, KeyCode::Equal, KeyCode::Slash, KeyCode::Semicolon, KeyCode::Apostrophe
+];
+
+/// A key or gamepad button, written as `key:<name>` or `gamepad:<name>`, e.g. `key:Space` or `gamepad:ButtonA`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Input {
+    Key(KeyCode),
+    Gamepad(GamepadButton)
+}
+
+impl Input {
+    pub fn name(&self) -> String {
+        match self {
+            Self::Key(key) => format!("key:{key:?}"),
+            Self::Gamepad(button) => format!("gamepad:{button:?}")
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        let (kind, input) = name.split_once(':')?;
+        match kind {
+            "key" => BINDABLE_KEYS.into_iter().find(|key| format!("{key:?}") == input).map(Self::Key),
+            "gamepad" => (0..).map_while(GamepadButton::from_i32).find(|button| format!("{button:?}") == input).map(Self::Gamepad),
+            _ => None
+        }
+    }
+
+    /// `Some(true)` if the event presses this input, `Some(false)` if it releases it
+    fn state(&self, event: &Event) -> Option<bool> {
+        match (self, event) {
+            (Self::Key(key), Event::KeyPressed(pressed)) if key == pressed => Some(true),
+            (Self::Key(key), Event::KeyReleased(released)) if key == released => Some(false),
+            (Self::Gamepad(button), Event::GamepadButton(_, changed, pressed)) if button == changed => Some(*pressed),
+            _ => None
+        }
+    }
+}
+
+/// Which [`Input`]s trigger which [`Action`]s, so layers and handles don't match raw keys.
+/// The client adds it as a [`DataStore`](crate::data_store::DataStore) store built from the config,
+/// `store.mut_or_default::<ActionMap>()` falls back to the default bindings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActionMap {
+    bindings: HashMap<Action, Vec<Input>>
+}
+
+impl Default for ActionMap {
+    fn default() -> Self {
+        Self {
+            bindings: Action::ALL.into_iter().map(|action| (action, action.default_inputs())).collect()
+        }
+    }
+}
+
+impl ActionMap {
+    /// The default bindings with the actions in `key_bindings` rebound, see [`ClientConfig::key_bindings`](crate::config::ClientConfig::key_bindings).
+    /// Unknown actions and inputs are skipped.
+    pub fn from_config(key_bindings: &HashMap<String, Vec<String>>) -> Self {
+        let mut map = Self::default();
+        for (name, inputs) in key_bindings {
+            let Some(action) = Action::from_name(name) else {
+                log!(WARN, "unknown action `{name}` in key bindings");
+                continue
+            };
+            let inputs = inputs.iter()
+                .filter_map(|input| {
+                    let parsed = Input::from_name(input);
+                    if parsed.is_none() {
+                        log!(WARN, "unknown input `{input}` bound to `{name}`");
+                    }
+                    parsed
+                })
+                .collect();
+            map.bind(action, inputs);
+        }
+        map
+    }
+
+    /// Replaces the action's inputs, an empty list unbinds it.
+    pub fn bind(&mut self, action: Action, inputs: Vec<Input>) {
+        self.bindings.insert(action, inputs);
+    }
+
+    pub fn inputs(&self, action: Action) -> &[Input] {
+        self.bindings.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    /// Whether the event presses one of the action's inputs.
+    pub fn matches(&self, event: &Event, action: Action) -> bool {
+        self.state(event, action) == Some(true)
+    }
+
+    /// `Some(true)` if the event presses one of the action's inputs, `Some(false)` if it releases one.
+    /// For actions that last while held.
+    pub fn state(&self, event: &Event, action: Action) -> Option<bool> {
+        self.inputs(action).iter().find_map(|input| input.state(event))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_rebinds_actions_to_several_inputs() {
+        let defaults = ActionMap::default();
+        assert!(defaults.matches(&Event::KeyPressed(KeyCode::M), Action::LightUp));
+        assert!(!defaults.matches(&Event::KeyPressed(KeyCode::N), Action::LightUp));
+        assert_eq!(defaults.state(&Event::KeyReleased(KeyCode::Space), Action::Hover), Some(false));
+
+        let key_bindings = HashMap::from([
+            ("light_up".to_string(), vec!["key:Up".to_string(), "gamepad:ButtonA".to_string(), "key:Nope".to_string()]),
+            ("shake_camera".to_string(), vec![]),
+            ("dance".to_string(), vec!["key:X".to_string()])
+        ]);
+        let map = ActionMap::from_config(&key_bindings);
+        assert_eq!(map.inputs(Action::LightUp), &[Input::Key(KeyCode::Up), Input::Gamepad(GamepadButton::ButtonA)]);
+        assert!(map.matches(&Event::KeyPressed(KeyCode::Up), Action::LightUp));
+        assert!(!map.matches(&Event::KeyPressed(KeyCode::M), Action::LightUp));
+        assert!(!map.matches(&Event::KeyPressed(KeyCode::Enter), Action::ShakeCamera));
+        assert_eq!(map.inputs(Action::LightDown), defaults.inputs(Action::LightDown));
+
+        assert_eq!(Input::from_name(&Input::Key(KeyCode::GraveAccent).name()), None);
+        assert_eq!(Input::from_name(&Input::Key(KeyCode::F5).name()), Some(Input::Key(KeyCode::F5)));
+    }
+}
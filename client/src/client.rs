@@ -4,54 +4,152 @@ use aeonetica_engine::error::ErrorResult;
 use aeonetica_engine::networking::client_packets::{ClientMessage, ClientPacket};
 use aeonetica_engine::networking::SendMode;
 use aeonetica_engine::time::Time;
+use aeonetica_engine::util::id_map::IdMap;
+use crate::actions::ActionMap;
 use crate::client_runtime::ClientRuntime;
+use crate::config::ClientConfig;
 use crate::data_store::DataStore;
+use crate::menu::{menu_font, MenuLayer, MenuState};
 use crate::renderer::context::RenderContext;
 use crate::renderer::window::Window;
 
 const FULL_SEC: usize = 1_000_000_000;
+/// Cap of the gameplay delta time, see [`Time::delta`]
+const MAX_DELTA: f32 = 0.05;
 
-pub fn run(mut client: ClientRuntime, client_id: ClientId, store: &mut DataStore) -> ErrorResult<()> {
-    let _ = client.nc.borrow().send(&ClientPacket {
-        client_id,
-        conv_id: Id::new(),
-        message: ClientMessage::Login,
-    }, SendMode::Safe);
+/// What the client is doing, the window stays open through all of it.
+enum ConnectionState {
+    /// showing the [`MenuLayer`]
+    Menu,
+    /// the [`ClientRuntime`] is created for this server address. This blocks the render thread
+    /// until the mods are loaded or connecting failed, the window shows the menu's last frame meanwhile.
+    Connecting(String),
+    InGame(Session),
+    /// the connection failed or ended, shows the reason in the menu
+    Disconnected(String)
+}
+
+/// Everything that belongs to one connection. The mods' layers and stores are dropped before the mods are unloaded.
+struct Session {
+    context: RenderContext,
+    store: DataStore,
+    client: ClientRuntime,
+    /// see [`ClientConfig::batch_reliable_messages`]
+    batch_reliable: bool
+}
 
-    log!("sent login");
+impl Session {
+    fn connect(client_id: ClientId, client_addr: &str, server_addr: &str, window: &Window, config: &ClientConfig) -> ErrorResult<Self> {
+        let mut store = DataStore::new();
+        store.add_store(config.clone());
+        store.add_store(ActionMap::from_config(&config.key_bindings));
+        let client = ClientRuntime::create(client_id, client_addr, server_addr, &mut store)?;
+        client.nc.borrow().set_nodelay(config.tcp_nodelay)?;
+        client.nc.borrow().send(&ClientPacket {
+            client_id,
+            conv_id: Id::new(),
+            message: ClientMessage::Login(client.login_info()),
+        }, SendMode::Safe)?;
+        log!("sent login");
 
-    let mut window = Window::new(false)?;
+        let mut context = RenderContext::new();
+        context.resize(window.size());
+        client.loaded_mods.iter()
+            .for_each(|loaded_mod| { loaded_mod.client_mod.start(&mut store, window.context_provider().with_render(&mut context)); });
+        Ok(Self { context, store, client, batch_reliable: config.batch_reliable_messages })
+    }
+
+    fn frame(&mut self, window: &mut Window, time: Time) {
+        if self.batch_reliable {
+            self.client.nc.borrow().batch_reliable();
+        }
+        window.poll_events(self.client.handles(), &mut self.context, &mut self.store);
+
+        let _ = self.client.handle_queued(&mut self.store, &mut self.context).map_err(|e| {
+            log!(ERROR, "{e}")
+        });
+
+        window.on_render(&mut self.context, self.client.handles(), &mut self.store, time);
+        self.client.nc.borrow().flush();
+    }
+
+    fn finish(mut self) {
+        let _ = self.client.nc.borrow().send(&ClientPacket {
+            client_id: self.client.client_id,
+            conv_id: Id::new(),
+            message: ClientMessage::Logout,
+        }, SendMode::Safe);
+        self.context.finish(&mut self.store);
+    }
+}
+
+/// Opens the window with the menu, connecting to the server typed into it.
+/// `server_addr` is the menu's initial address. The window doesn't react to input while connecting.
+pub fn run(client_id: ClientId, client_addr: &str, server_addr: &str, store: &mut DataStore) -> ErrorResult<()> {
+    let config = store.try_get_store::<ClientConfig>().cloned().unwrap_or_default();
+    let mut window = Window::new(&config)?;
     let mut time_nanos = 0;
     let mut frames = 0;
     let mut last_full_sec = 0;
-    let mut time = Time {
-        time: 0.0,
-        delta: 0.0,
-		raw_delta: 0.0
-    };
-
-    let mut context = RenderContext::new();
+    let mut time = Time::default();
 
-    client.loaded_mods.iter()
-        .for_each(|loaded_mod| { loaded_mod.client_mod.start(store, window.context_provider().with_render(&mut context)); });
+    store.add_store(MenuState::new(server_addr));
+    let mut menu = RenderContext::new();
+    menu.resize(window.size());
+    window.context_provider().with_render(&mut menu).make_context().push(MenuLayer::new(menu_font()?), store)?;
+    // the menu has no handles, the game's live in its client runtime
+    let mut no_handles = IdMap::default();
+    let mut state = ConnectionState::Menu;
 
     while !window.should_close() {
         let t = Instant::now();
 
-        window.poll_events(&mut client, &mut context, store);
-        
-        let _ = client.handle_queued(store, &mut context).map_err(|e| {
-            log!(ERROR, "{e}")
-        });
-        
-        window.on_render(&mut context, &mut client, store, time);
-        
+        state = match state {
+            ConnectionState::Menu => {
+                window.poll_events(&mut no_handles, &mut menu, store);
+                window.on_render(&mut menu, &mut no_handles, store, time);
+                match store.mut_store::<MenuState>().take_connect_request() {
+                    Some(server_addr) => ConnectionState::Connecting(server_addr),
+                    None => ConnectionState::Menu
+                }
+            }
+            ConnectionState::Connecting(server_addr) => {
+                log!("connecting to {server_addr}");
+                match Session::connect(client_id, client_addr, &server_addr, &window, &config) {
+                    Ok(session) => ConnectionState::InGame(session),
+                    Err(e) => {
+                        e.log();
+                        ConnectionState::Disconnected(format!("could not connect to {server_addr}: {e}"))
+                    }
+                }
+            }
+            ConnectionState::InGame(mut session) => {
+                session.frame(&mut window, time);
+                time.time_scale = session.client.time_scale;
+                time.paused = session.client.paused;
+                match session.client.disconnect_reason() {
+                    Some(reason) => {
+                        let reason = reason.to_string();
+                        session.finish();
+                        ConnectionState::Disconnected(reason)
+                    }
+                    None => ConnectionState::InGame(session)
+                }
+            }
+            ConnectionState::Disconnected(reason) => {
+                log!(WARN, "disconnected: {reason}");
+                time = Time::default();
+                // the window may have been resized in game
+                menu.resize(window.size());
+                store.mut_store::<MenuState>().set_status(reason);
+                ConnectionState::Menu
+            }
+        };
+
         let delta_time_nanos = t.elapsed().as_nanos();
         time_nanos += delta_time_nanos;
-        time.raw_delta = delta_time_nanos as f32 / FULL_SEC as f32;
-		time.delta = time.raw_delta.min(0.05);
-        time.time = time_nanos as f32 / FULL_SEC as f32;
-        
+        time.advance(delta_time_nanos as f32 / FULL_SEC as f32, MAX_DELTA);
+
         frames += 1;
 
         if time_nanos - last_full_sec >= FULL_SEC as u128 {
@@ -61,12 +159,11 @@ pub fn run(mut client: ClientRuntime, client_id: ClientId, store: &mut DataStore
         }
     }
 
-    log!("shutting down client after {}s", time.time);
-    context.finish(store);
+    log!("shutting down client after {}s", time_nanos as f32 / FULL_SEC as f32);
+    if let ConnectionState::InGame(session) = state {
+        session.finish();
+    }
+    menu.finish(store);
     window.finish();
-    client.nc.borrow().send(&ClientPacket {
-        client_id: client.client_id,
-        conv_id: Id::new(),
-        message: ClientMessage::Logout,
-    }, SendMode::Safe)
+    Ok(())
 }
@@ -3,23 +3,26 @@ use std::cell::RefCell;
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::{Read, Cursor, Write};
-use std::process::exit;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
 use std::thread;
 use std::rc::Rc;
-use std::time::Duration;
-use aeonetica_engine::error::{Error, Fatality, ErrorResult};
-use aeonetica_engine::error::builtin::ModError;
+use std::time::{Duration, Instant};
+use aeonetica_engine::error::{Error, Fatality, ErrorResult, ResultExt};
+use aeonetica_engine::error::builtin::{ModError, NetworkError};
+use aeonetica_engine::util::load_order::load_order;
 use aeonetica_engine::libloading::{Library, Symbol};
 use aeonetica_engine::nanoserde::SerBin;
 use aeonetica_engine::{ENGINE_VERSION, Id, log, MAX_CLIENT_TIMEOUT, MOD_TARGET};
-use aeonetica_engine::networking::client_packets::{ClientInfo, ClientMessage, ClientPacket};
+use aeonetica_engine::networking::client_packets::{ClientInfo, ClientMessage, ClientPacket, LoginInfo};
 use aeonetica_engine::networking::server_packets::{ServerMessage, ServerPacket};
-use aeonetica_engine::networking::{MOD_DOWNLOAD_CHUNK_SIZE, NetResult, SendMode};
+use aeonetica_engine::networking::{datagram, MOD_DOWNLOAD_CHUNK_SIZE, NetResult, SendMode};
+use aeonetica_engine::networking::datagram::DEFAULT_FRAGMENT_TIMEOUT;
 use aeonetica_engine::util::id_map::IdMap;
 use crate::networking::messaging::{ClientHandle, ClientMessenger};
 use aeonetica_engine::util::unzip_archive;
 use crate::{ClientMod, ClientModBox};
 use crate::networking::NetworkClient;
+use crate::config::ClientConfig;
 
 
 mod paths_util {
@@ -38,23 +41,37 @@ pub(crate) use paths_util::*;
 use crate::data_store::DataStore;
 use crate::renderer::context::RenderContext;
 
+/// How long to wait for the server to accept the registration
+const REGISTER_TIMEOUT: Duration = Duration::from_secs(5);
+
 #[derive(Debug, PartialEq)]
 pub(crate) enum ClientState {
     Start,
     Registered,
-    DownloadedMods
+    DownloadedMods,
+    /// the server refused the registration or sent an unexpected reply while connecting
+    Rejected(String),
+    /// the server unregistered or kicked the client
+    Disconnected(String)
 }
 
 pub struct ClientRuntime {
     pub(crate) client_id: Id,
     pub(crate) mod_profile: String,
     pub(crate) mod_profile_version: String,
+    /// sent with every login, see [`LoginInfo`]
+    pub(crate) mod_profile_hash: String,
     pub(crate) nc: Rc<RefCell<NetworkClient>>,
     pub(crate) awaiting_replies: IdMap<Box<dyn Fn(&mut ClientRuntime, &ServerPacket)>>,
     pub(crate) loaded_mods: Vec<ClientModBox>,
     pub(crate) registered_handles: IdMap<fn() -> Box<dyn ClientHandle>>,
     pub(crate) handles: IdMap<ClientHandleBox>,
-    pub(crate) state: ClientState
+    pub(crate) state: ClientState,
+    /// mirrored from the server
+    pub(crate) time_scale: f32,
+    pub(crate) paused: bool,
+    /// stops the keep alive thread once dropped
+    _keep_alive: Sender<()>
 }
 
 pub(crate) struct LoadingMod{
@@ -77,52 +94,89 @@ pub(crate) struct ClientHandleBox {
 type LoadingModList = Rc<RefCell<HashMap<String, Rc<RefCell<LoadingMod>>>>>;
 
 impl ClientRuntime {
+    /// Connects to the server, downloads missing mods and loads all of them.
+    /// Fails if the server can't be reached, refuses the client or a mod can't be loaded.
     pub fn create(client_id: Id, addr: &str, server_addr: &str, store: &mut DataStore) -> ErrorResult<Self>{
-        let nc = NetworkClient::start(addr, server_addr).map_err(|e| {
-            e.log_exit();
-        }).unwrap();
+        let fragment_timeout = store.try_get_store::<ClientConfig>()
+            .and_then(|config| config.fragment_timeout_ms)
+            .map_or(DEFAULT_FRAGMENT_TIMEOUT, Duration::from_millis);
+        let nc = NetworkClient::start(client_id, addr, server_addr, fragment_timeout)?;
         log!("started client {addr} and initiating handshake to {server_addr}");
+        let (keep_alive, stop_keep_alive) = mpsc::channel();
         let mut client = Self {
             client_id,
             nc: Rc::new(RefCell::new(nc)),
             mod_profile: String::new(),
             mod_profile_version: String::new(),
+            mod_profile_hash: String::new(),
             awaiting_replies: Default::default(),
             registered_handles: Default::default(),
             handles: Default::default(),
             loaded_mods: vec![],
             state: ClientState::Start,
+            time_scale: 1.0,
+            paused: false,
+            _keep_alive: keep_alive
         };
         let mod_list = client.register()?;
         let timeout_socket = client.nc.borrow().udp.try_clone()?;
+        let keep_alive_stats = client.nc.borrow().stats.clone();
         thread::spawn(move || {
             loop {
-                let data = SerBin::serialize_bin(&ClientPacket {
+                let packet = ClientPacket {
                     client_id,
                     conv_id: Id::new(),
                     message: ClientMessage::KeepAlive,
-                });
-                let _ = timeout_socket.send(data.as_slice()).map_err(|e|{
+                };
+                let data = SerBin::serialize_bin(&packet);
+                keep_alive_stats.sent.record(&packet.message, SendMode::Quick, data.len());
+                if let Err(e) = timeout_socket.send(&datagram::wrap_quick(&data)) {
                     let e: Box<Error> = e.into();
-                    log!(ERROR, "{e}");
-                    exit(1);
-                });
-                std::thread::sleep(Duration::from_millis((MAX_CLIENT_TIMEOUT / 2) as u64))
+                    log!(ERROR, "stopped sending keep alive packets: {e}");
+                    break
+                }
+                match stop_keep_alive.recv_timeout(Duration::from_millis((MAX_CLIENT_TIMEOUT / 2) as u64)) {
+                    Err(RecvTimeoutError::Timeout) => (),
+                    _ => break
+                }
             }
         });
         log!("started timeout preventer");
-        let _ = client.download_mods(&mod_list).map_err(|e| client.gracefully_abort(e));
-
-        let _ = client.enable_mods(&mod_list, store).map_err(|e| client.gracefully_abort(e));
+        client.download_mods(&mod_list)?;
+        client.enable_mods(&mod_list, store)?;
 
         log!("finished client creation");
         Ok(client)
     }
 
+    /// What the server checks the client against when logging in
+    pub(crate) fn login_info(&self) -> LoginInfo {
+        LoginInfo {
+            client_version: ENGINE_VERSION.to_string(),
+            mod_profile_hash: self.mod_profile_hash.clone()
+        }
+    }
+
     pub(crate) fn handles(&mut self) -> &mut IdMap<ClientHandleBox> {
         &mut self.handles
     }
 
+    /// Why the server ended the session, `None` while still connected.
+    pub fn disconnect_reason(&self) -> Option<&str> {
+        match &self.state {
+            ClientState::Disconnected(reason) => Some(reason),
+            _ => None
+        }
+    }
+
+    /// Fails once the server refused the client while connecting.
+    fn check_rejected(&self) -> ErrorResult<()> {
+        match &self.state {
+            ClientState::Rejected(reason) => Err(Error::new(NetworkError(reason.clone()), Fatality::DEFAULT, false)),
+            _ => Ok(())
+        }
+    }
+
     pub(crate) fn request_response<F: Fn(&mut ClientRuntime, &ServerPacket) + 'static>(&mut self, packet: &ClientPacket, handler: F, mode: SendMode) -> ErrorResult<()> {
         self.awaiting_replies.insert(packet.conv_id, Box::new(handler));
         self.nc.borrow().send(packet, mode)?;
@@ -144,12 +198,17 @@ impl ClientRuntime {
             match &resp.message {
                 ServerMessage::RegisterResponse(res) => {
                     match res {
+                        NetResult::Ok(info) if info.server_version != ENGINE_VERSION => {
+                            log!(ERROR, "server runs engine {}, client {ENGINE_VERSION}", info.server_version);
+                            client.state = ClientState::Rejected(format!("version mismatch: server engine {} != client engine {ENGINE_VERSION}", info.server_version));
+                        }
                         NetResult::Ok(info) => {
                             log!("successfully connected to server");
                             log!("registered client");
                             client.state = ClientState::Registered;
                             client.mod_profile = info.mod_profile.clone();
                             client.mod_profile_version = info.mod_version.clone();
+                            client.mod_profile_hash = info.mod_profile_hash();
                             log!("server has mod profile {} v{} with {} mod(s):", client.mod_profile, client.mod_profile_version, info.mods.len());
                             let local_mod_list: HashMap<_, _> = info.mods.clone().into_iter()
                                 .map(|(name_path, flags, hash, size)| {
@@ -187,17 +246,22 @@ impl ClientRuntime {
                         }
                         NetResult::Err(msg) => {
                             log!(ERROR, "server did not accept connection: {msg}");
-                            exit(1);
+                            client.state = ClientState::Rejected(format!("server did not accept connection: {msg}"));
                         }
                     }
                 },
                 e => {
                     log!(ERROR, "invalid response: {e:?}");
-                    exit(1);
+                    client.state = ClientState::Rejected(format!("invalid response: {e:?}"));
                 }
             }
         }, SendMode::Safe)?;
+        let started = Instant::now();
         while self.state != ClientState::Registered {
+            self.check_rejected()?;
+            if started.elapsed() > REGISTER_TIMEOUT {
+                return Err(Error::new(NetworkError("server did not answer the registration".to_string()), Fatality::DEFAULT, false))
+            }
             let packets = self.nc.borrow_mut().queued_packets();
             for packet in packets {
                 self.handle_packet(&packet, &mut DataStore::new(), &mut RenderContext::new())?;
@@ -222,7 +286,7 @@ impl ClientRuntime {
                     client_id: self.client_id,
                     conv_id: Id::new(),
                     message: ClientMessage::DownloadMod(name_path.clone(), MOD_TARGET.to_string(), i),
-                }, move |_client, resp| {
+                }, move |client, resp| {
                     let mut lmb = lm.borrow_mut();
                     match &resp.message {
                         ServerMessage::RawData(data) => {
@@ -231,12 +295,10 @@ impl ClientRuntime {
                         },
                         e => {
                             log!(ERROR, "invalid response: {e:?}");
-                            exit(1);
+                            client.state = ClientState::Rejected(format!("invalid response: {e:?}"));
                         }
                     }
-                }, SendMode::Safe).map_err(|e| {
-                    e.log_exit();
-                }).unwrap();
+                }, SendMode::Safe)?;
                 //std::thread::sleep(Duration::from_nanos(20));
                 //let packets = self.nc.borrow_mut().queued_packets();
                 //for packet in packets {
@@ -247,6 +309,7 @@ impl ClientRuntime {
         log!("(sent all mod download requests)");
         let mut p = 0.0;
         while self.state != ClientState::DownloadedMods {
+            self.check_rejected()?;
             let packets = self.nc.borrow_mut().queued_packets();
             for packet in packets {
                 self.handle_packet(&packet, &mut DataStore::new(), &mut RenderContext::new())?;
@@ -279,9 +342,20 @@ impl ClientRuntime {
     }
 
     fn enable_mods(&mut self, mod_list: &LoadingModList, store: &mut DataStore) -> ErrorResult<()>{
-        for (name_path, lm) in mod_list.borrow_mut().iter_mut() {
+        let mut mods = mod_list.borrow().iter().map(|(name_path, lm)| (name_path.clone(), lm.clone())).collect::<Vec<_>>();
+        mods.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let mut loaded = vec![];
+        for (name_path, _) in &mods {
             log!("loading mod {} ...", name_path);
-            let mut loaded_mod = load_mod(name_path)?;
+            loaded.push(Some(load_mod(name_path).with_context(|| format!("loading mod {name_path}"))?));
+        }
+
+        let order = load_order(&mods.iter().zip(loaded.iter())
+            .map(|((name_path, _), m)| (name_path.as_str(), m.as_ref().unwrap().dependencies()))
+            .collect::<Vec<_>>())?;
+        for i in order {
+            let (name_path, lm) = &mods[i];
+            let mut loaded_mod = loaded[i].take().unwrap();
             loaded_mod.init(&lm.borrow().flags);
             let mut handles = Default::default();
             loaded_mod.register_handlers(&mut handles, store);
@@ -292,12 +366,13 @@ impl ClientRuntime {
         log!("successfully loaded {} mods from profile {} v{}", self.loaded_mods.len(), self.mod_profile, self.mod_profile_version);
         Ok(())
     }
+}
 
-    fn gracefully_abort<E: Into<Box<Error>>>(&self, e: E) -> !{
-        let err = e.into();
-        err.log();
-        log!(ERROR, "gracefully aborted client");
-        exit(1);
+impl Drop for ClientRuntime {
+    fn drop(&mut self) {
+        // the handles' code lives in the mod libraries, which are unloaded with `loaded_mods`
+        self.handles.clear();
+        self.registered_handles.clear();
     }
 }
 
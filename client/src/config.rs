@@ -0,0 +1,126 @@
+// the code DeRon generates for the optional fields trips this lint
+#![allow(clippy::question_mark)]
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use aeonetica_engine::log;
+use aeonetica_engine::error::ErrorResult;
+use aeonetica_engine::nanoserde;
+use aeonetica_engine::nanoserde::{DeRon, SerRon};
+
+/// Where the client looks for its config, relative to the working directory.
+pub const CONFIG_PATH: &str = "client.ron";
+
+/// User settings read once at client startup. Available to mods as a [`DataStore`](crate::data_store::DataStore) store.
+#[derive(Debug, Clone, PartialEq, SerRon, DeRon)]
+pub struct ClientConfig {
+    pub window_width: u32,
+    pub window_height: u32,
+    pub fullscreen: bool,
+    /// overridden by the `AEONETICA_VSYNC` environment variable if set
+    pub vsync: bool,
+    /// maximum frames per second, uncapped if not set or 0
+    pub frame_cap: Option<u32>,
+    /// horizontal radius in chunks around the camera that is kept loaded
+    pub view_distance_x: i32,
+    /// vertical radius in chunks around the camera that is kept loaded
+    pub view_distance_y: i32,
+    /// rebinds actions by name, e.g. `"light_up": ["key:M", "gamepad:ButtonDpadUp"]`, see [`ActionMap`](crate::actions::ActionMap).
+    /// Actions not listed keep their default bindings.
+    #[nserde(default)]
+    pub key_bindings: HashMap<String, Vec<String>>,
+    /// coalesces the reliable messages of a frame into a single tcp write at its end
+    #[nserde(default)]
+    pub batch_reliable_messages: bool,
+    /// disables Nagle's algorithm on the tcp connection, so small reliable messages go out right away
+    #[nserde(default)]
+    pub tcp_nodelay: bool,
+    /// milliseconds after which partially received udp messages are discarded,
+    /// [`DEFAULT_FRAGMENT_TIMEOUT`](aeonetica_engine::networking::datagram::DEFAULT_FRAGMENT_TIMEOUT) if not set
+    pub fragment_timeout_ms: Option<u64>
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            window_width: 1280,
+            window_height: 720,
+            fullscreen: false,
+            vsync: false,
+            frame_cap: None,
+            view_distance_x: 2,
+            view_distance_y: 1,
+            key_bindings: HashMap::new(),
+            batch_reliable_messages: false,
+            tcp_nodelay: false,
+            fragment_timeout_ms: None
+        }
+    }
+}
+
+impl ClientConfig {
+    /// Reads the config at `path`, falling back to the defaults if it is missing or malformed.
+    /// A missing file is created with the defaults, so there is something to edit.
+    /// A malformed file is left untouched.
+    pub fn load<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref();
+        let data = match fs::read_to_string(path) {
+            Ok(data) => data,
+            Err(_) => {
+                let config = Self::default();
+                match config.save(path) {
+                    Ok(()) => log!("created default config at {}", path.display()),
+                    Err(e) => log!(WARN, "could not write default config to {}: {e}", path.display())
+                }
+                return config
+            }
+        };
+        match Self::deserialize_ron(&data) {
+            Ok(config) if config.is_valid() => config,
+            Ok(_) => {
+                log!(WARN, "config at {} has invalid values, using defaults", path.display());
+                Self::default()
+            }
+            Err(e) => {
+                log!(WARN, "could not parse config at {}: {e}, using defaults", path.display());
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> ErrorResult<()> {
+        fs::write(path, self.serialize_ron())?;
+        Ok(())
+    }
+
+    fn is_valid(&self) -> bool {
+        self.window_width > 0 && self.window_height > 0 && self.view_distance_x >= 0 && self.view_distance_y >= 0
+            && self.fragment_timeout_ms != Some(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_or_malformed_config_falls_back_to_defaults() {
+        let path = std::env::temp_dir().join(format!("aeonetica-config-{}.ron", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(ClientConfig::load(&path), ClientConfig::default());
+        assert!(path.exists(), "default config should be written on first run");
+
+        let config = ClientConfig { window_width: 1920, window_height: 1080, fullscreen: true, ..Default::default() };
+        config.save(&path).unwrap();
+        assert_eq!(ClientConfig::load(&path), config);
+
+        fs::write(&path, "(window_width: ").unwrap();
+        assert_eq!(ClientConfig::load(&path), ClientConfig::default());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "(window_width: ");
+
+        let _ = fs::remove_file(&path);
+    }
+}
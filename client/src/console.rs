@@ -0,0 +1,339 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::rc::Rc;
+
+use aeonetica_engine::logging::{self, LogLevel};
+use aeonetica_engine::math::camera::Camera;
+use aeonetica_engine::math::vector::Vector2;
+use aeonetica_engine::time::Time;
+
+use crate::data_store::DataStore;
+use crate::renderer::Renderer;
+use crate::renderer::builtin::DynTextArea;
+use crate::renderer::layer::Layer;
+use crate::renderer::material::FlatTexture;
+use crate::renderer::texture::font::BitmapFont;
+use crate::renderer::window::events::{Event, KeyCode};
+
+/// A command that can be run from the [`ConsoleLayer`]. Register commands in the [`ConsoleCommands`] store.
+pub trait ConsoleCommand {
+    fn name(&self) -> &'static str;
+    /// One line shown by `help`
+    fn description(&self) -> &'static str;
+    /// Runs the command with the whitespace separated arguments following its name.
+    /// Returns the text to print, or an error message.
+    fn run(&self, args: &[&str], store: &mut DataStore) -> Result<String, String>;
+}
+
+/// All commands known to the console, by name.
+#[derive(Default)]
+pub struct ConsoleCommands {
+    commands: BTreeMap<&'static str, Rc<dyn ConsoleCommand>>
+}
+
+impl ConsoleCommands {
+    /// Fails if a command with the same name already exists.
+    pub fn register<C: ConsoleCommand + 'static>(&mut self, command: C) -> bool {
+        if self.commands.contains_key(command.name()) || BUILTIN_COMMANDS.contains(&command.name()) {
+            return false
+        }
+        self.commands.insert(command.name(), Rc::new(command));
+        true
+    }
+
+    pub fn get(&self, name: &str) -> Option<Rc<dyn ConsoleCommand>> {
+        self.commands.get(name).cloned()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Rc<dyn ConsoleCommand>> {
+        self.commands.values()
+    }
+}
+
+/// Whether the [`ConsoleLayer`] is open, for other text inputs to stay out of its way.
+#[derive(Debug, Default)]
+pub struct ConsoleState {
+    open: bool
+}
+
+impl ConsoleState {
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// `false` if there is no console
+    pub fn is_open_in(store: &DataStore) -> bool {
+        store.try_get_store::<Self>().is_some_and(Self::is_open)
+    }
+}
+
+const BUILTIN_COMMANDS: [&str; 3] = ["help", "clear", "loglevel"];
+
+/// Input line and scrollback of the console, independent of rendering.
+#[derive(Debug, Default)]
+pub struct Console {
+    input: String,
+    scrollback: VecDeque<String>,
+    open: bool
+}
+
+impl Console {
+    pub const MAX_SCROLLBACK: usize = 256;
+    pub const MAX_INPUT_LEN: usize = 96;
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn set_open(&mut self, open: bool) {
+        self.open = open;
+    }
+
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+
+    pub fn scrollback(&self) -> &VecDeque<String> {
+        &self.scrollback
+    }
+
+    pub fn print<S: Into<String>>(&mut self, text: S) {
+        for line in text.into().lines() {
+            if self.scrollback.len() == Self::MAX_SCROLLBACK {
+                self.scrollback.pop_front();
+            }
+            self.scrollback.push_back(line.to_string());
+        }
+    }
+
+    pub fn type_char(&mut self, c: char) {
+        if !c.is_control() && self.input.chars().count() < Self::MAX_INPUT_LEN {
+            self.input.push(c);
+        }
+    }
+
+    pub fn backspace(&mut self) {
+        self.input.pop();
+    }
+
+    /// Echoes and runs the current input line.
+    pub fn submit(&mut self, store: &mut DataStore) {
+        let line = std::mem::take(&mut self.input);
+        if line.trim().is_empty() {
+            return
+        }
+        self.print(format!("> {line}"));
+        let output = self.execute(&line, store);
+        match output {
+            Ok(text) if text.is_empty() => (),
+            Ok(text) => self.print(text),
+            Err(message) => self.print(format!("error: {message}"))
+        }
+    }
+
+    fn execute(&mut self, line: &str, store: &mut DataStore) -> Result<String, String> {
+        if line.split_whitespace().next() == Some("clear") {
+            self.scrollback.clear();
+            return Ok(String::new())
+        }
+        run_command(line, store)
+    }
+}
+
+/// Runs a command line like the console does, for other inputs like chat `/commands`.
+/// `clear` only makes sense for the console itself and is not handled.
+pub fn run_command(line: &str, store: &mut DataStore) -> Result<String, String> {
+    let mut words = line.split_whitespace();
+    let name = words.next().unwrap_or_default();
+    let args = words.collect::<Vec<_>>();
+    match name {
+        "help" => {
+            let mut help = String::from("help - lists all commands\nclear - clears the console\nloglevel <level> - sets the log level");
+            if let Some(commands) = store.try_get_store::<ConsoleCommands>() {
+                commands.iter().for_each(|c| help += &format!("\n{} - {}", c.name(), c.description()));
+            }
+            Ok(help)
+        }
+        "loglevel" => {
+            let levels = [LogLevel::Pack, LogLevel::Debug, LogLevel::Info, LogLevel::Warn, LogLevel::Error];
+            let Some(arg) = args.first() else {
+                return Ok(format!("log level is {}", logging::log_level().name()))
+            };
+            let level = levels.into_iter().find(|l| l.name().eq_ignore_ascii_case(arg))
+                .ok_or_else(|| format!("unknown log level '{arg}'"))?;
+            logging::set_log_level(level);
+            Ok(format!("log level set to {}", level.name()))
+        }
+        _ => {
+            let command = store.try_get_store::<ConsoleCommands>()
+                .and_then(|commands| commands.get(name))
+                .ok_or_else(|| format!("unknown command '{name}', try 'help'"))?;
+            command.run(&args, store)
+        }
+    }
+}
+
+/// Overlay with a command line and scrollback, toggled with the backtick key.
+/// While open, it swallows all key presses, text input and mouse buttons, so typing doesn't trigger gameplay.
+/// Key releases still pass through, so keys held while opening the console don't get stuck.
+pub struct ConsoleLayer {
+    font: Rc<BitmapFont>,
+    console: Console,
+    lines: Vec<DynTextArea>,
+    shown: bool,
+    dirty: bool
+}
+
+impl ConsoleLayer {
+    pub const TOGGLE_KEY: KeyCode = KeyCode::GraveAccent;
+    const VISIBLE_LINES: usize = 10;
+    const FONT_SIZE: f32 = 3.0;
+    const LINE_HEIGHT: f32 = 3.5;
+    const TOP: f32 = 8.0;
+    const Z_INDEX: u8 = 250;
+
+    pub fn new(font: Rc<BitmapFont>) -> Self {
+        Self {
+            font,
+            console: Console::default(),
+            lines: vec![],
+            shown: false,
+            dirty: true
+        }
+    }
+
+    /// Drops characters the font can't draw, not even as its fallback character.
+    fn printable(&self, text: &str) -> String {
+        text.chars().filter(|c| c.is_whitespace() || self.font.glyph_index(*c).is_some()).collect()
+    }
+
+    fn set_open(&mut self, open: bool, store: &mut DataStore) {
+        self.console.set_open(open);
+        store.mut_or_default::<ConsoleState>().open = open;
+    }
+
+    fn update_lines(&mut self, renderer: &mut Renderer) {
+        let history = self.console.scrollback().iter().rev().take(Self::VISIBLE_LINES - 1).rev();
+        let texts = history.cloned()
+            .chain(std::iter::once(format!("> {}_", self.console.input())))
+            .map(|text| self.printable(&text))
+            .collect::<Vec<_>>();
+        let empty = String::new();
+        for (line, text) in self.lines.iter_mut().zip(texts.iter().chain(std::iter::repeat(&empty))) {
+            line.set_string(renderer, text.as_str());
+        }
+    }
+}
+
+impl Layer for ConsoleLayer {
+    fn instantiate_camera(&self) -> Camera {
+        Camera::new(0.0, 160.0, 90.0, 0.0, 1.0, -1.0)
+    }
+
+    fn resize_camera(&mut self, camera: &mut Camera, aspect_ratio: f32) {
+        // keep the text anchored to the left edge
+        camera.set_projection(0.0, 90.0 * aspect_ratio, 90.0, 0.0, 1.0, -1.0);
+    }
+
+    fn attach(&mut self, _renderer: &mut Renderer, _store: &mut DataStore) {
+        self.lines = (0..Self::VISIBLE_LINES)
+            .map(|i| DynTextArea::with_string(
+                Vector2::new(2.0, Self::TOP + i as f32 * Self::LINE_HEIGHT),
+                Self::Z_INDEX, Self::FONT_SIZE, 0.5, self.font.clone(), FlatTexture::get(), ""
+            ))
+            .collect();
+    }
+
+    fn quit(&mut self, renderer: &mut Renderer, _store: &mut DataStore) {
+        self.lines.iter_mut().for_each(|line| renderer.remove(line));
+    }
+
+    fn post_handles_update(&mut self, _store: &mut DataStore, renderer: &mut Renderer, _time: Time) {
+        if !self.console.is_open() {
+            if self.shown {
+                self.lines.iter_mut().for_each(|line| renderer.remove(line));
+                self.shown = false;
+            }
+            return
+        }
+        if self.dirty {
+            self.update_lines(renderer);
+            self.dirty = false;
+        }
+        for line in self.lines.iter_mut() {
+            let _ = renderer.draw(line);
+        }
+        self.shown = true;
+    }
+
+    fn event(&mut self, event: &Event, store: &mut DataStore) -> bool {
+        if let Event::KeyPressed(key) = event && *key == Self::TOGGLE_KEY {
+            self.set_open(!self.console.is_open(), store);
+            self.dirty = true;
+            return true
+        }
+        if !self.console.is_open() {
+            return false
+        }
+        match event {
+            Event::KeyPressed(KeyCode::Escape) => self.set_open(false, store),
+            Event::KeyPressed(KeyCode::Enter) => self.console.submit(store),
+            Event::KeyPressed(KeyCode::Backspace) => self.console.backspace(),
+            // the toggle key also produces text input
+            Event::CharTyped('`') => (),
+            Event::CharTyped(c) => self.console.type_char(*c),
+            Event::KeyPressed(_) | Event::MouseButtonPressed(_) | Event::MouseButtonReleased(_) | Event::MouseScrolled(_) => (),
+            _ => return false
+        }
+        self.dirty = true;
+        true
+    }
+
+    fn name(&self) -> &'static str {
+        "Console"
+    }
+
+    fn is_overlay(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Echo;
+
+    impl ConsoleCommand for Echo {
+        fn name(&self) -> &'static str { "echo" }
+        fn description(&self) -> &'static str { "prints its arguments" }
+        fn run(&self, args: &[&str], _store: &mut DataStore) -> Result<String, String> {
+            Ok(args.join(" "))
+        }
+    }
+
+    fn submit(console: &mut Console, store: &mut DataStore, line: &str) {
+        line.chars().for_each(|c| console.type_char(c));
+        console.submit(store);
+    }
+
+    #[test]
+    fn console_runs_registered_commands() {
+        let mut store = DataStore::new();
+        assert!(store.mut_or_default::<ConsoleCommands>().register(Echo));
+        assert!(!store.mut_or_default::<ConsoleCommands>().register(Echo));
+
+        let mut console = Console::default();
+        submit(&mut console, &mut store, "echo  hello world");
+        assert_eq!(console.scrollback(), &["> echo  hello world", "hello world"]);
+        assert!(console.input().is_empty());
+
+        submit(&mut console, &mut store, "nope");
+        assert!(console.scrollback().back().unwrap().starts_with("error: unknown command"));
+
+        submit(&mut console, &mut store, "help");
+        assert!(console.scrollback().iter().any(|line| line == "echo - prints its arguments"));
+
+        submit(&mut console, &mut store, "clear");
+        assert!(console.scrollback().is_empty());
+    }
+}
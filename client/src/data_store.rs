@@ -38,9 +38,18 @@ impl DataStore {
     pub fn stores(&self) -> Vec<TypeId> {
         self.stores.keys().copied().collect()
     }
+    /// Removes the store of type `T` and hands it back, or returns `None` if no such store exists.
     #[inline]
-    pub fn remove_store<T: Sized + 'static>(&mut self) -> bool{
-        self.stores.remove(&type_to_id::<T>()).is_some()
+    pub fn remove_store<T: Sized + 'static>(&mut self) -> Option<T> {
+        self.stores.remove(&type_to_id::<T>()).and_then(|m| m.downcast::<T>().ok()).map(|b| *b)
+    }
+    #[inline]
+    pub fn try_get_store<T: Sized + 'static>(&self) -> Option<&T> {
+        self.get_store::<T>().into()
+    }
+    #[inline]
+    pub fn try_mut_store<T: Sized + 'static>(&mut self) -> Option<&mut T> {
+        self.mut_store::<T>().into()
     }
     #[inline]
     pub fn get_store<T: Sized + 'static>(&self) -> Nullable<&T> {
@@ -88,4 +97,23 @@ impl DataStore {
     pub fn has_store_type(&self, ty: &TypeId) -> bool {
         self.stores.contains_key(ty)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Counter(u32);
+
+    #[test]
+    fn add_and_remove_store() {
+        let mut store = DataStore::new();
+        assert!(store.try_get_store::<Counter>().is_none());
+        assert!(store.add_store(Counter(1)));
+        store.try_mut_store::<Counter>().unwrap().0 += 1;
+        assert_eq!(store.try_get_store::<Counter>().map(|c| c.0), Some(2));
+        assert_eq!(store.remove_store::<Counter>().map(|c| c.0), Some(2));
+        assert!(store.try_get_store::<Counter>().is_none());
+        assert!(store.remove_store::<Counter>().is_none());
+    }
+}
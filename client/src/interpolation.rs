@@ -0,0 +1,185 @@
+use std::collections::VecDeque;
+
+use aeonetica_engine::math::vector::Vector2;
+
+/// Values an [`InterpolationBuffer`] can blend between two snapshots.
+pub trait Interpolate: Clone {
+    /// Returns `self` at `t = 0` and `other` at `t = 1`, values above 1 extrapolate.
+    fn interpolate(&self, other: &Self, t: f32) -> Self;
+}
+
+impl Interpolate for f32 {
+    fn interpolate(&self, other: &Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Interpolate for Vector2<f32> {
+    fn interpolate(&self, other: &Self, t: f32) -> Self {
+        self.lerp(*other, t)
+    }
+}
+
+/// Element wise, elements only one of both has are taken from `other`.
+impl<T: Interpolate> Interpolate for Vec<T> {
+    fn interpolate(&self, other: &Self, t: f32) -> Self {
+        other.iter().enumerate()
+            .map(|(i, b)| self.get(i).map_or_else(|| b.clone(), |a| a.interpolate(b, t)))
+            .collect()
+    }
+}
+
+/// What an [`InterpolationBuffer`] samples once the render time passed its newest snapshot.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Extrapolation {
+    /// stays at the newest snapshot
+    Hold,
+    /// keeps moving like between the last two snapshots for up to this many seconds, then holds
+    Linear(f32)
+}
+
+/// Timestamped snapshots of a remote value, e.g. an entity's position, sampled a fixed delay in the past.
+/// The delay keeps a newer snapshot ahead of the render time, so the value moves smoothly between updates
+/// instead of jumping whenever one arrives.
+#[derive(Debug, Clone)]
+pub struct InterpolationBuffer<T: Interpolate> {
+    /// oldest first
+    snapshots: VecDeque<(f32, T)>,
+    delay: f32,
+    extrapolation: Extrapolation
+}
+
+impl<T: Interpolate> InterpolationBuffer<T> {
+    /// About two updates of an entity sent every tick
+    pub const DEFAULT_DELAY: f32 = 0.1;
+    pub const MAX_SNAPSHOTS: usize = 32;
+
+    pub fn new() -> Self {
+        Self {
+            snapshots: VecDeque::new(),
+            delay: Self::DEFAULT_DELAY,
+            extrapolation: Extrapolation::Hold
+        }
+    }
+
+    /// How far in seconds the sampled value lags behind, should cover the time between two snapshots.
+    pub fn with_delay(mut self, delay: f32) -> Self {
+        self.delay = delay.max(0.0);
+        self
+    }
+
+    pub fn with_extrapolation(mut self, extrapolation: Extrapolation) -> Self {
+        self.extrapolation = extrapolation;
+        self
+    }
+
+    pub fn delay(&self) -> f32 {
+        self.delay
+    }
+
+    pub fn set_delay(&mut self, delay: f32) {
+        self.delay = delay.max(0.0);
+    }
+
+    /// Adds the value received at `time`, snapshots older than the newest one are ignored.
+    pub fn push(&mut self, time: f32, value: T) {
+        if self.snapshots.back().is_some_and(|(newest, _)| time < *newest) {
+            return
+        }
+        self.snapshots.push_back((time, value));
+        // one snapshot at or before the render time is enough to interpolate from
+        let render_time = time - self.delay;
+        while (self.snapshots.len() > 2 && self.snapshots[1].0 <= render_time) || self.snapshots.len() > Self::MAX_SNAPSHOTS {
+            self.snapshots.pop_front();
+        }
+    }
+
+    /// Jumps to `value` without interpolating from the previous snapshots, e.g. after a teleport.
+    pub fn snap(&mut self, time: f32, value: T) {
+        self.snapshots.clear();
+        self.snapshots.push_back((time, value));
+    }
+
+    /// The value at `now` minus the delay, `None` until the first snapshot was pushed.
+    pub fn sample(&self, now: f32) -> Option<T> {
+        let render_time = now - self.delay;
+        let (first_time, first) = self.snapshots.front()?;
+        if render_time <= *first_time {
+            return Some(first.clone())
+        }
+        let next = self.snapshots.iter().position(|(time, _)| *time > render_time);
+        let (from, to) = match next {
+            Some(next) => (&self.snapshots[next - 1], &self.snapshots[next]),
+            None => {
+                let newest = self.snapshots.len() - 1;
+                match self.extrapolation {
+                    Extrapolation::Linear(limit) if newest > 0 => {
+                        let (from, to) = (&self.snapshots[newest - 1], &self.snapshots[newest]);
+                        let render_time = render_time.min(to.0 + limit);
+                        return Some(from.1.interpolate(&to.1, (render_time - from.0) / (to.0 - from.0).max(f32::EPSILON)))
+                    }
+                    _ => return Some(self.snapshots[newest].1.clone())
+                }
+            }
+        };
+        Some(from.1.interpolate(&to.1, (render_time - from.0) / (to.0 - from.0)))
+    }
+
+    /// The newest snapshot's value
+    pub fn latest(&self) -> Option<&T> {
+        self.snapshots.back().map(|(_, value)| value)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+    }
+}
+
+impl<T: Interpolate> Default for InterpolationBuffer<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_between_snapshots_are_interpolated() {
+        let mut buffer = InterpolationBuffer::new().with_delay(0.5);
+        assert_eq!(buffer.sample(0.0), None);
+        buffer.push(1.0, Vector2::new(0.0, 0.0));
+        buffer.push(2.0, Vector2::new(4.0, -2.0));
+        assert_eq!(buffer.sample(0.5), Some(Vector2::new(0.0, 0.0)));
+        assert_eq!(buffer.sample(1.75), Some(Vector2::new(1.0, -0.5)));
+
+        buffer.push(3.0, Vector2::new(8.0, -2.0));
+        assert_eq!(buffer.sample(3.0), Some(Vector2::new(6.0, -2.0)));
+
+        buffer.snap(3.5, Vector2::new(-10.0, 0.0));
+        assert_eq!(buffer.sample(3.75), Some(Vector2::new(-10.0, 0.0)));
+        // late snapshots don't move it back
+        buffer.push(3.0, Vector2::new(8.0, -2.0));
+        assert_eq!(buffer.latest(), Some(&Vector2::new(-10.0, 0.0)));
+    }
+
+    #[test]
+    fn a_dry_buffer_holds_or_extrapolates_the_last_value() {
+        let mut hold = InterpolationBuffer::new().with_delay(0.0);
+        hold.push(0.0, 1.0);
+        hold.push(1.0, 2.0);
+        assert_eq!(hold.sample(5.0), Some(2.0));
+
+        let mut linear = hold.clone().with_extrapolation(Extrapolation::Linear(0.5));
+        assert_eq!(linear.sample(1.25), Some(2.25));
+        assert_eq!(linear.sample(5.0), Some(2.5));
+        // a single snapshot has nothing to extrapolate from
+        linear.snap(2.0, 7.0);
+        assert_eq!(linear.sample(3.0), Some(7.0));
+    }
+}
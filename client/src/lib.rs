@@ -16,8 +16,15 @@ pub mod client_runtime;
 pub mod client;
 pub mod renderer;
 pub mod data_store;
+pub mod console;
+pub mod menu;
+pub mod config;
+pub mod actions;
+pub mod interpolation;
 
 pub trait ClientMod {
+    /// Mods (by `path:name` or just `name`) that have to be initialized and started before this one.
+    fn dependencies(&self) -> Vec<&str> { vec![] }
     #[allow(unused_variables)]
     fn init(&mut self, flags: &Vec<String>){}
     #[allow(unused_variables)]
@@ -7,7 +7,7 @@
 use std::net::SocketAddr;
 
 use aeonetica_engine::{log, Id};
-use client::{client::run, data_store::DataStore, client_runtime::ClientRuntime};
+use client::{client::run, data_store::DataStore, config::{ClientConfig, CONFIG_PATH}};
 
 mod defaults {
     pub(crate) const CLIENT_IP: &str = "127.0.0.1:9000";
@@ -26,7 +26,7 @@ fn main() {
 
     match args.as_slice() {
         [a, ..] if a == "--help" => {
-            log!("Usage: {} [<client ip>] [<server ip>] | --help", std::env::args().next().unwrap());
+            log!("Usage: {} [<client ip>] [<server ip shown in the menu>] | --help", std::env::args().next().unwrap());
             return;
         }
         [c_ip, _] if SocketAddr::parse_ascii(c_ip.as_bytes()).is_err() => {
@@ -51,11 +51,9 @@ fn main() {
     let client_id = Id::new();
     
     let mut store = DataStore::new();
-    let client = ClientRuntime::create(client_id, client_ip, server_ip, &mut store).map_err(|e| {
-        e.log_exit();
-    }).unwrap();
-
-    if let Err(err) = run(client, client_id, &mut store) {
+    store.add_store(ClientConfig::load(CONFIG_PATH));
+    // the server ip is only preselected, the menu connects
+    if let Err(err) = run(client_id, client_ip, server_ip, &mut store) {
         err.log_exit()
     }
 }
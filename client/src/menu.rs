@@ -0,0 +1,204 @@
+use std::net::SocketAddr;
+use std::rc::Rc;
+
+use aeonetica_engine::error::ErrorResult;
+use aeonetica_engine::math::camera::Camera;
+use aeonetica_engine::math::vector::Vector2;
+use aeonetica_engine::time::Time;
+
+use crate::data_store::DataStore;
+use crate::renderer::Renderer;
+use crate::renderer::builtin::{DynTextArea, Quad};
+use crate::renderer::layer::Layer;
+use crate::renderer::material::{FlatColor, FlatTexture};
+use crate::renderer::texture::Texture;
+use crate::renderer::texture::font::BitmapFont;
+use crate::renderer::window::events::{Event, KeyCode, MouseButton};
+
+/// The client's own font, mods bring their own.
+pub fn menu_font() -> ErrorResult<Rc<BitmapFont>> {
+    Ok(Rc::new(BitmapFont::from_texture_and_fontdata(
+        Texture::from_bytes(include_bytes!("../assets/fonts/default/default.png"))?,
+        include_str!("../assets/fonts/default/default.bmf")
+    )?))
+}
+
+/// Server address typed into the [`MenuLayer`] and the status shown below it, independent of rendering.
+#[derive(Debug, Default)]
+pub struct MenuState {
+    address: String,
+    status: String,
+    connect: bool
+}
+
+impl MenuState {
+    pub const MAX_ADDRESS_LEN: usize = 64;
+
+    pub fn new<S: Into<String>>(address: S) -> Self {
+        Self {
+            address: address.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    pub fn status(&self) -> &str {
+        &self.status
+    }
+
+    pub fn set_status<S: Into<String>>(&mut self, status: S) {
+        self.status = status.into();
+    }
+
+    pub fn type_char(&mut self, c: char) {
+        if !c.is_control() && !c.is_whitespace() && self.address.chars().count() < Self::MAX_ADDRESS_LEN {
+            self.address.push(c);
+        }
+    }
+
+    pub fn backspace(&mut self) {
+        self.address.pop();
+    }
+
+    /// Asks to connect to the typed address. An invalid address only sets the status.
+    pub fn request_connect(&mut self) {
+        if self.address.parse::<SocketAddr>().is_err() {
+            self.status = format!("`{}` is not a valid address, expected <ip>:<port>", self.address);
+            return
+        }
+        self.status = format!("connecting to {}...", self.address);
+        self.connect = true;
+    }
+
+    /// The address to connect to, once per request.
+    pub(crate) fn take_connect_request(&mut self) -> Option<String> {
+        std::mem::take(&mut self.connect).then(|| self.address.clone())
+    }
+}
+
+/// Main menu shown before connecting and after the connection ends.
+/// Type the server address and press Enter or click the button to connect, the [`MenuState`] store holds the input.
+pub struct MenuLayer {
+    title: DynTextArea,
+    label: DynTextArea,
+    address: DynTextArea,
+    status: DynTextArea,
+    button: Quad<FlatColor>,
+    button_label: DynTextArea,
+    /// mouse position in the layer's coordinates
+    cursor: Vector2<f32>,
+    dirty: bool
+}
+
+impl MenuLayer {
+    const LEFT: f32 = 10.0;
+    const FONT_SIZE: f32 = 4.0;
+    const BUTTON_POSITION: Vector2<f32> = Vector2::new(Self::LEFT, 48.0);
+    const BUTTON_SIZE: Vector2<f32> = Vector2::new(30.0, 8.0);
+    const BUTTON_COLOR: [f32; 4] = [0.2, 0.3, 0.6, 1.0];
+    const Z_INDEX: u8 = 200;
+
+    pub fn new(font: Rc<BitmapFont>) -> Self {
+        let text = |position, z_index, font_size, string| DynTextArea::with_string(
+            position, z_index, font_size, 0.5, font.clone(), FlatTexture::get(), string
+        );
+        let line = |y, font_size, string| text(Vector2::new(Self::LEFT, y), Self::Z_INDEX, font_size, string);
+        Self {
+            title: line(15.0, 8.0, "aeonetica"),
+            label: line(32.0, 3.0, "server address"),
+            address: line(38.0, Self::FONT_SIZE, ""),
+            status: line(62.0, 3.0, "").with_wrap_width(140.0),
+            button: Quad::with_color(Self::BUTTON_POSITION, Self::BUTTON_SIZE, Self::Z_INDEX, Self::BUTTON_COLOR),
+            button_label: text(Self::BUTTON_POSITION + Vector2::new(3.0, 2.0), Self::Z_INDEX + 1, Self::FONT_SIZE, "connect"),
+            cursor: Vector2::default(),
+            dirty: true
+        }
+    }
+
+    fn over_button(&self) -> bool {
+        let end = Self::BUTTON_POSITION + Self::BUTTON_SIZE;
+        (Self::BUTTON_POSITION.x..end.x).contains(&self.cursor.x) && (Self::BUTTON_POSITION.y..end.y).contains(&self.cursor.y)
+    }
+}
+
+impl Layer for MenuLayer {
+    fn instantiate_camera(&self) -> Camera {
+        Camera::new(0.0, 160.0, 90.0, 0.0, 1.0, -1.0)
+    }
+
+    fn resize_camera(&mut self, camera: &mut Camera, aspect_ratio: f32) {
+        // keep the menu anchored to the left edge
+        camera.set_projection(0.0, 90.0 * aspect_ratio, 90.0, 0.0, 1.0, -1.0);
+    }
+
+    fn quit(&mut self, renderer: &mut Renderer, _store: &mut DataStore) {
+        renderer.remove(&mut self.title);
+        renderer.remove(&mut self.label);
+        renderer.remove(&mut self.address);
+        renderer.remove(&mut self.status);
+        renderer.remove(&mut self.button);
+        renderer.remove(&mut self.button_label);
+    }
+
+    fn post_handles_update(&mut self, store: &mut DataStore, renderer: &mut Renderer, _time: Time) {
+        let state = store.mut_or_default::<MenuState>();
+        // the status also changes from outside, e.g. after connecting failed
+        if self.dirty || self.status.string() != state.status() {
+            self.address.set_string(renderer, format!("> {}_", state.address()));
+            self.status.set_string(renderer, state.status());
+            self.dirty = false;
+        }
+        let _ = renderer.draw(&mut self.title);
+        let _ = renderer.draw(&mut self.label);
+        let _ = renderer.draw(&mut self.address);
+        let _ = renderer.draw(&mut self.status);
+        let _ = renderer.draw(&mut self.button);
+        let _ = renderer.draw(&mut self.button_label);
+    }
+
+    fn event(&mut self, event: &Event, store: &mut DataStore) -> bool {
+        let state = store.mut_or_default::<MenuState>();
+        match event {
+            Event::KeyPressed(KeyCode::Enter) => state.request_connect(),
+            Event::KeyPressed(KeyCode::Backspace) => state.backspace(),
+            Event::CharTyped(c) => state.type_char(*c),
+            Event::MouseMoved(position) => {
+                self.cursor = *position;
+                return false
+            }
+            Event::MouseButtonPressed(MouseButton::Left) if self.over_button() => state.request_connect(),
+            _ => return false
+        }
+        self.dirty = true;
+        true
+    }
+
+    fn name(&self) -> &'static str {
+        "Menu"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_valid_addresses_are_connected_to_once() {
+        let mut state = MenuState::new("127.0.0.1:6090");
+        " :".chars().for_each(|c| state.type_char(c));
+        assert_eq!(state.address(), "127.0.0.1:6090:");
+        state.request_connect();
+        assert!(state.status().contains("not a valid address"));
+        assert_eq!(state.take_connect_request(), None);
+
+        state.backspace();
+        state.backspace();
+        state.type_char('1');
+        state.request_connect();
+        assert_eq!(state.take_connect_request().as_deref(), Some("127.0.0.1:6091"));
+        assert_eq!(state.take_connect_request(), None);
+    }
+}
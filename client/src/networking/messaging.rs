@@ -5,6 +5,7 @@ use aeonetica_engine::nanoserde::{DeBin, SerBin};
 use aeonetica_engine::networking::client_packets::{ClientMessage, ClientPacket};
 use aeonetica_engine::networking::messaging::ClientEntity;
 use aeonetica_engine::networking::SendMode;
+use aeonetica_engine::networking::stats::NetworkStatsSnapshot;
 use aeonetica_engine::util::id_map::IdMap;
 use aeonetica_engine::util::nullable::Nullable;
 use aeonetica_engine::util::type_to_id;
@@ -30,11 +31,14 @@ unsafe impl aeonetica_server::ecs::messaging::ClientMessenger for ClientMessenge
 unsafe impl aeonetica_server::ecs::messaging::Renderer for Renderer {}
 unsafe impl aeonetica_server::ecs::messaging::DataStore for DataStore {}
 
+/// Returns the serialized reply if registered with [`ClientMessenger::register_replying_receiver`]
+pub(crate) type ClientReceiver = Box<dyn Fn(&mut dyn ClientHandle, &mut ClientMessenger, Nullable<&mut Renderer>,  &mut DataStore, &Vec<u8>) -> Option<Vec<u8>>>;
+
 pub struct ClientMessenger {
     nc: Rc<RefCell<NetworkClient>>,
     client_id: ClientId,
     entity_id: EntityId,
-    pub(crate) client_receivers: IdMap<Box<dyn Fn(&mut dyn ClientHandle, &mut ClientMessenger, Nullable<&mut Renderer>,  &mut DataStore, &Vec<u8>)>>
+    pub(crate) client_receivers: IdMap<ClientReceiver>
 }
 
 impl ClientMessenger {
@@ -50,8 +54,17 @@ impl ClientMessenger {
 
 impl ClientMessenger {
     pub fn register_receiver<F: Fn(&mut T, &mut ClientMessenger, Nullable<&mut Renderer>, &mut DataStore, M) + 'static, T: ClientHandle, M: SerBin + DeBin>(&mut self, f: F) {
-        let m = move |handle: &mut dyn ClientHandle, messenger: &mut ClientMessenger, renderer: Nullable<&mut Renderer>, store: &mut DataStore, data: &Vec<u8>|
+        let m = move |handle: &mut dyn ClientHandle, messenger: &mut ClientMessenger, renderer: Nullable<&mut Renderer>, store: &mut DataStore, data: &Vec<u8>| {
             f(unsafe { &mut *std::mem::transmute::<_, &(*mut T, usize)>(Box::new(handle)).0 }, messenger, renderer, store, M::deserialize_bin(data).unwrap());
+            None
+        };
+        self.client_receivers.insert(type_to_id::<F>(), Box::new(m));
+    }
+
+    /// Registers a receiver for `Messenger::call_client_fn_await`. Its return value is sent back to the server as the reply.
+    pub fn register_replying_receiver<F: Fn(&mut T, &mut ClientMessenger, Nullable<&mut Renderer>, &mut DataStore, M) -> R + 'static, T: ClientHandle, M: SerBin + DeBin, R: SerBin + DeBin>(&mut self, f: F) {
+        let m = move |handle: &mut dyn ClientHandle, messenger: &mut ClientMessenger, renderer: Nullable<&mut Renderer>, store: &mut DataStore, data: &Vec<u8>|
+            Some(f(unsafe { &mut *std::mem::transmute::<_, &(*mut T, usize)>(Box::new(handle)).0 }, messenger, renderer, store, M::deserialize_bin(data).unwrap()).serialize_bin());
         self.client_receivers.insert(type_to_id::<F>(), Box::new(m));
     }
 
@@ -59,7 +72,24 @@ impl ClientMessenger {
         self.client_receivers.remove(&type_to_id::<F>());
     }
 
-    pub fn call_server_fn<F: Fn(&EntityId, &mut Engine, &ClientId, M), M: SerBin + DeBin>(&mut self, _: F, message: M, mode: SendMode) {
+    /// Whether the reliable connection to the server is up. `false` while the client is reconnecting;
+    /// `SendMode::Safe` messages sent in the meantime are delivered once the connection is re-established.
+    pub fn is_connected(&self) -> bool {
+        self.nc.borrow().is_connected()
+    }
+
+    /// Traffic of this client since it started, e.g. for a debug overlay.
+    pub fn network_stats(&self) -> NetworkStatsSnapshot {
+        self.nc.borrow().stats()
+    }
+
+    /// Calls `F` on the server side counterpart of this handle, the entity with the same id, passing `message`.
+    ///
+    /// `F` has to be registered on that entity's `Messenger` with `register_receiver`, otherwise the server drops the
+    /// message and logs an error. The function is identified by `type_to_id::<F>()`, which hashes the full type name of
+    /// the function item, so both ends have to name the same function, e.g. `World::request_world_chunk`, from the same
+    /// version of the server crate. `M` is serialized with `SerBin` and has to match the receiver's message type exactly.
+    pub fn call_server_fn<F: Fn(&EntityId, &mut Engine, &ClientId, M) + 'static, M: SerBin + DeBin>(&mut self, _: F, message: M, mode: SendMode) {
         let id = type_to_id::<F>();
         let _ = self.nc.borrow().send(&ClientPacket {
             client_id: self.client_id,
@@ -67,4 +97,10 @@ impl ClientMessenger {
             message: ClientMessage::ModMessage(self.entity_id, id, message.serialize_bin()),
         }, mode);
     }
-}
\ No newline at end of file
+
+    /// Like [`ClientMessenger::call_server_fn`], but always uses [`SendMode::Safe`],
+    /// so the call is delivered exactly once and in order with other reliable messages.
+    pub fn call_server_fn_reliable<F: Fn(&EntityId, &mut Engine, &ClientId, M) + 'static, M: SerBin + DeBin>(&mut self, f: F, message: M) {
+        self.call_server_fn(f, message, SendMode::Safe)
+    }
+}
@@ -1,69 +1,306 @@
 use std::cell::RefCell;
-use std::io::{Read, Write};
-use std::net::{TcpStream, UdpSocket};
+use std::collections::VecDeque;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{Shutdown, TcpStream, UdpSocket};
 use std::sync::{Arc, Mutex};
-use aeonetica_engine::error::{Error, Fatality, ErrorResult};
-use aeonetica_engine::error::builtin::NetworkError;
-use aeonetica_engine::{log};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use aeonetica_engine::error::{Error, ErrorResult};
+use aeonetica_engine::{ClientId, Id, log};
 use aeonetica_engine::nanoserde::{SerBin, DeBin};
 use aeonetica_engine::networking::{MAX_PACKET_SIZE, SendMode};
-use aeonetica_engine::networking::client_packets::{ClientPacket};
-use aeonetica_engine::networking::server_packets::ServerPacket;
+use aeonetica_engine::networking::datagram::{DatagramReceiver, DatagramSender};
+use aeonetica_engine::networking::client_packets::{ClientMessage, ClientPacket, LoginInfo};
+use aeonetica_engine::networking::server_packets::{ServerMessage, ServerPacket};
+use aeonetica_engine::networking::stats::{NetworkStats, NetworkStatsSnapshot};
 
 mod protocol;
 pub mod messaging;
 
+const RECONNECT_INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const RECONNECT_MAX_BACKOFF: Duration = Duration::from_secs(10);
+/// How often the receiving threads check whether the client was dropped
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// Reliable packets kept while disconnected, the oldest ones are dropped beyond that
+const MAX_PENDING_PACKETS: usize = 4096;
+
 pub(crate) struct NetworkClient {
     pub(crate) udp: UdpSocket,
-    pub(crate) tcp: RefCell<TcpStream>,
-    received: Arc<Mutex<Vec<ServerPacket>>>
+    tcp: Arc<Mutex<TcpConnection>>,
+    connected: Arc<AtomicBool>,
+    /// sent again after reconnecting while logged in
+    login: Arc<Mutex<Option<LoginInfo>>>,
+    /// cleared on drop to stop the receiving threads, which frees the sockets for the next connection
+    running: Arc<AtomicBool>,
+    datagrams: RefCell<DatagramSender>,
+    received: Arc<Mutex<Vec<ServerPacket>>>,
+    pub(crate) stats: Arc<ClientStats>
+}
+
+pub(crate) type ClientStats = NetworkStats<ClientMessage, ServerMessage>;
+
+struct TcpConnection {
+    /// `None` while reconnecting
+    stream: Option<TcpStream>,
+    /// reliable packets sent while disconnected, flushed in order once reconnected.
+    /// At most [`MAX_PENDING_PACKETS`], see [`TcpConnection::queue_pending`]
+    pending: VecDeque<Vec<u8>>,
+    /// reliable packets waiting for [`TcpConnection::flush`], `None` unless batching
+    batch: Option<Vec<Vec<u8>>>,
+    /// reused for writing a batch at once
+    buffer: Vec<u8>,
+    /// applied to every new stream as well
+    nodelay: bool
+}
+
+impl TcpConnection {
+    fn new(stream: TcpStream) -> Self {
+        Self {
+            stream: Some(stream),
+            pending: VecDeque::new(),
+            batch: None,
+            buffer: vec![],
+            nodelay: false
+        }
+    }
+
+    fn send(&mut self, data: Vec<u8>) {
+        if let Some(batch) = &mut self.batch {
+            batch.push(data);
+            return
+        }
+        if let Some(stream) = &mut self.stream {
+            match write_packet(stream, &data) {
+                Ok(()) => return,
+                Err(e) => {
+                    let e: Box<Error> = e.into();
+                    e.log();
+                    self.stream = None;
+                }
+            }
+        }
+        self.queue_pending([data]);
+    }
+
+    fn queue_pending(&mut self, packets: impl IntoIterator<Item = Vec<u8>>) {
+        self.pending.extend(packets);
+        let overflow = self.pending.len().saturating_sub(MAX_PENDING_PACKETS);
+        if overflow > 0 {
+            log!(WARN, "dropping {overflow} reliable packets queued while disconnected");
+            self.pending.drain(..overflow);
+        }
+    }
+
+    /// Writes the batched packets in one go and stops batching.
+    fn flush(&mut self) {
+        let Some(batch) = self.batch.take() else {
+            return
+        };
+        if batch.is_empty() {
+            return
+        }
+        if let Some(stream) = &mut self.stream {
+            match write_batch(stream, &batch, &mut self.buffer) {
+                Ok(()) => return,
+                Err(e) => {
+                    let e: Box<Error> = e.into();
+                    e.log();
+                    self.stream = None;
+                }
+            }
+        }
+        self.queue_pending(batch);
+    }
+}
+
+fn write_packet(writer: &mut impl Write, data: &[u8]) -> std::io::Result<()> {
+    writer.write_all(&(data.len() as u32).to_le_bytes())?;
+    writer.write_all(data)
+}
+
+/// Frames every packet like [`write_packet`] into `buffer` and writes them with a single call.
+fn write_batch(writer: &mut impl Write, packets: &[Vec<u8>], buffer: &mut Vec<u8>) -> std::io::Result<()> {
+    buffer.clear();
+    for data in packets {
+        buffer.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        buffer.extend_from_slice(data);
+    }
+    writer.write_all(buffer)
+}
+
+fn read_packets(stream: &mut TcpStream, received: &Mutex<Vec<ServerPacket>>, stats: &ClientStats) -> std::io::Error {
+    loop {
+        let mut size = [0u8;4];
+        if let Err(e) = stream.read_exact(&mut size) { return e }
+        let size = u32::from_le_bytes(size);
+        let mut buffer: Vec<u8> = vec![0;size as usize];
+        if let Err(e) = stream.read_exact(&mut buffer[..]) { return e }
+        match ServerPacket::deserialize_bin(&buffer[..]) {
+            Ok(packet) => {
+                stats.received.record(&packet.message, SendMode::Safe, buffer.len());
+                received.lock().unwrap().push(packet)
+            }
+            Err(e) => log!(ERROR, "invalid server packet: {e}")
+        }
+    }
+}
+
+/// Reconnects to the server with exponential backoff, logs back in if the client was logged in before
+/// and flushes all reliable packets queued in the meantime. Returns the new stream for reading,
+/// or `None` if the client was dropped in the meantime.
+fn reconnect(server: &str, client_id: ClientId, tcp: &Mutex<TcpConnection>, login: &Mutex<Option<LoginInfo>>, running: &AtomicBool) -> Option<TcpStream> {
+    let mut backoff = RECONNECT_INITIAL_BACKOFF;
+    loop {
+        std::thread::sleep(backoff);
+        if !running.load(Ordering::SeqCst) {
+            return None
+        }
+        backoff = (backoff * 2).min(RECONNECT_MAX_BACKOFF);
+
+        let stream = match TcpStream::connect(server) {
+            Ok(stream) => stream,
+            Err(e) => {
+                log!(WARN, "reconnecting to {server} failed: {e}, retrying in {}ms", backoff.as_millis());
+                continue
+            }
+        };
+        let Ok(mut writer) = stream.try_clone() else { continue };
+
+        let mut connection = tcp.lock().unwrap();
+        if let Err(e) = writer.set_nodelay(connection.nodelay) {
+            log!(WARN, "could not set nodelay on the connection to {server}: {e}");
+        }
+        let mut flush = || {
+            if let Some(login) = login.lock().unwrap().clone() {
+                write_packet(&mut writer, &SerBin::serialize_bin(&ClientPacket {
+                    client_id,
+                    conv_id: Id::new(),
+                    message: ClientMessage::Login(login),
+                }))?;
+            }
+            while let Some(data) = connection.pending.front() {
+                write_packet(&mut writer, data)?;
+                connection.pending.pop_front();
+            }
+            Ok::<_, std::io::Error>(())
+        };
+        if let Err(e) = flush() {
+            log!(WARN, "lost connection to {server} while reconnecting: {e}");
+            continue
+        }
+        connection.stream = Some(writer);
+        return Some(stream)
+    }
 }
 
 impl NetworkClient {
-    pub(crate) fn start(addr: &str, server: &str) -> ErrorResult<Self>{
+    /// Partially received udp messages are discarded after `fragment_timeout`.
+    pub(crate) fn start(client_id: ClientId, addr: &str, server: &str, fragment_timeout: Duration) -> ErrorResult<Self>{
         let tcp = TcpStream::connect(server)?;
         tcp.set_nonblocking(false).unwrap();
         let udp = UdpSocket::bind(addr)?;
         udp.connect(server)?;
         let udp_sock = udp.try_clone()?;
+        udp_sock.set_read_timeout(Some(SHUTDOWN_POLL_INTERVAL))?;
         let mut tcp_sock = tcp.try_clone()?;
+        let tcp = Arc::new(Mutex::new(TcpConnection::new(tcp)));
+        let connected = Arc::new(AtomicBool::new(true));
+        let login = Arc::new(Mutex::new(None));
+        let running = Arc::new(AtomicBool::new(true));
+        let (udp_running, tcp_running) = (running.clone(), running.clone());
         let received = Arc::new(Mutex::new(vec![]));
         let recv_udp = received.clone();
         let recv_tcp = received.clone();
+        let stats = Arc::new(ClientStats::default());
+        let (udp_stats, tcp_stats) = (stats.clone(), stats.clone());
         std::thread::spawn(move || {
             let mut buf = [0u8; MAX_PACKET_SIZE];
-            loop {
+            let mut datagrams = DatagramReceiver::new(fragment_timeout);
+            while udp_running.load(Ordering::SeqCst) {
                 match udp_sock.recv_from(&mut buf) {
-                    Ok((len, src)) => match DeBin::deserialize_bin(&buf[..len]) {
-                       Ok(packet) => recv_udp.lock().unwrap().push(packet),
-                       Err(e) => log!(ERROR, "invalid server packet from {src}: {e}")
+                    Ok((len, src)) => {
+                        let Some(packets) = datagrams.receive(&buf[..len]) else {
+                            log!(ERROR, "invalid datagram from {src}");
+                            continue
+                        };
+                        for (data, mode) in packets {
+                            match ServerPacket::deserialize_bin(&data[..]) {
+                                Ok(packet) => {
+                                    udp_stats.received.record(&packet.message, mode, data.len());
+                                    recv_udp.lock().unwrap().push(packet)
+                                }
+                                Err(e) => log!(ERROR, "invalid server packet from {src}: {e}")
+                            }
+                        }
                     },
+                    Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => (),
                     Err(e) => {
                         log!(ERROR, "couldn't recieve a datagram: {}", e);
                     }
                 }
             }
         });
+        let (server, reconnect_tcp, reconnect_connected, reconnect_login) = (server.to_string(), tcp.clone(), connected.clone(), login.clone());
         std::thread::spawn(move || {
             loop {
-                let mut size = [0u8;4];
-                tcp_sock.read_exact(&mut size).unwrap();
-                let size = u32::from_le_bytes(size);
-                let mut buffer: Vec<u8> = vec![0;size as usize];
-                tcp_sock.read_exact(&mut buffer[..]).unwrap();
-                match DeBin::deserialize_bin(&buffer[..]) {
-                    Ok(packet) => recv_tcp.lock().unwrap().push(packet),
-                    Err(e) => log!(ERROR, "invalid server packet: {e}")
+                let e = read_packets(&mut tcp_sock, &recv_tcp, &tcp_stats);
+                reconnect_connected.store(false, Ordering::SeqCst);
+                reconnect_tcp.lock().unwrap().stream = None;
+                if !tcp_running.load(Ordering::SeqCst) {
+                    break
                 }
+                log!(WARN, "lost tcp connection to server: {e}, reconnecting...");
+                let Some(stream) = reconnect(&server, client_id, &reconnect_tcp, &reconnect_login, &tcp_running) else {
+                    break
+                };
+                tcp_sock = stream;
+                reconnect_connected.store(true, Ordering::SeqCst);
+                log!("reconnected to server {server}");
             }
         });
         Ok(Self {
             udp,
-            tcp: RefCell::new(tcp),
-            received
+            tcp,
+            connected,
+            login,
+            running,
+            datagrams: Default::default(),
+            received,
+            stats
         })
     }
 
+    /// Whether the reliable connection to the server is currently up.
+    /// Returns `false` while reconnecting after the connection dropped.
+    pub(crate) fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::SeqCst)
+    }
+
+    /// Packets and bytes sent and received since the client started.
+    pub(crate) fn stats(&self) -> NetworkStatsSnapshot {
+        self.stats.snapshot()
+    }
+
+    /// Disables Nagle's algorithm on the tcp connection if `nodelay` is set, also after reconnecting.
+    pub(crate) fn set_nodelay(&self, nodelay: bool) -> ErrorResult<()> {
+        let mut connection = self.tcp.lock().unwrap();
+        connection.nodelay = nodelay;
+        if let Some(stream) = &connection.stream {
+            stream.set_nodelay(nodelay)?;
+        }
+        Ok(())
+    }
+
+    /// Collects reliable packets instead of writing each on its own, until [`NetworkClient::flush`] writes them at once.
+    pub(crate) fn batch_reliable(&self) {
+        self.tcp.lock().unwrap().batch.get_or_insert_with(Vec::new);
+    }
+
+    /// Writes the reliable packets collected since [`NetworkClient::batch_reliable`], does nothing if not batching.
+    pub(crate) fn flush(&self) {
+        self.tcp.lock().unwrap().flush();
+    }
+
     pub(crate) fn queued_packets(&mut self) -> Vec<ServerPacket> {
         let mut packets = vec![];
         std::mem::swap(&mut self.received.lock().unwrap() as &mut Vec<ServerPacket>, &mut packets);
@@ -72,29 +309,106 @@ impl NetworkClient {
 
     pub(crate) fn send(&self, packet: &ClientPacket, mode: SendMode) -> ErrorResult<()> {
         let data = SerBin::serialize_bin(packet);
+        self.stats.sent.record(&packet.message, mode, data.len());
         match mode {
-            SendMode::Quick => {
-                if data.len() > MAX_PACKET_SIZE {
-                    return Err(Error::new(NetworkError(format!("Packet is too large: {} > {}", data.len(), MAX_PACKET_SIZE)), Fatality::WARN, false))
-                }
+            SendMode::Quick | SendMode::Ordered => {
+                let datagrams = self.datagrams.borrow_mut().wrap(&data, matches!(mode, SendMode::Ordered));
                 let sock = self.udp.try_clone()?;
-                std::thread::spawn(move || sock.send(&data[..]).map_err(|e| {
-                    let e: Box<Error> = e.into();
-                    e.log();
-                }));
+                std::thread::spawn(move || for datagram in datagrams {
+                    let _ = sock.send(&datagram[..]).map_err(|e| {
+                        let e: Box<Error> = e.into();
+                        e.log();
+                    });
+                });
             }
             SendMode::Safe => {
-                let mut tcp = self.tcp.borrow_mut();
-                let _ = tcp.write_all(&(data.len() as u32).to_le_bytes()).map_err(|e| {
-                    let e: Box<Error> = e.into();
-                    e.log();
-                });
-                let _ = tcp.write_all(&data[..]).map_err(|e| {
-                    let e: Box<Error> = e.into();
-                    e.log();
-                });
+                match &packet.message {
+                    ClientMessage::Login(login) => *self.login.lock().unwrap() = Some(login.clone()),
+                    ClientMessage::Logout => *self.login.lock().unwrap() = None,
+                    _ => ()
+                }
+                self.tcp.lock().unwrap().send(data);
             }
         }
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+impl Drop for NetworkClient {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        let mut connection = self.tcp.lock().unwrap();
+        connection.flush();
+        // wakes up the tcp thread blocked on reading
+        if let Some(stream) = &connection.stream {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Counts the write calls, each one is a syscall on a socket.
+    #[derive(Default)]
+    struct CountingWriter {
+        writes: usize,
+        data: Vec<u8>
+    }
+
+    impl Write for CountingWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.writes += 1;
+            self.data.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn batches_are_written_at_once_with_the_same_framing() {
+        let packets: Vec<Vec<u8>> = (1..=16u8).map(|i| vec![i; i as usize * 10]).collect();
+
+        let mut single = CountingWriter::default();
+        for data in &packets {
+            write_packet(&mut single, data).unwrap();
+        }
+        let mut batched = CountingWriter::default();
+        write_batch(&mut batched, &packets, &mut vec![]).unwrap();
+
+        assert_eq!(single.writes, 32);
+        assert_eq!(batched.writes, 1);
+        assert_eq!(batched.data, single.data);
+    }
+
+    #[test]
+    fn batches_sent_while_disconnected_are_kept_in_order() {
+        let mut connection = TcpConnection { stream: None, pending: VecDeque::new(), batch: None, buffer: vec![], nodelay: false };
+        connection.send(vec![1]);
+        connection.batch = Some(vec![]);
+        connection.send(vec![2]);
+        connection.send(vec![3]);
+        assert_eq!(connection.pending, vec![vec![1]]);
+
+        connection.flush();
+        assert!(connection.batch.is_none());
+        assert_eq!(connection.pending, vec![vec![1], vec![2], vec![3]]);
+        // flushing without a batch does nothing
+        connection.flush();
+        assert_eq!(connection.pending.len(), 3);
+    }
+
+    #[test]
+    fn pending_packets_are_capped() {
+        let mut connection = TcpConnection { stream: None, pending: VecDeque::new(), batch: None, buffer: vec![], nodelay: false };
+        for i in 0..MAX_PENDING_PACKETS + 10 {
+            connection.send((i as u32).to_le_bytes().to_vec());
+        }
+        assert_eq!(connection.pending.len(), MAX_PENDING_PACKETS);
+        assert_eq!(connection.pending[0], 10u32.to_le_bytes().to_vec());
+    }
+}
@@ -1,11 +1,10 @@
-use std::process::exit;
 use aeonetica_engine::error::ErrorResult;
 use aeonetica_engine::log;
 use aeonetica_engine::networking::client_packets::{ClientMessage, ClientPacket};
 use aeonetica_engine::networking::SendMode;
 use aeonetica_engine::networking::server_packets::{ServerMessage, ServerPacket};
 use aeonetica_engine::util::nullable::Nullable::{Null, Value};
-use crate::client_runtime::{ClientHandleBox, ClientRuntime};
+use crate::client_runtime::{ClientHandleBox, ClientRuntime, ClientState};
 use crate::data_store::DataStore;
 use crate::networking::messaging::ClientMessenger;
 use crate::renderer::context::RenderContext;
@@ -32,9 +31,17 @@ impl ClientRuntime {
                 conv_id: packet.conv_id,
                 message: ClientMessage::Pong(msg.clone()),
             }, SendMode::Safe)?,
+            ServerMessage::TimeScale(scale, paused) => {
+                self.time_scale = *scale;
+                self.paused = *paused;
+            }
             ServerMessage::Unregister(reason) => {
                 log!("server unregistered client: {reason}");
-                exit(0)
+                self.state = ClientState::Disconnected(format!("unregistered by the server: {reason}"));
+            }
+            ServerMessage::Kick(reason) => {
+                log!(WARN, "kicked by the server: {reason}");
+                self.state = ClientState::Disconnected(format!("kicked by the server: {reason}"));
             }
             ServerMessage::AddClientHandle(eid, handle_id) => {
                 log!("added client handle: {handle_id}");
@@ -66,12 +73,19 @@ impl ClientRuntime {
             ServerMessage::ModMessage(eid, rid, data) => {
                 if let Some(h) = self.handles.get_mut(eid) {
                     if let Some(f) = h.messenger.client_receivers.remove(rid) {
-                        if let Some(layer) = context.layer_stack.layer_map.get(&h.handle.owning_layer()) {
+                        let reply = if let Some(layer) = context.layer_stack.layer_map.get(&h.handle.owning_layer()) {
                             f(&mut *h.handle, &mut h.messenger, Value(&mut layer.borrow_mut().renderer), store, data)
                         } else {
                             f(&mut *h.handle, &mut h.messenger, Null, store, data)
-                        }
+                        };
                         h.messenger.client_receivers.insert(*rid, f);
+                        if let Some(reply) = reply {
+                            self.nc.borrow().send(&ClientPacket {
+                                client_id: self.client_id,
+                                conv_id: packet.conv_id,
+                                message: ClientMessage::ModReply(reply),
+                            }, SendMode::Safe)?;
+                        }
                     }
                 }
             }
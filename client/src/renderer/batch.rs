@@ -2,8 +2,8 @@ use std::{rc::Rc, cell::Cell};
 
 use crate::{uniform_str, renderer::{shader::UniformStr, RenderError}};
 
-use super::{buffer::{Buffer, BufferLayout, BufferType, BufferUsage, vertex_array::VertexArray}, RenderID, shader::{self, ShaderDataType}, Renderer, material::Material};
-use aeonetica_engine::{collections::ordered_map::ExtractComparable, error::{ErrorResult, IntoError}, Id};
+use super::{buffer::{Buffer, BufferLayout, BufferLayoutBuilder, BufferType, BufferUsage, Vertex, TexCoord, TextureID, vertex_array::VertexArray}, RenderID, shader::{self, ShaderDataType}, Renderer, material::Material, util};
+use aeonetica_engine::{collections::ordered_map::ExtractComparable, error::{ErrorResult, IntoError}, Id, log};
 
 pub type BatchID = Id;
 
@@ -22,8 +22,78 @@ impl Offset {
     }
 }
 
+/// a removed vertex range that can be refilled by data of the exact same size
+#[derive(Debug)]
+struct FreeSlot {
+    offset_index: usize,
+    num_vertices: u32,
+    num_indices: u32,
+}
+
+impl FreeSlot {
+    fn fits(&self, data: &VertexData) -> bool {
+        self.num_vertices == data.num_vertices() && self.num_indices == data.num_indices()
+    }
+}
+
+fn has_texture_slot(textures: &[RenderID], texture: RenderID) -> bool {
+    textures.contains(&texture) || textures.len() < Batch::NUM_TEXTURE_SLOTS
+}
+
+/// slot `texture` is bound to, taking the next free one if it isn't bound yet.
+/// `None` if all slots are taken by other textures
+fn texture_slot(textures: &mut Vec<RenderID>, texture: RenderID) -> Option<usize> {
+    if let Some(slot) = textures.iter().position(|id| *id == texture) {
+        return Some(slot)
+    }
+    if textures.len() >= Batch::NUM_TEXTURE_SLOTS {
+        return None
+    }
+    textures.push(texture);
+    Some(textures.len() - 1)
+}
+
+/// corners of the unit quad every instance of an instanced batch is drawn from
+const QUAD_CORNERS: [[f32; 2]; 4] = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+const QUAD_INDICES: [u32; 6] = [0, 1, 2, 2, 3, 0];
+/// position, size, texture offset and texture size as floats followed by the texture slot
+const INSTANCE_STRIDE: usize = 9 * 4;
+
+thread_local! {
+    static CORNER_LAYOUT: Rc<BufferLayout> = Rc::new(BufferLayoutBuilder::<(Vertex,)>::build());
+    static INSTANCE_LAYOUT: Rc<BufferLayout> = Rc::new(BufferLayoutBuilder::<(Vertex, Vertex, TexCoord, TexCoord, TextureID)>::build());
+}
+
+/// Turns the four vertices of an axis aligned quad in the layout of [`FlatTexture`](super::material::FlatTexture)
+/// into the instance of the unit quad covering the same area and texture region.
+fn quad_instance(vertices: &[u8], layout: &BufferLayout) -> [u8; INSTANCE_STRIDE] {
+    let stride = layout.stride() as usize;
+    let offsets = layout.elements().iter().map(|element| element.offset() as usize).collect::<Vec<_>>();
+    let float = |vertex: usize, element: usize, component: usize| {
+        let at = vertex * stride + offsets[element] + component * 4;
+        f32::from_ne_bytes(vertices[at..at + 4].try_into().unwrap())
+    };
+
+    // the first vertex is the top left corner, the third the bottom right one
+    let mut instance = [0; INSTANCE_STRIDE];
+    let floats = [
+        float(0, 0, 0), float(0, 0, 1),
+        float(2, 0, 0) - float(0, 0, 0), float(2, 0, 1) - float(0, 0, 1),
+        float(0, 1, 0), float(0, 1, 1),
+        float(2, 1, 0) - float(0, 1, 0), float(2, 1, 1) - float(0, 1, 1)
+    ];
+    for (i, value) in floats.into_iter().enumerate() {
+        instance[i * 4..i * 4 + 4].copy_from_slice(&value.to_ne_bytes());
+    }
+    instance[32..].copy_from_slice(&vertices[offsets[2]..offsets[2] + 4]);
+    instance
+}
+
+/// Batches hold either plain vertices or, for [instanced](super::material::Material::instanced) materials,
+/// one instance per quad in `vertices`, which are drawn from a shared unit quad in a single instanced draw call.
 pub(super) struct Batch {
     id: BatchID,
+    instanced: bool,
 
     layout: Rc<BufferLayout>,
     vertex_array: VertexArray,
@@ -33,6 +103,7 @@ pub(super) struct Batch {
     indices: Vec<u32>,
     indices_dirty: Cell<bool>,
     offsets: Vec<Offset>,
+    free_slots: Vec<FreeSlot>,
 
     shader: Rc<shader::Program>,
     textures: Vec<RenderID>,
@@ -46,7 +117,13 @@ impl Batch {
     const TEXTURE_SLOTS: [i32; 16] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15]; // 16 is the minimum amount per stage required by OpenGL
     const NUM_TEXTURE_SLOTS: usize = Self::TEXTURE_SLOTS.len();
 
+    pub(super) const MAX_BATCH_INSTANCE_COUNT: usize = 4096;
+
     pub fn new(id: BatchID, data: &VertexData) -> ErrorResult<Batch> {
+        if data.instanced {
+            return Self::new_instanced(id, data)
+        }
+
         let mut vertex_array = VertexArray::new()?;
 
         let vertex_buffer = Buffer::new_sized(
@@ -71,6 +148,7 @@ impl Batch {
 
         Ok(Self {
             id,
+            instanced: false,
 
             layout: data.layout().clone(),
             vertex_array,
@@ -80,6 +158,7 @@ impl Batch {
             indices,
             indices_dirty: Cell::new(false),
             offsets,
+            free_slots: vec![],
 
             shader: data.shader().clone(),
             textures: vec![],
@@ -87,62 +166,164 @@ impl Batch {
         })
     }
 
+    fn new_instanced(id: BatchID, data: &VertexData) -> ErrorResult<Batch> {
+        let mut vertex_array = VertexArray::new()?;
+
+        let corners = Buffer::new(
+            BufferType::Array,
+            util::to_raw_byte_slice!(&QUAD_CORNERS),
+            Some(CORNER_LAYOUT.with(|layout| layout.clone())),
+            BufferUsage::STATIC
+        )?;
+        vertex_array.set_vertex_buffer(corners);
+
+        let index_buffer = Buffer::new(
+            BufferType::ElementArray,
+            util::to_raw_byte_slice!(&QUAD_INDICES),
+            None,
+            BufferUsage::STATIC
+        )?;
+        vertex_array.set_index_buffer(index_buffer);
+
+        let instance_buffer = Buffer::new_sized(
+            BufferType::Array,
+            (Self::MAX_BATCH_INSTANCE_COUNT * INSTANCE_STRIDE) as isize,
+            Some(INSTANCE_LAYOUT.with(|layout| layout.clone())),
+            BufferUsage::DYNAMIC
+        )?;
+        vertex_array.set_instance_buffer(instance_buffer);
+
+        Ok(Self {
+            id,
+            instanced: true,
+
+            // the layout of the quads going in, used to match them to the batch
+            layout: data.layout().clone(),
+            vertex_array,
+
+            vertices: Vec::with_capacity(Self::MAX_BATCH_INSTANCE_COUNT * INSTANCE_STRIDE),
+            vertices_dirty: Cell::new(false),
+            indices: vec![],
+            indices_dirty: Cell::new(false),
+            offsets: Vec::with_capacity(Self::MAX_BATCH_INSTANCE_COUNT),
+            free_slots: vec![],
+
+            shader: data.shader().clone(),
+            textures: vec![],
+            z_index: data.z_index
+        })
+    }
+
+    /// bytes a quad of `num_vertices` vertices takes up in this batch
+    fn stored_size(&self, num_vertices: u32) -> usize {
+        if self.instanced {
+            INSTANCE_STRIDE
+        }
+        else {
+            (num_vertices * self.layout.stride()) as usize
+        }
+    }
+
     pub fn delete(mut self) {
         self.vertex_array.delete();
     }
 
     pub fn has_space_for(&self, data: &VertexData) -> bool {
         // check if the data belongs in the batch
-        if self.z_index != data.z_index || self.shader != *data.shader() || self.layout != *data.layout() {
+        if self.z_index != data.z_index || self.instanced != data.instanced || self.shader != *data.shader() || self.layout != *data.layout() {
             return false
         }
 
         // check if the batch has space for the data
-        if self.vertices.len() as u32 + data.vertices_num_bytes() >= Self::MAX_BATCH_VERTEX_COUNT {
+        let reusable = self.free_slots.iter().any(|slot| slot.fits(data));
+        if self.instanced {
+            if !reusable && self.offsets.len() >= Self::MAX_BATCH_INSTANCE_COUNT {
+                return false
+            }
+        }
+        else if !reusable && self.vertices.len() as u32 + data.vertices_num_bytes() >= Self::MAX_BATCH_VERTEX_COUNT {
             return false
         }
 
-        if self.indices.len() as u32 + data.num_indices() >= Self::MAX_BATCH_INDEX_COUNT {
+        if !reusable && self.indices.len() as u32 + data.num_indices() >= Self::MAX_BATCH_INDEX_COUNT {
             return false
         }
 
         // check if the batch contains or has space for the texture
         if let Some(t) = data.texture { 
-            return self.textures.contains(&t) || self.textures.len() < Self::NUM_TEXTURE_SLOTS
+            return has_texture_slot(&self.textures, t)
         }
 
         return true;
     }
 
-    pub fn add_vertices(&mut self, data: &mut VertexData) -> VertexLocation {
+    /// Returns `None` without touching the batch if all texture slots are taken by other textures,
+    /// callers should check [`Batch::has_space_for`] first and fall back to a new batch.
+    pub fn add_vertices(&mut self, data: &mut VertexData) -> Option<VertexLocation> {
         if let Some(tex_id) = data.texture {
-            let index = self.textures.iter().position(|id| *id == tex_id)
-                .unwrap_or_else(|| {
-                    self.textures.push(tex_id);
-                    self.textures.len() - 1
-                });
-
+            let index = texture_slot(&mut self.textures, tex_id);
+            let Some(index) = index else {
+                log!(WARN, "batch {} has no texture slot left for texture {tex_id}", self.id);
+                return None
+            };
             data.patch_texture_id(index as u32);
         }
 
+        let instance;
+        let stored: &[u8] = if self.instanced {
+            instance = quad_instance(data.vertices, &self.layout);
+            &instance
+        }
+        else {
+            data.vertices
+        };
+
+        if let Some(pos) = self.free_slots.iter().position(|slot| slot.fits(data)) {
+            let slot = self.free_slots.swap_remove(pos);
+            let offset = &self.offsets[slot.offset_index];
+
+            self.vertices[offset.vertices..offset.vertices + stored.len()].copy_from_slice(stored);
+            self.vertices_dirty.set(true);
+
+            // instances all share the indices of the unit quad
+            if !self.instanced {
+                let base_vertex = offset.vertices as u32 / self.layout.stride();
+                self.indices[offset.indices..offset.indices + slot.num_indices as usize].iter_mut()
+                    .zip(data.indices())
+                    .for_each(|(dst, i)| *dst = i + base_vertex);
+                self.indices_dirty.set(true);
+            }
+
+            return Some(VertexLocation {
+                batch: self.id,
+                offset_index: slot.offset_index,
+                num_vertices: slot.num_vertices,
+                num_indices: slot.num_indices,
+                z_index: self.z_index
+            })
+        }
+
         let num_existing_vert_bytes = self.vertices.len();
-        let num_existing_vertices = num_existing_vert_bytes as u32 / self.layout.stride();
-        self.vertices.extend_from_slice(data.vertices);
+        self.vertices.extend_from_slice(stored);
         self.vertices_dirty.set(true);
         
-        let indices = data.indices().iter().map(|i| i + num_existing_vertices);
         let num_existing_indices = self.indices.len();
-        self.indices.extend(indices);
-        self.indices_dirty.set(true);
+        if !self.instanced {
+            let num_existing_vertices = num_existing_vert_bytes as u32 / self.layout.stride();
+            let indices = data.indices().iter().map(|i| i + num_existing_vertices);
+            self.indices.extend(indices);
+            self.indices_dirty.set(true);
+        }
 
         self.offsets.push(Offset::new(num_existing_vert_bytes, num_existing_indices));
 
-        VertexLocation {
+        Some(VertexLocation {
             batch: self.id, 
             offset_index: self.offsets.len() - 1,
             num_vertices: data.num_vertices(),
-            num_indices: data.num_indices()
-        }
+            num_indices: data.num_indices(),
+            z_index: self.z_index
+        })
     }
 
     pub fn modify_vertices(&mut self, location: &VertexLocation, data: &mut [u8], texture: Option<RenderID>) -> ErrorResult<()> {
@@ -160,7 +341,12 @@ impl Batch {
 
         let offset = &self.offsets[location.offset_index()];
 
-        self.vertices[offset.vertices..offset.vertices + num_vert_bytes].copy_from_slice(data);
+        if self.instanced {
+            self.vertices[offset.vertices..offset.vertices + INSTANCE_STRIDE].copy_from_slice(&quad_instance(data, &self.layout));
+        }
+        else {
+            self.vertices[offset.vertices..offset.vertices + num_vert_bytes].copy_from_slice(data);
+        }
         self.vertices_dirty.set(true);
 
         Ok(())
@@ -168,29 +354,58 @@ impl Batch {
 
     pub fn remove_vertices(&mut self, location: &VertexLocation) {
         let offset_index = location.offset_index();
-        let offset = &self.offsets[offset_index];
-        let num_vert_bytes: usize = (location.num_vertices() * self.layout.stride()) as usize;
-        let num_indices = location.num_indices() as usize;
-        
-        self.vertices.drain(offset.vertices .. offset.vertices + num_vert_bytes);
-        self.vertices_dirty.set(true);
 
-        self.indices.drain(offset.indices .. offset.indices + num_indices);
-        self.indices_dirty.set(true);
+        if self.offsets.len() - 1 != offset_index {
+            let offset = &self.offsets[offset_index];
+            if self.instanced {
+                // an instance without size covers nothing, so the slot can be refilled later
+                self.vertices[offset.vertices..offset.vertices + INSTANCE_STRIDE].fill(0);
+                self.vertices_dirty.set(true);
+            }
+            else {
+                // collapse the indices into a degenerate triangle, so the slot can be refilled later
+                let base_vertex = offset.vertices as u32 / self.layout.stride();
+                self.indices[offset.indices..offset.indices + location.num_indices() as usize].fill(base_vertex);
+                self.indices_dirty.set(true);
+            }
 
-        if self.offsets.len() - 1 == offset_index {
-            self.offsets.pop();
+            self.free_slots.push(FreeSlot {
+                offset_index,
+                num_vertices: location.num_vertices(),
+                num_indices: location.num_indices()
+            });
+            return;
         }
-        else {
-            self.indices[offset.indices..].iter_mut().for_each(|i| *i -= location.num_vertices());
 
-            self.offsets[offset_index + 1..].iter_mut().for_each(|offset| {
-                offset.vertices -= num_vert_bytes;
-                offset.indices -= num_indices;
-            });
+        self.truncate_last_offset();
+
+        // trailing free slots can be given back entirely
+        while let Some(last) = self.offsets.len().checked_sub(1) && let Some(pos) = self.free_slots.iter().position(|slot| slot.offset_index == last) {
+            self.free_slots.swap_remove(pos);
+            self.truncate_last_offset();
+        }
+    }
+
+    fn truncate_last_offset(&mut self) {
+        if let Some(offset) = self.offsets.pop() {
+            self.vertices.truncate(offset.vertices);
+            self.vertices_dirty.set(true);
+
+            self.indices.truncate(offset.indices);
+            self.indices_dirty.set(true);
         }
     }
 
+    /// ratio of vertex bytes occupied by removed slots to all vertex bytes in the batch
+    pub fn fragmentation_ratio(&self) -> f32 {
+        if self.vertices.is_empty() {
+            return 0.0;
+        }
+
+        let free_bytes: usize = self.free_slots.iter().map(|slot| self.stored_size(slot.num_vertices)).sum();
+        free_bytes as f32 / self.vertices.len() as f32
+    }
+
     pub fn draw_vertices(&self, renderer: &mut Renderer) {
         if self.indices_dirty.get() {
             self.update_indices();
@@ -228,9 +443,17 @@ impl Batch {
         }
 
         self.vertex_array.bind();
-        let num_indices = self.indices.len() as i32;
-        unsafe {
-            gl::DrawElements(gl::TRIANGLES, num_indices, gl::UNSIGNED_INT, std::ptr::null());
+        if self.instanced {
+            let num_instances = (self.vertices.len() / INSTANCE_STRIDE) as i32;
+            unsafe {
+                gl::DrawElementsInstanced(gl::TRIANGLES, QUAD_INDICES.len() as i32, gl::UNSIGNED_INT, std::ptr::null(), num_instances);
+            }
+        }
+        else {
+            let num_indices = self.indices.len() as i32;
+            unsafe {
+                gl::DrawElements(gl::TRIANGLES, num_indices, gl::UNSIGNED_INT, std::ptr::null());
+            }
         }
 
         self.vertex_array.unbind();
@@ -246,6 +469,15 @@ impl Batch {
         &self.id
     }
 
+    pub fn num_vertices(&self) -> usize {
+        if self.instanced {
+            self.vertices.len() / INSTANCE_STRIDE * QUAD_CORNERS.len()
+        }
+        else {
+            self.vertices.len() / self.layout.stride() as usize
+        }
+    }
+
     pub fn is_deletable(&self) -> bool {
         self.indices.is_empty() && self.vertices.is_empty()
     }
@@ -271,7 +503,12 @@ impl Batch {
     pub fn update_vertices(&self) {
         let num_bytes = self.vertices.len();
 
-        let vertex_buffer = self.vertex_array.vertex_buffer().as_ref().unwrap();
+        let vertex_buffer = if self.instanced {
+            self.vertex_array.instance_buffer()
+        }
+        else {
+            self.vertex_array.vertex_buffer()
+        }.as_ref().unwrap();
         vertex_buffer.bind();
 
         //eprintln!("Batch {} -> z_index: {} @ 0x{:08X}:\n\tindices ({} u32): {:?}\n\tvertices ({} u8): {:?}", self.id, self.z_index, self as *const _ as usize, self.indices.len(), self.indices, self.vertices.len(), self.vertices);
@@ -302,6 +539,7 @@ pub struct VertexData<'a> {
     shader: &'a Rc<shader::Program>,
     z_index: u8,
     texture: Option<RenderID>,
+    instanced: bool,
 }
 
 impl<'a> VertexData<'a> {
@@ -313,6 +551,7 @@ impl<'a> VertexData<'a> {
             shader,
             z_index,
             texture: None,
+            instanced: false,
         }
     }
 
@@ -324,6 +563,7 @@ impl<'a> VertexData<'a> {
             shader,
             z_index,
             texture: Some(texture),
+            instanced: false,
         }
     }
 
@@ -334,7 +574,8 @@ impl<'a> VertexData<'a> {
             layout: M::layout(),
             shader: material.shader(),
             z_index,
-            texture: M::texture_id(data)
+            texture: M::texture_id(data),
+            instanced: material.instanced()
         }
     }
 
@@ -377,6 +618,11 @@ impl<'a> VertexData<'a> {
     pub fn z_index(&self) -> u8 {
         self.z_index
     }
+
+    /// Whether the vertices form a quad of an [instanced](super::material::Material::instanced) material.
+    pub fn instanced(&self) -> bool {
+        self.instanced
+    }
 }
 
 fn patch_texture_id(vertices: &mut [u8], layout: &BufferLayout, slot: u32) {
@@ -394,7 +640,8 @@ pub struct VertexLocation {
     batch: BatchID,
     offset_index: usize,
     num_vertices: u32,
-    num_indices: u32
+    num_indices: u32,
+    z_index: u8
 }
 
 impl VertexLocation {
@@ -413,4 +660,57 @@ impl VertexLocation {
     pub(super) fn num_indices(&self) -> u32 {
         self.num_indices
     }
+
+    /// z-index of the batch these vertices are stored in
+    pub fn z_index(&self) -> u8 {
+        self.z_index
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn textures_beyond_the_slot_limit_need_a_new_batch() {
+        // the texture lists of the batches, filled the same way the renderer picks batches
+        let mut batches: Vec<Vec<RenderID>> = vec![];
+        for texture in 1..=17 {
+            match batches.iter_mut().find(|textures| has_texture_slot(textures, texture)) {
+                Some(textures) => assert!(texture_slot(textures, texture).is_some()),
+                None => {
+                    let mut textures = vec![];
+                    assert_eq!(texture_slot(&mut textures, texture), Some(0));
+                    batches.push(textures);
+                }
+            }
+        }
+
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0].len(), Batch::NUM_TEXTURE_SLOTS);
+        assert_eq!(batches[1], vec![17]);
+
+        // a full batch refuses new textures without changing, but still hands out its bound slots
+        assert_eq!(texture_slot(&mut batches[0], 17), None);
+        assert_eq!(batches[0].len(), Batch::NUM_TEXTURE_SLOTS);
+        assert_eq!(texture_slot(&mut batches[0], 5), Some(4));
+    }
+
+    #[test]
+    fn quads_turn_into_unit_quad_instances() {
+        use crate::{vertex, renderer::{material::FlatTexture, texture::Sampler2D}};
+
+        let vertices = [
+            vertex!([2.0f32, 3.0f32], [0.25f32, 0.5f32], Sampler2D(7)),
+            vertex!([3.0, 3.0], [0.5, 0.5], Sampler2D(7)),
+            vertex!([3.0, 5.0], [0.5, 0.75], Sampler2D(7)),
+            vertex!([2.0, 5.0], [0.25, 0.75], Sampler2D(7))
+        ];
+        let instance = quad_instance(util::to_raw_byte_slice!(&vertices), <FlatTexture as Material>::layout());
+
+        let floats = (0..8).map(|i| f32::from_ne_bytes(instance[i * 4..i * 4 + 4].try_into().unwrap())).collect::<Vec<_>>();
+        assert_eq!(floats, [2.0, 3.0, 1.0, 2.0, 0.25, 0.5, 0.25, 0.25]);
+        assert_eq!(i32::from_ne_bytes(instance[32..].try_into().unwrap()), 7);
+        assert_eq!(INSTANCE_LAYOUT.with(|layout| layout.stride()) as usize, INSTANCE_STRIDE);
+    }
 }
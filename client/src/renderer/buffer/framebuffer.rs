@@ -5,6 +5,55 @@ use aeonetica_engine::{math::vector::Vector2, error::{*, builtin::DataError}};
 use crate::{renderer::{glerror::GLError, util::Target, shader::{self, UniformStr}, buffer::{BufferLayoutBuilder, Vertex, TexCoord, Buffer, BufferType, BufferUsage}}, vertex, to_raw_byte_slice};
 use super::{RenderID, texture::Texture, renderbuffer::RenderBuffer, vertex_array::VertexArray};
 
+/// Reason reported by `glCheckFramebufferStatus` why a framebuffer can't be rendered to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameBufferStatus {
+    Undefined,
+    IncompleteAttachment,
+    MissingAttachment,
+    IncompleteDrawBuffer,
+    IncompleteReadBuffer,
+    Unsupported,
+    IncompleteMultisample,
+    IncompleteLayerTargets,
+    Unknown(u32)
+}
+
+impl FrameBufferStatus {
+    /// Maps a status returned by `glCheckFramebufferStatus`, `None` if the framebuffer is complete.
+    pub fn from_gl(status: u32) -> Option<Self> {
+        Some(match status {
+            gl::FRAMEBUFFER_COMPLETE => return None,
+            gl::FRAMEBUFFER_UNDEFINED => Self::Undefined,
+            gl::FRAMEBUFFER_INCOMPLETE_ATTACHMENT => Self::IncompleteAttachment,
+            gl::FRAMEBUFFER_INCOMPLETE_MISSING_ATTACHMENT => Self::MissingAttachment,
+            gl::FRAMEBUFFER_INCOMPLETE_DRAW_BUFFER => Self::IncompleteDrawBuffer,
+            gl::FRAMEBUFFER_INCOMPLETE_READ_BUFFER => Self::IncompleteReadBuffer,
+            gl::FRAMEBUFFER_UNSUPPORTED => Self::Unsupported,
+            gl::FRAMEBUFFER_INCOMPLETE_MULTISAMPLE => Self::IncompleteMultisample,
+            gl::FRAMEBUFFER_INCOMPLETE_LAYER_TARGETS => Self::IncompleteLayerTargets,
+            other => Self::Unknown(other)
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct FrameBufferError(pub FrameBufferStatus);
+
+impl ErrorValue for FrameBufferError {}
+
+impl IntoError for FrameBufferError {
+    fn into_error(self) -> Box<Error> {
+        Error::new(self, Fatality::DEFAULT, true)
+    }
+}
+
+impl std::fmt::Display for FrameBufferError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "framebuffer incomplete: {:?}", self.0)
+    }
+}
+
 pub enum Attachment {
     Color(Texture),
     DepthStencil(RenderBuffer)
@@ -14,8 +63,12 @@ impl Attachment {
     fn attach(self, fb: &mut FrameBuffer) -> ErrorResult<()> {
         match self {
             Attachment::Color(texture) => unsafe {
-                // TODO: check if enough free color attachment pointers exist
                 let idx = fb.textures.len() as u32;
+                let mut max_attachments = 0;
+                gl::GetIntegerv(gl::MAX_COLOR_ATTACHMENTS, &mut max_attachments);
+                if idx >= max_attachments as u32 {
+                    return Err(Error::new(DataError(format!("framebuffer supports at most {max_attachments} color attachments")), Fatality::DEFAULT, true));
+                }
                 let attachment = gl::COLOR_ATTACHMENT0 + idx;
 
                 gl::FramebufferTexture(gl::FRAMEBUFFER, attachment, texture.id(), 0);
@@ -87,7 +140,10 @@ impl FrameBuffer {
         }
 
         for attachment in attachments {
-            attachment.attach(&mut fb)?
+            if let Err(err) = attachment.attach(&mut fb) {
+                fb.unbind();
+                return Err(err);
+            }
         }
         
         unsafe {
@@ -95,14 +151,16 @@ impl FrameBuffer {
             let tex_attachments = (gl::COLOR_ATTACHMENT0 .. gl::COLOR_ATTACHMENT0 + n_attachments as u32).collect::<Vec<_>>();
             gl::DrawBuffers(n_attachments, tex_attachments.as_ptr());
 
-            if gl::CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE {
-                let mut err = GLError::from_gl_errno().into_error();
-                err.add_info("error creating framebuffer".to_string());
-                Err(err)
-            }
-            else {
-                gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
-                Ok(fb)
+            let status = FrameBufferStatus::from_gl(gl::CheckFramebufferStatus(gl::FRAMEBUFFER));
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+
+            match status {
+                Some(status) => {
+                    let mut err = FrameBufferError(status).into_error();
+                    err.add_info("error creating framebuffer".to_string());
+                    Err(err)
+                }
+                None => Ok(fb)
             }
         }
     }
@@ -121,6 +179,29 @@ impl FrameBuffer {
         &self.textures
     }
 
+    /// The texture bound to `GL_COLOR_ATTACHMENT0 + index`, in the order the attachments were passed to [`FrameBuffer::new`].
+    pub fn attachment(&self, index: usize) -> Option<&Texture> {
+        self.textures.get(index)
+    }
+
+    pub fn color_attachment_count(&self) -> usize {
+        self.textures.len()
+    }
+
+    /// Restricts drawing to the given color attachments. The framebuffer has to be bound.
+    pub fn set_draw_buffers(&self, attachments: &[usize]) {
+        let buffers = attachments.iter()
+            .filter(|i| **i < self.textures.len())
+            .map(|i| gl::COLOR_ATTACHMENT0 + *i as u32)
+            .collect::<Vec<_>>();
+        unsafe { gl::DrawBuffers(buffers.len() as i32, buffers.as_ptr()) }
+    }
+
+    /// Re-enables drawing to all color attachments. The framebuffer has to be bound.
+    pub fn reset_draw_buffers(&self) {
+        self.set_draw_buffers(&(0..self.textures.len()).collect::<Vec<_>>())
+    }
+
     pub fn delete(&mut self) {
         if self.id != 0 {
             unsafe { 
@@ -130,8 +211,10 @@ impl FrameBuffer {
         }
     }
 
+    /// Size of the render buffer, or of the first color attachment if there is none.
     pub fn size(&self) -> Option<Vector2<u32>> {
         self.renderbuffer.as_ref().map(|rb| *rb.size())
+            .or_else(|| self.textures.first().map(|texture| *texture.size()))
     }
 
     pub fn render<const N: usize>(&self, texture_attachments: [(usize, &UniformStr); N], target: &Target, shader: &shader::Program) {
@@ -144,7 +227,11 @@ impl FrameBuffer {
         }
 
         for (i, (attachment, uniform)) in texture_attachments.iter().enumerate() {
-            self.textures[*attachment].bind(i as u32);
+            let Some(texture) = self.attachment(*attachment) else {
+                debug_assert!(false, "framebuffer has no color attachment {attachment}");
+                continue;
+            };
+            texture.bind(i as u32);
             shader.upload_uniform(uniform, &(i as i32));
         }
 
@@ -168,4 +255,16 @@ impl Drop for FrameBuffer {
     fn drop(&mut self) {
         self.delete();
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn framebuffer_status_from_gl() {
+        assert_eq!(FrameBufferStatus::from_gl(gl::FRAMEBUFFER_COMPLETE), None);
+        assert_eq!(FrameBufferStatus::from_gl(gl::FRAMEBUFFER_INCOMPLETE_MISSING_ATTACHMENT), Some(FrameBufferStatus::MissingAttachment));
+        assert_eq!(FrameBufferStatus::from_gl(0x1234), Some(FrameBufferStatus::Unknown(0x1234)));
+    }
+}
@@ -237,7 +237,8 @@ impl BufferLayout {
         buffer
     }
     
-    pub(super) fn stride(&self) -> u32 {
+    /// Size of one vertex in bytes
+    pub fn stride(&self) -> u32 {
         self.stride
     }
 
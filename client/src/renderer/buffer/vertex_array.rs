@@ -4,6 +4,7 @@ pub struct VertexArray {
     id: RenderID,
     vertex_buffer: Option<Buffer>,
     index_buffer: Option<Buffer>,
+    instance_buffer: Option<Buffer>,
 }
 
 #[allow(unused)]
@@ -15,7 +16,8 @@ impl VertexArray {
             Ok(Self {
                 id: vao,
                 vertex_buffer: None,
-                index_buffer: None
+                index_buffer: None,
+                instance_buffer: None
             })
         }
         else {
@@ -42,12 +44,34 @@ impl VertexArray {
 
         assert!(!layout.elements().is_empty(), "Vertex Buffer has no layout!");
 
+        Self::set_attributes(layout, 0);
+    }
+
+    /// Sets a buffer whose attributes advance once per instance instead of once per vertex.
+    /// They follow the attributes of the vertex buffer, which has to be set first.
+    pub fn set_instance_buffer(&mut self, buffer: Buffer) {
+        let first_attribute = self.vertex_buffer.as_ref()
+            .and_then(|buffer| buffer.layout().as_ref())
+            .map_or(0, |layout| layout.elements().len());
+        self.instance_buffer = Some(buffer);
+        let buffer = self.instance_buffer.as_ref().unwrap();
+        unsafe { gl::BindVertexArray(self.id) }
+        buffer.bind();
+
+        let layout = buffer.layout().as_ref().expect("Instance Buffer has no Layout!");
+        Self::set_attributes(layout, first_attribute);
+        for i in first_attribute..first_attribute + layout.elements().len() {
+            unsafe { gl::VertexAttribDivisor(i as u32, 1) }
+        }
+    }
+
+    fn set_attributes(layout: &BufferLayout, first_attribute: usize) {
         let stride = layout.stride() as i32;
         for (i, element) in layout.elements().iter().enumerate() {
             unsafe {
-                gl::EnableVertexAttribArray(i as u32);
+                let i = (first_attribute + i) as u32;
+                gl::EnableVertexAttribArray(i);
 
-                let i = i as u32;
                 let size = element.component_count();
                 let base_type = element.base_type();
                 let normalized = element.normalized();
@@ -88,6 +112,10 @@ impl VertexArray {
         &mut self.index_buffer
     }
 
+    pub fn instance_buffer(&self) -> &Option<Buffer> {
+        &self.instance_buffer
+    }
+
     pub fn draw(&self, num_indices: i32) {
         unsafe { gl::DrawElements(gl::TRIANGLES, num_indices, gl::UNSIGNED_INT, std::ptr::null()); }
     }
@@ -99,5 +127,6 @@ impl VertexArray {
         }
         self.index_buffer = None;
         self.vertex_buffer = None;
+        self.instance_buffer = None;
     }
 }
\ No newline at end of file
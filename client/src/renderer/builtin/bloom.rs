@@ -0,0 +1,134 @@
+use aeonetica_engine::{error::ErrorResult, math::vector::Vector2};
+
+use crate::{renderer::{buffer::framebuffer::{FrameBuffer, Attachment}, texture::{Texture, Format}, shader::{self, UniformStr}, util::Target}, uniform_str};
+
+/// Makes bright parts of a frame glow.
+/// Pixels above `threshold` are extracted, blurred with a separable gaussian blur `iterations` times
+/// and added back onto the frame, scaled by `intensity`.
+pub struct BloomPass {
+    ping_pong: [FrameBuffer; 2],
+
+    extract_shader: shader::Program,
+    blur_shader: shader::Program,
+    composite_shader: shader::Program,
+
+    threshold: f32,
+    intensity: f32,
+    iterations: u32,
+    enabled: bool
+}
+
+impl BloomPass {
+    pub const DEFAULT_THRESHOLD: f32 = 1.0;
+    pub const DEFAULT_INTENSITY: f32 = 1.0;
+    pub const DEFAULT_ITERATIONS: u32 = 5;
+
+    const FRAME_USTR: UniformStr = uniform_str!("u_Frame");
+    const THRESHOLD_USTR: UniformStr = uniform_str!("u_Threshold");
+    const HORIZONTAL_USTR: UniformStr = uniform_str!("u_Horizontal");
+    const INTENSITY_USTR: UniformStr = uniform_str!("u_Intensity");
+
+    const CLEAR_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.0];
+
+    /// `size` should match the framebuffer the pass gets applied to.
+    pub fn new(size: Vector2<u32>) -> ErrorResult<Self> {
+        Ok(Self {
+            ping_pong: Self::create_ping_pong(size)?,
+            extract_shader: shader::Program::from_source(include_str!("../../../assets/bloom-extract-shader.glsl"))?,
+            blur_shader: shader::Program::from_source(include_str!("../../../assets/bloom-blur-shader.glsl"))?,
+            composite_shader: shader::Program::from_source(include_str!("../../../assets/bloom-composite-shader.glsl"))?,
+            threshold: Self::DEFAULT_THRESHOLD,
+            intensity: Self::DEFAULT_INTENSITY,
+            iterations: Self::DEFAULT_ITERATIONS,
+            enabled: true
+        })
+    }
+
+    fn create_ping_pong(size: Vector2<u32>) -> ErrorResult<[FrameBuffer; 2]> {
+        Ok([
+            FrameBuffer::new([Attachment::Color(Texture::create(size, Format::RgbaF16))], true)?,
+            FrameBuffer::new([Attachment::Color(Texture::create(size, Format::RgbaF16))], true)?
+        ])
+    }
+
+    pub fn size(&self) -> Vector2<u32> {
+        self.ping_pong[0].size().unwrap()
+    }
+
+    /// Recreates the intermediate framebuffers, call this when the framebuffer the pass gets applied to is resized.
+    pub fn resize(&mut self, size: Vector2<u32>) -> ErrorResult<()> {
+        if self.size() != size {
+            self.ping_pong = Self::create_ping_pong(size)?;
+        }
+        Ok(())
+    }
+
+    pub fn threshold(&self) -> f32 {
+        self.threshold
+    }
+
+    pub fn set_threshold(&mut self, threshold: f32) {
+        self.threshold = threshold;
+    }
+
+    pub fn intensity(&self) -> f32 {
+        self.intensity
+    }
+
+    pub fn set_intensity(&mut self, intensity: f32) {
+        self.intensity = intensity;
+    }
+
+    pub fn iterations(&self) -> u32 {
+        self.iterations
+    }
+
+    pub fn set_iterations(&mut self, iterations: u32) {
+        self.iterations = iterations;
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Applies bloom to color attachment `attachment` of `frame`, which has to be freestanding.
+    /// Expects blending to be enabled with `BlendMode::One`, like it is during layer rendering.
+    pub fn apply(&self, frame: &FrameBuffer, attachment: usize) {
+        if !self.enabled || frame.attachment(attachment).is_none() {
+            return;
+        }
+
+        let mut previous_fb = 0;
+        unsafe { gl::GetIntegerv(gl::FRAMEBUFFER_BINDING, &mut previous_fb) };
+
+        for fb in &self.ping_pong {
+            fb.bind();
+            fb.clear(Self::CLEAR_COLOR);
+        }
+
+        self.extract_shader.bind();
+        self.extract_shader.upload_uniform(&Self::THRESHOLD_USTR, &self.threshold);
+        frame.render([(attachment, &Self::FRAME_USTR)], &Target::FrameBuffer(&self.ping_pong[0]), &self.extract_shader);
+
+        for _ in 0..self.iterations {
+            for (horizontal, (src, dst)) in [(1, (0, 1)), (0, (1, 0))] {
+                self.blur_shader.bind();
+                self.blur_shader.upload_uniform(&Self::HORIZONTAL_USTR, &horizontal);
+                self.ping_pong[src].render([(0, &Self::FRAME_USTR)], &Target::FrameBuffer(&self.ping_pong[dst]), &self.blur_shader);
+            }
+        }
+
+        self.composite_shader.bind();
+        self.composite_shader.upload_uniform(&Self::INTENSITY_USTR, &self.intensity);
+        frame.bind();
+        frame.set_draw_buffers(&[attachment]);
+        self.ping_pong[0].render([(0, &Self::FRAME_USTR)], &Target::FrameBuffer(frame), &self.composite_shader);
+        frame.reset_draw_buffers();
+
+        unsafe { gl::BindFramebuffer(gl::FRAMEBUFFER, previous_fb as u32) };
+    }
+}
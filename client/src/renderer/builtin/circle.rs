@@ -0,0 +1,166 @@
+use std::rc::Rc;
+
+use aeonetica_engine::math::vector::Vector2;
+
+use crate::renderer::{material::{FlatColor, Material}, VertexLocation, shader, Renderable, batch::VertexData};
+
+/// A filled circle, tessellated into a triangle fan around its center.
+/// With a low segment count this doubles as a regular polygon.
+pub struct Circle {
+    center: Vector2<f32>,
+    radius: f32,
+    segments: u32,
+    z_index: u8,
+
+    material: Rc<FlatColor>,
+    vertices: Option<Vec<<FlatColor as Material>::VertexTuple>>,
+    indices: Vec<u32>,
+    params: <FlatColor as Material>::Data<1>,
+
+    location: Option<VertexLocation>,
+}
+
+impl Circle {
+    /// Segment count that looks smooth at typical zoom levels.
+    pub const DEFAULT_SEGMENTS: u32 = 32;
+    const MIN_SEGMENTS: u32 = 3;
+
+    pub fn new(center: Vector2<f32>, radius: f32, segments: u32, z_index: u8, color: [f32; 4]) -> Self {
+        Self::with_material(center, radius, segments, z_index, color, FlatColor::get())
+    }
+
+    pub fn with_material(center: Vector2<f32>, radius: f32, segments: u32, z_index: u8, color: [f32; 4], material: Rc<FlatColor>) -> Self {
+        let segments = segments.max(Self::MIN_SEGMENTS);
+        Self {
+            center,
+            radius,
+            segments,
+            z_index,
+            params: color,
+            material,
+            vertices: None,
+            indices: Self::fan_indices(segments),
+            location: None
+        }
+    }
+
+    fn fan_indices(segments: u32) -> Vec<u32> {
+        (1..=segments).flat_map(|i| [0, i, i % segments + 1]).collect()
+    }
+
+    pub fn set_dirty(&mut self) {
+        self.vertices = None;
+    }
+
+    pub fn center(&self) -> &Vector2<f32> {
+        &self.center
+    }
+
+    pub fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    pub fn segments(&self) -> u32 {
+        self.segments
+    }
+
+    pub fn z_index(&self) -> u8 {
+        self.z_index
+    }
+
+    pub fn color(&self) -> &[f32; 4] {
+        &self.params
+    }
+
+    pub fn set_center(&mut self, center: Vector2<f32>) {
+        self.center = center;
+        self.set_dirty();
+    }
+
+    pub fn set_radius(&mut self, radius: f32) {
+        self.radius = radius;
+        self.set_dirty();
+    }
+
+    pub fn set_color(&mut self, color: [f32; 4]) {
+        self.params = color;
+        self.set_dirty();
+    }
+
+    pub fn set_z_index(&mut self, z_index: u8) {
+        self.z_index = z_index;
+        self.set_dirty();
+    }
+
+    pub fn shader(&self) -> &shader::Program {
+        self.material.shader()
+    }
+
+    fn recalculate_vertex_data(&mut self) {
+        let step = std::f32::consts::TAU / self.segments as f32;
+        let outline = (0..self.segments).map(|i| self.center + Vector2::new(self.radius, 0.0).rotate(step * i as f32));
+
+        self.vertices = Some(std::iter::once(self.center)
+            .chain(outline)
+            .map(|v| self.material.vertices([v.into_array()], &self.params)[0].clone())
+            .collect()
+        );
+    }
+}
+
+impl Renderable for Circle {
+    fn vertex_data(&mut self) -> VertexData<'_> {
+        if self.is_dirty() {
+            self.recalculate_vertex_data();
+        }
+
+        let vertices = self.vertices.as_mut().unwrap();
+        let vertices = unsafe {
+            std::slice::from_raw_parts_mut(vertices.as_mut_ptr() as *mut u8, std::mem::size_of_val(vertices.as_slice()))
+        };
+
+        VertexData::from_material::<FlatColor, 1>(
+            vertices,
+            self.indices.as_slice(),
+            &self.material,
+            &self.params,
+            self.z_index
+        )
+    }
+
+    fn texture_id(&self) -> Option<crate::renderer::RenderID> {
+        None
+    }
+
+    fn location(&self) -> &Option<VertexLocation> {
+        &self.location
+    }
+
+    fn set_location(&mut self, location: Option<VertexLocation>) {
+        self.location = location;
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.vertices.is_none()
+    }
+
+    fn has_location(&self) -> bool {
+        self.location.is_some()
+    }
+
+    fn bounds(&self) -> Option<(Vector2<f32>, Vector2<f32>)> {
+        let r = Vector2::new(self.radius, self.radius);
+        Some((self.center - r, self.center + r))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fan_indices_close_the_circle() {
+        assert_eq!(Circle::fan_indices(3), vec![0, 1, 2, 0, 2, 3, 0, 3, 1]);
+        assert_eq!(Circle::fan_indices(32).len(), 32 * 3);
+    }
+}
@@ -86,7 +86,9 @@ impl Line {
     }
 
     fn recalculate_vertex_data(&mut self) {
-        let n = (self.to - self.from).normalized().rotate_90();
+        let direction = self.to - self.from;
+        // a zero length line has no direction and is drawn as an empty quad
+        let n = if direction.mag_sq() > 0.0 { direction.normalized().rotate_90() } else { Vector2::default() };
         let w = Vector2::new(self.weight, self.weight).half();
 
         self.vertices = Some(self.material.vertices(
@@ -137,4 +139,12 @@ impl Renderable for Line {
     fn has_location(&self) -> bool {
         self.location.is_some()
     }
+
+    fn bounds(&self) -> Option<(Vector2<f32>, Vector2<f32>)> {
+        let w = Vector2::new(self.weight, self.weight).half();
+        Some((
+            Vector2::new(self.from.x.min(self.to.x), self.from.y.min(self.to.y)) - w,
+            Vector2::new(self.from.x.max(self.to.x), self.from.y.max(self.to.y)) + w
+        ))
+    }
 }
\ No newline at end of file
@@ -1,9 +1,15 @@
 pub mod text_area;
 pub mod quad;
 pub mod line;
+pub mod polyline;
+pub mod circle;
 pub mod particle;
+pub mod bloom;
 
 pub use text_area::*;
 pub use quad::*;
 pub use line::*;
+pub use polyline::*;
+pub use circle::*;
 pub use particle::*;
+pub use bloom::*;
@@ -2,23 +2,321 @@ use std::rc::Rc;
 
 use aeonetica_engine::math::vector::Vector2;
 
-use crate::renderer::material::Material;
+use crate::renderer::{*, material::Material};
 
-#[allow(unused)]
+/// Short-lived quads sharing one material, drawn as a single renderable.
+///
+/// Particles are kept in a pool of fixed capacity: dead ones are recycled by the next spawn and drawn as empty quads,
+/// so the vertex data never changes size and updates are done in place. Particles shrink to nothing over their lifetime.
+///
+/// ```ignore
+/// let mut sparks = ParticleEmitter::new(position, 64, 12, FlatColor::get(), [1.0, 0.8, 0.2, 1.0])
+///     .with_velocity(Vector2::new(0.0, -4.0), Vector2::new(3.0, 2.0))
+///     .with_acceleration(Vector2::new(0.0, -GRAVITY));
+/// sparks.burst(32);
+/// // every frame
+/// sparks.update(time.delta);
+/// renderer.draw(&mut sparks)?;
+/// ```
 pub struct ParticleEmitter<M: Material> {
     position: Vector2<f32>,
-    lifetime: f32,
+    z_index: u8,
     particles: Vec<Particle>,
+    /// where the next spawn looks for a dead particle
+    next_slot: usize,
+
+    lifetime: f32,
+    size: Vector2<f32>,
+    velocity: Vector2<f32>,
+    velocity_spread: Vector2<f32>,
+    acceleration: Vector2<f32>,
+    spawn_rate: f32,
+    spawn_accumulator: f32,
+    rng: u32,
 
     material: Rc<M>,
-    params: M::Data<4>
+    params: M::Data<4>,
+    /// reused between frames, rebuilt when dirty
+    vertices: Vec<M::VertexTuple>,
+    dirty: bool,
+    indices: Vec<u32>,
+
+    location: Option<VertexLocation>
 }
 
-#[allow(unused)]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
 pub struct Particle {
     position: Vector2<f32>,
     velocity: Vector2<f32>,
-    acceleration: Vector2<f32>,
+    /// remaining lifetime in seconds, dead once it reaches 0
     lifetime: f32
 }
 
+impl Particle {
+    pub fn position(&self) -> Vector2<f32> {
+        self.position
+    }
+
+    pub fn velocity(&self) -> Vector2<f32> {
+        self.velocity
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.lifetime > 0.0
+    }
+}
+
+impl<M: Material> ParticleEmitter<M> {
+    /// Maximum number of particles a single emitter can hold
+    pub const MAX_CAPACITY: usize = Batch::MAX_BATCH_INDEX_COUNT as usize / 6;
+
+    /// Creates an emitter that doesn't emit anything until [`ParticleEmitter::burst`] is called or a spawn rate is set.
+    pub fn new(position: Vector2<f32>, capacity: usize, z_index: u8, material: Rc<M>, params: M::Data<4>) -> Self {
+        let capacity = capacity.clamp(1, Self::MAX_CAPACITY);
+        let indices = (0..capacity as u32 * 4).step_by(4)
+            .flat_map(|i| [i, i + 1, i + 2, i + 2, i + 3, i])
+            .collect();
+        Self {
+            position,
+            z_index,
+            particles: vec![Particle::default(); capacity],
+            next_slot: 0,
+            lifetime: 1.0,
+            size: Vector2::new(0.25, 0.25),
+            velocity: Vector2::default(),
+            velocity_spread: Vector2::new(1.0, 1.0),
+            acceleration: Vector2::default(),
+            spawn_rate: 0.0,
+            spawn_accumulator: 0.0,
+            rng: 0x9e37_79b9,
+            material,
+            params,
+            vertices: Vec::with_capacity(capacity * 4),
+            dirty: true,
+            indices,
+            location: None
+        }
+    }
+
+    /// How long each particle lives, in seconds
+    pub fn with_lifetime(mut self, lifetime: f32) -> Self {
+        self.lifetime = lifetime.max(f32::EPSILON);
+        self
+    }
+
+    /// Size of a freshly spawned particle
+    pub fn with_size(mut self, size: Vector2<f32>) -> Self {
+        self.size = size;
+        self
+    }
+
+    /// Particles start with `velocity`, plus a random offset of up to `spread` in either direction per axis.
+    pub fn with_velocity(mut self, velocity: Vector2<f32>, spread: Vector2<f32>) -> Self {
+        self.velocity = velocity;
+        self.velocity_spread = spread;
+        self
+    }
+
+    /// Constant acceleration of all particles, e.g. `Vector2::new(0.0, -GRAVITY)` for the world's gravity.
+    pub fn with_acceleration(mut self, acceleration: Vector2<f32>) -> Self {
+        self.acceleration = acceleration;
+        self
+    }
+
+    /// Particles spawned per second by [`ParticleEmitter::update`], for continuous effects like waterfalls.
+    pub fn with_spawn_rate(mut self, spawn_rate: f32) -> Self {
+        self.set_spawn_rate(spawn_rate);
+        self
+    }
+
+    pub fn set_spawn_rate(&mut self, spawn_rate: f32) {
+        self.spawn_rate = spawn_rate.max(0.0);
+    }
+
+    pub fn position(&self) -> Vector2<f32> {
+        self.position
+    }
+
+    /// Moves where new particles spawn, e.g. to follow an entity. Particles already spawned are not moved.
+    pub fn set_position(&mut self, position: Vector2<f32>) {
+        self.position = position;
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.particles.len()
+    }
+
+    pub fn particles(&self) -> impl Iterator<Item = &Particle> {
+        self.particles.iter().filter(|p| p.is_alive())
+    }
+
+    pub fn alive(&self) -> usize {
+        self.particles().count()
+    }
+
+    /// Spawns `count` particles at once. If the pool is full, the particles closest to dying are replaced.
+    pub fn burst(&mut self, count: usize) {
+        for _ in 0..count.min(self.capacity()) {
+            self.spawn();
+        }
+    }
+
+    /// Moves all particles and spawns new ones according to the spawn rate.
+    pub fn update(&mut self, delta: f32) {
+        for particle in self.particles.iter_mut().filter(|p| p.is_alive()) {
+            particle.velocity += self.acceleration * delta;
+            particle.position += particle.velocity * delta;
+            particle.lifetime -= delta;
+        }
+
+        self.spawn_accumulator += self.spawn_rate * delta;
+        let spawns = self.spawn_accumulator.floor();
+        self.spawn_accumulator -= spawns;
+        for _ in 0..(spawns as usize).min(self.capacity()) {
+            self.spawn();
+        }
+        self.dirty = true;
+    }
+
+    fn spawn(&mut self) {
+        let capacity = self.capacity();
+        let slot = (0..capacity)
+            .map(|i| (self.next_slot + i) % capacity)
+            .find(|i| !self.particles[*i].is_alive())
+            .unwrap_or_else(|| (0..capacity)
+                .min_by(|a, b| self.particles[*a].lifetime.total_cmp(&self.particles[*b].lifetime))
+                .unwrap()
+            );
+        self.next_slot = (slot + 1) % capacity;
+
+        let spread = Vector2::new(self.random() * self.velocity_spread.x, self.random() * self.velocity_spread.y);
+        self.particles[slot] = Particle {
+            position: self.position,
+            velocity: self.velocity + spread,
+            lifetime: self.lifetime
+        };
+    }
+
+    /// xorshift, uniformly distributed in `[-1, 1]`
+    fn random(&mut self) -> f32 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        self.rng as f32 / u32::MAX as f32 * 2.0 - 1.0
+    }
+
+    fn recalculate_vertex_data(&mut self) {
+        self.vertices.clear();
+        for particle in &self.particles {
+            let size = self.size * (particle.lifetime / self.lifetime).clamp(0.0, 1.0);
+            let [x, y]: [f32; 2] = (particle.position - size.half()).into();
+            let [w, h]: [f32; 2] = size.into();
+            self.vertices.extend(self.material.vertices([
+                [x,     y    ],
+                [x + w, y    ],
+                [x + w, y + h],
+                [x,     y + h]
+            ], &self.params));
+        }
+        self.dirty = false;
+    }
+}
+
+impl<M: Material> Renderable for ParticleEmitter<M> {
+    fn vertex_data(&mut self) -> VertexData<'_> {
+        if self.is_dirty() {
+            self.recalculate_vertex_data();
+        }
+
+        let vertices = unsafe {
+            std::slice::from_raw_parts_mut(self.vertices.as_mut_ptr() as *mut u8, std::mem::size_of_val(self.vertices.as_slice()))
+        };
+        VertexData::from_material(
+            vertices,
+            self.indices.as_slice(),
+            &self.material,
+            &self.params,
+            self.z_index
+        )
+    }
+
+    fn texture_id(&self) -> Option<RenderID> {
+        M::texture_id(&self.params)
+    }
+
+    fn location(&self) -> &Option<VertexLocation> {
+        &self.location
+    }
+
+    fn set_location(&mut self, location: Option<VertexLocation>) {
+        self.location = location;
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    fn has_location(&self) -> bool {
+        self.location.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::material::FlatColor;
+
+    #[test]
+    fn particles_move_die_and_are_recycled() {
+        let mut particles = vec![Particle::default(); 4];
+        particles[1] = Particle { position: Vector2::default(), velocity: Vector2::new(0.0, -2.0), lifetime: 1.0 };
+        let mut emitter = ParticleEmitter {
+            particles,
+            ..test_emitter()
+        };
+        assert_eq!(emitter.alive(), 1);
+
+        emitter.burst(2);
+        assert_eq!(emitter.alive(), 3);
+        assert!(emitter.particles().all(|p| p.velocity().y == -2.0 && p.velocity().x.abs() <= 1.0));
+
+        emitter.update(0.5);
+        assert!(emitter.particles().all(|p| p.position().y == -0.5 && p.velocity().y == -1.0), "gravity should slow particles down");
+
+        // the pool is full, the oldest particle gets replaced
+        emitter.burst(2);
+        assert_eq!(emitter.alive(), 4);
+        assert_eq!(emitter.capacity(), 4);
+
+        emitter.update(1.1);
+        assert_eq!(emitter.alive(), 0);
+
+        emitter.set_spawn_rate(10.0);
+        emitter.update(0.25);
+        assert_eq!(emitter.alive(), 2);
+    }
+
+    /// An emitter that is never drawn, so its material has no shader loaded
+    fn test_emitter() -> ParticleEmitter<FlatColor> {
+        ParticleEmitter {
+            position: Vector2::default(),
+            z_index: 0,
+            particles: vec![],
+            next_slot: 0,
+            lifetime: 1.0,
+            size: Vector2::new(1.0, 1.0),
+            velocity: Vector2::new(0.0, -2.0),
+            velocity_spread: Vector2::new(1.0, 0.0),
+            acceleration: Vector2::new(0.0, 2.0),
+            spawn_rate: 0.0,
+            spawn_accumulator: 0.0,
+            rng: 1,
+            material: Rc::new(FlatColor::with_shader(Rc::new(shader::Program::null()))),
+            params: [1.0; 4],
+            vertices: vec![],
+            dirty: true,
+            indices: vec![],
+            location: None
+        }
+    }
+}
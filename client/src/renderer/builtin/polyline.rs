@@ -0,0 +1,378 @@
+use std::rc::Rc;
+
+use aeonetica_engine::math::vector::Vector2;
+
+use crate::renderer::{material::{FeatheredColor, Material}, VertexLocation, Renderable, batch::VertexData, RenderID};
+
+/// How the ends of a [`Polyline`] are drawn.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LineCap {
+    /// the stroke ends exactly at the end points
+    #[default]
+    Butt,
+    /// the stroke extends half its weight past the end points
+    Square,
+    /// a half circle around the end points
+    Round
+}
+
+/// How consecutive segments of a [`Polyline`] are connected.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineJoin {
+    /// the outer edges are cut off straight
+    Bevel,
+    /// the outer edges are extended until they meet, unless the tip would be longer than `limit` times the weight,
+    /// in which case the joint is beveled
+    Miter { limit: f32 }
+}
+
+impl Default for LineJoin {
+    fn default() -> Self {
+        Self::Miter { limit: 4.0 }
+    }
+}
+
+/// A continuous stroke through a list of points, with configurable caps, joins, and anti-aliased edges.
+///
+/// Consecutive points closer than [`Polyline::MIN_SEGMENT_LENGTH`] are merged. A single point
+/// (or only coincident points) is drawn as a dot for round and square caps and not at all for butt caps.
+///
+/// ```ignore
+/// let mut outline = Polyline::new(segments, 0.1, 10, [1.0, 1.0, 1.0, 1.0])
+///     .with_cap(LineCap::Round);
+/// renderer.draw(&mut outline)?;
+/// ```
+pub struct Polyline {
+    points: Vec<Vector2<f32>>,
+    weight: f32,
+    z_index: u8,
+    cap: LineCap,
+    join: LineJoin,
+    feather: bool,
+
+    material: Rc<FeatheredColor>,
+    params: <FeatheredColor as Material>::Data<1>,
+    vertices: Option<Vec<<FeatheredColor as Material>::VertexTuple>>,
+    indices: Vec<u32>,
+
+    location: Option<VertexLocation>
+}
+
+impl Polyline {
+    /// Segments shorter than this are treated as coincident points
+    pub const MIN_SEGMENT_LENGTH: f32 = 1e-5;
+    /// Vertices of a half circle of a round cap
+    const ROUND_CAP_SEGMENTS: u32 = 8;
+
+    pub fn new(points: Vec<Vector2<f32>>, weight: f32, z_index: u8, color: [f32; 4]) -> Self {
+        Self::with_material(points, weight, z_index, color, FeatheredColor::get())
+    }
+
+    pub fn with_material(points: Vec<Vector2<f32>>, weight: f32, z_index: u8, color: [f32; 4], material: Rc<FeatheredColor>) -> Self {
+        Self {
+            points,
+            weight,
+            z_index,
+            cap: LineCap::default(),
+            join: LineJoin::default(),
+            feather: true,
+            material,
+            params: (color, [0.0]),
+            vertices: None,
+            indices: vec![],
+            location: None
+        }
+    }
+
+    pub fn with_cap(mut self, cap: LineCap) -> Self {
+        self.set_cap(cap);
+        self
+    }
+
+    pub fn with_join(mut self, join: LineJoin) -> Self {
+        self.set_join(join);
+        self
+    }
+
+    /// Anti-aliases the edges by fading them out over about a pixel. Enabled by default.
+    pub fn with_feather(mut self, feather: bool) -> Self {
+        self.set_feather(feather);
+        self
+    }
+
+    pub fn set_dirty(&mut self) {
+        self.vertices = None;
+    }
+
+    pub fn points(&self) -> &[Vector2<f32>] {
+        &self.points
+    }
+
+    pub fn weight(&self) -> f32 {
+        self.weight
+    }
+
+    pub fn z_index(&self) -> u8 {
+        self.z_index
+    }
+
+    pub fn color(&self) -> &[f32; 4] {
+        &self.params.0
+    }
+
+    pub fn cap(&self) -> LineCap {
+        self.cap
+    }
+
+    pub fn join(&self) -> LineJoin {
+        self.join
+    }
+
+    pub fn feather(&self) -> bool {
+        self.feather
+    }
+
+    pub fn set_points(&mut self, points: Vec<Vector2<f32>>) {
+        self.points = points;
+        self.set_dirty();
+    }
+
+    /// Moves a single point, e.g. one segment of a worm
+    pub fn set_point(&mut self, index: usize, point: Vector2<f32>) {
+        self.points[index] = point;
+        self.set_dirty();
+    }
+
+    pub fn set_weight(&mut self, weight: f32) {
+        self.weight = weight;
+        self.set_dirty();
+    }
+
+    pub fn set_color(&mut self, color: [f32; 4]) {
+        self.params.0 = color;
+        self.set_dirty();
+    }
+
+    pub fn set_cap(&mut self, cap: LineCap) {
+        self.cap = cap;
+        self.set_dirty();
+    }
+
+    pub fn set_join(&mut self, join: LineJoin) {
+        self.join = join;
+        self.set_dirty();
+    }
+
+    pub fn set_feather(&mut self, feather: bool) {
+        self.feather = feather;
+        self.set_dirty();
+    }
+
+    fn recalculate_vertex_data(&mut self) {
+        let (vertices, indices) = stroke(&self.points, self.weight, self.cap, self.join);
+        self.vertices = Some(vertices.into_iter()
+            .flat_map(|(position, edge)| self.material.vertices([position.into_array()], &(self.params.0, [if self.feather { edge } else { 0.0 }])))
+            .collect()
+        );
+        self.indices = indices;
+    }
+}
+
+/// Triangulates the stroke, returning `(position, signed edge distance)` vertices and indices.
+fn stroke(points: &[Vector2<f32>], weight: f32, cap: LineCap, join: LineJoin) -> (Vec<(Vector2<f32>, f32)>, Vec<u32>) {
+    let mut points = points.to_vec();
+    points.dedup_by(|b, a| (*b - *a).mag_sq() < Polyline::MIN_SEGMENT_LENGTH * Polyline::MIN_SEGMENT_LENGTH);
+
+    let mut vertices = vec![];
+    let mut indices = vec![];
+    let hw = weight / 2.0;
+    if points.is_empty() || hw <= 0.0 || (points.len() == 1 && cap == LineCap::Butt) {
+        return (vertices, indices)
+    }
+
+    // a lone point is drawn as a zero length segment in an arbitrary direction, which only shows its caps
+    let directions = if points.len() == 1 {
+        points.push(points[0]);
+        vec![Vector2::new(1.0, 0.0)]
+    }
+    else {
+        points.windows(2).map(|p| (p[1] - p[0]).normalized()).collect::<Vec<_>>()
+    };
+
+    let mut quad = |vertices: &mut Vec<(Vector2<f32>, f32)>, [a, b, c, d]: [Vector2<f32>; 4]| {
+        let i = vertices.len() as u32;
+        vertices.extend([(a, 1.0), (b, -1.0), (c, -1.0), (d, 1.0)]);
+        indices.extend([i, i + 1, i + 2, i + 2, i + 3, i]);
+    };
+
+    // the (+normal, -normal) edge vertices of each segment's start and end
+    let mut ends = directions.iter().enumerate().map(|(i, dir)| {
+        let n = dir.rotate_90() * hw;
+        let mut start = (points[i] + n, points[i] - n);
+        let mut end = (points[i + 1] + n, points[i + 1] - n);
+        if cap == LineCap::Square {
+            if i == 0 {
+                start = (start.0 - *dir * hw, start.1 - *dir * hw);
+            }
+            if i == directions.len() - 1 {
+                end = (end.0 + *dir * hw, end.1 + *dir * hw);
+            }
+        }
+        (start, end)
+    }).collect::<Vec<_>>();
+
+    let mut joints = vec![];
+    for i in 1..directions.len() {
+        let (prev, next) = (directions[i - 1], directions[i]);
+        let (n_prev, n_next) = (prev.rotate_90(), next.rotate_90());
+        let miter = n_prev + n_next;
+        let limit = match join {
+            LineJoin::Miter { limit } => limit,
+            LineJoin::Bevel => 0.0
+        };
+        // |miter| is 2 * cos(angle / 2), so the tip is weight / |miter| away from the point
+        if miter.mag_sq() > f32::EPSILON && 1.0 / miter.mag() <= limit {
+            let m = miter.normalized() * (hw / miter.normalized().dot(&n_next));
+            let p = points[i];
+            ends[i - 1].1 = (p + m, p - m);
+            ends[i].0 = (p + m, p - m);
+        }
+        else {
+            // the side the path turns away from needs filling
+            let side = if n_prev.dot(&next) > 0.0 { -1.0 } else { 1.0 };
+            joints.push((points[i], n_prev * hw * side, n_next * hw * side));
+        }
+    }
+
+    for (start, end) in &ends {
+        quad(&mut vertices, [start.0, start.1, end.1, end.0]);
+    }
+
+    let mut fan = |vertices: &mut Vec<(Vector2<f32>, f32)>, center: Vector2<f32>, rim: &mut dyn Iterator<Item = Vector2<f32>>| {
+        let c = vertices.len() as u32;
+        vertices.push((center, 0.0));
+        vertices.extend(rim.map(|offset| (center + offset, 1.0)));
+        indices.extend((c + 1..vertices.len() as u32 - 1).flat_map(|i| [c, i, i + 1]));
+    };
+
+    for (p, from, to) in joints {
+        fan(&mut vertices, p, &mut [from, to].into_iter());
+    }
+
+    if cap == LineCap::Round {
+        let half_circle = |center_dir: Vector2<f32>| (0..=Polyline::ROUND_CAP_SEGMENTS).map(move |i| {
+            let angle = std::f32::consts::PI * i as f32 / Polyline::ROUND_CAP_SEGMENTS as f32;
+            let n = center_dir.rotate_90();
+            (n * angle.cos() + center_dir * angle.sin()) * hw
+        });
+        let (first, last) = (directions[0], directions[directions.len() - 1]);
+        fan(&mut vertices, points[0], &mut half_circle(-first));
+        fan(&mut vertices, points[points.len() - 1], &mut half_circle(last));
+    }
+
+    (vertices, indices)
+}
+
+impl Renderable for Polyline {
+    fn vertex_data(&mut self) -> VertexData<'_> {
+        if self.is_dirty() {
+            self.recalculate_vertex_data();
+        }
+
+        let vertices = self.vertices.as_mut().unwrap();
+        let vertices = unsafe {
+            std::slice::from_raw_parts_mut(vertices.as_mut_ptr() as *mut u8, std::mem::size_of_val(vertices.as_slice()))
+        };
+        VertexData::from_material::<FeatheredColor, 1>(
+            vertices,
+            self.indices.as_slice(),
+            &self.material,
+            &self.params,
+            self.z_index
+        )
+    }
+
+    fn texture_id(&self) -> Option<RenderID> {
+        None
+    }
+
+    fn location(&self) -> &Option<VertexLocation> {
+        &self.location
+    }
+
+    fn set_location(&mut self, location: Option<VertexLocation>) {
+        self.location = location;
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.vertices.is_none()
+    }
+
+    fn has_location(&self) -> bool {
+        self.location.is_some()
+    }
+
+    fn bounds(&self) -> Option<(Vector2<f32>, Vector2<f32>)> {
+        let first = *self.points.first()?;
+        // miter tips can reach further out than half the weight
+        let reach = match self.join {
+            LineJoin::Miter { limit } => limit.max(1.0),
+            LineJoin::Bevel => 1.0
+        } * self.weight;
+        let (min, max) = self.points.iter().fold((first, first), |(min, max), p|
+            (Vector2::new(min.x.min(p.x), min.y.min(p.y)), Vector2::new(max.x.max(p.x), max.y.max(p.y)))
+        );
+        let r = Vector2::new(reach, reach);
+        Some((min - r, max + r))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_finite(vertices: &[(Vector2<f32>, f32)], indices: &[u32]) {
+        assert!(vertices.iter().all(|(p, e)| p.x.is_finite() && p.y.is_finite() && e.is_finite()), "{vertices:?}");
+        assert!(indices.iter().all(|i| (*i as usize) < vertices.len()));
+        assert_eq!(indices.len() % 3, 0);
+    }
+
+    #[test]
+    fn strokes_handle_caps_joins_and_degenerate_points() {
+        let points = [Vector2::new(0.0, 0.0), Vector2::new(2.0, 0.0), Vector2::new(2.0, 2.0)];
+        let (vertices, indices) = stroke(&points, 1.0, LineCap::Butt, LineJoin::default());
+        assert_finite(&vertices, &indices);
+        // two quads sharing the miter tip at (2.5, -0.5), no joint triangles
+        assert_eq!(indices.len(), 12);
+        assert!(vertices.iter().any(|(p, _)| (*p - Vector2::new(2.5, -0.5)).mag() < 1e-5), "{vertices:?}");
+
+        let (bevel, bevel_indices) = stroke(&points, 1.0, LineCap::Butt, LineJoin::Bevel);
+        assert_finite(&bevel, &bevel_indices);
+        assert_eq!(bevel_indices.len(), 15);
+        // the fill triangle is on the outside of the turn
+        assert!(bevel.iter().any(|(p, _)| (*p - Vector2::new(2.5, 0.0)).mag() < 1e-5), "{bevel:?}");
+
+        let (square, _) = stroke(&points, 1.0, LineCap::Square, LineJoin::Bevel);
+        assert!(square.iter().any(|(p, _)| p.x == -0.5));
+        assert!(square.iter().any(|(p, _)| p.y == 2.5));
+
+        let (round, round_indices) = stroke(&points, 1.0, LineCap::Round, LineJoin::Bevel);
+        assert_finite(&round, &round_indices);
+        assert!(round.iter().all(|(p, _)| p.x >= -0.5 - 1e-5 && p.y <= 2.5 + 1e-5));
+
+        // coincident points and a path that turns back on itself
+        let degenerate = [Vector2::new(1.0, 1.0), Vector2::new(1.0, 1.0), Vector2::new(3.0, 1.0), Vector2::new(3.0, 1.0), Vector2::new(1.0, 1.0)];
+        for cap in [LineCap::Butt, LineCap::Square, LineCap::Round] {
+            let (vertices, indices) = stroke(&degenerate, 0.5, cap, LineJoin::default());
+            assert_finite(&vertices, &indices);
+            assert!(!indices.is_empty());
+        }
+
+        let dot = [Vector2::new(1.0, 1.0); 3];
+        assert_eq!(stroke(&dot, 1.0, LineCap::Butt, LineJoin::default()).1.len(), 0);
+        let (vertices, indices) = stroke(&dot, 1.0, LineCap::Round, LineJoin::default());
+        assert_finite(&vertices, &indices);
+        assert!(vertices.iter().all(|(p, _)| (*p - Vector2::new(1.0, 1.0)).mag() <= 0.5 + 1e-5));
+        assert!(stroke(&[], 1.0, LineCap::Round, LineJoin::default()).0.is_empty());
+    }
+}
@@ -91,7 +91,12 @@ impl Quad<FlatTexture> {
 }
 
 impl<M: Material> Quad<M> {
-    pub fn new(position: Vector2<f32>, size: Vector2<f32>, z_index: u8, material: Rc<M>, params: M::Data<4>) -> Self {
+    /// A quad drawn with any [`Material`], including ones defined by mods.
+    /// `data` is the material's [`Material::Data`] for the four corners, in the order `position`,
+    /// `position + (size.x, 0)`, `position + size` and `position + (0, size.y)`.
+    ///
+    /// A mod only needs to implement [`Material`] for its own type to build quads of it, no further trait is required.
+    pub fn with_material(position: Vector2<f32>, size: Vector2<f32>, z_index: u8, material: Rc<M>, data: M::Data<4>) -> Self {
         Self {
             position,
             size,
@@ -99,11 +104,16 @@ impl<M: Material> Quad<M> {
             z_index,
             material,
             vertices: None,
-            params,
+            params: data,
             location: None
         }
     }
 
+    /// Same as [`Quad::with_material`].
+    pub fn new(position: Vector2<f32>, size: Vector2<f32>, z_index: u8, material: Rc<M>, params: M::Data<4>) -> Self {
+        Self::with_material(position, size, z_index, material, params)
+    }
+
     pub fn params(&self) -> &M::Data<4> {
         &self.params
     }
@@ -138,6 +148,12 @@ impl<M: Material> Quad<M> {
         self.set_dirty();
     }
 
+    /// Changing the z-index moves the quad to a different batch on the next `Renderer::draw` or `Renderer::modify`.
+    pub fn set_z_index(&mut self, z_index: u8) {
+        self.z_index = z_index;
+        self.set_dirty();
+    }
+
     pub fn set_rotation(&mut self, rotation: f32) {
         self.rotation = rotation;
         self.set_dirty();
@@ -224,4 +240,16 @@ impl<M: Material> Renderable for Quad<M> {
     fn has_location(&self) -> bool {
         self.location.is_some()
     }
+
+    fn bounds(&self) -> Option<(Vector2<f32>, Vector2<f32>)> {
+        if self.rotation % f32::consts::TAU == 0.0 {
+            return Some((self.position, self.position + self.size));
+        }
+        let edges = self.rotate_edges();
+        let (xs, ys) = (edges.map(|e| e.0), edges.map(|e| e.1));
+        Some((
+            Vector2::new(xs.into_iter().fold(f32::INFINITY, f32::min), ys.into_iter().fold(f32::INFINITY, f32::min)),
+            Vector2::new(xs.into_iter().fold(f32::NEG_INFINITY, f32::max), ys.into_iter().fold(f32::NEG_INFINITY, f32::max))
+        ))
+    }
 }
@@ -1,6 +1,6 @@
 use std::array;
 
-use aeonetica_engine::{math::vector::Vector2, util::generic_assert::{Assert, IsTrue}};
+use aeonetica_engine::{log, math::vector::Vector2, util::generic_assert::{Assert, IsTrue}};
 
 use crate::renderer::{*, material::{Material, FlatTexture}};
 
@@ -15,6 +15,7 @@ pub struct TextArea<const N: usize, const L: usize>
     font: Rc<BitmapFont>,
     font_size: f32,
     spacing: f32,
+    wrap_width: Option<f32>,
     
     material: Rc<FlatTexture>,
     vertices: Option<[<FlatTexture as Material>::VertexTuple; N]>,
@@ -87,12 +88,19 @@ impl<const N: usize, const L: usize> TextArea<N, L>
                 material,
                 params,
                 spacing,
+                wrap_width: None,
                 location: None,
                 vertices: None,
                 indices
         }
     }
 
+    /// Wraps lines at word boundaries once they get wider than `wrap_width`, see [`BitmapFont::layout`].
+    pub fn with_wrap_width(mut self, wrap_width: f32) -> Self {
+        self.wrap_width = Some(wrap_width);
+        self
+    }
+
     fn gen_indices() -> Vec<u32> {
         let mut indices = Vec::with_capacity(N * 6);
         for i in 0 .. L {
@@ -145,6 +153,15 @@ impl<const N: usize, const L: usize> TextArea<N, L>
         self.set_dirty();
     }
 
+    pub fn wrap_width(&self) -> Option<f32> {
+        self.wrap_width
+    }
+
+    pub fn set_wrap_width(&mut self, wrap_width: Option<f32>) {
+        self.wrap_width = wrap_width;
+        self.set_dirty();
+    }
+
     fn set_dirty(&mut self) {
         self.vertices = None;
     }
@@ -152,49 +169,252 @@ impl<const N: usize, const L: usize> TextArea<N, L>
     pub fn recalculate_vertex_data(&mut self) {
         let size = self.font_size / self.font.char_size().y;
         let half_size = (self.font.char_size() * size).half();
+        let glyphs = self.font.layout(&self.string(), self.font_size, self.spacing, self.wrap_width);
+
+        let mut vertices = Vec::with_capacity(N);
+        for i in 0..L {
+            // whitespace has no glyph, unused glyphs collapse into a point
+            let (position, half_size, tex_coords) = match glyphs.get(i) {
+                Some(glyph) => {
+                    let char_sprite = self.font.sprite_sheet().get(glyph.index).unwrap_or_else(|| panic!("font has no sprite for character '{}'", glyph.c));
+                    (self.position + glyph.position, half_size, [
+                        [char_sprite.left(),  char_sprite.top()   ],
+                        [char_sprite.right(), char_sprite.top()   ],
+                        [char_sprite.right(), char_sprite.bottom()],
+                        [char_sprite.left(),  char_sprite.bottom()]
+                    ])
+                }
+                None => (self.position, Vector2::default(), [[0.0; 2]; 4])
+            };
 
-        let mut x_offset = self.position.x();
-        
-        let mut next_char = |i, c| {
-            let position = Vector2::new(x_offset, self.position.y());
-
-            let char_idx = self.font.char_index(c);
-            if char_idx.is_none() {
-                panic!(); // todo: error handling
-            }
-            let char_idx = *char_idx.unwrap();
-
-            let width = self.font.index_width(char_idx) as f32;
-            x_offset += width * size + self.spacing;
-
-            let char_sprite = self.font.sprite_sheet().get(char_idx);
-            if char_sprite.is_none() {
-                panic!(); // todo: error
-            }
-            let char_sprite = char_sprite.unwrap();
             let i = i * 4;
-
-            self.params.0[i]     = [char_sprite.left(),  char_sprite.top()   ];
-            self.params.0[i + 1] = [char_sprite.right(), char_sprite.top()   ];
-            self.params.0[i + 2] = [char_sprite.right(), char_sprite.bottom()];
-            self.params.0[i + 3] = [char_sprite.left(),  char_sprite.bottom()];
-
-            return self.material.vertices([
+            self.params.0[i..i + 4].copy_from_slice(&tex_coords);
+            vertices.extend(self.material.vertices([
                 [position.x() - half_size.x(), position.y() - half_size.y()],
                 [position.x() + half_size.x(), position.y() - half_size.y()],
                 [position.x() + half_size.x(), position.y() + half_size.y()],
                 [position.x() - half_size.x(), position.y() + half_size.y()]
-            ], &self.material.data_slice(&self.params, i));
+            ], &self.material.data_slice::<N, 4>(&self.params, i)));
+        }
+
+        self.vertices = Some(vertices.try_into().unwrap_or_else(|_| unreachable!("text area has exactly N vertices")));
+    }
+}
+
+/// A text area whose capacity is decided at runtime.
+/// Unlike [`TextArea`], it can display strings of any length (up to what fits into a single batch),
+/// at the cost of heap allocating its vertices.
+pub struct DynTextArea {
+    position: Vector2<f32>,
+    z_index: u8,
+
+    content: Vec<char>,
+    capacity: usize,
+    font: Rc<BitmapFont>,
+    font_size: f32,
+    spacing: f32,
+    wrap_width: Option<f32>,
+
+    material: Rc<FlatTexture>,
+    texture: RenderID,
+    vertices: Option<Vec<<FlatTexture as Material>::VertexTuple>>,
+    indices: Vec<u32>,
+
+    location: Option<VertexLocation>
+}
+
+impl Renderable for DynTextArea {
+    fn has_location(&self) -> bool {
+        self.location.is_some()
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.vertices.is_none()
+    }
+
+    fn location(&self) -> &Option<VertexLocation> {
+        &self.location
+    }
+
+    fn set_location(&mut self, location: Option<VertexLocation>) {
+        self.location = location;
+    }
+
+    fn texture_id(&self) -> Option<RenderID> {
+        Some(self.texture)
+    }
+
+    fn vertex_data(&mut self) -> VertexData {
+        if self.is_dirty() {
+            self.recalculate_vertex_data();
+        }
+
+        let vertices = self.vertices.as_mut().unwrap();
+        let vertices = unsafe {
+            std::slice::from_raw_parts_mut(vertices.as_mut_ptr() as *mut u8, std::mem::size_of_val(vertices.as_slice()))
         };
+        VertexData::new_textured(
+            vertices,
+            self.indices.as_slice(),
+            FlatTexture::layout(),
+            self.material.shader(),
+            self.z_index,
+            self.texture
+        )
+    }
+}
+
+impl DynTextArea {
+    /// Maximum number of characters a single text area can hold
+    pub const MAX_CAPACITY: usize = Batch::MAX_BATCH_INDEX_COUNT as usize / 6;
+
+    pub fn with_string<S: Into<String>>(position: Vector2<f32>, z_index: u8, font_size: f32, spacing: f32, font: Rc<BitmapFont>, material: Rc<FlatTexture>, string: S) -> Self {
+        let content = Self::clamp_content(string.into());
+        let capacity = content.len().max(1);
+
+        Self {
+            position,
+            z_index,
+            content,
+            capacity,
+            texture: font.sprite_sheet().texture().id(),
+            font,
+            font_size,
+            spacing,
+            wrap_width: None,
+            material,
+            vertices: None,
+            indices: Self::gen_indices(capacity),
+            location: None
+        }
+    }
+
+    /// Wraps lines at word boundaries once they get wider than `wrap_width`, see [`BitmapFont::layout`].
+    pub fn with_wrap_width(mut self, wrap_width: f32) -> Self {
+        self.wrap_width = Some(wrap_width);
+        self
+    }
+
+    fn clamp_content(string: String) -> Vec<char> {
+        let mut content: Vec<char> = string.chars().collect();
+        if content.len() > Self::MAX_CAPACITY {
+            log!(WARN, "text area content exceeds max capacity of {} characters, truncating", Self::MAX_CAPACITY);
+            content.truncate(Self::MAX_CAPACITY);
+        }
+        content
+    }
+
+    fn gen_indices(capacity: usize) -> Vec<u32> {
+        let mut indices = Vec::with_capacity(capacity * 6);
+        for i in 0 .. capacity {
+            let i = i as u32 * 4;
+            indices.extend_from_slice(&[i, i + 1, i + 2, i + 2, i + 3, i])
+        }
+        indices
+    }
+
+    pub fn len(&self) -> usize {
+        self.content.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.content.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn string(&self) -> String {
+        self.content.iter().collect::<String>()
+    }
+
+    /// Sets the content of the text area.
+    /// If the string does not fit into the current capacity, the text area is removed from the
+    /// renderer and has to be re-added, which [`Renderer::draw`] does automatically.
+    pub fn set_string<S: Into<String>>(&mut self, renderer: &mut Renderer, string: S) {
+        self.content = Self::clamp_content(string.into());
+        if self.content.len() > self.capacity {
+            renderer.remove(self);
+            self.capacity = (self.capacity * 2).max(self.content.len()).min(Self::MAX_CAPACITY);
+            self.indices = Self::gen_indices(self.capacity);
+        }
+        self.set_dirty();
+    }
+
+    pub fn position(&self) -> Vector2<f32> {
+        self.position
+    }
+
+    pub fn set_position(&mut self, position: Vector2<f32>) {
+        self.position = position;
+        self.set_dirty();
+    }
+
+    pub fn font_size(&self) -> f32 {
+        self.font_size
+    }
+
+    pub fn set_font_size(&mut self, font_size: f32) {
+        self.font_size = font_size;
+        self.set_dirty();
+    }
+
+    pub fn spacing(&self) -> f32 {
+        self.spacing
+    }
+
+    pub fn set_spacing(&mut self, spacing: f32) {
+        self.spacing = spacing;
+        self.set_dirty();
+    }
+
+    pub fn wrap_width(&self) -> Option<f32> {
+        self.wrap_width
+    }
+
+    pub fn set_wrap_width(&mut self, wrap_width: Option<f32>) {
+        self.wrap_width = wrap_width;
+        self.set_dirty();
+    }
+
+    fn set_dirty(&mut self) {
+        self.vertices = None;
+    }
+
+    pub fn recalculate_vertex_data(&mut self) {
+        let size = self.font_size / self.font.char_size().y;
+        let half_size = (self.font.char_size() * size).half();
+        let glyphs = self.font.layout(&self.string(), self.font_size, self.spacing, self.wrap_width);
 
-        let mut current_char = next_char(0, self.content[0]);
+        let mut vertices = Vec::with_capacity(self.capacity * 4);
 
-        self.vertices = Some(array::from_fn(|i| {
-            if i % 4 == 0 && i != 0 {
-                current_char = next_char(i / 4, self.content[i / 4]);
-            }
+        for i in 0..self.capacity {
+            let Some(glyph) = glyphs.get(i) else {
+                // collapse unused glyphs into a point, so they don't show stale characters
+                let p = self.position.into_array();
+                vertices.extend(self.material.vertices([p; 4], &([[0.0; 2]; 4], self.texture)));
+                continue;
+            };
+
+            let position = self.position + glyph.position;
+            let char_sprite = self.font.sprite_sheet().get(glyph.index).unwrap_or_else(|| panic!("font has no sprite for character '{}'", glyph.c));
+
+            let tex_coords = [
+                [char_sprite.left(),  char_sprite.top()   ],
+                [char_sprite.right(), char_sprite.top()   ],
+                [char_sprite.right(), char_sprite.bottom()],
+                [char_sprite.left(),  char_sprite.bottom()]
+            ];
+
+            vertices.extend(self.material.vertices([
+                [position.x() - half_size.x(), position.y() - half_size.y()],
+                [position.x() + half_size.x(), position.y() - half_size.y()],
+                [position.x() + half_size.x(), position.y() + half_size.y()],
+                [position.x() - half_size.x(), position.y() + half_size.y()]
+            ], &(tex_coords, self.texture)));
+        }
 
-            current_char[i % 4].clone()
-        }))
+        self.vertices = Some(vertices);
     }
 }
@@ -1,10 +1,10 @@
 use std::cell::RefCell;
 use std::rc::Rc;
 
-use aeonetica_engine::{Id, TypeId, error::*, log, math::camera::Camera, util::{id_map::IdMap, type_to_id}, time::Time};
+use aeonetica_engine::{Id, TypeId, error::*, log, math::{camera::Camera, vector::Vector2}, util::{id_map::IdMap, type_to_id, nullable::Nullable::Value}, time::Time};
 
-use crate::{client_runtime::ClientHandleBox, renderer::window::Window};
-use crate::{renderer::{window::events::Event, layer::Layer, Renderer}, client_runtime::ClientRuntime, data_store::DataStore};
+use crate::client_runtime::ClientHandleBox;
+use crate::{renderer::{window::events::Event, layer::Layer, Renderer}, data_store::DataStore};
 
 use super::{layer::LayerUpdater, shader::PostProcessingLayer, util::Target};
 
@@ -40,6 +40,10 @@ impl LayerBox {
         self.layer.quit(&mut self.renderer, store)
     }
 
+    fn resize_camera(&mut self, aspect_ratio: f32) {
+        self.layer.resize_camera(&mut self.camera, aspect_ratio)
+    }
+
     fn on_render(&mut self, id: &mut Id, handles: &mut IdMap<ClientHandleBox>, target: &Target, store: &mut DataStore, time: Time) {
         self.layer.update_camera(store, &mut self.camera, time);
         self.renderer.on_layer_update(&self.camera, target, LayerUpdater::new(&mut self.layer, handles, *id, store), time);
@@ -92,18 +96,55 @@ impl LayerStack {
         self.layer_map.insert(type_to_id::<L>(), l.clone());
         self.layer_stack.insert(self.insert_index, (l, type_to_id::<L>()));
     }
+
+    fn remove(&mut self, index: usize) -> (LayerBox, TypeId) {
+        let (l, id) = self.layer_stack.remove(index);
+        self.layer_map.remove(&id);
+        if index < self.insert_index {
+            self.insert_index -= 1;
+        }
+        let l = Rc::try_unwrap(l).ok().expect("removed layer is still borrowed").into_inner();
+        (l, id)
+    }
 }
 
 pub struct RenderContext {
     pub(crate) layer_stack: LayerStack,
-    post_processing_layer: Option<Rc<dyn PostProcessingLayer>>
+    post_processing_layer: Option<Rc<dyn PostProcessingLayer>>,
+    viewport_size: Vector2<u32>,
+    /// renderers of removed layers, kept until the handles owned by the layers are removed from them
+    detached: Vec<(TypeId, Renderer)>
 }
 
 impl RenderContext {
+    const DEFAULT_VIEWPORT_SIZE: Vector2<u32> = Vector2 { x: 1920, y: 1080 };
+
     pub(crate) fn new() -> Self {
         Self {
             layer_stack: LayerStack::new(),
-            post_processing_layer: None
+            post_processing_layer: None,
+            viewport_size: Self::DEFAULT_VIEWPORT_SIZE,
+            detached: vec![]
+        }
+    }
+
+    pub fn viewport_size(&self) -> Vector2<u32> {
+        self.viewport_size
+    }
+
+    fn aspect_ratio(&self) -> f32 {
+        self.viewport_size.x() as f32 / self.viewport_size.y() as f32
+    }
+
+    /// Refits the cameras of all layers to a new viewport size. Sizes of zero are ignored.
+    pub(crate) fn resize(&mut self, viewport_size: Vector2<u32>) {
+        if viewport_size.x() == 0 || viewport_size.y() == 0 {
+            return
+        }
+        self.viewport_size = viewport_size;
+        let aspect_ratio = self.aspect_ratio();
+        for layer_box in self.layer_stack.layer_map.values() {
+            layer_box.borrow_mut().resize_camera(aspect_ratio);
         }
     }
 
@@ -118,10 +159,46 @@ impl RenderContext {
         else {
             self.layer_stack.push(layer, store);
         }
+        self.layer_stack.layer_map[&type_to_id::<L>()].borrow_mut().resize_camera(self.aspect_ratio());
         Ok(())
     }
 
-    pub(crate) fn on_event(&mut self, client: &mut ClientRuntime, event: Event, store: &mut DataStore) {
+    /// Removes the topmost layer that isn't an overlay, usually the last one pushed. Overlays are removed by [`RenderContext::remove_layer_by_name`].
+    ///
+    /// The layer's `quit` hook runs right away, the handles owned by the layer are removed before the next frame.
+    pub fn pop_layer(&mut self, store: &mut DataStore) -> Option<Box<dyn Layer>> {
+        let index = self.layer_stack.insert_index.checked_sub(1)?;
+        Some(self.detach(index, store))
+    }
+
+    /// Removes the topmost layer named `name`, see [`RenderContext::pop_layer`].
+    pub fn remove_layer_by_name(&mut self, name: &str, store: &mut DataStore) -> Option<Box<dyn Layer>> {
+        let index = self.layer_stack.layer_stack.iter().rposition(|(layer_box, _)| layer_box.borrow().layer.name() == name)?;
+        Some(self.detach(index, store))
+    }
+
+    fn detach(&mut self, index: usize, store: &mut DataStore) -> Box<dyn Layer> {
+        let (mut layer_box, id) = self.layer_stack.remove(index);
+        layer_box.quit(store);
+        self.detached.push((id, layer_box.renderer));
+        layer_box.layer
+    }
+
+    /// Removes the handles owned by removed layers, so they don't keep running against a renderer that is never drawn.
+    fn remove_detached_handles(&mut self, handles: &mut IdMap<ClientHandleBox>, store: &mut DataStore) {
+        for (id, mut renderer) in self.detached.drain(..) {
+            handles.retain(|_, h_box| {
+                if h_box.handle.owning_layer() != id {
+                    return true
+                }
+                h_box.handle.remove(&mut h_box.messenger, Value(&mut renderer), store);
+                false
+            });
+            renderer.clear_batches();
+        }
+    }
+
+    pub(crate) fn on_event(&mut self, handles: &mut IdMap<ClientHandleBox>, event: Event, store: &mut DataStore) {
         for (layer_box, id) in self.layer_stack.layer_stack.iter()
             .filter(|(layer_box, _)| layer_box.borrow().layer.active()).rev() {
             let mut layer_box = layer_box.borrow_mut();
@@ -129,14 +206,14 @@ impl RenderContext {
 
             if let Event::MouseMoved(position) = &mut event {
                 // translate the event's position to world coordinates
-                *position = layer_box.camera.to_world(*position, Window::FRAMEBUFFER_SIZE.to_f32())
+                *position = layer_box.camera.to_world(*position, self.viewport_size.to_f32())
             }
             
             if layer_box.layer.event(&event, store) { 
                 return;
             }
 
-            if client.handles.iter_mut()
+            if handles.iter_mut()
                 .filter(|(_, h_box)| h_box.handle.owning_layer() == *id)
                 .any(|(_, h_box)| h_box.handle.event(&event, &mut h_box.messenger, &mut layer_box.renderer, store)) { 
                     return;
@@ -146,8 +223,8 @@ impl RenderContext {
         log!(PACK, "Unhandled Event: {event:?}");
     }
 
-    pub(crate) fn on_render(&mut self, client: &mut ClientRuntime, target: &Target, store: &mut DataStore, time: Time) {
-        let handles = client.handles();
+    pub(crate) fn on_render(&mut self, handles: &mut IdMap<ClientHandleBox>, target: &Target, store: &mut DataStore, time: Time) {
+        self.remove_detached_handles(handles, store);
         self.layer_stack.layer_stack.iter_mut()
             .filter(|(layer_box, _)| layer_box.borrow().layer.active())
             .for_each(|(layer_box, id)| layer_box.borrow_mut().on_render(id, handles, target, store, time));
@@ -167,10 +244,79 @@ impl RenderContext {
         &self.post_processing_layer
     }
 
-    pub(crate) fn finish(self, store: &mut DataStore) {
+    pub(crate) fn finish(mut self, store: &mut DataStore) {
         for (layer_box, _) in self.layer_stack.layer_stack.iter() {
             layer_box.borrow_mut().quit(store);
         }
+        self.detached.drain(..).for_each(|(_, mut renderer)| renderer.clear_batches());
         if let Some(layer) = self.post_processing_layer { layer.detach() }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// names of the layers that quit, in order
+    #[derive(Default)]
+    struct Quits(Vec<&'static str>);
+
+    macro_rules! test_layer {
+        ($ty: ident, $overlay: literal) => {
+            struct $ty;
+
+            impl Layer for $ty {
+                fn instantiate_camera(&self) -> Camera {
+                    Camera::new(0.0, 1.0, 1.0, 0.0, 1.0, -1.0)
+                }
+
+                fn quit(&mut self, _renderer: &mut Renderer, store: &mut DataStore) {
+                    store.mut_or_default::<Quits>().0.push(self.name());
+                }
+
+                fn name(&self) -> &'static str {
+                    stringify!($ty)
+                }
+
+                fn is_overlay(&self) -> bool {
+                    $overlay
+                }
+            }
+        };
+    }
+
+    test_layer!(Menu, false);
+    test_layer!(Game, false);
+    test_layer!(Hud, true);
+
+    fn names(context: &RenderContext) -> Vec<&'static str> {
+        context.layer_stack.layer_stack.iter().map(|(layer_box, _)| layer_box.borrow().layer.name()).collect()
+    }
+
+    #[test]
+    fn removed_layers_quit_and_leave_the_stack() {
+        let mut store = DataStore::new();
+        let mut context = RenderContext::new();
+        context.push(Game, &mut store).unwrap();
+        context.push(Hud, &mut store).unwrap();
+        context.push(Menu, &mut store).unwrap();
+        assert_eq!(names(&context), ["Game", "Menu", "Hud"]);
+
+        // overlays stay on top
+        assert_eq!(context.pop_layer(&mut store).unwrap().name(), "Menu");
+        assert_eq!(store.get_store::<Quits>().0, ["Menu"]);
+        assert!(!context.layer_stack.layer_map.contains_key(&type_to_id::<Menu>()));
+        assert_eq!(context.detached.len(), 1);
+
+        assert_eq!(context.remove_layer_by_name("Hud", &mut store).unwrap().name(), "Hud");
+        assert!(context.remove_layer_by_name("Hud", &mut store).is_none());
+
+        // removed layers can be pushed again
+        context.push(Menu, &mut store).unwrap();
+        assert_eq!(names(&context), ["Game", "Menu"]);
+        context.pop_layer(&mut store);
+        context.pop_layer(&mut store);
+        assert!(context.pop_layer(&mut store).is_none());
+        assert_eq!(store.get_store::<Quits>().0, ["Menu", "Hud", "Menu", "Game"]);
+    }
+}
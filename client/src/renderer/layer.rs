@@ -16,6 +16,7 @@ pub trait Layer {
     fn quit(&mut self, renderer: &mut Renderer, store: &mut DataStore) {} // run on layer deletion
 
     fn update_camera(&mut self, store: &mut DataStore, camera: &mut Camera, time: Time) {}
+    fn resize_camera(&mut self, camera: &mut Camera, aspect_ratio: f32) { camera.set_aspect(aspect_ratio) } // run on layer creation and window resize
     fn pre_handles_update(&mut self, store: &mut DataStore, renderer: &mut Renderer, time: Time) {}
     fn post_handles_update(&mut self, store: &mut DataStore, renderer: &mut Renderer, time: Time) {}
 
@@ -2,19 +2,40 @@ use std::rc::Rc;
 
 use crate::{uniform_str, vertex};
 
-use super::{shader::{self, UniformStr}, buffer::{Vertex, Color, TexCoord, TextureID, BufferLayoutBuilder, BufferLayout, VertexTuple2, VertexTuple3}, RenderID, texture::Sampler2D};
+use super::{shader::{self, UniformStr}, buffer::{Vertex, Color, TexCoord, TextureID, Float, BufferLayoutBuilder, BufferLayout, VertexTuple2, VertexTuple3}, RenderID, texture::Sampler2D};
 
+/// How renderables are drawn: the shader, the vertex layout it reads and the per renderable data it is fed with.
+///
+/// Mods can implement it for their own types and build quads with [`Quad::with_material`](crate::renderer::builtin::Quad::with_material),
+/// the builtin materials below are written the same way.
 pub trait Material {
+    /// The vertex attributes, a [`BufferLayoutBuilder`] over a tuple of [`ShaderLayoutType`](shader::ShaderLayoutType)s
+    /// like [`Vertex`] or [`TexCoord`].
     type Layout;
+    /// Everything besides the positions needed for the vertices of a renderable with `N` vertices,
+    /// e.g. texture coordinates per vertex and one texture.
     type Data<const N: usize>;
+    /// One vertex, the `VertexTuple` matching `Layout` built with [`vertex!`](crate::vertex).
     type VertexTuple: Clone;
 
     fn shader(&self) -> &Rc<shader::Program>;
+    /// The texture bound for the renderable, batches hand out its texture slot.
     fn texture_id<const N: usize>(data: &Self::Data<N>) -> Option<RenderID>;
+    /// `Self::Layout::build()`, kept in a thread local so all batches of the material share it.
     fn layout<'a>() -> &'a Rc<BufferLayout>;
     fn vertices<const N: usize>(&self, vertices: [[f32; 2]; N], data: &Self::Data<N>) -> [Self::VertexTuple; N];
+    /// The data of the `NN` vertices starting at `offset`.
     fn data_slice<const N: usize, const NN: usize>(&self, data: &Self::Data<N>, offset: usize) -> Self::Data<NN>;
     fn default_data<const N: usize>(&self) -> Self::Data<N>;
+
+    /// Whether quads of this material are drawn as instances of one shared unit quad, so a batch only uploads
+    /// position, size and texture region per quad instead of four vertices. Worth it for many static quads like tiles.
+    ///
+    /// Instanced materials need the vertex layout of [`FlatTexture`], only draw axis aligned quads
+    /// and need a shader reading the instance attributes, see `flat-texture-instanced-shader.glsl`.
+    fn instanced(&self) -> bool {
+        false
+    }
 }
 
 pub struct FlatColor {
@@ -80,32 +101,115 @@ impl Material for FlatColor {
     }
 }
 
-pub struct FlatTexture {
+/// [`FlatColor`] with anti-aliased edges, for strokes like [`crate::renderer::builtin::Polyline`].
+///
+/// Every vertex carries its signed distance to the center of the stroke, `-1` and `1` on the edges.
+/// The shared flat color shader fades the color out over about a pixel towards the edges.
+pub struct FeatheredColor {
     shader: Rc<shader::Program>
 }
 
+thread_local! {
+    static FEATHERED_COLOR_LAYOUT: Rc<BufferLayout> = Rc::new(<FeatheredColor as Material>::Layout::build());
+    static FEATHERED_COLOR_INSTANCE: Rc<FeatheredColor> = Rc::new(FeatheredColor::new());
+}
+
+impl FeatheredColor {
+    fn new() -> Self {
+        Self {
+            shader: FLAT_COLOR_SHADER.with(|shader| shader.clone())
+        }
+    }
+
+    pub fn get() -> Rc<Self> {
+        FEATHERED_COLOR_INSTANCE.with(|instance| instance.clone())
+    }
+
+    pub fn with_shader(shader: Rc<shader::Program>) -> Self {
+        Self {
+            shader
+        }
+    }
+}
+
+impl Material for FeatheredColor {
+    type Layout = BufferLayoutBuilder<(Vertex, Color, Float)>;
+    /// color and per vertex edge distance
+    type Data<const N: usize> = ([f32; 4], [f32; N]);
+    type VertexTuple = VertexTuple3<[f32; 2], [f32; 4], f32>;
+
+    fn shader(&self) -> &Rc<shader::Program> {
+        &self.shader
+    }
+
+    fn texture_id<const N: usize>(_: &Self::Data<N>) -> Option<RenderID> {
+        None
+    }
+
+    fn layout<'a>() -> &'a Rc<BufferLayout> {
+        unsafe {
+            let x: *const Rc<BufferLayout> = FEATHERED_COLOR_LAYOUT.with(|l| l as *const _);
+            x.as_ref().unwrap_unchecked()
+        }   
+    }
+
+    fn vertices<const N: usize>(&self, vertices: [[f32; 2]; N], data: &Self::Data<N>) -> [Self::VertexTuple; N] {
+        Self::Layout::array(std::array::from_fn(|i| vertex!(vertices[i], data.0, data.1[i])))
+    }
+
+    fn data_slice<const N: usize, const NN: usize>(&self, data: &Self::Data<N>, offset: usize) -> Self::Data<NN> {
+        (data.0, std::array::from_fn(|i| data.1[i + offset]))
+    }
+
+    fn default_data<const N: usize>(&self) -> Self::Data<N> {
+        ([0.0; 4], [0.0; N])
+    }
+}
+
+pub struct FlatTexture {
+    shader: Rc<shader::Program>,
+    instanced: bool
+}
+
 thread_local! {
     static FLAT_TEXTURE_LAYOUT: Rc<BufferLayout> = Rc::new(<FlatTexture as Material>::Layout::build());
     static FLAT_TEXTURE_SHADER: Rc<shader::Program> = Rc::new(shader::Program::from_source(include_str!("../../../assets/flat-texture-shader.glsl")).expect("failed loading flat color shader"));
+    static FLAT_TEXTURE_INSTANCED_SHADER: Rc<shader::Program> = Rc::new(shader::Program::from_source(include_str!("../../../assets/flat-texture-instanced-shader.glsl")).expect("failed loading instanced flat texture shader"));
     static FLAT_TEXTURE_INSTANCE: Rc<FlatTexture> = Rc::new(FlatTexture::new());
+    static INSTANCED_FLAT_TEXTURE_INSTANCE: Rc<FlatTexture> = Rc::new(FlatTexture::instanced_with_shader(FLAT_TEXTURE_INSTANCED_SHADER.with(|shader| shader.clone())));
 }
 
 impl FlatTexture {
     fn new() -> Self {
         Self {
-            shader: FLAT_TEXTURE_SHADER.with(|shader| shader.clone())
+            shader: FLAT_TEXTURE_SHADER.with(|shader| shader.clone()),
+            instanced: false
         }
     }
 
     pub fn with_shader(shader: Rc<shader::Program>) -> Self {
         Self {
-            shader
+            shader,
+            instanced: false
+        }
+    }
+
+    /// Instanced variant of [`FlatTexture::with_shader`], see [`Material::instanced`].
+    pub fn instanced_with_shader(shader: Rc<shader::Program>) -> Self {
+        Self {
+            shader,
+            instanced: true
         }
     }
 
     pub fn get() -> Rc<Self> {
         FLAT_TEXTURE_INSTANCE.with(|instance| instance.clone())
     }
+
+    /// Flat texture material drawing its quads instanced, for large amounts of quads that rarely change.
+    pub fn get_instanced() -> Rc<Self> {
+        INSTANCED_FLAT_TEXTURE_INSTANCE.with(|instance| instance.clone())
+    }
 }
 
 impl Material for FlatTexture {
@@ -139,4 +243,8 @@ impl Material for FlatTexture {
     fn default_data<const N: usize>(&self) -> Self::Data<N> {
         (std::array::from_fn(|_| [0.0; 2]), 0)
     }
+
+    fn instanced(&self) -> bool {
+        self.instanced
+    }
 }
\ No newline at end of file
@@ -5,6 +5,7 @@ pub mod glerror;
 pub mod layer;
 pub mod material;
 pub mod pipeline;
+pub mod screenshot;
 pub mod shader;
 pub mod texture;
 pub mod util;
@@ -53,13 +54,17 @@ pub trait Renderable {
     fn set_location(&mut self, location: Option<VertexLocation>);
     fn has_location(&self) -> bool;
     fn is_dirty(&self) -> bool;
+    /// World-space bounding box `(min, max)` used for frustum culling. `None` means the item is always drawn.
+    fn bounds(&self) -> Option<(Vector2<f32>, Vector2<f32>)> { None }
 }
 
 pub struct Renderer {
     shader: Option<Rc<Program>>,
     view_projection: Option<Matrix4<f32>>,
+    visible_bounds: Option<(Vector2<f32>, Vector2<f32>)>,
     batches: OrderedMap<BatchID, Batch, u8>,
-    pipeline: Box<dyn Pipeline>,    
+    pipeline: Box<dyn Pipeline>,
+    clear_color: Option<[f32; 4]>
 }
 
 impl Renderer {
@@ -69,11 +74,33 @@ impl Renderer {
         Self {
             shader: None,
             view_projection: None,
+            visible_bounds: None,
             pipeline: Box::new(DefaultPipeline::new()),
             batches: OrderedMap::new(),
+            clear_color: None
         }
     }
 
+    /// Color the pipeline clears its target with before rendering the scene.
+    /// Renderers without a clear color draw on top of the layers below them.
+    pub fn set_clear_color(&mut self, color: [f32; 4]) {
+        self.clear_color = Some(color);
+    }
+
+    pub fn reset_clear_color(&mut self) {
+        self.clear_color = None;
+    }
+
+    pub fn clear_color(&self) -> Option<[f32; 4]> {
+        self.clear_color
+    }
+
+    /// Reads back a region of the currently bound framebuffer as 8-bit RGBA, rows ordered top to bottom.
+    /// Call this from a [`Pipeline`] after drawing, see [`screenshot::Screenshot`] for encoding it.
+    pub fn read_pixels(&self, offset: Vector2<u32>, size: Vector2<u32>) -> Vec<u8> {
+        screenshot::Screenshot::read_bound(offset, size).into_pixels()
+    }
+
     pub fn set_pipeline<P: Pipeline + 'static>(&mut self, pipeline: P) {
         self.pipeline = Box::new(pipeline);
     }
@@ -83,6 +110,7 @@ impl Renderer {
             shader.upload_uniform(&Self::VIEW_PROJECTION_UNIFORM, camera.view_projection_matrix());
         }
         self.view_projection = Some(camera.view_projection_matrix().clone());
+        self.visible_bounds = Some(camera.visible_bounds());
     }
 
     pub fn end_scene(&mut self) {
@@ -141,19 +169,39 @@ impl Renderer {
         }
     }
 
+    /// Deletes all batches at once.
+    /// Locations of renderables added before are invalidated, so they have to be re-added instead of modified.
+    pub fn clear_batches(&mut self) {
+        self.batches.drain().for_each(|(_, batch)| batch.delete());
+    }
+
+    pub fn batch_count(&self) -> usize {
+        self.batches.len()
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.batches.iter().map(|(_, batch)| batch.num_vertices()).sum()
+    }
+
+    /// highest share of a batch's vertex bytes left unused by removed quads, for diagnostics
+    pub fn fragmentation_ratio(&self) -> f32 {
+        self.batches.iter().map(|(_, batch)| batch.fragmentation_ratio()).fold(0.0, f32::max)
+    }
+
     pub fn add_vertices(&mut self, data: &mut VertexData) -> VertexLocation {
         if let Some(idx) = self.batches.iter().position(|(_, batch)| batch.has_space_for(data)) {
             // matching batch with enough space found
-            self.batches.nth_mut(idx, |batch| batch.add_vertices(data)).unwrap()
+            if let Some(location) = self.batches.nth_mut(idx, |batch| batch.add_vertices(data)).flatten() {
+                return location
+            }
         }
-        else {
-            // create new batch
-            let mut batch = Batch::new(self.next_id(), data).expect("Error creating new render batch");
-            let location = batch.add_vertices(data);
-            self.batches.insert(*batch.id(), batch);
 
-            location
-        }
+        // create new batch
+        let mut batch = Batch::new(self.next_id(), data).expect("Error creating new render batch");
+        let location = batch.add_vertices(data).expect("new render batch has no free texture slot");
+        self.batches.insert(*batch.id(), batch);
+
+        location
     }
 
     pub fn modify_vertices(&mut self, location: &VertexLocation, data: &mut [u8], texture: Option<RenderID>) -> ErrorResult<()> {
@@ -177,25 +225,58 @@ impl Renderer {
         }
     }
 
+    /// Items outside of the view are not added, [`Renderer::draw`] adds them once they become visible.
     pub fn add(&mut self, item: &mut impl Renderable) {
+        if !self.is_visible(item) {
+            return;
+        }
         let location = self.add_vertices(&mut item.vertex_data());
         item.set_location(Some(location));
     }
 
     pub fn modify(&mut self, item: &mut impl Renderable) -> ErrorResult<()> {
+        // culled items have no location
+        let Some(location) = item.location().clone() else {
+            return self.draw(item)
+        };
+        if item.vertex_data().z_index() != location.z_index() {
+            self.rebatch(&location, item);
+            return Ok(());
+        }
         let texture = item.texture_id();
-        self.modify_vertices(&item.location().as_ref().unwrap().clone(), item.vertex_data().mut_vertices(), texture)
+        self.modify_vertices(&location, item.vertex_data().mut_vertices(), texture)
+    }
+
+    // batches only hold one z-index, so a changed z-index moves the item to another batch
+    fn rebatch(&mut self, location: &VertexLocation, item: &mut impl Renderable) {
+        self.remove_vertices(location);
+        let location = self.add_vertices(&mut item.vertex_data());
+        item.set_location(Some(location));
+    }
+
+    /// Whether the item is within the bounds of the last camera used in `begin_scene`.
+    /// Items without bounds and all items before the first scene are considered visible.
+    pub fn is_visible(&self, item: &impl Renderable) -> bool {
+        match (self.visible_bounds, item.bounds()) {
+            (Some((view_min, view_max)), Some((min, max))) =>
+                min.x <= view_max.x && max.x >= view_min.x && min.y <= view_max.y && max.y >= view_min.y,
+            _ => true
+        }
     }
 
-    // add or modify a given item, if needed
+    // add or modify a given item, if needed. Items outside of the view are removed
+    // and added again once they become visible.
     pub fn draw(&mut self, item: &mut impl Renderable) -> ErrorResult<()> {
+        if !self.is_visible(item) {
+            self.remove(item);
+            return Ok(());
+        }
         if !item.has_location() {
             let location = self.add_vertices(&mut item.vertex_data());
             item.set_location(Some(location));
         }
         else if item.is_dirty() {
-            let texture = item.texture_id();
-            self.modify_vertices(&item.location().clone().unwrap(), item.vertex_data().mut_vertices(), texture)?
+            self.modify(item)?
         }
 
         Ok(())
@@ -220,4 +301,47 @@ pub(crate) mod gpu_debug{
     thread_local! {
         pub(crate) static RENDERER: RefCell<usize> = RefCell::new(0);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use glfw::Context as _;
+
+    use super::*;
+    use crate::renderer::{builtin::Quad, material::FlatColor};
+
+    /// A hidden window with its OpenGL context current on this thread, `None` without a display.
+    fn gl_window() -> Option<glfw::Window> {
+        let mut glfw = glfw::init(glfw::LOG_ERRORS).ok()?;
+        glfw.window_hint(glfw::WindowHint::ContextVersion(4, 5));
+        glfw.window_hint(glfw::WindowHint::OpenGlProfile(glfw::OpenGlProfileHint::Core));
+        glfw.window_hint(glfw::WindowHint::Visible(false));
+        let (mut window, _events) = glfw.create_window(16, 16, "test", glfw::WindowMode::Windowed)?;
+        window.make_current();
+        gl::load_with(|s| glfw.get_proc_address_raw(s));
+        Some(window)
+    }
+
+    #[test]
+    #[ignore = "needs a display with OpenGL 4.5, run with `cargo test -- --ignored`"]
+    fn changing_the_z_index_moves_quads_between_batches() {
+        let _window = gl_window().expect("no OpenGL context available");
+        let mut renderer = Renderer::new();
+        let mut quad = Quad::<FlatColor>::with_color(Vector2::new(0.0, 0.0), Vector2::new(1.0, 1.0), 1, [1.0; 4]);
+        // keeps the first batch alive after `quad` left it
+        let mut other = Quad::<FlatColor>::with_color(Vector2::new(2.0, 0.0), Vector2::new(1.0, 1.0), 1, [1.0; 4]);
+        renderer.add(&mut quad);
+        renderer.add(&mut other);
+        let old = quad.location().clone().unwrap();
+        assert_eq!(old.z_index(), 1);
+        assert_eq!(renderer.batch_count(), 1);
+
+        quad.set_z_index(3);
+        renderer.draw(&mut quad).unwrap();
+        let new = quad.location().clone().unwrap();
+        assert_eq!(new.z_index(), 3);
+        assert_ne!(new.batch(), old.batch());
+        assert_eq!(other.location().as_ref().unwrap().batch(), old.batch());
+        assert_eq!(renderer.batch_count(), 2);
+    }
+}
@@ -16,6 +16,10 @@ impl DefaultPipeline {
 
 impl Pipeline for DefaultPipeline {
     fn pipeline(&mut self, renderer: &mut Renderer, camera: &Camera, target: &Target, mut updater: LayerUpdater, time: Time) {
+        if let (Some(color), Target::FrameBuffer(fb)) = (renderer.clear_color(), target) {
+            fb.bind();
+            fb.clear(color);
+        }
         renderer.begin_scene(camera);
         updater.update(renderer, time);
         renderer.draw_vertices(target);
@@ -0,0 +1,103 @@
+use aeonetica_engine::{math::vector::Vector2, error::{ErrorResult, IntoError}};
+use image::{DynamicImage, ImageOutputFormat, RgbaImage};
+
+use super::{buffer::framebuffer::FrameBuffer, texture::ImageError};
+
+/// 8-bit RGBA pixels read back from the GPU, with rows ordered top to bottom like image files.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Screenshot {
+    size: Vector2<u32>,
+    pixels: Vec<u8>
+}
+
+impl Screenshot {
+    /// Reads the region at `offset` of size `size` from the currently bound read framebuffer.
+    /// Floating point attachments like `RgbaF16` are tonemapped by clamping each channel to `[0, 1]`.
+    pub fn read_bound(offset: Vector2<u32>, size: Vector2<u32>) -> Self {
+        let mut data = vec![0.0_f32; (size.x() * size.y() * 4) as usize];
+        unsafe {
+            gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+            gl::ReadPixels(
+                offset.x() as i32, offset.y() as i32,
+                size.x() as i32, size.y() as i32,
+                gl::RGBA, gl::FLOAT,
+                data.as_mut_ptr() as *mut _
+            );
+        }
+        Self::from_gl_pixels(size, &data)
+    }
+
+    /// Reads color attachment `attachment` of `framebuffer`. The previously bound framebuffer is restored.
+    pub fn from_framebuffer(framebuffer: &FrameBuffer, attachment: usize, offset: Vector2<u32>, size: Vector2<u32>) -> Self {
+        let mut previous_fb = 0;
+        unsafe { gl::GetIntegerv(gl::FRAMEBUFFER_BINDING, &mut previous_fb) };
+
+        framebuffer.bind();
+        unsafe { gl::ReadBuffer(gl::COLOR_ATTACHMENT0 + attachment as u32) };
+        let screenshot = Self::read_bound(offset, size);
+
+        unsafe { gl::BindFramebuffer(gl::FRAMEBUFFER, previous_fb as u32) };
+        screenshot
+    }
+
+    /// Converts float RGBA pixels in OpenGL's bottom to top row order.
+    fn from_gl_pixels(size: Vector2<u32>, data: &[f32]) -> Self {
+        let row_len = size.x() as usize * 4;
+        let pixels = data.chunks_exact(row_len.max(1))
+            .rev()
+            .flatten()
+            .map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+            .collect();
+        Self { size, pixels }
+    }
+
+    pub fn size(&self) -> Vector2<u32> {
+        self.size
+    }
+
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub fn into_pixels(self) -> Vec<u8> {
+        self.pixels
+    }
+
+    pub fn encode_png(&self) -> ErrorResult<Vec<u8>> {
+        let image = RgbaImage::from_raw(self.size.x(), self.size.y(), self.pixels.clone())
+            .ok_or_else(|| ImageError::Encode("screenshot has the wrong number of pixels".to_string()).into_error())?;
+        let mut bytes = std::io::Cursor::new(vec![]);
+        DynamicImage::ImageRgba8(image).write_to(&mut bytes, ImageOutputFormat::Png)
+            .map_err(|e| ImageError::Encode(e.to_string()).into_error())?;
+        Ok(bytes.into_inner())
+    }
+
+    pub fn save_png(&self, path: &str) -> ErrorResult<()> {
+        std::fs::write(path, self.encode_png()?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn readback_is_flipped_clamped_and_encodable() {
+        // two rows, bottom row first like glReadPixels returns them
+        let data = [
+            0.0, 0.5, 1.0, 1.0,   4.0, -1.0, 0.25, 1.0,
+            1.0, 1.0, 1.0, 1.0,   0.0, 0.0, 0.0, 0.0
+        ];
+        let screenshot = Screenshot::from_gl_pixels(Vector2::new(2, 2), &data);
+        assert_eq!(screenshot.pixels(), &[
+            255, 255, 255, 255,   0, 0, 0, 0,
+            0, 128, 255, 255,     255, 0, 64, 255
+        ]);
+
+        let png = screenshot.encode_png().unwrap();
+        let decoded = image::load_from_memory(&png).unwrap().into_rgba8();
+        assert_eq!(decoded.dimensions(), (2, 2));
+        assert_eq!(decoded.into_raw(), screenshot.into_pixels());
+    }
+}
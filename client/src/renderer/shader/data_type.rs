@@ -20,6 +20,24 @@ pub enum ShaderDataType {
 }
 
 impl ShaderDataType {
+    pub(crate) const fn from_gl(ty: gl::types::GLenum) -> Option<Self> {
+        Some(match ty {
+            gl::FLOAT => Self::Float,
+            gl::FLOAT_VEC2 => Self::Float2,
+            gl::FLOAT_VEC3 => Self::Float3,
+            gl::FLOAT_VEC4 => Self::Float4,
+            gl::FLOAT_MAT3 => Self::Mat3,
+            gl::FLOAT_MAT4 => Self::Mat4,
+            gl::INT => Self::Int,
+            gl::INT_VEC2 => Self::Int2,
+            gl::INT_VEC3 => Self::Int3,
+            gl::INT_VEC4 => Self::Int4,
+            gl::BOOL => Self::Bool,
+            gl::SAMPLER_2D => Self::Sampler2D,
+            _ => return None
+        })
+    }
+
     pub(crate) const fn size(&self) -> u32 {
         use {std::mem::size_of, gl::types::*};
         (match self {
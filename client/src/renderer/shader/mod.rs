@@ -7,10 +7,12 @@ pub use uniform::*;
 pub mod data_type;
 pub use data_type::*;
 
-use std::{collections::HashMap, fmt::Display};
+use std::{cell::Cell, collections::HashMap, fmt::Display};
 use super::*;
 
 use aeonetica_engine::error::{ErrorValue, ErrorResult, Error, Fatality};
+#[cfg(any(feature = "uniform_debug", feature = "hot_reload"))]
+use aeonetica_engine::log;
 use regex::Regex;
 
 #[derive(Debug)]
@@ -103,42 +105,75 @@ impl Shader {
     }
 }
 
-#[derive(PartialEq, Eq)]
-pub struct Program(RenderID);
+pub struct Program {
+    id: Cell<RenderID>,
+    #[cfg(feature = "hot_reload")]
+    watched: Option<WatchedSource>
+}
+
+impl PartialEq for Program {
+    fn eq(&self, other: &Self) -> bool {
+        self.id.get() == other.id.get()
+    }
+}
+
+impl Eq for Program {}
+
+#[cfg(feature = "hot_reload")]
+struct WatchedSource {
+    path: std::path::PathBuf,
+    modified: Cell<Option<std::time::SystemTime>>,
+    last_check: Cell<std::time::Instant>
+}
+
 impl Program {
     pub fn new() -> Option<Self> {
         let prog = unsafe { gl::CreateProgram() };
         if prog != 0 {
-            Some(Self(prog))
+            Some(Self {
+                id: Cell::new(prog),
+                #[cfg(feature = "hot_reload")]
+                watched: None
+            })
         }
         else {
             None
         }
     }
 
+    /// A program without an OpenGL object, for tests of renderables that are never drawn.
+    #[cfg(test)]
+    pub(crate) fn null() -> Self {
+        Self {
+            id: Cell::new(0),
+            #[cfg(feature = "hot_reload")]
+            watched: None
+        }
+    }
+
     pub(super) fn attach_shader(&self, shader: &Shader) {
-        unsafe { gl::AttachShader(self.0, shader.0) }
+        unsafe { gl::AttachShader(self.id.get(), shader.0) }
     }
 
     pub(super) fn link(&self) {
-        unsafe { gl::LinkProgram(self.0) }
+        unsafe { gl::LinkProgram(self.id.get()) }
     }
 
     pub(super) fn link_success(&self) -> bool {
         let mut success = 0;
-        unsafe { gl::GetProgramiv(self.0, gl::LINK_STATUS, &mut success) };
+        unsafe { gl::GetProgramiv(self.id.get(), gl::LINK_STATUS, &mut success) };
         success == gl::TRUE.into()
     }
 
     pub(super) fn info_log(&self) -> String {
         let mut needed_len = 0;
-        unsafe { gl::GetProgramiv(self.0, gl::INFO_LOG_LENGTH, &mut needed_len) };
+        unsafe { gl::GetProgramiv(self.id.get(), gl::INFO_LOG_LENGTH, &mut needed_len) };
         
         let mut v: Vec<u8> = Vec::with_capacity(needed_len.try_into().unwrap());
         let mut len_written = 0;
         unsafe {
             gl::GetProgramInfoLog(
-                self.0,
+                self.id.get(),
                 v.capacity().try_into().unwrap(),
                 &mut len_written,
                 v.as_mut_ptr().cast()
@@ -149,7 +184,9 @@ impl Program {
     }
 
     pub fn bind(&self) {
-        unsafe { gl::UseProgram(self.0) }
+        #[cfg(feature = "hot_reload")]
+        self.reload_if_changed();
+        unsafe { gl::UseProgram(self.id.get()) }
     }
 
     pub fn unbind(&self) {
@@ -157,34 +194,63 @@ impl Program {
     }
 
     pub fn delete(&mut self) {
-        if self.0 != 0 {
-            unsafe { gl::DeleteProgram(self.0) }
-            self.0 = 0;
+        if self.id.get() != 0 {
+            unsafe { gl::DeleteProgram(self.id.get()) }
+            self.id.set(0);
         }
     }
 
     pub fn upload_uniforms<U: Uniform + ?Sized>(&self, uniforms: &[(&UniformStr, &U)]) {
         for (name, data) in uniforms {
-            unsafe {
-                let location = gl::GetUniformLocation(self.0, name.0 as *const i8);
-                data.upload(location);
-            }
+            self.upload_uniform(name, *data);
         }
     }
 
     pub fn upload_uniform<U: Uniform + ?Sized>(&self, name: &UniformStr, data: &U) {
-        unsafe {
-            let location = gl::GetUniformLocation(self.0, name.0 as *const i8);
-            data.upload(location);
+        let location = self.uniform_location(name);
+        #[cfg(feature = "uniform_debug")]
+        if location == -1 {
+            log!(WARN, "uniform {} does not exist in shader program {} (or was optimized out)", name.as_str(), self.id.get());
         }
+        data.upload(location);
     }
 
     pub fn uniform_location(&self, name: &UniformStr) -> i32 {
         unsafe {
-            gl::GetUniformLocation(self.0, name.0 as *const i8)
+            gl::GetUniformLocation(self.id.get(), name.0 as *const i8)
         }
     }
 
+    /// Whether the program has an active uniform called `name`.
+    /// Uniforms that are declared but unused get optimized out by the driver and are not active.
+    pub fn has_uniform(&self, name: &str) -> bool {
+        let Ok(name) = std::ffi::CString::new(name) else { return false };
+        unsafe { gl::GetUniformLocation(self.id.get(), name.as_ptr()) != -1 }
+    }
+
+    /// Lists the names and types of all active uniforms. Uniforms of types without a
+    /// [`ShaderDataType`] equivalent are left out. Array uniforms are listed once, named like `u_Array[0]`.
+    pub fn active_uniforms(&self) -> Vec<(String, ShaderDataType)> {
+        let mut count = 0;
+        let mut max_len = 0;
+        unsafe {
+            gl::GetProgramiv(self.id.get(), gl::ACTIVE_UNIFORMS, &mut count);
+            gl::GetProgramiv(self.id.get(), gl::ACTIVE_UNIFORM_MAX_LENGTH, &mut max_len);
+        }
+
+        (0..count as u32).filter_map(|i| {
+            let mut name: Vec<u8> = vec![0; max_len as usize];
+            let mut len = 0;
+            let mut size = 0;
+            let mut ty = 0;
+            unsafe {
+                gl::GetActiveUniform(self.id.get(), i, max_len, &mut len, &mut size, &mut ty, name.as_mut_ptr().cast());
+            }
+            name.truncate(len as usize);
+            ShaderDataType::from_gl(ty).map(|ty| (String::from_utf8_lossy(&name).into_owned(), ty))
+        }).collect()
+    }
+
     fn preprocess_sources(src: &str) -> ErrorResult<(String, String)> {
         // remove all block comments /* */
         let comment_re = Regex::new(r"(?m)/\*[\s\S]*?\*/").unwrap();
@@ -236,6 +302,54 @@ impl Program {
     }
 }
 
+#[cfg(feature = "hot_reload")]
+impl Program {
+    const RELOAD_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+
+    /// Loads the program from `path` and recompiles it whenever the file changes on disk.
+    /// Changes are picked up on `bind`, so everything sharing this program sees the new version.
+    /// If the changed source fails to compile, the error is logged and the last working program is kept.
+    pub fn from_file_watched<P: Into<std::path::PathBuf>>(path: P) -> ErrorResult<Self> {
+        let path = path.into();
+        let src = std::fs::read_to_string(&path)
+            .map_err(|e| Error::new(ShaderError(format!("could not read shader {}: {e}", path.display())), Fatality::FATAL, true))?;
+        let mut program = Self::from_source(&src)?;
+        program.watched = Some(WatchedSource {
+            modified: Cell::new(std::fs::metadata(&path).and_then(|m| m.modified()).ok()),
+            last_check: Cell::new(std::time::Instant::now()),
+            path
+        });
+        Ok(program)
+    }
+
+    fn reload_if_changed(&self) {
+        let Some(watched) = &self.watched else { return };
+        if watched.last_check.get().elapsed() < Self::RELOAD_CHECK_INTERVAL {
+            return;
+        }
+        watched.last_check.set(std::time::Instant::now());
+
+        let modified = std::fs::metadata(&watched.path).and_then(|m| m.modified()).ok();
+        if modified.is_none() || modified == watched.modified.get() {
+            return;
+        }
+        watched.modified.set(modified);
+
+        let reloaded = std::fs::read_to_string(&watched.path)
+            .map_err(|e| Error::new(ShaderError(format!("could not read shader {}: {e}", watched.path.display())), Fatality::WARN, false))
+            .and_then(|src| Self::from_source(&src));
+        match reloaded {
+            Ok(program) => {
+                // take over the freshly linked program object, the old one is deleted with `program`
+                let new_id = program.id.replace(self.id.get());
+                self.id.set(new_id);
+                log!("reloaded shader {}", watched.path.display());
+            }
+            Err(e) => log!(ERROR, "failed to reload shader {}, keeping the last working version: {e}", watched.path.display())
+        }
+    }
+}
+
 impl Drop for Program {
     fn drop(&mut self) {
         self.delete();
@@ -12,6 +12,13 @@ pub use uniform_str;
 
 pub struct UniformStr(pub *const u8);
 
+impl UniformStr {
+    /// The uniform name without its nul terminator.
+    pub fn as_str(&self) -> &str {
+        unsafe { std::ffi::CStr::from_ptr(self.0 as *const std::ffi::c_char) }.to_str().unwrap_or("<invalid utf-8>")
+    }
+}
+
 pub trait Uniform {
     fn upload(&self, location: i32);
 }
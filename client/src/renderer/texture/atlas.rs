@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+
+use aeonetica_engine::{math::vector::Vector2, error::{ErrorResult, IntoError}};
+use image::{DynamicImage, RgbaImage};
+
+use super::{Texture, TextureConfig, Sprite, ImageError};
+
+/// Packs individual images into a single texture. Images don't need power-of-two sizes.
+///
+/// ```ignore
+/// let atlas = AtlasBuilder::new()
+///     .add_bytes("coin", include_bytes!("coin.png"))?
+///     .add_bytes("heart", include_bytes!("heart.png"))?
+///     .build()?;
+/// let coin = atlas.get("coin").unwrap();
+/// ```
+#[derive(Default)]
+pub struct AtlasBuilder {
+    images: Vec<(String, RgbaImage)>,
+    padding: u32,
+    config: TextureConfig
+}
+
+impl AtlasBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Empty pixels between neighbouring images, to avoid bleeding when sampling with linear filtering.
+    pub fn with_padding(mut self, padding: u32) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    pub fn with_config(mut self, config: TextureConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Adds an encoded image, like the contents of a png file.
+    pub fn add_bytes(self, name: &str, bytes: &[u8]) -> ErrorResult<Self> {
+        let image = image::load_from_memory(bytes).map_err(|e| ImageError::Decode(e.to_string()).into_error())?;
+        self.add_image(name, image)
+    }
+
+    pub fn add_file(self, name: &str, path: &str) -> ErrorResult<Self> {
+        let image = image::io::Reader::open(path)?
+            .decode().map_err(|e| ImageError::Decode(e.to_string()).into_error())?;
+        self.add_image(name, image)
+    }
+
+    /// Adds raw RGBA8 pixels, row by row starting at the top.
+    pub fn add_pixels(self, name: &str, size: Vector2<u32>, pixels: Vec<u8>) -> ErrorResult<Self> {
+        let image = RgbaImage::from_raw(size.x(), size.y(), pixels)
+            .ok_or_else(|| ImageError::Unsupported(format!("wrong pixel data size for image {name} of size {size}")).into_error())?;
+        self.add_image(name, DynamicImage::ImageRgba8(image))
+    }
+
+    fn add_image(mut self, name: &str, image: DynamicImage) -> ErrorResult<Self> {
+        if self.images.iter().any(|(n, _)| n == name) {
+            return Err(ImageError::Unsupported(format!("atlas already contains an image named {name}")).into_error());
+        }
+        self.images.push((name.to_string(), image.into_rgba8()));
+        Ok(self)
+    }
+
+    /// Packs all images and uploads them. Fails if they don't fit into the largest texture OpenGL supports.
+    pub fn build(self) -> ErrorResult<Atlas> {
+        let mut max_size = 0;
+        unsafe { gl::GetIntegerv(gl::MAX_TEXTURE_SIZE, &mut max_size) };
+
+        let sizes = self.images.iter().map(|(_, image)| Vector2::new(image.width(), image.height())).collect::<Vec<_>>();
+        let (size, positions) = pack_shelves(&sizes, self.padding, max_size.max(1) as u32)
+            .ok_or_else(|| ImageError::Unsupported(format!("{} images don't fit into a {max_size}x{max_size} atlas texture", self.images.len())).into_error())?;
+
+        let mut pixels = RgbaImage::new(size.x(), size.y());
+        for ((_, image), position) in self.images.iter().zip(positions.iter()) {
+            image::imageops::replace(&mut pixels, image, position.x() as i64, position.y() as i64);
+        }
+        let texture = Texture::load(DynamicImage::ImageRgba8(pixels), self.config)?;
+
+        let sprites = self.images.into_iter().zip(positions)
+            .map(|((name, image), position)| {
+                let max = position + Vector2::new(image.width(), image.height());
+                (name, Sprite::from_pixels(texture.id(), position, max, size, 0.0))
+            })
+            .collect();
+
+        Ok(Atlas { texture, sprites })
+    }
+}
+
+/// A texture packed by an [`AtlasBuilder`], with a sprite for each image.
+pub struct Atlas {
+    texture: Texture,
+    sprites: HashMap<String, Sprite>
+}
+
+impl Atlas {
+    pub fn texture(&self) -> &Texture {
+        &self.texture
+    }
+
+    pub fn get(&self, name: &str) -> Option<Sprite> {
+        self.sprites.get(name).cloned()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.sprites.keys().map(String::as_str)
+    }
+}
+
+/// Places rectangles of `sizes` row by row, tallest first, into the narrowest power-of-two wide atlas
+/// whose height doesn't exceed `max_size`. Returns the atlas size and the top left corner of each rectangle.
+fn pack_shelves(sizes: &[Vector2<u32>], padding: u32, max_size: u32) -> Option<(Vector2<u32>, Vec<Vector2<u32>>)> {
+    let padded = sizes.iter().map(|s| *s + Vector2::new(padding, padding)).collect::<Vec<_>>();
+    let widest = padded.iter().map(|s| s.x()).max().unwrap_or(1);
+    let area = padded.iter().map(|s| s.x() as u64 * s.y() as u64).sum::<u64>();
+    if widest > max_size + padding {
+        return None;
+    }
+
+    let mut order = (0..sizes.len()).collect::<Vec<_>>();
+    order.sort_by_key(|i| std::cmp::Reverse(padded[*i].y()));
+
+    let mut width = widest.max((area as f64).sqrt().ceil() as u32).next_power_of_two().min(max_size);
+    loop {
+        let mut positions = vec![Vector2::default(); sizes.len()];
+        let (mut x, mut y, mut shelf_height) = (0, 0, 0);
+        for i in &order {
+            let size = padded[*i];
+            if x + size.x() > width + padding {
+                y += shelf_height;
+                x = 0;
+                shelf_height = 0;
+            }
+            positions[*i] = Vector2::new(x, y);
+            x += size.x();
+            shelf_height = shelf_height.max(size.y());
+        }
+        // the padding after the last row and column is not needed
+        let height = (y + shelf_height).saturating_sub(padding).max(1);
+        if height <= max_size {
+            let used_width = (0..sizes.len()).map(|i| positions[i].x() + sizes[i].x()).max().unwrap_or(1);
+            return Some((Vector2::new(used_width.max(1), height), positions));
+        }
+        if width >= max_size {
+            return None;
+        }
+        width = (width * 2).min(max_size);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overlaps(a: (Vector2<u32>, Vector2<u32>), b: (Vector2<u32>, Vector2<u32>)) -> bool {
+        a.0.x() < b.0.x() + b.1.x() && b.0.x() < a.0.x() + a.1.x() && a.0.y() < b.0.y() + b.1.y() && b.0.y() < a.0.y() + a.1.y()
+    }
+
+    #[test]
+    fn packed_rectangles_fit_without_overlap() {
+        let sizes = [Vector2::new(13, 7), Vector2::new(16, 16), Vector2::new(5, 30), Vector2::new(1, 1), Vector2::new(29, 3), Vector2::new(16, 16)];
+        let (size, positions) = pack_shelves(&sizes, 1, 64).unwrap();
+        assert!(size.x() <= 64 && size.y() <= 64, "{size}");
+        for (i, (pos, s)) in positions.iter().zip(sizes.iter()).enumerate() {
+            assert!(pos.x() + s.x() <= size.x() && pos.y() + s.y() <= size.y());
+            for (other_pos, other_size) in positions.iter().zip(sizes.iter()).skip(i + 1) {
+                // padding keeps rectangles a pixel apart
+                assert!(!overlaps((*pos, *s + Vector2::new(1, 1)), (*other_pos, *other_size)));
+            }
+        }
+
+        assert!(pack_shelves(&[Vector2::new(65, 1)], 0, 64).is_none());
+        assert!(pack_shelves(&[Vector2::new(64, 64), Vector2::new(1, 1)], 0, 64).is_none());
+        assert_eq!(pack_shelves(&[Vector2::new(64, 64)], 0, 64).unwrap().0, Vector2::new(64, 64));
+    }
+}
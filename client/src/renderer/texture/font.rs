@@ -13,31 +13,122 @@ struct BMPFontFile {
     texture: String,
     monospaced: bool,
     char_size: (u32, u32),
-    characters: HashMap<String, u32>
+    characters: HashMap<String, u32>,
+    /// consecutive sprite cells for a range of unicode codepoints, as `(first codepoint, last codepoint, first cell)`,
+    /// e.g. `(192, 255, 200)` for the accented latin-1 letters starting at cell 200
+    #[nserde(default)]
+    codepoints: Vec<(u32, u32, u32)>,
+    /// drawn in place of characters the font has no cell for, `'?'` if not given
+    #[nserde(default)]
+    fallback: Option<String>,
+    /// pixel offsets added between two characters, keyed by the pair, e.g. `"AV": -1`
+    #[nserde(default)]
+    kerning: HashMap<String, i32>
+}
+
+impl BMPFontFile {
+    /// Fails if a character or codepoint range refers to a cell outside of the `cell_count` cells of the sprite sheet.
+    fn char_map(&self, cell_count: u32) -> ErrorResult<CharMap> {
+        let single_char = |key: &str| match key.chars().collect::<Vec<_>>()[..] {
+            [c] => Ok(c),
+            _ => Err(Error::new(DataError(format!("key '{}' in font data has length {}, expected 1 char", key, key.chars().count())), Fatality::DEFAULT, true))
+        };
+
+        let mut characters = self.characters.iter()
+            .map(|(k, v)| match single_char(k) {
+                Ok(_) if *v >= cell_count => Err(Error::new(DataError(format!("character '{k}' in font data uses cell {v}, but the font texture only has {cell_count} cells")), Fatality::DEFAULT, true)),
+                c => c.map(|c| (c, *v))
+            })
+            .collect::<ErrorResult<HashMap<char, u32>>>()?;
+        for (first, last, cell) in &self.codepoints {
+            let last_cell = last.checked_sub(*first).and_then(|len| cell.checked_add(len));
+            if !last_cell.is_some_and(|last_cell| last_cell < cell_count) {
+                return Err(Error::new(DataError(format!("codepoint range ({first}, {last}, {cell}) in font data does not fit the {cell_count} cells of the font texture")), Fatality::DEFAULT, true))
+            }
+            for codepoint in *first..=*last {
+                let c = char::from_u32(codepoint)
+                    .ok_or_else(|| Error::new(DataError(format!("{codepoint} in font data is not a valid unicode codepoint")), Fatality::DEFAULT, true))?;
+                characters.entry(c).or_insert(cell + codepoint - first);
+            }
+        }
+
+        let mut map = CharMap::new(characters);
+        map.set_fallback(match &self.fallback {
+            Some(fallback) => Some(single_char(fallback)?),
+            None => Some('?')
+        });
+        Ok(map)
+    }
+}
+
+/// Sprite cells of a font's characters. ASCII is looked up in a table, everything else in a map.
+#[derive(Debug, Clone)]
+struct CharMap {
+    ascii: [Option<u32>; 128],
+    other: HashMap<char, u32>,
+    fallback: Option<u32>
+}
+
+impl CharMap {
+    fn new(characters: HashMap<char, u32>) -> Self {
+        let mut ascii = [None; 128];
+        let mut other = HashMap::new();
+        for (c, cell) in characters {
+            match ascii.get_mut(c as usize) {
+                Some(slot) => *slot = Some(cell),
+                None => { other.insert(c, cell); }
+            }
+        }
+        Self { ascii, other, fallback: None }
+    }
+
+    fn get(&self, c: char) -> Option<&u32> {
+        match self.ascii.get(c as usize) {
+            Some(cell) => cell.as_ref(),
+            None => self.other.get(&c)
+        }
+    }
+
+    /// `c`'s cell, or the fallback's if the font doesn't have `c`
+    fn resolve(&self, c: char) -> Option<u32> {
+        self.get(c).copied().or(self.fallback)
+    }
+
+    /// Only takes effect if the font has `fallback` itself.
+    fn set_fallback(&mut self, fallback: Option<char>) {
+        self.fallback = fallback.and_then(|c| self.get(c).copied());
+    }
 }
 
 pub struct BitmapFont {
     char_size: Vector2<u32>,
     sprite_sheet: SpriteSheet,
-    characters: HashMap<char, u32>,
-    widths: Vec<u32>
+    characters: CharMap,
+    widths: Vec<u32>,
+    kerning: HashMap<(char, char), i32>
+}
+
+/// A character placed by [`BitmapFont::layout`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Glyph {
+    pub c: char,
+    /// index into the font's sprite sheet
+    pub index: u32,
+    /// offset from the first glyph of the text, lines go down by the font size
+    pub position: Vector2<f32>
 }
 
 impl BitmapFont {
     pub fn from_texture_and_fontdata(texture: Texture, fontdata: &str) -> ErrorResult<Self> {
         let font_data = BMPFontFile::deserialize_ron(fontdata).map_err(|e| Error::new(IOError(e.to_string()), Fatality::DEFAULT, true))?;
-        Self::from_texture(texture,
-                           font_data.char_size.into(),
-                           font_data.characters.into_iter().map(|(k, v)| {
-                               let c: Vec<_> = k.chars().collect();
-                               if c.len() != 1 {
-                                   return Err(Error::new(DataError(format!("key '{}' in font data las length {}, expected 1 char", k, c.len())), Fatality::DEFAULT, true))
-                               }
-                               let c = c[0];
-                               Ok((c, v))
-                           }).collect::<ErrorResult<HashMap<char, u32>>>()?,
-                           font_data.monospaced
-        )
+        let kerning = font_data.kerning.iter().map(|(k, v)| match k.chars().collect::<Vec<_>>()[..] {
+            [a, b] => Ok(((a, b), *v)),
+            _ => Err(Error::new(DataError(format!("kerning pair '{k}' in font data has to be exactly 2 chars")), Fatality::DEFAULT, true))
+        }).collect::<ErrorResult<HashMap<(char, char), i32>>>()?;
+        let mut font = Self::from_texture(texture, font_data.char_size.into(), HashMap::new(), font_data.monospaced)?
+            .with_kerning(kerning);
+        font.characters = font_data.char_map(font.widths.len() as u32)?;
+        Ok(font)
     }
 
     pub fn from_texture(texture: Texture, char_size: Vector2<u32>, characters: HashMap<char, u32>, monospaced: bool) -> ErrorResult<Self> {
@@ -80,12 +171,41 @@ impl BitmapFont {
             char_size,
             sprite_sheet: SpriteSheet::from_texture(texture, char_size)?,
             widths,
-            characters
+            characters: {
+                let mut characters = CharMap::new(characters);
+                characters.set_fallback(Some('?'));
+                characters
+            },
+            kerning: HashMap::new()
         })
     }
 
+    /// Sets the pixel offsets added between pairs of characters.
+    pub fn with_kerning(mut self, kerning: HashMap<(char, char), i32>) -> Self {
+        self.kerning = kerning;
+        self
+    }
+
+    /// Pixel offset between `a` and a directly following `b`
+    pub fn kerning(&self, a: char, b: char) -> i32 {
+        self.kerning.get(&(a, b)).copied().unwrap_or(0)
+    }
+
+    /// Replaces characters the font has no cell for when laying out text, `'?'` by default.
+    /// If the font doesn't have `fallback` either, or it is `None`, those characters are skipped.
+    pub fn with_fallback(mut self, fallback: Option<char>) -> Self {
+        self.characters.set_fallback(fallback);
+        self
+    }
+
+    /// The cell of `c`, without falling back
     pub fn char_index(&self, c: char) -> Option<&u32> {
-        self.characters.get(&c)
+        self.characters.get(c)
+    }
+
+    /// The cell of `c`, or of the fallback character if the font doesn't have `c`
+    pub fn glyph_index(&self, c: char) -> Option<u32> {
+        self.characters.resolve(c)
     }
 
     pub fn index_width(&self, i: u32) -> u32 {
@@ -103,8 +223,158 @@ impl BitmapFont {
     pub fn index_str(&self, string: &str) -> Option<Vec<u32>> {
         let mut indices = Vec::with_capacity(string.len());
         for c in string.chars() {
-            indices.push(*self.characters.get(&c)?);
+            indices.push(*self.characters.get(c)?);
         }
         Some(indices)
     }
+
+    /// Places the glyphs of `text` like a text area with the same font size and spacing would draw them.
+    ///
+    /// `\n` starts a new line and tabs advance to the next multiple of [`BitmapFont::TAB_WIDTH`] spaces.
+    /// With a `wrap_width`, words that would extend past it move to the next line, and words longer than a whole
+    /// line are broken up. Whitespace is not drawn, so trailing whitespace never causes a wrap.
+    /// Characters the font doesn't have are replaced by the fallback character, or skipped without one.
+    pub fn layout(&self, text: &str, font_size: f32, spacing: f32, wrap_width: Option<f32>) -> Vec<Glyph> {
+        let size = font_size / self.char_size.y as f32;
+        let space = self.char_index(' ').map_or(self.char_size.x, |i| self.index_width(*i)) as f32 * size;
+        layout_glyphs(
+            text, font_size, spacing, wrap_width, space,
+            |c| self.glyph_index(c).map(|i| (i, self.index_width(i) as f32 * size)),
+            |a, b| self.kerning(a, b) as f32 * size
+        )
+    }
+
+    /// Width of a tab in spaces
+    pub const TAB_WIDTH: u32 = 4;
+}
+
+/// [`BitmapFont::layout`] with the font looked up through `glyph`, which returns the sprite index and advance of a character.
+fn layout_glyphs(text: &str, line_height: f32, spacing: f32, wrap_width: Option<f32>, space: f32, glyph: impl Fn(char) -> Option<(u32, f32)>, kerning: impl Fn(char, char) -> f32) -> Vec<Glyph> {
+    let tab = space * BitmapFont::TAB_WIDTH as f32;
+    let mut glyphs = vec![];
+    let mut y = 0.0;
+
+    for line in text.split('\n') {
+        let chars = line.chars().collect::<Vec<_>>();
+        let mut x = 0.0;
+        let mut i = 0;
+        while i < chars.len() {
+            match chars[i] {
+                '\t' if tab > 0.0 => x = ((x / tab).floor() + 1.0) * tab,
+                '\r' => (),
+                c if c.is_whitespace() => x += space,
+                _ => {
+                    let end = chars[i..].iter().position(|c| c.is_whitespace()).map_or(chars.len(), |len| i + len);
+                    let word = chars[i..end].iter().filter_map(|c| glyph(*c).map(|(index, advance)| (*c, index, advance))).collect::<Vec<_>>();
+                    let width = word.iter().zip(std::iter::once(None).chain(word.iter().map(|(c, ..)| Some(*c))))
+                        .map(|((c, _, advance), prev)| advance + spacing + prev.map_or(0.0, |prev| kerning(prev, *c)))
+                        .sum::<f32>() - spacing;
+                    let overflows = |x: f32, width: f32| wrap_width.is_some_and(|wrap| x > 0.0 && x + width > wrap);
+
+                    if overflows(x, width) {
+                        x = 0.0;
+                        y += line_height;
+                    }
+                    let mut prev = None;
+                    for (c, index, advance) in word {
+                        if let Some(prev) = prev {
+                            x += kerning(prev, c);
+                        }
+                        // only words longer than a whole line get here
+                        if overflows(x, advance) {
+                            x = 0.0;
+                            y += line_height;
+                        }
+                        glyphs.push(Glyph { c, index, position: Vector2::new(x, y) });
+                        x += advance + spacing;
+                        prev = Some(c);
+                    }
+                    i = end;
+                    continue;
+                }
+            }
+            i += 1;
+        }
+        y += line_height;
+    }
+    glyphs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// every character is one unit wide, `V` and `A` are kerned together
+    fn layout(text: &str, wrap_width: Option<f32>) -> Vec<Glyph> {
+        layout_glyphs(text, 2.0, 0.0, wrap_width, 1.0, |c| Some((c as u32, 1.0)), |a, b| if (a, b) == ('V', 'A') { -0.5 } else { 0.0 })
+    }
+
+    fn line_starts(glyphs: &[Glyph]) -> Vec<String> {
+        let mut lines: Vec<String> = vec![];
+        let mut y = None;
+        for glyph in glyphs {
+            if y != Some(glyph.position.y) {
+                lines.push(String::new());
+                y = Some(glyph.position.y);
+            }
+            lines.last_mut().unwrap().push(glyph.c);
+        }
+        lines
+    }
+
+    #[test]
+    fn long_text_wraps_at_word_boundaries() {
+        let glyphs = layout("the quick brown fox   ", Some(10.0));
+        assert_eq!(line_starts(&glyphs), ["thequick", "brownfox"]);
+        let b = glyphs.iter().find(|g| g.c == 'b').unwrap();
+        assert_eq!(b.position, Vector2::new(0.0, 2.0));
+        assert_eq!(glyphs.last().unwrap().position, Vector2::new(8.0, 2.0));
+
+        // without a wrap width only newlines break lines
+        assert_eq!(line_starts(&layout("the quick brown fox", None)), ["thequickbrownfox"]);
+        assert_eq!(line_starts(&layout("the\n\nfox", None)), ["the", "fox"]);
+        assert_eq!(layout("the\n\nfox", None)[3].position, Vector2::new(0.0, 4.0));
+
+        // words longer than a line are broken up
+        assert_eq!(line_starts(&layout("abcdefghijklmn", Some(5.0))), ["abcde", "fghij", "klmn"]);
+
+        assert_eq!(layout("a\tb", None)[1].position.x, 4.0);
+        assert_eq!(layout("abcde\tb", None)[5].position.x, 8.0);
+        assert_eq!(layout("VA", None)[1].position.x, 0.5);
+    }
+
+    #[test]
+    fn unicode_characters_resolve_to_cells_or_fallback() {
+        let font_data = BMPFontFile::deserialize_ron(r#"(
+            texture: "font.png",
+            monospaced: true,
+            char_size: (5, 10),
+            characters: { "c": 2, "a": 0, "f": 5, "?": 7, "ß": 9 },
+            codepoints: [(224, 233, 20)]
+        )"#).unwrap();
+        let map = font_data.char_map(30).unwrap();
+        assert!(font_data.char_map(29).is_err(), "the codepoint range needs 30 cells");
+        assert_eq!(map.get('a'), Some(&0));
+        assert_eq!(map.get('ß'), Some(&9));
+        assert_eq!(map.get('é'), Some(&29));
+        assert_eq!(map.get('ñ'), None);
+        assert_eq!(map.resolve('ñ'), Some(7));
+
+        let glyphs = layout_glyphs("café ñ✓", 1.0, 0.0, None, 1.0, |c| map.resolve(c).map(|i| (i, 1.0)), |_, _| 0.0);
+        assert_eq!(glyphs.iter().map(|g| g.index).collect::<Vec<_>>(), [2, 0, 5, 29, 7, 7]);
+        assert_eq!(glyphs[5].c, '✓');
+
+        let mut map = map;
+        map.set_fallback(None);
+        assert_eq!(map.resolve('ñ'), None);
+        map.set_fallback(Some('✓'));
+        assert_eq!(map.resolve('ñ'), None, "the fallback has to be in the font");
+
+        let invalid = BMPFontFile::deserialize_ron(r#"(texture: "", monospaced: true, char_size: (1, 1), characters: { "ab": 0 })"#).unwrap();
+        assert!(invalid.char_map(1).is_err());
+        let invalid = BMPFontFile::deserialize_ron(r#"(texture: "", monospaced: true, char_size: (1, 1), characters: { "a": 4 })"#).unwrap();
+        assert!(invalid.char_map(4).is_err());
+        let invalid = BMPFontFile::deserialize_ron(r#"(texture: "", monospaced: true, char_size: (1, 1), characters: {}, codepoints: [(66, 65, 0)])"#).unwrap();
+        assert!(invalid.char_map(4).is_err());
+    }
 }
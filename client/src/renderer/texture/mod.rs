@@ -3,10 +3,13 @@ pub use sprite_sheet::*;
 
 pub mod font;
 
+pub mod atlas;
+pub use atlas::*;
+
 use aeonetica_engine::{math::vector::Vector2, error::ErrorResult};
 use image::{io::Reader as ImageReader, DynamicImage};
 
-use aeonetica_engine::error::{IntoError, Error, ErrorValue, Fatality};
+use aeonetica_engine::error::{IntoError, Error, ErrorValue, Fatality, ResultExt};
 
 use super::{RenderID, glerror::GLError};
 
@@ -40,6 +43,58 @@ impl Format {
     }
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Filter {
+    Nearest              = gl::NEAREST                as isize,
+    Linear               = gl::LINEAR                 as isize,
+    NearestMipmapNearest = gl::NEAREST_MIPMAP_NEAREST as isize,
+    LinearMipmapNearest  = gl::LINEAR_MIPMAP_NEAREST  as isize,
+    NearestMipmapLinear  = gl::NEAREST_MIPMAP_LINEAR  as isize,
+    LinearMipmapLinear   = gl::LINEAR_MIPMAP_LINEAR   as isize
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Wrap {
+    Repeat         = gl::REPEAT          as isize,
+    MirroredRepeat = gl::MIRRORED_REPEAT as isize,
+    ClampToEdge    = gl::CLAMP_TO_EDGE   as isize,
+    ClampToBorder  = gl::CLAMP_TO_BORDER as isize
+}
+
+/// Sampling parameters of a loaded [`Texture`].
+/// Mipmaps are only generated if `mip_levels > 1`, in which case `min_filter` should be one of the `*Mipmap*` filters.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TextureConfig {
+    pub min_filter: Filter,
+    pub mag_filter: Filter,
+    pub wrap_s: Wrap,
+    pub wrap_t: Wrap,
+    pub mip_levels: u32
+}
+
+impl Default for TextureConfig {
+    fn default() -> Self {
+        Self {
+            min_filter: Filter::Linear,
+            mag_filter: Filter::Nearest,
+            wrap_s: Wrap::Repeat,
+            wrap_t: Wrap::Repeat,
+            mip_levels: 1
+        }
+    }
+}
+
+impl TextureConfig {
+    /// Pixel art friendly config, that still samples nicely when zoomed out
+    pub fn mipmapped(mip_levels: u32) -> Self {
+        Self {
+            min_filter: Filter::NearestMipmapLinear,
+            mip_levels,
+            ..Default::default()
+        }
+    }
+}
+
 pub enum TexCoordFormat {
     LeftRightTopBottom,
     RightLeftTopBottom,
@@ -52,7 +107,10 @@ pub enum TexCoordFormat {
 pub enum ImageError {
     Io(std::io::Error),
     Decode(String),
+    Encode(String),
     Unsupported(String),
+    /// a region outside of the texture or pixel data of the wrong size
+    InvalidRegion(String),
 }
 
 impl IntoError for ImageError {
@@ -70,7 +128,9 @@ impl std::fmt::Display for ImageError {
         match self {
             Self::Io(err) => f.write_str(format!("ImageError: IO error: {err}").as_str()),
             Self::Decode(err) => f.write_str(format!("ImageError: Decode error: {err}").as_str()),
+            Self::Encode(err) => f.write_str(format!("ImageError: Encode error: {err}").as_str()),
             Self::Unsupported(err) => f.write_str(format!("ImageError: Unsupported error: {err}").as_str()),
+            Self::InvalidRegion(err) => f.write_str(format!("ImageError: Invalid region: {err}").as_str()),
         }
     }
 }
@@ -96,26 +156,36 @@ pub struct Texture {
 
 impl Texture {
     pub fn from_bytes(bytes: &[u8]) -> ErrorResult<Self> {
+        Self::from_bytes_with_config(bytes, TextureConfig::default())
+    }
+
+    pub fn from_bytes_with_config(bytes: &[u8], config: TextureConfig) -> ErrorResult<Self> {
         let cursor = std::io::Cursor::new(bytes);
         let img = ImageReader::new(cursor)
             .with_guessed_format()?
             .decode().map_err(|e| ImageError::Decode(e.to_string()).into_error())?;
          //   .flipv();
-        Self::load(img)
+        Self::load(img, config)
     }
 
     pub fn from_file(img_path: &str) -> ErrorResult<Self> {
-        let img = ImageReader::open(img_path)?
-            .decode().map_err(|e| ImageError::Decode(e.to_string()).into_error())?;
+        Self::from_file_with_config(img_path, TextureConfig::default())
+    }
+
+    pub fn from_file_with_config(img_path: &str, config: TextureConfig) -> ErrorResult<Self> {
+        let img = ImageReader::open(img_path)
+            .map_err(|e| e.into_error())
+            .and_then(|reader| reader.decode().map_err(|e| ImageError::Decode(e.to_string()).into_error()))
+            .with_context(|| format!("loading texture {img_path}"))?;
           //  .flipv();
-        Self::load(img)
+        Self::load(img, config)
     }
 
     pub fn data_format(&self) -> gl::types::GLenum {
         self.data_format
     }
 
-    fn load(img: DynamicImage) -> ErrorResult<Self> {
+    fn load(img: DynamicImage, config: TextureConfig) -> ErrorResult<Self> {
         let mut t = Self {
             id: 0,
             size: (img.width(), img.height()).into(),
@@ -142,15 +212,19 @@ impl Texture {
                 err.add_info("error creating opengl texture");
                 return Err(err);
             }
-            gl::TextureStorage2D(t.id, 1, t.internal_format, t.size.x() as i32, t.size.y() as i32);
+            gl::TextureStorage2D(t.id, config.mip_levels.max(1) as i32, t.internal_format, t.size.x() as i32, t.size.y() as i32);
+
+            gl::TextureParameteri(t.id, gl::TEXTURE_MIN_FILTER, config.min_filter as i32);
+            gl::TextureParameteri(t.id, gl::TEXTURE_MAG_FILTER, config.mag_filter as i32);
 
-            gl::TextureParameteri(t.id, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
-            gl::TextureParameteri(t.id, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+            gl::TextureParameteri(t.id, gl::TEXTURE_WRAP_S, config.wrap_s as i32);
+            gl::TextureParameteri(t.id, gl::TEXTURE_WRAP_T, config.wrap_t as i32);
 
-            gl::TextureParameteri(t.id, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
-            gl::TextureParameteri(t.id, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
+            gl::TextureSubImage2D(t.id, 0, 0, 0, t.size.x() as i32, t.size.y() as i32, t.data_format, gl::UNSIGNED_BYTE, img.into_bytes().as_ptr() as *const _);
 
-            gl::TextureSubImage2D(t.id, 0, 0, 0, t.size.x() as i32, t.size.y() as i32, t.data_format, gl::UNSIGNED_BYTE, img.into_bytes().as_ptr() as *const _)
+            if config.mip_levels > 1 {
+                gl::GenerateTextureMipmap(t.id);
+            }
         }
 
         Ok(t)
@@ -197,6 +271,38 @@ impl Texture {
         }
     }
 
+    /// Replaces the `w` x `h` pixels at `x`, `y` with `data`, rows ordered top to bottom.
+    /// Fails if the region is not within the texture or `data` does not hold exactly its pixels in the texture's format,
+    /// without padding at the end of the rows.
+    pub fn sub_update(&self, x: u32, y: u32, w: u32, h: u32, data: &[u8]) -> ErrorResult<()> {
+        let bytes_per_pixel = match self.data_format {
+            gl::RGBA => 4,
+            gl::RGB => 3,
+            _ => return Err(ImageError::Unsupported(format!("partial updates of texture data format {}", self.data_format)).into_error())
+        };
+        validate_region(self.size, bytes_per_pixel, Vector2::new(x, y), Vector2::new(w, h), data.len())
+            .map_err(IntoError::into_error)?;
+        if w == 0 || h == 0 {
+            return Ok(())
+        }
+        unsafe {
+            // rows of `data` are tightly packed, RGB rows are not 4 byte aligned in general
+            let mut alignment = 0;
+            gl::GetIntegerv(gl::UNPACK_ALIGNMENT, &mut alignment);
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+            gl::TextureSubImage2D(
+                self.id,
+                0,
+                x as i32, y as i32,
+                w as i32, h as i32,
+                self.data_format, gl::UNSIGNED_BYTE,
+                data.as_ptr() as *const _
+            );
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, alignment);
+        }
+        Ok(())
+    }
+
     pub fn bind(&self, slot: u32) {
         unsafe { gl::BindTextureUnit(slot, self.id); }
     }
@@ -229,4 +335,38 @@ impl Drop for Texture {
     fn drop(&mut self) {
         self.delete();
     }
-}
\ No newline at end of file
+}
+
+/// Checks that the region at `offset` fits into a texture of `size` and that `data_len` bytes are exactly its pixels.
+fn validate_region(size: Vector2<u32>, bytes_per_pixel: u32, offset: Vector2<u32>, region: Vector2<u32>, data_len: usize) -> Result<(), ImageError> {
+    let fits = |offset: u32, len: u32, size: u32| offset.checked_add(len).is_some_and(|end| end <= size);
+    if !fits(offset.x, region.x, size.x) || !fits(offset.y, region.y, size.y) {
+        return Err(ImageError::InvalidRegion(format!("{}x{} pixels at {offset} exceed the texture size {}x{}", region.x, region.y, size.x, size.y)))
+    }
+    let expected = region.x as usize * region.y as usize * bytes_per_pixel as usize;
+    if data_len != expected {
+        return Err(ImageError::InvalidRegion(format!("expected {expected} bytes for {}x{} pixels, got {data_len}", region.x, region.y)))
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regions_must_fit_the_texture_and_data() {
+        let size = Vector2::new(64, 32);
+        assert!(validate_region(size, 4, Vector2::new(48, 16), Vector2::new(16, 16), 16 * 16 * 4).is_ok());
+        assert!(validate_region(size, 3, Vector2::new(0, 0), Vector2::new(64, 32), 64 * 32 * 3).is_ok());
+        assert!(validate_region(size, 4, Vector2::new(10, 10), Vector2::new(0, 0), 0).is_ok());
+        // rgb rows of odd widths are not padded to 4 bytes
+        assert!(validate_region(size, 3, Vector2::new(1, 1), Vector2::new(5, 3), 5 * 3 * 3).is_ok());
+        assert!(matches!(validate_region(size, 3, Vector2::new(1, 1), Vector2::new(5, 3), 16 * 3), Err(ImageError::InvalidRegion(_))));
+
+        assert!(matches!(validate_region(size, 4, Vector2::new(49, 0), Vector2::new(16, 1), 16 * 4), Err(ImageError::InvalidRegion(_))));
+        assert!(matches!(validate_region(size, 4, Vector2::new(0, 31), Vector2::new(1, 2), 2 * 4), Err(ImageError::InvalidRegion(_))));
+        assert!(matches!(validate_region(size, 4, Vector2::new(u32::MAX, 0), Vector2::new(2, 1), 2 * 4), Err(ImageError::InvalidRegion(_))));
+        assert!(matches!(validate_region(size, 4, Vector2::new(0, 0), Vector2::new(2, 2), 2 * 2 * 3), Err(ImageError::InvalidRegion(_))));
+    }
+}
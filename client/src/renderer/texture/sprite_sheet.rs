@@ -1,6 +1,6 @@
 use std::ops::Div;
 
-use aeonetica_engine::{math::vector::Vector2, error::{ErrorResult, IntoError}};
+use aeonetica_engine::{math::vector::Vector2, error::{ErrorResult, IntoError}, time::Time};
 
 use super::{Texture, TexCoordFormat, RenderID, ImageError};
 
@@ -24,6 +24,15 @@ impl Sprite {
         }
     }
 
+    /// Sprite covering the pixel rectangle from `min` to `max` of a texture of size `texture_size`,
+    /// with each edge moved `inset` texels inwards.
+    pub fn from_pixels(texture_id: RenderID, min: Vector2<u32>, max: Vector2<u32>, texture_size: Vector2<u32>, inset: f32) -> Self {
+        let total = texture_size.to_f32();
+        let min = (min.to_f32() + Vector2::new(inset, inset)) / total;
+        let max = (max.to_f32() - Vector2::new(inset, inset)) / total;
+        Self::new(texture_id, min.x(), max.x(), min.y(), max.y())
+    }
+
     pub fn texture(&self) -> RenderID {
         self.texture_id
     }
@@ -78,9 +87,12 @@ pub struct SpriteSheet {
     texture: Texture,
     sprite_size: Vector2<u32>,
     num_sprites: u32,
+    inset: f32
 }
 
 impl SpriteSheet {
+    pub const HALF_TEXEL: f32 = 0.5;
+
     pub fn from_texture(texture: Texture, sprite_size: Vector2<u32>) -> ErrorResult<Self> {
         if texture.size().x() % sprite_size.x() != 0 ||
             texture.size().y() % sprite_size.y() != 0 {
@@ -91,11 +103,25 @@ impl SpriteSheet {
             Ok(Self {
                 texture,
                 sprite_size,
-                num_sprites: num_sprites.x() * num_sprites.y()
+                num_sprites: num_sprites.x() * num_sprites.y(),
+                inset: 0.0
             })
         }
     }
 
+    /// Shrinks the UVs of all sprites by `inset` texels on every side, usually [`SpriteSheet::HALF_TEXEL`].
+    ///
+    /// Without an inset, filtering or rounding can sample up to half a texel past the sprite edge, which shows
+    /// as seams of the neighbouring sprite between tiles. Load the texture with [`Wrap::ClampToEdge`](super::Wrap::ClampToEdge)
+    /// as well, so sprites on the sheet border don't bleed into the opposite side.
+    /// The tradeoff is that half of the outermost texel row is cut off. With mipmaps, a texel of mip level `n`
+    /// covers `2^n` texels of the base level, so seams can reappear when zoomed out unless the inset is scaled
+    /// accordingly or the sheet leaves gaps between sprites.
+    pub fn with_inset(mut self, inset: f32) -> Self {
+        self.inset = inset;
+        self
+    }
+
     pub fn sprite_size(&self) -> &Vector2<u32> {
         &self.sprite_size
     }
@@ -113,27 +139,119 @@ impl SpriteSheet {
             return None;
         }
 
-        let total_x = self.texture.size().x();
-        let total_y = self.texture.size().y();
+        let columns = self.texture.size().x() / self.sprite_size.x();
+        let min = Vector2::new(idx % columns, idx / columns) * self.sprite_size;
+        Some(Sprite::from_pixels(self.texture.id(), min, min + self.sprite_size, *self.texture.size(), self.inset))
+    }
+}
+
+/// A sequence of sprites of a sprite sheet, each shown for a fixed duration.
+#[derive(Debug, Clone)]
+pub struct Animation {
+    frames: Vec<Sprite>,
+    frame_duration: f32,
+    looping: bool,
+    start_time: f32
+}
+
+impl Animation {
+    pub fn new(frames: Vec<Sprite>, frame_duration: f32, looping: bool) -> Self {
+        assert!(!frames.is_empty(), "animation needs at least one frame");
+        Self {
+            frames,
+            frame_duration,
+            looping,
+            start_time: 0.0
+        }
+    }
+
+    /// Creates an animation from the sprites at `indices` in `sheet`.
+    /// Returns `None` if any index is out of bounds or no index was given.
+    pub fn from_sprite_sheet(sheet: &SpriteSheet, indices: &[u32], frame_duration: f32, looping: bool) -> Option<Self> {
+        let frames = indices.iter().map(|i| sheet.get(*i)).collect::<Option<Vec<_>>>()?;
+        if frames.is_empty() {
+            return None;
+        }
+        Some(Self::new(frames, frame_duration, looping))
+    }
+
+    /// Restarts the animation from its first frame at the given time.
+    pub fn restart(&mut self, time: Time) {
+        self.start_time = time.time;
+    }
+
+    pub fn total_duration(&self) -> f32 {
+        self.frame_duration * self.frames.len() as f32
+    }
+
+    pub fn num_frames(&self) -> usize {
+        self.frames.len()
+    }
 
-        let x_idx = idx % (total_x / self.sprite_size.x());
-        let y_idx = idx / (total_x / self.sprite_size.x());
+    pub fn is_looping(&self) -> bool {
+        self.looping
+    }
 
-        let left_x = x_idx * self.sprite_size.x();
-        let right_x = (x_idx + 1) * self.sprite_size.x();
-        let top_y = y_idx * self.sprite_size.y();
-        let bottom_y = (y_idx + 1) * self.sprite_size.y();
+    /// Returns true once a one-shot animation has shown its last frame for the full duration.
+    /// Looping animations never finish.
+    pub fn is_finished(&self, time: Time) -> bool {
+        !self.looping && time.time - self.start_time >= self.total_duration()
+    }
 
-        let total_x = total_x as f32;
-        let total_y = total_y as f32;
+    /// Index of the frame shown at `time`. One-shot animations stay on their last frame once finished.
+    pub fn frame_index(&self, time: Time) -> usize {
+        let elapsed = (time.time - self.start_time).max(0.0);
+        let elapsed = if self.looping { elapsed % self.total_duration() } else { elapsed };
+        ((elapsed / self.frame_duration) as usize).min(self.frames.len() - 1)
+    }
 
-        Some(Sprite {
-            texture_id: self.texture.id(),
-            left: (left_x as f32 / total_x),
-            right: (right_x as f32 / total_x),
-            top: (top_y as f32 / total_y),
-            bottom: (bottom_y as f32 / total_y)
-        })
+    pub fn current_sprite(&self, time: Time) -> Sprite {
+        self.frames[self.frame_index(time)].clone()
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: f32) -> Time {
+        Time { time, ..Default::default() }
+    }
+
+    fn frames(n: u32) -> Vec<Sprite> {
+        (0..n).map(|i| Sprite::new(0, i as f32, i as f32 + 1.0, 0.0, 1.0)).collect()
+    }
+
+    #[test]
+    fn inset_shrinks_uvs_by_texels() {
+        let sprite = Sprite::from_pixels(0, Vector2::new(16, 0), Vector2::new(32, 16), Vector2::new(64, 32), 0.0);
+        assert_eq!((sprite.left(), sprite.right(), sprite.top(), sprite.bottom()), (0.25, 0.5, 0.0, 0.5));
+        let sprite = Sprite::from_pixels(0, Vector2::new(16, 0), Vector2::new(32, 16), Vector2::new(64, 32), SpriteSheet::HALF_TEXEL);
+        assert_eq!((sprite.left(), sprite.right(), sprite.top(), sprite.bottom()), (16.5 / 64.0, 31.5 / 64.0, 0.5 / 32.0, 15.5 / 32.0));
+    }
+
+    #[test]
+    fn looping_animation_wraps() {
+        let animation = Animation::new(frames(3), 0.5, true);
+        assert_eq!(animation.frame_index(at(0.0)), 0);
+        assert_eq!(animation.frame_index(at(0.49)), 0);
+        assert_eq!(animation.frame_index(at(0.5)), 1);
+        assert_eq!(animation.frame_index(at(1.0)), 2);
+        assert_eq!(animation.frame_index(at(1.5)), 0);
+        assert_eq!(animation.frame_index(at(2.25)), 1);
+        assert_eq!(animation.current_sprite(at(1.25)).left(), 2.0);
+        assert!(!animation.is_finished(at(100.0)));
+    }
+
+    #[test]
+    fn one_shot_animation_holds_last_frame() {
+        let mut animation = Animation::new(frames(3), 0.5, false);
+        animation.restart(at(10.0));
+        assert_eq!(animation.frame_index(at(9.0)), 0);
+        assert_eq!(animation.frame_index(at(10.5)), 1);
+        assert!(!animation.is_finished(at(11.49)));
+        assert_eq!(animation.frame_index(at(11.5)), 2);
+        assert_eq!(animation.frame_index(at(20.0)), 2);
+        assert!(animation.is_finished(at(11.5)));
+    }
+}
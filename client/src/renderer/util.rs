@@ -69,6 +69,14 @@ pub fn scissor(position: Vector2<i32>, size: Vector2<i32>) {
     }
 }
 
+/// Number of texture units usable by fragment shaders, at least 16.
+#[inline]
+pub fn max_texture_units() -> u32 {
+    let mut units = 0;
+    unsafe { gl::GetIntegerv(gl::MAX_TEXTURE_IMAGE_UNITS, &mut units) };
+    units as u32
+}
+
 pub enum Target<'a> {
     Raw,
     FrameBuffer(&'a FrameBuffer)
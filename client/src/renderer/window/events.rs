@@ -1,10 +1,20 @@
+use std::collections::HashSet;
+
 use aeonetica_engine::math::vector::Vector2;
 
 extern crate glfw;
 
 pub use glfw::Key as KeyCode;
+pub use glfw::{JoystickId as GamepadId, GamepadButton, GamepadAxis};
+
+/// Axis values with a smaller magnitude than this are reported as `0.0`.
+pub const GAMEPAD_AXIS_DEADZONE: f32 = 0.15;
 
-#[derive(Debug, Clone, Copy)]
+const GAMEPAD_COUNT: usize = 16;
+const GAMEPAD_BUTTON_COUNT: usize = 15;
+const GAMEPAD_AXIS_COUNT: usize = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum MouseButton {
     Left,
     Right,
@@ -27,12 +37,20 @@ impl From<glfw::MouseButton> for MouseButton {
 pub enum Event {
     KeyPressed(KeyCode),
     KeyReleased(KeyCode),
+    /// Text input, already translated by the keyboard layout. Follows the corresponding [`Event::KeyPressed`].
+    CharTyped(char),
     MouseButtonPressed(MouseButton),
     MouseButtonReleased(MouseButton),
     MouseScrolled(Vector2<f32>),
     MouseMoved(Vector2<f32>),
     WindowClose(),
     WindowResize(Vector2<i32>),
+    GamepadConnected(GamepadId),
+    GamepadDisconnected(GamepadId),
+    /// `true` if the button was pressed, `false` if it was released
+    GamepadButton(GamepadId, GamepadButton, bool),
+    /// Deadzone-corrected axis value. Sticks range from `-1.0` to `1.0`, triggers from `0.0` to `1.0`.
+    GamepadAxis(GamepadId, GamepadAxis, f32),
 
     Unknown()
 }
@@ -47,6 +65,7 @@ impl Event {
                 glfw::Action::Press => Self::KeyPressed(key),
                 _ => Self::Unknown()
             }
+            glfw::WindowEvent::Char(c) => Self::CharTyped(c),
             glfw::WindowEvent::MouseButton(button, action, _) => match action {
                 glfw::Action::Press => Self::MouseButtonPressed(button.into()),
                 glfw::Action::Release => Self::MouseButtonReleased(button.into()),
@@ -57,4 +76,159 @@ impl Event {
             _ => Self::Unknown()
         }
     }
-}
\ No newline at end of file
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct GamepadSnapshot {
+    buttons: [bool; GAMEPAD_BUTTON_COUNT],
+    axes: [f32; GAMEPAD_AXIS_COUNT]
+}
+
+/// Polls the state of all connected gamepads, turning changes since the last poll into events.
+pub(super) struct GamepadPoller {
+    gamepads: [Option<GamepadSnapshot>; GAMEPAD_COUNT]
+}
+
+impl GamepadPoller {
+    pub(super) fn new() -> Self {
+        Self {
+            gamepads: [None; GAMEPAD_COUNT]
+        }
+    }
+
+    pub(super) fn poll(&mut self, glfw: &glfw::Glfw) -> Vec<Event> {
+        let mut events = vec![];
+        for (i, previous) in self.gamepads.iter_mut().enumerate() {
+            let Some(id) = GamepadId::from_i32(i as i32) else { continue };
+            let joystick = glfw.get_joystick(id);
+            let state = joystick.get_gamepad_state().filter(|_| joystick.is_present() && joystick.is_gamepad());
+
+            let Some(state) = state else {
+                if previous.take().is_some() {
+                    events.push(Event::GamepadDisconnected(id));
+                }
+                continue
+            };
+
+            let current = GamepadSnapshot {
+                buttons: std::array::from_fn(|b| GamepadButton::from_i32(b as i32)
+                    .is_some_and(|button| state.get_button_state(button) == glfw::Action::Press)),
+                axes: std::array::from_fn(|a| GamepadAxis::from_i32(a as i32)
+                    .map(|axis| normalize_axis(axis, state.get_axis(axis)))
+                    .unwrap_or(0.0))
+            };
+
+            let last = previous.unwrap_or_else(|| {
+                events.push(Event::GamepadConnected(id));
+                GamepadSnapshot { buttons: [false; GAMEPAD_BUTTON_COUNT], axes: [0.0; GAMEPAD_AXIS_COUNT] }
+            });
+
+            for (b, (&now, &before)) in current.buttons.iter().zip(last.buttons.iter()).enumerate() {
+                if now != before && let Some(button) = GamepadButton::from_i32(b as i32) {
+                    events.push(Event::GamepadButton(id, button, now));
+                }
+            }
+            for (a, (&now, &before)) in current.axes.iter().zip(last.axes.iter()).enumerate() {
+                if now != before && let Some(axis) = GamepadAxis::from_i32(a as i32) {
+                    events.push(Event::GamepadAxis(id, axis, now));
+                }
+            }
+            *previous = Some(current);
+        }
+        events
+    }
+}
+
+fn normalize_axis(axis: GamepadAxis, value: f32) -> f32 {
+    let value = match axis {
+        // triggers rest at -1.0
+        GamepadAxis::AxisLeftTrigger | GamepadAxis::AxisRightTrigger => (value + 1.0) / 2.0,
+        _ => value
+    };
+    apply_deadzone(value)
+}
+
+fn apply_deadzone(value: f32) -> f32 {
+    if value.abs() < GAMEPAD_AXIS_DEADZONE {
+        0.0
+    } else {
+        value.signum() * (value.abs() - GAMEPAD_AXIS_DEADZONE) / (1.0 - GAMEPAD_AXIS_DEADZONE)
+    }
+}
+
+/// Snapshot of the currently held keys and mouse buttons, kept up to date by the window on every event poll.
+/// Available to layers and handles as a store via `store.get_store::<InputState>()`.
+#[derive(Debug, Default)]
+pub struct InputState {
+    keys_down: HashSet<KeyCode>,
+    mouse_buttons_down: HashSet<MouseButton>,
+    mouse_position: Vector2<f32>,
+    viewport_size: Vector2<u32>
+}
+
+impl InputState {
+    pub(crate) fn apply(&mut self, event: &Event) {
+        match event {
+            Event::KeyPressed(key) => { self.keys_down.insert(*key); }
+            Event::KeyReleased(key) => { self.keys_down.remove(key); }
+            Event::MouseButtonPressed(button) => { self.mouse_buttons_down.insert(*button); }
+            Event::MouseButtonReleased(button) => { self.mouse_buttons_down.remove(button); }
+            Event::MouseMoved(pos) => self.mouse_position = *pos,
+            _ => ()
+        }
+    }
+
+    pub fn is_key_down(&self, key: KeyCode) -> bool {
+        self.keys_down.contains(&key)
+    }
+
+    pub fn mouse_button_down(&self, button: MouseButton) -> bool {
+        self.mouse_buttons_down.contains(&button)
+    }
+
+    /// Mouse position in framebuffer pixels, origin at the top left.
+    pub fn mouse_position(&self) -> Vector2<f32> {
+        self.mouse_position
+    }
+
+    /// Size of the framebuffer the mouse position is in, for [`Camera::screen_to_world`](aeonetica_engine::math::camera::Camera::screen_to_world).
+    pub fn viewport_size(&self) -> Vector2<u32> {
+        self.viewport_size
+    }
+
+    pub(crate) fn set_viewport_size(&mut self, viewport_size: Vector2<u32>) {
+        self.viewport_size = viewport_size;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn input_state_tracks_held_keys() {
+        let mut input = InputState::default();
+        input.apply(&Event::KeyPressed(KeyCode::W));
+        input.apply(&Event::MouseButtonPressed(MouseButton::Left));
+        input.apply(&Event::MouseMoved(Vector2::new(3.0, 4.0)));
+        assert!(input.is_key_down(KeyCode::W));
+        assert!(!input.is_key_down(KeyCode::S));
+        assert!(input.mouse_button_down(MouseButton::Left));
+        assert_eq!(input.mouse_position(), Vector2::new(3.0, 4.0));
+
+        input.apply(&Event::KeyReleased(KeyCode::W));
+        input.apply(&Event::MouseButtonReleased(MouseButton::Left));
+        assert!(!input.is_key_down(KeyCode::W));
+        assert!(!input.mouse_button_down(MouseButton::Left));
+    }
+
+    #[test]
+    fn gamepad_deadzone() {
+        assert_eq!(apply_deadzone(0.1), 0.0);
+        assert_eq!(apply_deadzone(-0.1), 0.0);
+        assert_eq!(apply_deadzone(1.0), 1.0);
+        assert_eq!(apply_deadzone(-1.0), -1.0);
+        assert!(apply_deadzone(0.5) > 0.0 && apply_deadzone(0.5) < 0.5);
+        assert_eq!(normalize_axis(GamepadAxis::AxisLeftTrigger, -1.0), 0.0);
+    }
+}
@@ -0,0 +1,58 @@
+use std::time::{Duration, Instant};
+
+/// Software frame cap, sleeping away the rest of a frame that finished early.
+pub(crate) struct FrameLimiter {
+    frame_time: Option<Duration>,
+    last_frame: Instant
+}
+
+impl FrameLimiter {
+    /// `None` or `Some(0)` disables the cap.
+    pub(crate) fn new(max_fps: Option<u32>) -> Self {
+        let mut limiter = Self {
+            frame_time: None,
+            last_frame: Instant::now()
+        };
+        limiter.set_max_fps(max_fps);
+        limiter
+    }
+
+    pub(crate) fn set_max_fps(&mut self, max_fps: Option<u32>) {
+        self.frame_time = max_fps.filter(|fps| *fps > 0).map(|fps| Duration::from_secs(1) / fps);
+    }
+
+    /// Blocks until at least one frame time has passed since the previous call returned.
+    pub(crate) fn wait(&mut self) {
+        if let Some(frame_time) = self.frame_time {
+            let elapsed = self.last_frame.elapsed();
+            if elapsed < frame_time {
+                std::thread::sleep(frame_time - elapsed);
+            }
+        }
+        self.last_frame = Instant::now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_cap_limits_fps() {
+        let mut limiter = FrameLimiter::new(Some(100));
+        limiter.wait();
+        let start = Instant::now();
+        for _ in 0..10 {
+            limiter.wait();
+        }
+        assert!(start.elapsed() >= Duration::from_millis(100));
+
+        limiter.set_max_fps(Some(0));
+        assert_eq!(limiter.frame_time, None);
+        let start = Instant::now();
+        for _ in 0..10 {
+            limiter.wait();
+        }
+        assert!(start.elapsed() < Duration::from_millis(50));
+    }
+}
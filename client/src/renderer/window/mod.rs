@@ -1,16 +1,18 @@
 pub mod events;
+mod frame_limiter;
 
 use core::f32;
 use std::{sync::mpsc::Receiver, collections::HashMap};
 
-use aeonetica_engine::{log, math::vector::*, error::{*, builtin::IOError}, time::Time};
-use crate::{renderer::{context::RenderContext, buffer::{framebuffer::Attachment, renderbuffer::RenderBuffer}, util::*, shader::UniformStr, texture::{Texture, Format}}, uniform_str, client_runtime::ClientRuntime, data_store::DataStore};
+use aeonetica_engine::{log, math::vector::*, error::{*, builtin::IOError}, time::Time, util::id_map::IdMap};
+use crate::{renderer::{context::RenderContext, buffer::{framebuffer::Attachment, renderbuffer::RenderBuffer}, util::*, shader::UniformStr, texture::{Texture, Format}}, uniform_str, client_runtime::ClientHandleBox, data_store::DataStore, config::ClientConfig};
 use glfw::{*, Window as GlfwWindow, Context as GlfwContext};
 use image::{io::Reader as ImageReader, DynamicImage, EncodableLayout};
 
-use self::events::Event;
+use self::events::{Event, InputState, GamepadPoller, KeyCode};
+use self::frame_limiter::FrameLimiter;
 
-use super::{buffer::framebuffer::FrameBuffer, shader, texture::ImageError};
+use super::{buffer::framebuffer::FrameBuffer, shader, texture::ImageError, screenshot::Screenshot};
 
 pub struct OpenGlContextProvider(HashMap<&'static str, GLProc>);
 
@@ -49,7 +51,7 @@ struct Viewport {
 
 impl Viewport {
     fn calculate(window: &Window) -> Self {
-        let size = window.glfw_window.get_size().into_vector();
+        let size = window.glfw_window.get_framebuffer_size().into_vector();
         let aspect_ratio = window.target_aspect_ratio();
 
         let mut aspect = Vector2::new(size.x(), (size.x() as f32 / aspect_ratio) as i32);
@@ -68,8 +70,7 @@ impl Viewport {
         viewport(self.offset, self.size);
     }
 
-    fn translate(&self, input: Vector2<f32>) -> Vector2<f32> {
-        let fb_size = Window::FRAMEBUFFER_SIZE.to_f32();
+    fn translate(&self, input: Vector2<f32>, fb_size: Vector2<f32>) -> Vector2<f32> {
         ((input - self.offset.to_f32()) / self.size.to_f32() * fb_size).clamp(Vector2::default(), fb_size)
     }
 }
@@ -89,17 +90,20 @@ pub(crate) struct Window {
 
     framebuffer: FrameBuffer,
     framebuffer_viewport: Viewport,
+    gamepads: GamepadPoller,
+    frame_limiter: FrameLimiter,
+    minimized: bool,
 
     default_post_processing_shader: shader::Program,
 }
 
 impl Window {
-    const DEFAULT_WINDOW_WIDTH: u32 = 1280;
-    const DEFAULT_WINDOW_HEIGHT: u32 = 720;
     const DEFAULT_WINDOW_TITLE: &'static str = "Aeonetica Game Engine";
-    pub(super) const FRAMEBUFFER_SIZE: Vector2<u32> = Vector2 { x: 1920, y: 1080 };
+    const SCREENSHOT_DIR: &'static str = "screenshots";
+    /// how long to sleep per frame while minimized instead of rendering
+    const MINIMIZED_FRAME_TIME: std::time::Duration = std::time::Duration::from_millis(16);
 
-    pub(crate) fn new(full_screen: bool) -> ErrorResult<Self> {
+    pub(crate) fn new(config: &ClientConfig) -> ErrorResult<Self> {
         let mut glfw = glfw::init(glfw::FAIL_ON_ERRORS).expect("error creating window");
         
         glfw.window_hint(WindowHint::ContextVersion(4, 5));
@@ -108,10 +112,10 @@ impl Window {
 
         let (mut window, events) = glfw.with_primary_monitor(|glfw, monitor| {
             glfw.create_window(
-                Self::DEFAULT_WINDOW_WIDTH,
-                Self::DEFAULT_WINDOW_HEIGHT,
+                config.window_width,
+                config.window_height,
                 Self::DEFAULT_WINDOW_TITLE,
-                if full_screen {
+                if config.fullscreen {
                     monitor.map_or(WindowMode::Windowed, WindowMode::FullScreen)
                 } else {
                     WindowMode::Windowed
@@ -126,7 +130,6 @@ impl Window {
         let mut context_provider = OpenGlContextProvider::new();
 
         gl::load_with(|s| context_provider.insert(s, glfw.get_proc_address_raw(s)));
-        glfw.set_swap_interval(if use_vsync() { glfw::SwapInterval::Adaptive } else { glfw::SwapInterval::None });
         window.set_all_polling(true);
 
         log!(r#"
@@ -138,10 +141,8 @@ impl Window {
         );
 
         let default_post_processing_shader = shader::Program::from_source(include_str!("../../../assets/default-shader.glsl"))?;
-        let framebuffer = FrameBuffer::new([
-            Attachment::Color(Texture::create(Self::FRAMEBUFFER_SIZE, Format::RgbaF16)),
-            Attachment::DepthStencil(RenderBuffer::new(Self::FRAMEBUFFER_SIZE)?)
-        ], true)?;
+        let (width, height) = window.get_framebuffer_size();
+        let framebuffer = Self::create_framebuffer(Vector2::new(width.max(1) as u32, height.max(1) as u32))?;
 
         enable_blend_mode(true);
         blend_mode(BlendMode::One);
@@ -153,35 +154,91 @@ impl Window {
             framebuffer,
             default_post_processing_shader,
             context_provider,
-            framebuffer_viewport: Viewport::default()
+            framebuffer_viewport: Viewport::default(),
+            gamepads: GamepadPoller::new(),
+            frame_limiter: FrameLimiter::new(config.frame_cap),
+            minimized: false
         };
 
         window.framebuffer_viewport = Viewport::calculate(&window);
+        window.set_vsync(use_vsync().unwrap_or(config.vsync));
 
         Ok(window)
     }
 
-    pub(crate) fn poll_events(&mut self, client: &mut ClientRuntime, context: &mut RenderContext, store: &mut DataStore) {
+    fn create_framebuffer(size: Vector2<u32>) -> ErrorResult<FrameBuffer> {
+        FrameBuffer::new([
+            Attachment::Color(Texture::create(size, Format::RgbaF16)),
+            Attachment::DepthStencil(RenderBuffer::new(size)?)
+        ], true)
+    }
+
+    /// Size of the framebuffer everything is rendered to, matching the window's framebuffer size.
+    pub(crate) fn size(&self) -> Vector2<u32> {
+        self.framebuffer.size().unwrap()
+    }
+
+    /// Recreates the framebuffer at the new size and refits all layer cameras.
+    /// A size of zero means the window got minimized, which pauses rendering until it is restored.
+    fn resize(&mut self, size: Vector2<i32>, context: &mut RenderContext) -> ErrorResult<()> {
+        self.minimized = size.x() <= 0 || size.y() <= 0;
+        if self.minimized {
+            return Ok(())
+        }
+        let size = size.map(|i| i as u32);
+        if self.size() != size {
+            let mut old = std::mem::replace(&mut self.framebuffer, Self::create_framebuffer(size)?);
+            old.delete();
+        }
+        self.framebuffer_viewport = Viewport::calculate(self);
+        context.resize(size);
+        Ok(())
+    }
+
+    /// Saves the last rendered frame to [`Self::SCREENSHOT_DIR`].
+    fn save_screenshot(&self) {
+        let path = format!("{}/{}.png", Self::SCREENSHOT_DIR, aeonetica_engine::chrono::Local::now().format("%Y-%m-%d_%H-%M-%S"));
+        let screenshot = Screenshot::from_framebuffer(&self.framebuffer, 0, Vector2::default(), self.size());
+        let result = std::fs::create_dir_all(Self::SCREENSHOT_DIR)
+            .map_err(|e| e.into_error())
+            .and_then(|_| screenshot.save_png(&path));
+        match result {
+            Ok(()) => log!("saved screenshot to {path}"),
+            Err(err) => log!(ERROR, "could not save screenshot: {err}")
+        }
+    }
+
+    pub(crate) fn poll_events(&mut self, handles: &mut IdMap<ClientHandleBox>, context: &mut RenderContext, store: &mut DataStore) {
         self.glfw_handle.poll_events();
-        for (_, event) in flush_messages(&self.event_receiver) {
+        store.mut_or_default::<InputState>().set_viewport_size(self.size());
+        // collected first, resizing needs the window mutably
+        let events = flush_messages(&self.event_receiver).collect::<Vec<_>>();
+        for (_, event) in events {
             let mut event = Event::from_glfw(event);
             let mut handled = false;
 
             match &mut event {
                 Event::WindowClose() => self.glfw_window.set_should_close(true),
-                Event::WindowResize(_) => {
-                    self.framebuffer_viewport = Viewport::calculate(self);
+                Event::WindowResize(size) => if let Err(err) = self.resize(*size, context) {
+                    log!(ERROR, "could not resize window framebuffer: {err}");
+                }
+                Event::MouseMoved(pos) => *pos = self.framebuffer_viewport.translate(*pos, self.size().to_f32()),
+                Event::KeyPressed(KeyCode::F12) => {
+                    self.save_screenshot();
                     handled = true
                 }
-                Event::MouseMoved(pos) => *pos = self.framebuffer_viewport.translate(*pos),
                 Event::Unknown() => handled = true,
                 _ => ()
             }
+            store.mut_or_default::<InputState>().apply(&event);
 
             if !handled {
-                context.on_event(client, event, store);
+                context.on_event(handles, event, store);
             }
         }
+        for event in self.gamepads.poll(&self.glfw_handle) {
+            context.on_event(handles, event, store);
+        }
     }
 
     fn target_aspect_ratio(&self) -> f32 {
@@ -189,7 +246,12 @@ impl Window {
         size.x() as f32 / size.y() as f32
     }
 
-    pub(crate) fn on_render(&mut self, context: &mut RenderContext, client: &mut ClientRuntime, store: &mut DataStore, time: Time) {
+    pub(crate) fn on_render(&mut self, context: &mut RenderContext, handles: &mut IdMap<ClientHandleBox>, store: &mut DataStore, time: Time) {
+        if self.minimized {
+            std::thread::sleep(Self::MINIMIZED_FRAME_TIME);
+            return
+        }
+
         // main frame rendering
         self.framebuffer.bind();
         self.framebuffer.clear([0.0, 0.0, 0.0, 1.0]);
@@ -197,7 +259,7 @@ impl Window {
         viewport(Vector2::default(), self.framebuffer.size().unwrap().map(|i| i as i32));
         enable_blend_mode(true);
 
-        context.on_render(client, &Target::FrameBuffer(&self.framebuffer), store, time);
+        context.on_render(handles, &Target::FrameBuffer(&self.framebuffer), store, time);
 
         self.framebuffer.unbind();
         
@@ -227,6 +289,11 @@ impl Window {
         self.framebuffer.render([(0, &FRAME_UNIFORM_NAME)], &Target::Raw, post_processing_shader);
 
         self.glfw_window.swap_buffers();
+        self.frame_limiter.wait();
+    }
+
+    pub(crate) fn set_vsync(&mut self, vsync: bool) {
+        self.glfw_handle.set_swap_interval(if vsync { glfw::SwapInterval::Adaptive } else { glfw::SwapInterval::None });
     }
 
     pub(crate) fn should_close(&self) -> bool {
@@ -278,9 +345,7 @@ fn load_window_icons() -> ErrorResult<Vec<glfw::PixelImage>> {
 }
 
 const VSYNC_ENVIRONMENT_VAR: &'static str = "AEONETICA_VSYNC";
-fn use_vsync() -> bool {
-    match std::env::var(VSYNC_ENVIRONMENT_VAR) {
-        Ok(value) => matches!(value.to_uppercase().as_str(), "1" | "TRUE"),
-        _ => false
-    }
+fn use_vsync() -> Option<bool> {
+    std::env::var(VSYNC_ENVIRONMENT_VAR).ok()
+        .map(|value| matches!(value.to_uppercase().as_str(), "1" | "TRUE"))
 }
\ No newline at end of file
@@ -1,6 +1,7 @@
 use std::{
     collections::HashMap,
     hash::Hash,
+    ops::RangeBounds,
 };
 
 pub trait ExtractComparable<C> {
@@ -83,6 +84,12 @@ where
         })
     }
 
+    /// Removes all entries, returning them in arbitrary order.
+    pub fn drain(&mut self) -> std::collections::hash_map::Drain<'_, K, V> {
+        self.descending_pairs.clear();
+        self.map.drain()
+    }
+
     pub fn get(&self, k: &K) -> Option<&V> {
         self.map.get(k)
     }
@@ -128,6 +135,21 @@ where
         }
     }
 
+    /// Removes all entries for which `f` returns `false`, keeping the order of the others.
+    /// Use this instead of removing entries while iterating, which the borrow of [`OrderedMap::iter`] rules out.
+    /// Values can't be changed here, as that could break the order; use [`OrderedMap::get_mut`] for that.
+    pub fn retain(&mut self, mut f: impl FnMut(&K, &V) -> bool) {
+        let map = &mut self.map;
+        self.descending_pairs.retain(|(k, _)| {
+            let keep = map.get(k).map(|v| f(k, v)).unwrap_or(false);
+            if !keep {
+                map.remove(k);
+            }
+            keep
+        });
+    }
+
+    /// Iterates over all entries in descending order of their comparable.
     pub fn iter(&self) -> Iter<K, V, C> {
         Iter {
             map: &self.map,
@@ -137,6 +159,17 @@ where
     }
 }
 
+impl<K: Eq + Hash, V, C: PartialOrd> OrderedMap<K, V, C> {
+    /// Like [`OrderedMap::iter`], but only yields entries whose comparable lies within `range`,
+    /// e.g. all batches of a band of z-indices.
+    pub fn range<R: RangeBounds<C>>(&self, range: R) -> impl DoubleEndedIterator<Item = (&K, &V)> {
+        let map = &self.map;
+        self.descending_pairs.iter()
+            .filter(move |(_, c)| range.contains(c))
+            .filter_map(move |(k, _)| map.get_key_value(k))
+    }
+}
+
 pub struct Iter<'a, K, V, C> {
     map: &'a HashMap<K, V>,
     descending_pairs: &'a Vec<(K, C)>,
@@ -166,4 +199,44 @@ impl<'a, K: Eq + Hash, V, C> DoubleEndedIterator for Iter<'a, K, V, C> {
         self.index += 1;
         self.map.get_key_value(&self.descending_pairs[total - self.index].0)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Layer(u8);
+
+    impl ExtractComparable<u8> for Layer {
+        fn extract_comparable(&self) -> u8 {
+            self.0
+        }
+    }
+
+    fn layers() -> OrderedMap<u32, Layer, u8> {
+        let mut map = OrderedMap::new();
+        for (k, z) in [(1, 5), (2, 0), (3, 10), (4, 7), (5, 3)] {
+            map.insert(k, Layer(z));
+        }
+        map
+    }
+
+    #[test]
+    fn range_by_comparable() {
+        let map = layers();
+        assert_eq!(map.range(3..=7).map(|(k, _)| *k).collect::<Vec<_>>(), [4, 1, 5]);
+        assert_eq!(map.range(..3).map(|(k, _)| *k).collect::<Vec<_>>(), [2]);
+        assert_eq!(map.range(8..).rev().map(|(k, _)| *k).collect::<Vec<_>>(), [3]);
+    }
+
+    #[test]
+    fn retain_keeps_order() {
+        let mut map = layers();
+        map.retain(|k, v| k % 2 == 1 && v.0 > 0);
+        assert_eq!(map.len(), 3);
+        assert_eq!(map.iter().map(|(k, _)| *k).collect::<Vec<_>>(), [3, 1, 5]);
+        assert_eq!(map.get(&1), Some(&Layer(5)));
+        assert!(map.get(&2).is_none());
+    }
+}
@@ -94,6 +94,9 @@ impl Display for Error {
         write!(f, "{}", self.fatality.str().color(color))?;
         write!(f, "{}", ": ".color(color))?;
         write!(f, "{}", self.value.to_string().color(color))?;
+        for info in &self.additional {
+            write!(f, "\n  while {info}")?
+        }
 
         if let Some(trace) = &self.trace {
             write!(f, "\nin: {}", trace)?
@@ -111,6 +114,31 @@ impl<T: IntoError> From<T> for Box<Error> {
 
 pub type ErrorResult<T> = Result<T, Box<Error>>;
 
+/// Adds context to an error on its way up, shown below the error when it is displayed.
+/// Works on [`ErrorResult`]s as well as results of anything that implements [`IntoError`]:
+/// ```ignore
+/// let level = fs::read_to_string(&path).with_context(|| format!("loading {}", path.display()))?;
+/// ```
+pub trait ResultExt<T> {
+    fn with_context<S: ToString>(self, context: impl FnOnce() -> S) -> ErrorResult<T>;
+
+    fn context(self, context: impl ToString) -> ErrorResult<T>;
+}
+
+impl<T, E: Into<Box<Error>>> ResultExt<T> for Result<T, E> {
+    fn with_context<S: ToString>(self, context: impl FnOnce() -> S) -> ErrorResult<T> {
+        self.map_err(|e| {
+            let mut e = e.into();
+            e.add_info(context());
+            e
+        })
+    }
+
+    fn context(self, context: impl ToString) -> ErrorResult<T> {
+        self.with_context(|| context.to_string())
+    }
+}
+
 pub mod builtin {
     use super::*;
 
@@ -175,4 +203,24 @@ impl<T> ExpectLog for ErrorResult<T> {
             Ok(val) => val
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::builtin::DataError;
+
+    #[test]
+    fn context_is_displayed_in_order() {
+        let result: Result<(), std::io::Error> = Err(std::io::Error::new(std::io::ErrorKind::NotFound, "no such file"));
+        let error = result.with_context(|| format!("reading {}", "level.ron"))
+            .context("loading the world")
+            .unwrap_err();
+        let text = error.to_string();
+        let (read, load) = (text.find("while reading level.ron").unwrap(), text.find("while loading the world").unwrap());
+        assert!(text.contains("no such file") && read < load, "{text}");
+
+        let error: ErrorResult<()> = Err(Error::new(DataError("bad chunk".to_string()), Fatality::WARN, false));
+        assert!(error.context("chunk 1 2").unwrap_err().to_string().ends_with("\n  while chunk 1 2"));
+    }
+}
@@ -37,6 +37,7 @@ pub mod util;
 pub mod collections;
 pub mod math;
 pub mod time;
+pub mod logging;
 
 pub use enable_ansi_support;
 
@@ -101,19 +102,6 @@ pub const ENGINE_VERSION: &str = env!("CARGO_PKG_VERSION");
 pub const MAX_CLIENT_TIMEOUT: u128 = 10000; // 10s
 pub static MOD_TARGET: &str = const_format::concatcp!(std::env::consts::ARCH, "-", std::env::consts::FAMILY);
 
-#[macro_export]
-macro_rules! log_format {
-    ($color:ident, $level:literal, $($arg:tt)*) => {
-        format!(
-            "\x1b[38;5;245m{}[{}@{}:{}]: {}",
-            $crate::chrono::Local::now().format("[%H:%M:%S]"),
-            env!("CARGO_PKG_NAME"),
-            file!(), line!(),
-            $crate::colored::Colorize::$color(format!($($arg)*).as_str())
-        )
-    }
-}
-
 lazy_static! {
     static ref PACK_LOG_COUNTER: Mutex<u32> = Mutex::new(0);
     static ref PACK_LOG_HASH: Mutex<u64> = Mutex::new(0);
@@ -149,29 +137,41 @@ pub fn stop_pack_log() {
     }
 }
 
+#[macro_export]
+macro_rules! log_record {
+    ($level:ident, $($arg:tt)*) => {
+        if $crate::logging::log_enabled($crate::logging::LogLevel::$level) {
+            $crate::logging::write_log($crate::logging::LogRecord {
+                level: $crate::logging::LogLevel::$level,
+                package: env!("CARGO_PKG_NAME"),
+                file: file!(),
+                line: line!(),
+                message: format_args!($($arg)*)
+            })
+        }
+    }
+}
+
+/// Logs through the sink set with [`logging::set_log_sink`], filtered by [`logging::set_log_level`].
 #[macro_export]
 macro_rules! log {
     () => {
         println!()
     };
     (PACK, $($args:tt)*) => {{
-        $crate::pack_log(format!("{}:{}", file!(), line!()), $crate::log_format!(white, "PACK", $($args)*))
+        $crate::log_record!(Pack, $($args)*)
     }};
     (DEBUG, $($args:tt)*) => {{
-        $crate::stop_pack_log();
-        println!("{}", $crate::log_format!(cyan, "DEBUG", $($args)*))
+        $crate::log_record!(Debug, $($args)*)
     }};
     (WARN, $($args:tt)*) => {{
-        $crate::stop_pack_log();
-        println!("{}", $crate::log_format!(bright_yellow, "LOG", $($args)*))
+        $crate::log_record!(Warn, $($args)*)
     }};
     (ERROR, $($args:tt)*) => {{
-        $crate::stop_pack_log();
-        println!("{}", $crate::log_format!(red, "ERROR", $($args)*))
+        $crate::log_record!(Error, $($args)*)
     }};
     ($($args:tt)*) => {{
-        $crate::stop_pack_log();
-        println!("{}", $crate::log_format!(white, "LOG", $($args)*))
+        $crate::log_record!(Info, $($args)*)
     }};
 }
 
@@ -0,0 +1,198 @@
+use std::fmt::Arguments;
+use std::io::Write;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use lazy_static::lazy_static;
+
+/// Severity of a log message, ordered from least to most important.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(u8)]
+pub enum LogLevel {
+    /// Repetitive messages, consecutive ones from the same line get collapsed into a counter.
+    Pack,
+    Debug,
+    Info,
+    Warn,
+    Error
+}
+
+impl LogLevel {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Pack => "PACK",
+            Self::Debug => "DEBUG",
+            Self::Info => "LOG",
+            Self::Warn => "WARN",
+            Self::Error => "ERROR"
+        }
+    }
+
+    fn from_u8(level: u8) -> Self {
+        match level {
+            0 => Self::Pack,
+            1 => Self::Debug,
+            2 => Self::Info,
+            3 => Self::Warn,
+            _ => Self::Error
+        }
+    }
+}
+
+/// A single message passed to the [`LogSink`].
+pub struct LogRecord<'a> {
+    pub level: LogLevel,
+    /// name of the crate the message was logged from
+    pub package: &'static str,
+    pub file: &'static str,
+    pub line: u32,
+    pub message: Arguments<'a>
+}
+
+/// Receives every log message at or above the current [`log_level`].
+/// Register one with [`set_log_sink`] to redirect logging, e.g. into a file or an in-game console.
+pub trait LogSink: Send {
+    fn log(&mut self, record: &LogRecord);
+}
+
+/// Prints to stdout with colors and timestamps, collapsing consecutive [`LogLevel::Pack`] messages.
+#[derive(Default)]
+pub struct StdoutSink;
+
+impl LogSink for StdoutSink {
+    fn log(&mut self, record: &LogRecord) {
+        use colored::Colorize;
+
+        let message = record.message.to_string();
+        let message = match record.level {
+            LogLevel::Pack | LogLevel::Info => message.white(),
+            LogLevel::Debug => message.cyan(),
+            LogLevel::Warn => message.bright_yellow(),
+            LogLevel::Error => message.red()
+        };
+        let formatted = format!(
+            "\x1b[38;5;245m{}[{}@{}:{}]: {}",
+            chrono::Local::now().format("[%H:%M:%S]"),
+            record.package,
+            record.file, record.line,
+            message
+        );
+
+        if record.level == LogLevel::Pack {
+            crate::pack_log(format!("{}:{}", record.file, record.line), formatted)
+        } else {
+            crate::stop_pack_log();
+            println!("{formatted}")
+        }
+    }
+}
+
+/// Level filter and sink behind the [`crate::log!`] macro. The global one is configured through
+/// [`set_log_level`] and [`set_log_sink`], separate instances are mostly useful for testing sinks.
+pub struct Logger {
+    level: AtomicU8,
+    sink: Mutex<Box<dyn LogSink>>
+}
+
+impl Logger {
+    /// Logs everything, including [`LogLevel::Pack`] messages.
+    pub fn new(sink: Box<dyn LogSink>) -> Self {
+        Self {
+            level: AtomicU8::new(LogLevel::Pack as u8),
+            sink: Mutex::new(sink)
+        }
+    }
+
+    pub fn set_level(&self, level: LogLevel) {
+        self.level.store(level as u8, Ordering::Relaxed);
+    }
+
+    pub fn level(&self) -> LogLevel {
+        LogLevel::from_u8(self.level.load(Ordering::Relaxed))
+    }
+
+    #[inline]
+    pub fn enabled(&self, level: LogLevel) -> bool {
+        level >= self.level()
+    }
+
+    pub fn set_sink(&self, sink: Box<dyn LogSink>) -> Box<dyn LogSink> {
+        let mut current = self.sink.lock().unwrap_or_else(|e| e.into_inner());
+        std::mem::replace(&mut *current, sink)
+    }
+
+    pub fn write(&self, record: LogRecord) {
+        if !self.enabled(record.level) {
+            return;
+        }
+        self.sink.lock().unwrap_or_else(|e| e.into_inner()).log(&record);
+    }
+}
+
+lazy_static! {
+    static ref LOGGER: Logger = Logger::new(Box::new(StdoutSink));
+}
+
+/// Suppresses all messages below `level`. Defaults to [`LogLevel::Pack`], so everything is logged.
+pub fn set_log_level(level: LogLevel) {
+    LOGGER.set_level(level);
+}
+
+pub fn log_level() -> LogLevel {
+    LOGGER.level()
+}
+
+#[inline]
+pub fn log_enabled(level: LogLevel) -> bool {
+    LOGGER.enabled(level)
+}
+
+/// Replaces the sink all log messages are written to, returning the previous one.
+pub fn set_log_sink(sink: Box<dyn LogSink>) -> Box<dyn LogSink> {
+    LOGGER.set_sink(sink)
+}
+
+/// Passes `record` on to the current sink, used by [`crate::log!`].
+pub fn write_log(record: LogRecord) {
+    LOGGER.write(record);
+    let _ = std::io::stdout().flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    struct CollectSink(Arc<Mutex<Vec<(LogLevel, String)>>>);
+
+    impl LogSink for CollectSink {
+        fn log(&mut self, record: &LogRecord) {
+            self.0.lock().unwrap().push((record.level, record.message.to_string()));
+        }
+    }
+
+    #[test]
+    fn level_filter_and_sink() {
+        // a local logger, other tests log through the global one concurrently
+        let collected = Arc::new(Mutex::new(vec![]));
+        let logger = Logger::new(Box::new(CollectSink(collected.clone())));
+        fn record(level: LogLevel, message: Arguments) -> LogRecord {
+            LogRecord { level, package: "engine", file: file!(), line: line!(), message }
+        }
+
+        assert!(logger.enabled(LogLevel::Pack));
+        logger.write(record(LogLevel::Pack, format_args!("spam")));
+        logger.set_level(LogLevel::Warn);
+        logger.write(record(LogLevel::Pack, format_args!("more spam")));
+        logger.write(record(LogLevel::Info, format_args!("info")));
+        logger.write(record(LogLevel::Warn, format_args!("warning {}", 1)));
+        logger.write(record(LogLevel::Error, format_args!("error")));
+
+        assert_eq!(*collected.lock().unwrap(), vec![
+            (LogLevel::Pack, "spam".to_string()),
+            (LogLevel::Warn, "warning 1".to_string()),
+            (LogLevel::Error, "error".to_string())
+        ]);
+    }
+}
@@ -10,6 +10,8 @@ pub struct Camera {
     right: f32,
     bottom: f32,
     top: f32,
+    near: f32,
+    far: f32,
     rotation: f32
 }
 
@@ -30,12 +32,14 @@ impl Camera {
             right,
             bottom,
             top,
+            near,
+            far,
             rotation: 0.0
         }
     }
 
     pub fn fov_size(&self) -> Vector2<f32> {
-        Vector2::new(self.left.abs() + self.right.abs(), self.bottom.abs() + self.top.abs())
+        Vector2::new((self.right - self.left).abs(), (self.bottom - self.top).abs())
     }
 
     pub fn bottom_left(&self) -> Vector2<f32> {
@@ -60,9 +64,40 @@ impl Camera {
         self.right = right;
         self.bottom = bottom;
         self.top = top;
+        self.near = near;
+        self.far = far;
         self.recalculate_view_matrix();
     }
 
+    /// Changes the width of the view to `aspect_ratio` times its height, keeping the height and the horizontal center.
+    pub fn set_aspect(&mut self, aspect_ratio: f32) {
+        let center = (self.left + self.right) / 2.0;
+        let half_width = (self.bottom - self.top).abs() * aspect_ratio / 2.0;
+        self.set_projection(center - half_width, center + half_width, self.bottom, self.top, self.near, self.far);
+        // the view matrix is stored relative to the fov size
+        self.set_position(self.world_position);
+    }
+
+    /// Scales the view around its center to `height` world units, keeping the aspect ratio. Used for zooming.
+    pub fn set_view_height(&mut self, height: f32) {
+        let scale = height / self.view_height();
+        let center = Vector2::new((self.left + self.right) / 2.0, (self.top + self.bottom) / 2.0);
+        self.set_projection(
+            center.x + (self.left - center.x) * scale, center.x + (self.right - center.x) * scale,
+            center.y + (self.bottom - center.y) * scale, center.y + (self.top - center.y) * scale,
+            self.near, self.far
+        );
+        self.set_position(self.world_position);
+    }
+
+    pub fn view_height(&self) -> f32 {
+        (self.bottom - self.top).abs()
+    }
+
+    pub fn aspect(&self) -> f32 {
+        (self.right - self.left) / (self.bottom - self.top).abs()
+    }
+
     pub fn position(&self) -> &Vector2<f32> {
         &self.position
     }
@@ -90,9 +125,100 @@ impl Camera {
         point / range * self.fov_size() + Vector2::new(self.left, self.top) + self.world_position
     }
 
+    /// Maps a screen position (in pixels, origin top left) within a viewport of size `viewport` to world space.
+    pub fn screen_to_world(&self, screen_pos: Vector2<f32>, viewport: Vector2<f32>) -> Vector2<f32> {
+        let ndc = Vector2::new(
+            screen_pos.x / viewport.x * 2.0 - 1.0,
+            1.0 - screen_pos.y / viewport.y * 2.0
+        );
+        self.view_projection_matrix.inverse().transform_point(ndc)
+    }
+
+    /// Maps a world position to a screen position (in pixels, origin top left) within a viewport of size `viewport`.
+    pub fn world_to_screen(&self, world_pos: Vector2<f32>, viewport: Vector2<f32>) -> Vector2<f32> {
+        let ndc = self.view_projection_matrix.transform_point(world_pos);
+        Vector2::new(
+            (ndc.x + 1.0) / 2.0 * viewport.x,
+            (1.0 - ndc.y) / 2.0 * viewport.y
+        )
+    }
+
+    /// World-space axis-aligned bounding box `(min, max)` of everything the camera can see.
+    /// When the camera is rotated, the box encloses the whole rotated view.
+    pub fn visible_bounds(&self) -> (Vector2<f32>, Vector2<f32>) {
+        let inverse = self.view_projection_matrix.inverse();
+        let corners = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)].map(|(x, y)| inverse.transform_point(Vector2::new(x, y)));
+        corners.iter().skip(1).fold((corners[0], corners[0]), |(min, max), c| (
+            Vector2::new(min.x.min(c.x), min.y.min(c.y)),
+            Vector2::new(max.x.max(c.x), max.y.max(c.y))
+        ))
+    }
+
     fn recalculate_view_matrix(&mut self) {
         let transform = Matrix4::from(1.0_f32).translate(&self.position) * Matrix4::from(1.0_f32).rotate(self.rotation, Axis::Z);
         self.view_matrix = Matrix4::inverse(&transform);
         self.view_projection_matrix = &self.projection_matrix * &self.view_matrix;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn screen_world_roundtrip() {
+        let mut camera = Camera::new(-24.0, 24.0, 13.5, -13.5, -1.0, 1.0);
+        camera.set_position(Vector2::new(12.5, -3.0));
+        camera.set_rotation(0.3);
+        let viewport = Vector2::new(1920.0, 1080.0);
+
+        for point in [Vector2::new(0.0, 0.0), Vector2::new(12.5, -3.0), Vector2::new(-7.25, 40.0)] {
+            let screen = camera.world_to_screen(point, viewport);
+            let world = camera.screen_to_world(screen, viewport);
+            assert!((world - point).mag_sq() < 1e-6, "{point} -> {screen} -> {world}");
+        }
+    }
+
+    #[test]
+    fn visible_bounds_follow_camera() {
+        let mut camera = Camera::new(-24.0, 24.0, 13.5, -13.5, -1.0, 1.0);
+        camera.set_position(Vector2::new(10.0, 5.0));
+        let (min, max) = camera.visible_bounds();
+        assert!((min - Vector2::new(-14.0, -8.5)).mag_sq() < 1e-6, "{min}");
+        assert!((max - Vector2::new(34.0, 18.5)).mag_sq() < 1e-6, "{max}");
+    }
+
+    #[test]
+    fn set_aspect_keeps_height_and_position() {
+        let mut camera = Camera::new(-24.0, 24.0, 13.5, -13.5, -1.0, 1.0);
+        camera.set_position(Vector2::new(10.0, 5.0));
+        camera.set_aspect(2.0);
+        assert!((camera.aspect() - 2.0).abs() < 1e-6);
+        assert!((camera.fov_size() - Vector2::new(54.0, 27.0)).mag_sq() < 1e-6, "{}", camera.fov_size());
+        let (min, max) = camera.visible_bounds();
+        assert!((min - Vector2::new(-17.0, -8.5)).mag_sq() < 1e-6, "{min}");
+        assert!((max - Vector2::new(37.0, 18.5)).mag_sq() < 1e-6, "{max}");
+    }
+
+    #[test]
+    fn view_height_zooms_visible_bounds() {
+        let mut camera = Camera::new(-24.0, 24.0, 13.5, -13.5, -1.0, 1.0);
+        camera.set_position(Vector2::new(10.0, 5.0));
+        camera.set_view_height(13.5);
+        assert!((camera.aspect() - 48.0 / 27.0).abs() < 1e-6);
+        let (min, max) = camera.visible_bounds();
+        assert!((min - Vector2::new(-2.0, -1.75)).mag_sq() < 1e-6, "{min}");
+        assert!((max - Vector2::new(22.0, 11.75)).mag_sq() < 1e-6, "{max}");
+
+        camera.set_view_height(54.0);
+        let (min, max) = camera.visible_bounds();
+        assert!((max - min - Vector2::new(96.0, 54.0)).mag_sq() < 1e-4, "{min} {max}");
+    }
+
+    #[test]
+    fn screen_center_is_camera_center() {
+        let camera = Camera::new(-24.0, 24.0, 13.5, -13.5, -1.0, 1.0);
+        let center = camera.screen_to_world(Vector2::new(960.0, 540.0), Vector2::new(1920.0, 1080.0));
+        assert!(center.mag_sq() < 1e-6);
+    }
+}
@@ -1,3 +1,4 @@
+#[cfg(target_arch = "x86_64")]
 use core::arch::x86_64::*;
 use std::array;
 use std::ops::*;
@@ -118,7 +119,49 @@ impl Matrix4<f32> {
         self
     }
 
+    /// Transforms the point `(x, y, 0, 1)`, including the perspective divide.
+    pub fn transform_point(&self, point: Vector2<f32>) -> Vector2<f32> {
+        let x = point.x * self.0[0] + point.y * self.0[4] + self.0[12];
+        let y = point.x * self.0[1] + point.y * self.0[5] + self.0[13];
+        let w = point.x * self.0[3] + point.y * self.0[7] + self.0[15];
+        Vector2::new(x / w, y / w)
+    }
+
+    pub fn transpose(&self) -> Self {
+        Matrix4(array::from_fn(|i| self.0[(i % 4) * 4 + i / 4]))
+    }
+
+    pub fn determinant(&self) -> f32 {
+        self.det_with(&self.cofactors())
+    }
+
+    /// Returns the inverse of this matrix.
+    /// If the matrix is degenerate (see [`Matrix4::determinant`]), the unscaled adjugate is returned instead.
     pub fn inverse(&self) -> Self {
+        let mut inv = self.cofactors();
+
+        let mut det = self.det_with(&inv);
+        if det == 0.0 {
+            return inv;
+        }
+
+        det = 1.0 / det;
+
+        for i in 0..16 {
+            inv.0[i] *= det;
+        }
+
+        inv
+    }
+
+    fn det_with(&self, cofactors: &Self) -> f32 {
+        self.0[0] * cofactors.0[0]
+            + self.0[1] * cofactors.0[4]
+            + self.0[2] * cofactors.0[8]
+            + self.0[3] * cofactors.0[12]
+    }
+
+    fn cofactors(&self) -> Self {
         let mut inv = Matrix4::default();
 
         inv.0[0] = self.0[5] * self.0[10] * self.0[15]
@@ -233,25 +276,12 @@ impl Matrix4<f32> {
             + self.0[8] * self.0[1] * self.0[6]
             - self.0[8] * self.0[2] * self.0[5];
 
-        let mut det = self.0[0] * inv.0[0]
-            + self.0[1] * inv.0[4]
-            + self.0[2] * inv.0[8]
-            + self.0[3] * inv.0[12];
-        if det == 0.0 {
-            return inv;
-        }
-
-        det = 1.0 / det;
-
-        for i in 0..16 {
-            inv.0[i] *= det;
-        }
-
         inv
     }
 }
 
 //impl<T: Copy + Default + Mul<Output = T> + Add<Output = T>> Mul for &Matrix4<T> {
+#[cfg(target_arch = "x86_64")]
 impl Mul for &Matrix4<f32> {
     type Output = Matrix4<f32>;
 
@@ -287,6 +317,26 @@ impl Mul for &Matrix4<f32> {
     }
 }
 
+#[cfg(not(target_arch = "x86_64"))]
+impl Mul for &Matrix4<f32> {
+    type Output = Matrix4<f32>;
+
+    fn mul(self, rhs: Self) -> Matrix4<f32> {
+        let mut ret = Matrix4::default();
+
+        for i in 0..4 {
+            for j in 0..4 {
+                ret.0[4 * i + j] = self.0[4 * i] * rhs.0[j]
+                    + self.0[4 * i + 1] * rhs.0[4 + j]
+                    + self.0[4 * i + 2] * rhs.0[8 + j]
+                    + self.0[4 * i + 3] * rhs.0[12 + j];
+            }
+        }
+
+        ret
+    }
+}
+
 //impl<T: Copy + Default + Mul<Output = T> + Add<Output = T>> Mul for Matrix4<T> {
 impl Mul for Matrix4<f32> {
     type Output = Self;
@@ -303,3 +353,53 @@ impl MulAssign for Matrix4<f32> {
         *self = tmp * rhs;
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mul_matrix4() {
+        let a = Matrix4::new([
+            1.0, 2.0, 3.0, 4.0,
+            5.0, 6.0, 7.0, 8.0,
+            9.0, 10.0, 11.0, 12.0,
+            13.0, 14.0, 15.0, 16.0
+        ]);
+        let b = Matrix4::new([
+            17.0, 18.0, 19.0, 20.0,
+            21.0, 22.0, 23.0, 24.0,
+            25.0, 26.0, 27.0, 28.0,
+            29.0, 30.0, 31.0, 32.0
+        ]);
+        let expected = [
+            250.0, 260.0, 270.0, 280.0,
+            618.0, 644.0, 670.0, 696.0,
+            986.0, 1028.0, 1070.0, 1112.0,
+            1354.0, 1412.0, 1470.0, 1528.0
+        ];
+        assert_eq!((&a * &b).0, expected);
+        assert_eq!((&a * &Matrix4::from(1.0)).0, a.0);
+    }
+
+    #[test]
+    fn transpose_matrix4() {
+        let a = Matrix4::new(array::from_fn(|i| i as f32));
+        assert_eq!(a.transpose().0[1], 4.0);
+        assert_eq!(a.transpose().0[4], 1.0);
+        assert_eq!(a.transpose().transpose().0, a.0);
+
+        let mut identity = Matrix4::default();
+        identity.identity();
+        assert_eq!(identity.transpose().0, identity.0);
+    }
+
+    #[test]
+    fn determinant_matrix4() {
+        let ortho = Matrix4::ortho(-2.0, 2.0, -1.0, 1.0, -1.0, 1.0);
+        assert_eq!(ortho.determinant(), -0.5);
+        assert_eq!(Matrix4::from(1.0).determinant(), 1.0);
+        assert_eq!(Matrix4::fill(1.0).determinant(), 0.0);
+    }
+}
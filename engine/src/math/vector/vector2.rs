@@ -39,6 +39,23 @@ impl<T> Vector2<T> {
         self.x * self.y
     }
 
+    pub fn dot(&self, other: &Self) -> T where T: Add<Output=T> + Mul<Output=T> + Copy {
+        self.x * other.x + self.y * other.y
+    }
+
+    /// z component of the 3d cross product, positive if `other` is counter-clockwise of `self`
+    pub fn cross(&self, other: &Self) -> T where T: Sub<Output=T> + Mul<Output=T> + Copy {
+        self.x * other.y - self.y * other.x
+    }
+
+    /// Linear interpolation, returns `self` at `t = 0` and `other` at `t = 1`
+    pub fn lerp(self, other: Self, t: T) -> Self where T: Add<Output=T> + Sub<Output=T> + Mul<Output=T> + Copy {
+        Self {
+            x: self.x + (other.x - self.x) * t,
+            y: self.y + (other.y - self.y) * t
+        }
+    }
+
     pub fn into_array(self) -> [T; 2] {
         [self.x, self.y]
     }
@@ -374,4 +391,30 @@ mod tests {
         assert_eq!(Vector2::default() + output, output);
         assert_eq!(Vector2::from((21, 34)) + Vector2::from((21, 35)), output);
     }
+
+    #[test]
+    fn rotate_vector2() {
+        let v = Vector2::new(3.0, -2.0);
+        assert!((v.rotate(std::f32::consts::FRAC_PI_2) - v.rotate_90()).mag_sq() < 1e-10);
+        assert!((v.rotate(std::f32::consts::TAU) - v).mag_sq() < 1e-10);
+    }
+
+    #[test]
+    fn lerp_vector2() {
+        let a = Vector2::new(1.0, 2.0);
+        let b = Vector2::new(-3.0, 6.0);
+        assert_eq!(a.lerp(b, 0.0), a);
+        assert_eq!(a.lerp(b, 1.0), b);
+        assert_eq!(a.lerp(b, 0.5), Vector2::new(-1.0, 4.0));
+    }
+
+    #[test]
+    fn dot_cross_vector2() {
+        let x = Vector2::new(1, 0);
+        let y = Vector2::new(0, 1);
+        assert_eq!(x.dot(&y), 0);
+        assert_eq!(Vector2::new(2, 3).dot(&Vector2::new(4, 5)), 23);
+        assert_eq!(x.cross(&y), 1);
+        assert_eq!(y.cross(&x), -1);
+    }
 }
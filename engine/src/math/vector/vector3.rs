@@ -56,6 +56,19 @@ impl<T> Vector3<T> {
         self.x * self.y * self.z
     }
 
+    pub fn dot(&self, other: &Self) -> T where T: Add<Output=T> + Mul<Output=T> + Copy {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    /// Linear interpolation, returns `self` at `t = 0` and `other` at `t = 1`
+    pub fn lerp(self, other: Self, t: T) -> Self where T: Add<Output=T> + Sub<Output=T> + Mul<Output=T> + Copy {
+        Self {
+            x: self.x + (other.x - self.x) * t,
+            y: self.y + (other.y - self.y) * t,
+            z: self.z + (other.z - self.z) * t
+        }
+    }
+
     pub fn into_array(self) -> [T; 3] {
         [self.x, self.y, self.z]
     }
@@ -157,6 +170,10 @@ impl Vector3<f64> {
         f64::sqrt(self.mag_sq())
     }
 
+    pub fn normalized(&self) -> Self {
+        *self / self.mag()
+    }
+
     pub fn half(mut self) -> Self {
         self.x /= 2.0;
         self.y /= 2.0;
@@ -188,6 +205,24 @@ impl<T: PartialOrd> Vector3<T> {
 
         self
     }
+
+    /// Component-wise minimum
+    pub fn min(self, other: Self) -> Self {
+        Self {
+            x: if other.x < self.x { other.x } else { self.x },
+            y: if other.y < self.y { other.y } else { self.y },
+            z: if other.z < self.z { other.z } else { self.z }
+        }
+    }
+
+    /// Component-wise maximum
+    pub fn max(self, other: Self) -> Self {
+        Self {
+            x: if other.x > self.x { other.x } else { self.x },
+            y: if other.y > self.y { other.y } else { self.y },
+            z: if other.z > self.z { other.z } else { self.z }
+        }
+    }
 }
 
 impl<T: Into<f64> + Copy> Vector3<T> {
@@ -216,6 +251,38 @@ impl<T: Copy> Vector3<T> {
     }
 }
 
+impl<T> From<(T, T, T)> for Vector3<T> {
+    fn from(value: (T, T, T)) -> Self {
+        Self {
+            x: value.0,
+            y: value.1,
+            z: value.2
+        }
+    }
+}
+
+impl<T> From<Vector3<T>> for (T, T, T) {
+    fn from(val: Vector3<T>) -> Self {
+        (val.x, val.y, val.z)
+    }
+}
+
+impl<T> From<Vector3<T>> for [T; 3] {
+    fn from(val: Vector3<T>) -> Self {
+        [val.x, val.y, val.z]
+    }
+}
+
+impl<T: Copy> From<[T; 3]> for Vector3<T> {
+    fn from(value: [T; 3]) -> Self {
+        Self {
+            x: value[0],
+            y: value[1],
+            z: value[2]
+        }
+    }
+}
+
 impl<T: Add<Output = T>> Add for Vector3<T> {
     type Output = Self;
 
@@ -348,4 +415,28 @@ impl<T: Display> Display for Vector3<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "({}, {}, {})", self.x, self.y, self.z)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lerp_vector3() {
+        let warm = Vector3::new(1.0, 0.8, 0.4);
+        let cold = Vector3::new(0.2, 0.4, 1.0);
+        assert_eq!(warm.lerp(cold, 0.0), warm);
+        assert!((warm.lerp(cold, 1.0) - cold).mag_sq() < 1e-10);
+        assert!((warm.lerp(cold, 0.5) - Vector3::new(0.6, 0.6, 0.7)).mag_sq() < 1e-10);
+    }
+
+    #[test]
+    fn normalize_vector3() {
+        let v = Vector3::new(2.0f32, -3.0, 6.0);
+        assert_eq!(v.mag(), 7.0);
+        assert!((v.normalized() - Vector3::new(2.0 / 7.0, -3.0 / 7.0, 6.0 / 7.0)).mag_sq() < 1e-10);
+        assert!((v.normalized().mag() - 1.0).abs() < 1e-6);
+        assert_eq!(v.min(Vector3::new(0.0, 0.0, 0.0)), Vector3::new(0.0, -3.0, 0.0));
+        assert_eq!(v.max(Vector3::new(0.0, 0.0, 0.0)), Vector3::new(2.0, 0.0, 6.0));
+    }
+}
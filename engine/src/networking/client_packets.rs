@@ -4,18 +4,20 @@ use std::fmt::{Debug};
 use crate::{ClientId, EntityId, Id, TypeId};
 use crate::nanoserde;
 use crate::nanoserde::{SerBin, DeBin};
+use crate::networking::stats::MessageKind;
 
 
-#[derive(Debug, SerBin, DeBin)]
+#[derive(Debug, PartialEq, SerBin, DeBin)]
 pub struct ClientPacket {
     pub client_id: ClientId,
     pub conv_id: Id,
     pub message: ClientMessage,
 }
 
-#[derive(Debug, SerBin, DeBin)]
+#[derive(Debug, PartialEq, SerBin, DeBin)]
 pub enum ClientMessage {
-    Login,
+    /// sent after registering and again after reconnecting, see [`LoginInfo::mismatch`]
+    Login(LoginInfo),
     Logout,
     KeepAlive,
     Register(ClientInfo),
@@ -25,12 +27,57 @@ pub enum ClientMessage {
     Ping(String),
     Pong(String),
     RawData(Vec<u8>),
-    ModMessage(EntityId, TypeId, Vec<u8>)
+    ModMessage(EntityId, TypeId, Vec<u8>),
+    /// return value of a client function, sent with the `conv_id` of the server packet that called it
+    ModReply(Vec<u8>)
 }
 
-#[derive(Debug, SerBin, DeBin)]
+#[derive(Debug, PartialEq, SerBin, DeBin)]
 pub struct ClientInfo {
     pub client_id: ClientId,
     pub client_version: String,
     pub mod_target: String
-}
\ No newline at end of file
+}
+
+/// What the client was built with and which mods it loaded. The server kicks clients that don't match it.
+#[derive(Debug, Clone, PartialEq, SerBin, DeBin)]
+pub struct LoginInfo {
+    pub client_version: String,
+    /// [`ServerInfo::mod_profile_hash`](crate::networking::server_packets::ServerInfo::mod_profile_hash) of the mods the client loaded
+    pub mod_profile_hash: String
+}
+
+impl LoginInfo {
+    /// Why the login does not match the server, if it doesn't.
+    /// Both have to match exactly: mods are native libraries built against one engine version,
+    /// and mod messages only deserialize with the same mods on both sides.
+    pub fn mismatch(&self, server_version: &str, mod_profile_hash: &str) -> Option<String> {
+        if self.client_version != server_version {
+            Some(format!("version mismatch: client engine {} != server engine {server_version}", self.client_version))
+        } else if self.mod_profile_hash != mod_profile_hash {
+            Some("version mismatch: the client's mods are not the server's mod profile, reconnect to download them".to_string())
+        } else {
+            None
+        }
+    }
+}
+
+impl MessageKind for ClientMessage {
+    const KINDS: &'static [&'static str] = &["Login", "Logout", "KeepAlive", "Register", "DownloadMod", "Acknowlege", "Ping", "Pong", "RawData", "ModMessage", "ModReply"];
+
+    fn kind(&self) -> usize {
+        match self {
+            Self::Login(..) => 0,
+            Self::Logout => 1,
+            Self::KeepAlive => 2,
+            Self::Register(..) => 3,
+            Self::DownloadMod(..) => 4,
+            Self::Acknowlege(..) => 5,
+            Self::Ping(..) => 6,
+            Self::Pong(..) => 7,
+            Self::RawData(..) => 8,
+            Self::ModMessage(..) => 9,
+            Self::ModReply(..) => 10,
+        }
+    }
+}
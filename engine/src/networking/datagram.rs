@@ -0,0 +1,390 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+use crate::log;
+use super::{MAX_PACKET_SIZE, SendMode};
+
+/// Maximum number of out-of-order datagrams buffered per connection for [`SendMode::Ordered`](super::SendMode::Ordered).
+/// Once the window overflows, the oldest missing sequence numbers are given up on (treated as dropped)
+/// and the buffered datagrams after them are delivered.
+pub const ORDERED_WINDOW_SIZE: usize = 64;
+
+/// Byte length of the header of an ordered datagram (kind + sequence number)
+pub const ORDERED_HEADER_SIZE: usize = 5;
+
+/// Byte length of the header of a fragment (kind + message id + fragment index + fragment count)
+pub const FRAGMENT_HEADER_SIZE: usize = 9;
+
+/// Maximum payload carried by a single fragment
+pub const MAX_FRAGMENT_PAYLOAD: usize = MAX_PACKET_SIZE - FRAGMENT_HEADER_SIZE;
+
+/// Default time after which partially received messages are discarded
+pub const DEFAULT_FRAGMENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Most fragments a message may be split into, larger ones are neither sent nor reassembled
+pub const MAX_FRAGMENT_COUNT: usize = 64;
+
+/// Most partially received messages kept per connection, the oldest is discarded to make room for a new one
+pub const MAX_PARTIAL_MESSAGES: usize = 8;
+
+/// First byte of every udp datagram, telling the receiver how to unwrap it.
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DatagramKind {
+    Quick = 0,
+    Ordered = 1,
+    Fragment = 2,
+}
+
+impl DatagramKind {
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(Self::Quick),
+            1 => Some(Self::Ordered),
+            2 => Some(Self::Fragment),
+            _ => None
+        }
+    }
+}
+
+/// Prefixes a serialized packet with the [`DatagramKind::Quick`] header.
+pub fn wrap_quick(data: &[u8]) -> Vec<u8> {
+    let mut datagram = Vec::with_capacity(data.len() + 1);
+    datagram.push(DatagramKind::Quick as u8);
+    datagram.extend_from_slice(data);
+    datagram
+}
+
+/// Hands out sequence numbers for outgoing ordered datagrams of one connection.
+#[derive(Default, Debug)]
+pub struct OrderedSender {
+    next_seq: u32
+}
+
+impl OrderedSender {
+    pub fn wrap(&mut self, data: &[u8]) -> Vec<u8> {
+        let mut datagram = Vec::with_capacity(data.len() + ORDERED_HEADER_SIZE);
+        datagram.push(DatagramKind::Ordered as u8);
+        datagram.extend_from_slice(&self.next_seq.to_le_bytes());
+        datagram.extend_from_slice(data);
+        self.next_seq = self.next_seq.wrapping_add(1);
+        datagram
+    }
+}
+
+/// Receive window of one connection. Drops duplicates and datagrams older than the
+/// already delivered ones and holds back early arrivals until the gap before them is filled.
+#[derive(Default, Debug)]
+pub struct OrderedReceiver {
+    next_seq: u32,
+    window: BTreeMap<u32, Vec<u8>>
+}
+
+impl OrderedReceiver {
+    /// Takes the payload of an ordered datagram (without the kind byte) and returns all packets
+    /// that are now ready to be handed out, in order.
+    pub fn receive(&mut self, datagram: &[u8]) -> Vec<Vec<u8>> {
+        let mut ready = vec![];
+        if datagram.len() < ORDERED_HEADER_SIZE - 1 {
+            return ready
+        }
+        let seq = u32::from_le_bytes(datagram[..4].try_into().unwrap());
+        let data = &datagram[4..];
+
+        if (seq.wrapping_sub(self.next_seq) as i32) < 0 || self.window.contains_key(&seq) {
+            // late or duplicate
+            return ready
+        }
+
+        if seq == self.next_seq {
+            ready.push(data.to_vec());
+            self.next_seq = self.next_seq.wrapping_add(1);
+        } else {
+            self.window.insert(seq, data.to_vec());
+        }
+
+        while self.window.len() > ORDERED_WINDOW_SIZE {
+            // skip the missing datagrams in front of the oldest buffered one
+            self.next_seq = *self.window.keys().next().unwrap();
+            self.drain_ready(&mut ready);
+        }
+        self.drain_ready(&mut ready);
+
+        ready
+    }
+
+    fn drain_ready(&mut self, ready: &mut Vec<Vec<u8>>) {
+        while let Some(data) = self.window.remove(&self.next_seq) {
+            ready.push(data);
+            self.next_seq = self.next_seq.wrapping_add(1);
+        }
+    }
+}
+
+/// Splits datagrams larger than [`MAX_PACKET_SIZE`] into [`DatagramKind::Fragment`]s
+/// sharing one message id, so they can be reassembled by a [`FragmentAssembler`].
+#[derive(Default, Debug)]
+pub struct Fragmenter {
+    next_id: u32
+}
+
+impl Fragmenter {
+    pub fn split(&mut self, datagram: Vec<u8>) -> Vec<Vec<u8>> {
+        if datagram.len() <= MAX_PACKET_SIZE {
+            return vec![datagram]
+        }
+        let count = (datagram.len() + MAX_FRAGMENT_PAYLOAD - 1) / MAX_FRAGMENT_PAYLOAD;
+        if count > MAX_FRAGMENT_COUNT {
+            log!(ERROR, "dropping datagram of {} bytes, it would need {count} fragments but at most {MAX_FRAGMENT_COUNT} are allowed", datagram.len());
+            return vec![]
+        }
+        let count = count as u16;
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        datagram.chunks(MAX_FRAGMENT_PAYLOAD).enumerate().map(|(i, chunk)| {
+            let mut fragment = Vec::with_capacity(chunk.len() + FRAGMENT_HEADER_SIZE);
+            fragment.push(DatagramKind::Fragment as u8);
+            fragment.extend_from_slice(&id.to_le_bytes());
+            fragment.extend_from_slice(&(i as u16).to_le_bytes());
+            fragment.extend_from_slice(&count.to_le_bytes());
+            fragment.extend_from_slice(chunk);
+            fragment
+        }).collect()
+    }
+}
+
+#[derive(Debug)]
+struct PartialMessage {
+    fragments: Vec<Option<Vec<u8>>>,
+    missing: usize,
+    started: Instant
+}
+
+/// Collects fragments until their message is complete.
+/// Messages that are not complete after `timeout` are discarded with a warning, as are the oldest ones
+/// once more than [`MAX_PARTIAL_MESSAGES`] are incomplete.
+#[derive(Debug)]
+pub struct FragmentAssembler {
+    timeout: Duration,
+    partial: HashMap<u32, PartialMessage>
+}
+
+impl Default for FragmentAssembler {
+    fn default() -> Self {
+        Self::new(DEFAULT_FRAGMENT_TIMEOUT)
+    }
+}
+
+impl FragmentAssembler {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            partial: HashMap::new()
+        }
+    }
+
+    /// Takes the payload of a fragment (without the kind byte) and returns the reassembled datagram
+    /// once all fragments of its message arrived.
+    pub fn receive(&mut self, fragment: &[u8]) -> Option<Vec<u8>> {
+        self.discard_expired();
+
+        if fragment.len() < FRAGMENT_HEADER_SIZE - 1 {
+            return None
+        }
+        let id = u32::from_le_bytes(fragment[0..4].try_into().unwrap());
+        let index = u16::from_le_bytes(fragment[4..6].try_into().unwrap()) as usize;
+        let count = u16::from_le_bytes(fragment[6..8].try_into().unwrap()) as usize;
+        if index >= count || count > MAX_FRAGMENT_COUNT {
+            return None
+        }
+
+        if !self.partial.contains_key(&id) && self.partial.len() >= MAX_PARTIAL_MESSAGES {
+            self.discard_oldest();
+        }
+        let partial = self.partial.entry(id).or_insert_with(|| PartialMessage {
+            fragments: vec![None; count],
+            missing: count,
+            started: Instant::now()
+        });
+        if partial.fragments.len() != count {
+            return None
+        }
+        if partial.fragments[index].is_none() {
+            partial.fragments[index] = Some(fragment[8..].to_vec());
+            partial.missing -= 1;
+        }
+
+        if partial.missing == 0 {
+            let partial = self.partial.remove(&id).unwrap();
+            return Some(partial.fragments.into_iter().flatten().flatten().collect())
+        }
+        None
+    }
+
+    fn discard_oldest(&mut self) {
+        let Some(oldest) = self.partial.iter().min_by_key(|(_, partial)| partial.started).map(|(id, _)| *id) else {
+            return
+        };
+        let partial = self.partial.remove(&oldest).unwrap();
+        log!(WARN, "discarding message {oldest}: {} of {} fragments missing, too many incomplete messages", partial.missing, partial.fragments.len());
+    }
+
+    fn discard_expired(&mut self) {
+        let timeout = self.timeout;
+        self.partial.retain(|id, partial| {
+            let expired = partial.started.elapsed() > timeout;
+            if expired {
+                log!(WARN, "discarding message {id}: {} of {} fragments missing after {timeout:?}", partial.missing, partial.fragments.len());
+            }
+            !expired
+        });
+    }
+}
+
+/// Outgoing datagram state of one connection.
+#[derive(Default, Debug)]
+pub struct DatagramSender {
+    ordered: OrderedSender,
+    fragmenter: Fragmenter
+}
+
+impl DatagramSender {
+    /// Wraps a serialized packet into one or more datagrams ready to be sent.
+    pub fn wrap(&mut self, data: &[u8], ordered: bool) -> Vec<Vec<u8>> {
+        let datagram = if ordered { self.ordered.wrap(data) } else { wrap_quick(data) };
+        self.fragmenter.split(datagram)
+    }
+}
+
+/// Incoming datagram state of one connection.
+#[derive(Default, Debug)]
+pub struct DatagramReceiver {
+    ordered: OrderedReceiver,
+    fragments: FragmentAssembler
+}
+
+impl DatagramReceiver {
+    pub fn new(fragment_timeout: Duration) -> Self {
+        Self {
+            ordered: Default::default(),
+            fragments: FragmentAssembler::new(fragment_timeout)
+        }
+    }
+
+    /// Unwraps a received datagram, returning all serialized packets that are ready with the mode they were sent with,
+    /// or `None` if the datagram is malformed.
+    pub fn receive(&mut self, datagram: &[u8]) -> Option<Vec<(Vec<u8>, SendMode)>> {
+        let (kind, data) = datagram.split_first()?;
+        match DatagramKind::from_byte(*kind)? {
+            DatagramKind::Quick => Some(vec![(data.to_vec(), SendMode::Quick)]),
+            DatagramKind::Ordered => Some(self.ordered.receive(data).into_iter().map(|data| (data, SendMode::Ordered)).collect()),
+            DatagramKind::Fragment => match self.fragments.receive(data) {
+                Some(datagram) if datagram.first() != Some(&(DatagramKind::Fragment as u8)) => self.receive(&datagram),
+                Some(_) => None,
+                None => Some(vec![])
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload(seq: u32, byte: u8) -> Vec<u8> {
+        let mut data = seq.to_le_bytes().to_vec();
+        data.push(byte);
+        data
+    }
+
+    #[test]
+    fn ordered_receiver_reorders_and_drops_duplicates() {
+        let mut receiver = OrderedReceiver::default();
+        assert_eq!(receiver.receive(&payload(1, 1)), Vec::<Vec<u8>>::new());
+        assert_eq!(receiver.receive(&payload(0, 0)), vec![vec![0], vec![1]]);
+        assert_eq!(receiver.receive(&payload(1, 1)), Vec::<Vec<u8>>::new());
+        assert_eq!(receiver.receive(&payload(2, 2)), vec![vec![2]]);
+    }
+
+    #[test]
+    fn ordered_receiver_window_overflow() {
+        let mut receiver = OrderedReceiver::default();
+        for seq in 1..=ORDERED_WINDOW_SIZE as u32 {
+            assert!(receiver.receive(&payload(seq, 0)).is_empty());
+        }
+        assert_eq!(receiver.receive(&payload(ORDERED_WINDOW_SIZE as u32 + 1, 0)).len(), ORDERED_WINDOW_SIZE + 1);
+        // sequence 0 was given up on
+        assert!(receiver.receive(&payload(0, 0)).is_empty());
+    }
+
+    #[test]
+    fn ordered_sender_roundtrip() {
+        let mut sender = OrderedSender::default();
+        let mut receiver = OrderedReceiver::default();
+        let first = sender.wrap(&[42]);
+        let second = sender.wrap(&[69]);
+        assert_eq!(DatagramKind::from_byte(first[0]), Some(DatagramKind::Ordered));
+        assert!(receiver.receive(&second[1..]).is_empty());
+        assert_eq!(receiver.receive(&first[1..]), vec![vec![42], vec![69]]);
+    }
+
+    #[test]
+    fn fragments_reassemble_out_of_order() {
+        let data: Vec<u8> = (0..MAX_PACKET_SIZE * 2 + 100).map(|i| i as u8).collect();
+        let mut sender = DatagramSender::default();
+        let mut receiver = DatagramReceiver::default();
+        let mut fragments = sender.wrap(&data, false);
+        assert_eq!(fragments.len(), 3);
+        assert!(fragments.iter().all(|f| f.len() <= MAX_PACKET_SIZE));
+        fragments.swap(0, 2);
+        assert_eq!(receiver.receive(&fragments[0]), Some(vec![]));
+        assert_eq!(receiver.receive(&fragments[1]), Some(vec![]));
+        assert_eq!(receiver.receive(&fragments[2]), Some(vec![(data, SendMode::Quick)]));
+    }
+
+    #[test]
+    fn received_packets_keep_their_mode() {
+        let mut sender = DatagramSender::default();
+        let mut receiver = DatagramReceiver::default();
+        let large = vec![7; MAX_PACKET_SIZE * 2];
+        assert_eq!(receiver.receive(&sender.wrap(&[1], false)[0]), Some(vec![(vec![1], SendMode::Quick)]));
+        assert_eq!(receiver.receive(&sender.wrap(&[2], true)[0]), Some(vec![(vec![2], SendMode::Ordered)]));
+        let fragments = sender.wrap(&large, true);
+        assert_eq!(receiver.receive(&fragments[0]), Some(vec![]));
+        assert_eq!(receiver.receive(&fragments[1]), Some(vec![]));
+        assert_eq!(receiver.receive(&fragments[2]), Some(vec![(large, SendMode::Ordered)]));
+    }
+
+    #[test]
+    fn fragments_time_out() {
+        let mut sender = Fragmenter::default();
+        let mut assembler = FragmentAssembler::new(Duration::ZERO);
+        let fragments = sender.split(vec![0; MAX_PACKET_SIZE + 1]);
+        assert_eq!(assembler.receive(&fragments[0][1..]), None);
+        std::thread::sleep(Duration::from_millis(1));
+        assert_eq!(assembler.receive(&fragments[1][1..]), None);
+    }
+
+    #[test]
+    fn partial_messages_are_limited() {
+        let mut sender = Fragmenter::default();
+        assert!(sender.split(vec![0; MAX_FRAGMENT_PAYLOAD * MAX_FRAGMENT_COUNT + 1]).is_empty());
+
+        // a fragment claiming more than the allowed count is ignored
+        let mut forged = sender.split(vec![0; MAX_PACKET_SIZE + 1]).remove(0);
+        forged[7..9].copy_from_slice(&u16::MAX.to_le_bytes());
+        let mut assembler = FragmentAssembler::default();
+        assert_eq!(assembler.receive(&forged[1..]), None);
+        assert!(assembler.partial.is_empty());
+
+        let messages: Vec<_> = (0..=MAX_PARTIAL_MESSAGES).map(|i| sender.split(vec![i as u8; MAX_PACKET_SIZE + 1])).collect();
+        for fragments in &messages {
+            assert_eq!(assembler.receive(&fragments[0][1..]), None);
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(assembler.partial.len(), MAX_PARTIAL_MESSAGES);
+        // the oldest one was discarded, the newest still completes
+        let first_id = u32::from_le_bytes(messages[0][0][1..5].try_into().unwrap());
+        assert!(!assembler.partial.contains_key(&first_id));
+        assert_eq!(assembler.receive(&messages[MAX_PARTIAL_MESSAGES][1][1..]), Some(vec![MAX_PARTIAL_MESSAGES as u8; MAX_PACKET_SIZE + 1]));
+    }
+}
@@ -1,15 +1,18 @@
 use std::fmt::Debug;
 use nanoserde::{DeBin, SerBin};
+use sha2::{Digest, Sha256};
 
 pub mod client_packets;
 pub mod server_packets;
 pub mod messaging;
+pub mod datagram;
+pub mod stats;
 
 pub const MAX_PACKET_SIZE: usize = 25000;
 pub const MAX_RAW_DATA_SIZE: usize = MAX_PACKET_SIZE - 26;
 pub const MOD_DOWNLOAD_CHUNK_SIZE: usize = 65000;
 
-#[derive(Debug, SerBin, DeBin)]
+#[derive(Debug, PartialEq, SerBin, DeBin)]
 pub enum NetResult<T: Debug + SerBin + DeBin, E: Debug + SerBin + DeBin>{
     Ok(T),
     Err(E)
@@ -33,11 +36,90 @@ impl<T: Debug + SerBin + DeBin, E: Debug + SerBin + DeBin> From<Result<T, E>> fo
     }
 }
 
+/// Identifies a mod profile in the login handshake, see [`LoginInfo`](client_packets::LoginInfo).
+/// Covers the profile name and version and every mod's `name:path` and flags, not the archives,
+/// so it is the same for every mod target. The order of `mods` doesn't matter.
+pub fn mod_profile_hash<'a>(profile: &str, version: &str, mods: impl IntoIterator<Item = (&'a String, &'a Vec<String>)>) -> String {
+    let mut mods = mods.into_iter().collect::<Vec<_>>();
+    mods.sort();
+    let mut hasher = Sha256::default();
+    hasher.update(profile);
+    hasher.update([0u8]);
+    hasher.update(version);
+    for (name_path, flags) in mods {
+        hasher.update([1u8]);
+        hasher.update(name_path);
+        for flag in flags {
+            hasher.update([0u8]);
+            hasher.update(flag);
+        }
+    }
+    format!("{:X}", hasher.finalize())
+}
+
 /// The mode the network message will be send in
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum SendMode {
     /// Quick and lossy. Use for discardable packets, such as continous updates.
+    /// Packets larger than [`MAX_PACKET_SIZE`] are split into fragments, which are all lost if one of them is.
     Quick,
+    /// Lossy like [`SendMode::Quick`], but duplicates and packets arriving after newer ones are dropped,
+    /// so the receiver only ever sees packets in the order they were sent.
+    /// See [`datagram::ORDERED_WINDOW_SIZE`] for how out-of-order packets are buffered.
+    Ordered,
     /// Safe transfer, but slow. Data is buffered. Use for things like downloading resources or events that only happen on state change.
     Safe
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Id;
+    use crate::util::assert_ser_bin_roundtrip;
+    use super::*;
+    use super::client_packets::*;
+    use super::server_packets::*;
+
+    #[test]
+    fn packet_roundtrip() {
+        let id = Id::new();
+        for message in [
+            ClientMessage::Login(LoginInfo { client_version: "0.1".to_string(), mod_profile_hash: "hash".to_string() }),
+            ClientMessage::Register(ClientInfo { client_id: id, client_version: "0.1".to_string(), mod_target: "x86_64-unix".to_string() }),
+            ClientMessage::DownloadMod("world".to_string(), "x86_64-unix".to_string(), 1234),
+            ClientMessage::ModMessage(id, id, vec![1, 2, 3]),
+            ClientMessage::ModReply(vec![4, 5])
+        ] {
+            assert_ser_bin_roundtrip(&ClientPacket { client_id: id, conv_id: Id::new(), message });
+        }
+
+        for message in [
+            ServerMessage::KeepAlive,
+            ServerMessage::RegisterResponse(NetResult::Ok(ServerInfo {
+                server_version: "0.1".to_string(),
+                mod_profile: "default".to_string(),
+                mod_version: "1".to_string(),
+                mods: vec![("world".to_string(), vec!["42".to_string()], "hash".to_string(), 99)]
+            })),
+            ServerMessage::RegisterResponse(NetResult::Err("version mismatch".to_string())),
+            ServerMessage::AddClientHandle(id, id),
+            ServerMessage::ModMessage(id, id, vec![]),
+            ServerMessage::TimeScale(0.5, true)
+        ] {
+            assert_ser_bin_roundtrip(&ServerPacket { conv_id: id, message });
+        }
+    }
+
+    #[test]
+    fn login_must_match_engine_and_mods() {
+        let mods = [("world:mods/world".to_string(), vec!["client".to_string()]), ("debug:mods/debug".to_string(), vec![])];
+        let hash = mod_profile_hash("default", "1", mods.iter().map(|m| (&m.0, &m.1)));
+        assert_eq!(hash, mod_profile_hash("default", "1", mods.iter().rev().map(|m| (&m.0, &m.1))));
+        assert_ne!(hash, mod_profile_hash("default", "2", mods.iter().map(|m| (&m.0, &m.1))));
+        assert_ne!(hash, mod_profile_hash("default", "1", mods[..1].iter().map(|m| (&m.0, &m.1))));
+
+        let login = LoginInfo { client_version: "0.1.0".to_string(), mod_profile_hash: hash.clone() };
+        assert_eq!(login.mismatch("0.1.0", &hash), None);
+        assert!(login.mismatch("0.2.0", &hash).unwrap().contains("0.1.0 != server engine 0.2.0"));
+        assert!(login.mismatch("0.1.0", "other").unwrap().starts_with("version mismatch"));
+    }
+}
@@ -5,16 +5,17 @@ use std::fmt::{Debug};
 use crate::{ClientId, EntityId, Id, TypeId};
 use crate::nanoserde;
 use crate::nanoserde::{SerBin, DeBin};
-use crate::networking::NetResult;
+use crate::networking::stats::MessageKind;
+use crate::networking::{mod_profile_hash, NetResult};
 
 
-#[derive(Debug, SerBin, DeBin)]
+#[derive(Debug, PartialEq, SerBin, DeBin)]
 pub struct ServerPacket {
     pub conv_id: Id,
     pub message: ServerMessage
 }
 
-#[derive(Debug, SerBin, DeBin)]
+#[derive(Debug, PartialEq, SerBin, DeBin)]
 pub enum ServerMessage {
     KeepAlive,
     Acknowlege(Id),
@@ -28,14 +29,45 @@ pub enum ServerMessage {
     RawData(Vec<u8>),
     AddClientHandle(EntityId, TypeId),
     RemoveClientHandle(EntityId),
-    ModMessage(EntityId, TypeId, Vec<u8>)
+    ModMessage(EntityId, TypeId, Vec<u8>),
+    /// time scale and paused flag of the server, see [`Time`](crate::time::Time)
+    TimeScale(f32, bool)
 }
 
 /// mods: Vec<(ModName, ModFlags, ZipHash, FileSize)>
-#[derive(Debug, SerBin, DeBin)]
+#[derive(Debug, PartialEq, SerBin, DeBin)]
 pub struct ServerInfo {
     pub server_version: String,
     pub mod_profile: String,
     pub mod_version: String,
     pub mods: Vec<(String, Vec<String>, String, u64)>
 }
+
+impl ServerInfo {
+    pub fn mod_profile_hash(&self) -> String {
+        mod_profile_hash(&self.mod_profile, &self.mod_version, self.mods.iter().map(|(name_path, flags, ..)| (name_path, flags)))
+    }
+}
+
+impl MessageKind for ServerMessage {
+    const KINDS: &'static [&'static str] = &["KeepAlive", "Acknowlege", "Unregister", "RegisterResponse", "Kick", "Login", "Logout", "Ping", "Pong", "RawData", "AddClientHandle", "RemoveClientHandle", "ModMessage", "TimeScale"];
+
+    fn kind(&self) -> usize {
+        match self {
+            Self::KeepAlive => 0,
+            Self::Acknowlege(..) => 1,
+            Self::Unregister(..) => 2,
+            Self::RegisterResponse(..) => 3,
+            Self::Kick(..) => 4,
+            Self::Login(..) => 5,
+            Self::Logout(..) => 6,
+            Self::Ping(..) => 7,
+            Self::Pong(..) => 8,
+            Self::RawData(..) => 9,
+            Self::AddClientHandle(..) => 10,
+            Self::RemoveClientHandle(..) => 11,
+            Self::ModMessage(..) => 12,
+            Self::TimeScale(..) => 13,
+        }
+    }
+}
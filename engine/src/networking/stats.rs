@@ -0,0 +1,162 @@
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use super::SendMode;
+
+/// Packet enums whose variants are counted separately by [`TrafficCounters`].
+pub trait MessageKind {
+    /// Names of all variants, indexed by [`MessageKind::kind`]
+    const KINDS: &'static [&'static str];
+    fn kind(&self) -> usize;
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TrafficCount {
+    pub packets: u64,
+    /// serialized packet size, without datagram or tcp framing
+    pub bytes: u64
+}
+
+impl std::ops::Add for TrafficCount {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self { packets: self.packets + rhs.packets, bytes: self.bytes + rhs.bytes }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Counter {
+    packets: AtomicU64,
+    bytes: AtomicU64
+}
+
+impl Counter {
+    fn add(&self, bytes: usize) {
+        self.packets.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    fn get(&self) -> TrafficCount {
+        TrafficCount {
+            packets: self.packets.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed)
+        }
+    }
+}
+
+/// Packets and bytes of one direction, by [`SendMode`] and by message variant.
+/// Recording is a few relaxed atomic adds, so it can be done from any thread and stays enabled.
+pub struct TrafficCounters<M: MessageKind> {
+    by_mode: [Counter; 3],
+    by_kind: Box<[Counter]>,
+    _message: PhantomData<fn(&M)>
+}
+
+impl<M: MessageKind> Default for TrafficCounters<M> {
+    fn default() -> Self {
+        Self {
+            by_mode: Default::default(),
+            by_kind: M::KINDS.iter().map(|_| Counter::default()).collect(),
+            _message: PhantomData
+        }
+    }
+}
+
+impl<M: MessageKind> TrafficCounters<M> {
+    pub fn record(&self, message: &M, mode: SendMode, bytes: usize) {
+        let mode = match mode {
+            SendMode::Quick => 0,
+            SendMode::Ordered => 1,
+            SendMode::Safe => 2
+        };
+        self.by_mode[mode].add(bytes);
+        self.by_kind[message.kind()].add(bytes);
+    }
+
+    pub fn snapshot(&self) -> TrafficSnapshot {
+        TrafficSnapshot {
+            quick: self.by_mode[0].get(),
+            ordered: self.by_mode[1].get(),
+            safe: self.by_mode[2].get(),
+            by_kind: M::KINDS.iter().copied().zip(self.by_kind.iter().map(Counter::get)).collect()
+        }
+    }
+}
+
+/// Counts at the time of [`TrafficCounters::snapshot`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrafficSnapshot {
+    pub quick: TrafficCount,
+    pub ordered: TrafficCount,
+    pub safe: TrafficCount,
+    /// every message variant, including ones never seen
+    pub by_kind: Vec<(&'static str, TrafficCount)>
+}
+
+impl TrafficSnapshot {
+    pub fn total(&self) -> TrafficCount {
+        self.quick + self.ordered + self.safe
+    }
+
+    pub fn kind(&self, name: &str) -> TrafficCount {
+        self.by_kind.iter().find(|(kind, _)| *kind == name).map(|(_, count)| *count).unwrap_or_default()
+    }
+}
+
+/// Traffic of one end of a connection, sending `S` and receiving `R`.
+///
+/// Received datagrams can't be told apart by mode once reassembled, so they are all counted as [`SendMode::Quick`];
+/// everything received over tcp is counted as [`SendMode::Safe`].
+pub struct NetworkStats<S: MessageKind, R: MessageKind> {
+    pub sent: TrafficCounters<S>,
+    pub received: TrafficCounters<R>
+}
+
+impl<S: MessageKind, R: MessageKind> Default for NetworkStats<S, R> {
+    fn default() -> Self {
+        Self { sent: Default::default(), received: Default::default() }
+    }
+}
+
+impl<S: MessageKind, R: MessageKind> NetworkStats<S, R> {
+    pub fn snapshot(&self) -> NetworkStatsSnapshot {
+        NetworkStatsSnapshot {
+            sent: self.sent.snapshot(),
+            received: self.received.snapshot()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NetworkStatsSnapshot {
+    pub sent: TrafficSnapshot,
+    pub received: TrafficSnapshot
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Id;
+    use crate::networking::client_packets::ClientMessage;
+    use crate::networking::server_packets::ServerMessage;
+    use super::*;
+
+    #[test]
+    fn counts_by_mode_and_kind() {
+        let stats = NetworkStats::<ClientMessage, ServerMessage>::default();
+        stats.sent.record(&ClientMessage::KeepAlive, SendMode::Quick, 10);
+        stats.sent.record(&ClientMessage::KeepAlive, SendMode::Quick, 10);
+        stats.sent.record(&ClientMessage::ModMessage(Id::new(), Id::new(), vec![]), SendMode::Ordered, 40);
+        stats.received.record(&ServerMessage::ModMessage(Id::new(), Id::new(), vec![]), SendMode::Safe, 100);
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.sent.quick, TrafficCount { packets: 2, bytes: 20 });
+        assert_eq!(snapshot.sent.ordered, TrafficCount { packets: 1, bytes: 40 });
+        assert_eq!(snapshot.sent.total(), TrafficCount { packets: 3, bytes: 60 });
+        assert_eq!(snapshot.sent.kind("KeepAlive"), TrafficCount { packets: 2, bytes: 20 });
+        assert_eq!(snapshot.sent.kind("Login"), TrafficCount::default());
+        assert_eq!(snapshot.sent.by_kind.len(), ClientMessage::KINDS.len());
+        assert_eq!(snapshot.received.kind("ModMessage"), TrafficCount { packets: 1, bytes: 100 });
+        assert_eq!(snapshot.received.safe, snapshot.received.total());
+    }
+}
@@ -1,12 +1,71 @@
 #[derive(Copy, Clone, Debug)]
 pub struct Time {
-	/// The total time since startup in seconds
+	/// The total scaled time since startup in seconds. Stands still while paused.
 	pub time: f32,
-	/// The time since last frame (client) or last tick (server) in seconds. 
-	/// Capped to 0.05s (equivalent of 20fps) on client and 0.2s on server (equivalent of 5tps) 
+	/// The time since last frame (client) or last tick (server) in seconds, multiplied by `time_scale` and 0 while paused.
+	/// Capped to 0.05s (equivalent of 20fps) on client and 0.2s on server (equivalent of 5tps) before scaling
 	/// to avoid weird interpolation behavior when tabbed out of the game previously, when hitting a lag spike,
 	/// or when the game was paused due to any other reasons.
 	pub delta: f32,
-	/// The uncapped delta time. only use if you know what you are doing and delta is not sufficient.
-	pub raw_delta: f32
-}
\ No newline at end of file
+	/// The uncapped and unscaled delta time. only use if you know what you are doing and delta is not sufficient,
+	/// e.g. for UI animations and fps counters that should keep running while gameplay is slowed down or paused.
+	pub raw_delta: f32,
+	/// Speed of gameplay time, 1.0 being real time. Set by the server and mirrored to all clients.
+	pub time_scale: f32,
+	/// Freezes gameplay time. Set by the server and mirrored to all clients.
+	pub paused: bool
+}
+
+impl Default for Time {
+	fn default() -> Self {
+		Self {
+			time: 0.0,
+			delta: 0.0,
+			raw_delta: 0.0,
+			time_scale: 1.0,
+			paused: false
+		}
+	}
+}
+
+impl Time {
+	/// Delta time for gameplay, same as `delta`.
+	#[inline]
+	pub fn scaled_delta(&self) -> f32 {
+		self.delta
+	}
+
+	/// Delta time as it passed in the real world, same as `raw_delta`.
+	#[inline]
+	pub fn real_delta(&self) -> f32 {
+		self.raw_delta
+	}
+
+	/// Advances by `raw_delta` real seconds, capping the gameplay delta to `max_delta` before applying the time scale.
+	pub fn advance(&mut self, raw_delta: f32, max_delta: f32) {
+		self.raw_delta = raw_delta;
+		self.delta = if self.paused { 0.0 } else { raw_delta.min(max_delta) * self.time_scale };
+		self.time += self.delta;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn scale_and_pause_only_affect_gameplay_time() {
+		let mut time = Time::default();
+		time.advance(0.5, 0.05);
+		assert_eq!((time.scaled_delta(), time.real_delta(), time.time), (0.05, 0.5, 0.05));
+
+		time.time_scale = 0.5;
+		time.advance(0.02, 0.05);
+		assert_eq!(time.scaled_delta(), 0.01);
+
+		time.paused = true;
+		let before = time.time;
+		time.advance(0.02, 0.05);
+		assert_eq!((time.scaled_delta(), time.real_delta(), time.time), (0.0, 0.02, before));
+	}
+}
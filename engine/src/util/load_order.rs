@@ -0,0 +1,79 @@
+use crate::error::{Error, ErrorResult, Fatality};
+use crate::error::builtin::ModError;
+
+/// Orders mods so every mod comes after the mods it depends on.
+///
+/// `mods` are `(name_path, dependencies)` pairs, where `name_path` is the `path:name` key used in `mods.ron`
+/// and each dependency names either the full `path:name` or just the `name` of another mod.
+/// Mods without dependencies between them keep their relative order.
+/// Returns indices into `mods`, or a [`ModError`] naming the offending mod if a dependency is missing or circular.
+pub fn load_order(mods: &[(&str, Vec<&str>)]) -> ErrorResult<Vec<usize>> {
+    let find = |dependency: &str| mods.iter().position(|(name_path, _)|
+        *name_path == dependency || name_path.split_once(':').is_some_and(|(_, name)| name == dependency)
+    );
+
+    let dependencies = mods.iter()
+        .map(|(name_path, deps)| deps.iter()
+            .map(|dep| find(dep).ok_or_else(|| mod_error(format!("mod {name_path} depends on {dep}, which is not loaded"))))
+            .collect::<ErrorResult<Vec<_>>>()
+        )
+        .collect::<ErrorResult<Vec<_>>>()?;
+
+    #[derive(Clone, Copy, PartialEq)]
+    enum State { Unvisited, Visiting, Done }
+
+    fn visit(i: usize, mods: &[(&str, Vec<&str>)], dependencies: &[Vec<usize>], state: &mut [State], stack: &mut Vec<usize>, order: &mut Vec<usize>) -> ErrorResult<()> {
+        match state[i] {
+            State::Done => return Ok(()),
+            State::Visiting => {
+                let start = stack.iter().position(|m| *m == i).unwrap();
+                let cycle = stack[start..].iter().chain(std::iter::once(&i)).map(|m| mods[*m].0).collect::<Vec<_>>();
+                return Err(mod_error(format!("mod {} has circular dependencies: {}", mods[i].0, cycle.join(" -> "))))
+            }
+            State::Unvisited => ()
+        }
+        state[i] = State::Visiting;
+        stack.push(i);
+        for dep in &dependencies[i] {
+            visit(*dep, mods, dependencies, state, stack, order)?;
+        }
+        stack.pop();
+        state[i] = State::Done;
+        order.push(i);
+        Ok(())
+    }
+
+    let mut state = vec![State::Unvisited; mods.len()];
+    let mut order = Vec::with_capacity(mods.len());
+    for i in 0..mods.len() {
+        visit(i, mods, &dependencies, &mut state, &mut vec![], &mut order)?;
+    }
+    Ok(order)
+}
+
+fn mod_error(message: String) -> Box<Error> {
+    Error::new(ModError(message), Fatality::FATAL, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dependencies_load_first() {
+        let mods = [
+            ("worms:worms", vec!["world", "player:player"]),
+            ("debug:debug", vec![]),
+            ("player:player", vec!["world"]),
+            ("world:world", vec![])
+        ];
+        let order = load_order(&mods).unwrap().into_iter().map(|i| mods[i].0).collect::<Vec<_>>();
+        assert_eq!(order, ["world:world", "player:player", "worms:worms", "debug:debug"]);
+
+        let missing = load_order(&[("worms:worms", vec!["world"])]).unwrap_err();
+        assert!(missing.to_string().contains("worms:worms"), "{missing}");
+
+        let cycle = load_order(&[("a:a", vec!["b"]), ("b:b", vec!["a"])]).unwrap_err();
+        assert!(cycle.to_string().contains("a:a -> b:b -> a:a"), "{cycle}");
+    }
+}
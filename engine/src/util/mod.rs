@@ -1,6 +1,8 @@
 pub mod id_map;
 pub mod nullable;
 pub mod generic_assert;
+pub mod load_order;
+pub mod pool;
 
 use std::any::type_name;
 
@@ -13,6 +15,7 @@ use std::hash::SipHasher;
 use crate::error::*;
 use crate::error::builtin::IOError;
 use crate::{Id, TypeId};
+use crate::nanoserde::{SerBin, DeBin};
 
 pub fn unzip_archive<R: std::io::Read + std::io::Seek, P: AsRef<Path> + Display>(zip: R, dest_dir: P) -> ErrorResult<()> {
     let mut archive = zip::read::ZipArchive::new(zip)
@@ -37,6 +40,17 @@ pub fn unzip_archive<R: std::io::Read + std::io::Seek, P: AsRef<Path> + Display>
     Ok(())
 }
 
+/// Serializes `value` with [`SerBin`], deserializes it again and panics if the result is not equal
+/// or not all bytes were read. Meant for testing types sent over the network.
+#[track_caller]
+pub fn assert_ser_bin_roundtrip<T: SerBin + DeBin + PartialEq + std::fmt::Debug>(value: &T) {
+    let bytes = value.serialize_bin();
+    let mut offset = 0;
+    let decoded = T::de_bin(&mut offset, &bytes).unwrap_or_else(|e| panic!("could not deserialize {value:?}: {e:?}"));
+    assert_eq!(&decoded, value, "value changed in ser/de round trip");
+    assert_eq!(offset, bytes.len(), "deserializing {value:?} left {} bytes unread", bytes.len() - offset);
+}
+
 #[cfg(debug_assertions)]
 mod debug_id {
     use std::sync::Mutex;
@@ -0,0 +1,151 @@
+use std::collections::VecDeque;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+
+/// Something a [`Pool`] can hand out again, `recycle` clears it while keeping its allocation.
+pub trait Recycle: Default {
+    fn recycle(&mut self);
+}
+
+impl<T> Recycle for Vec<T> {
+    fn recycle(&mut self) {
+        self.clear();
+    }
+}
+
+impl<T> Recycle for VecDeque<T> {
+    fn recycle(&mut self) {
+        self.clear();
+    }
+}
+
+impl Recycle for String {
+    fn recycle(&mut self) {
+        self.clear();
+    }
+}
+
+/// Reusable buffers for hot paths, [`Pool::get`] hands out a [`Pooled`] item that goes back into the pool when dropped.
+/// Clones share the same items, so a pool can be kept in a store and passed to other threads.
+#[derive(Debug)]
+pub struct Pool<T: Recycle> {
+    free: Arc<Mutex<Vec<T>>>,
+    capacity: usize
+}
+
+impl<T: Recycle> Pool<T> {
+    pub const DEFAULT_CAPACITY: usize = 64;
+
+    pub fn new() -> Self {
+        Self::with_capacity(Self::DEFAULT_CAPACITY)
+    }
+
+    /// Keeps at most `capacity` returned items, any further ones are dropped.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            free: Arc::new(Mutex::new(Vec::with_capacity(capacity))),
+            capacity
+        }
+    }
+
+    /// A returned item if there is one, otherwise a new default one.
+    pub fn get(&self) -> Pooled<T> {
+        let item = self.free.lock().unwrap().pop().unwrap_or_default();
+        Pooled {
+            item: Some(item),
+            pool: self.clone()
+        }
+    }
+
+    /// Number of items waiting to be handed out again
+    pub fn free(&self) -> usize {
+        self.free.lock().unwrap().len()
+    }
+
+    fn reclaim(&self, mut item: T) {
+        item.recycle();
+        let mut free = self.free.lock().unwrap();
+        if free.len() < self.capacity {
+            free.push(item);
+        }
+    }
+}
+
+impl<T: Recycle> Default for Pool<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Recycle> Clone for Pool<T> {
+    fn clone(&self) -> Self {
+        Self {
+            free: self.free.clone(),
+            capacity: self.capacity
+        }
+    }
+}
+
+/// An item of a [`Pool`], derefs to it and returns it to the pool on drop.
+#[derive(Debug)]
+pub struct Pooled<T: Recycle> {
+    item: Option<T>,
+    pool: Pool<T>
+}
+
+impl<T: Recycle> Pooled<T> {
+    /// Keeps the item instead of returning it to the pool.
+    pub fn into_inner(mut self) -> T {
+        self.item.take().unwrap()
+    }
+}
+
+impl<T: Recycle> Deref for Pooled<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.item.as_ref().unwrap()
+    }
+}
+
+impl<T: Recycle> DerefMut for Pooled<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.item.as_mut().unwrap()
+    }
+}
+
+impl<T: Recycle> Drop for Pooled<T> {
+    fn drop(&mut self) {
+        if let Some(item) = self.item.take() {
+            self.pool.reclaim(item);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dropped_items_are_reused() {
+        let pool = Pool::<Vec<u32>>::with_capacity(1);
+        let mut buffer = pool.get();
+        buffer.extend(0..100);
+        let allocation = buffer.as_ptr();
+        drop(buffer);
+        assert_eq!(pool.free(), 1);
+
+        let buffer = pool.get();
+        assert!(buffer.is_empty());
+        assert!(buffer.capacity() >= 100);
+        assert_eq!(buffer.as_ptr(), allocation);
+        assert_eq!(pool.free(), 0);
+
+        // only `capacity` items are kept, detached ones never come back
+        let other = pool.get();
+        drop((buffer, other));
+        assert_eq!(pool.free(), 1);
+        let _ = pool.get().into_inner();
+        assert_eq!(pool.free(), 0);
+    }
+}
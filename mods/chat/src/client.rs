@@ -0,0 +1,265 @@
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use aeonetica_client::{ClientMod, networking::messaging::{ClientHandle, ClientMessenger}, data_store::DataStore, renderer::{layer::Layer, context::RenderContext, Renderer, builtin::DynTextArea, material::FlatTexture, texture::font::BitmapFont}};
+use aeonetica_client::console::{run_command, ConsoleState};
+use aeonetica_client::renderer::window::events::{Event, KeyCode};
+use aeonetica_client::renderer::window::OpenGlRenderContextProvider;
+use aeonetica_engine::{ClientId, TypeId};
+use aeonetica_engine::math::camera::Camera;
+use aeonetica_engine::math::vector::Vector2;
+use aeonetica_engine::networking::SendMode;
+use aeonetica_engine::networking::messaging::ClientEntity;
+use aeonetica_engine::util::id_map::IdMap;
+use aeonetica_engine::util::nullable::Nullable;
+use aeonetica_engine::util::type_to_id;
+use aeonetica_engine::time::Time;
+use world_mod::client::default_font;
+
+use crate::common::{sanitize, MAX_MESSAGE_LEN};
+use crate::server::Chat;
+
+pub struct ChatModClient {
+
+}
+
+impl ChatModClient {
+    pub(crate) fn new() -> Self {
+        Self {}
+    }
+}
+
+impl ClientMod for ChatModClient {
+    fn dependencies(&self) -> Vec<&str> {
+        vec!["world"]
+    }
+
+    fn register_handlers(&self, handlers: &mut IdMap<fn() -> Box<dyn ClientHandle>>, _store: &mut DataStore) {
+        handlers.insert(type_to_id::<ChatHandle>(), ChatHandle::new_boxed);
+    }
+
+    fn start<'a>(&self, store: &mut DataStore, provider: OpenGlRenderContextProvider<'a>) -> &'a mut RenderContext {
+        let context = provider.make_context();
+        store.add_store(ChatFont(default_font().expect("error loading font")));
+        context.push(ChatLayer, store).expect("duplicate layer");
+        context
+    }
+}
+
+struct ChatFont(Rc<BitmapFont>);
+
+/// Overlay the chat is drawn on, everything happens in the [`ChatHandle`].
+pub(crate) struct ChatLayer;
+
+impl Layer for ChatLayer {
+    fn instantiate_camera(&self) -> Camera {
+        Camera::new(0.0, 160.0, 90.0, 0.0, 1.0, -1.0)
+    }
+
+    fn resize_camera(&mut self, camera: &mut Camera, aspect_ratio: f32) {
+        // keep the chat anchored to the bottom left corner
+        camera.set_projection(0.0, 90.0 * aspect_ratio, 90.0, 0.0, 1.0, -1.0);
+    }
+
+    fn name(&self) -> &'static str {
+        "Chat"
+    }
+
+    fn is_overlay(&self) -> bool {
+        true
+    }
+}
+
+/// Chat history and input line. Enter starts typing, Enter again sends and Escape cancels.
+/// Lines starting with `/` are run as console commands instead of being sent.
+///
+/// New messages show up for a few seconds, the whole history while typing, which can be scrolled with the mouse wheel.
+pub(crate) struct ChatHandle {
+    history: VecDeque<String>,
+    /// `Some` while typing
+    input: Option<String>,
+    /// how many of the newest messages are scrolled past
+    scroll: usize,
+    /// seconds until the history hides again
+    visible_for: f32,
+    shown: bool,
+    dirty: bool,
+
+    font: Nullable<Rc<BitmapFont>>,
+    history_text: Nullable<DynTextArea>,
+    input_text: Nullable<DynTextArea>
+}
+
+impl ChatHandle {
+    const MAX_HISTORY: usize = 100;
+    const VISIBLE_MESSAGES: usize = 8;
+    const DISPLAY_SECONDS: f32 = 8.0;
+    const FONT_SIZE: f32 = 3.0;
+    const SPACING: f32 = 0.5;
+    const WRAP_WIDTH: f32 = 80.0;
+    const INPUT_Y: f32 = 84.0;
+    const LEFT: f32 = 2.0;
+    const Z_INDEX: u8 = 240;
+
+    fn new_boxed() -> Box<dyn ClientHandle> {
+        Box::new(Self {
+            history: VecDeque::new(),
+            input: None,
+            scroll: 0,
+            visible_for: 0.0,
+            shown: false,
+            dirty: true,
+            font: Nullable::Null,
+            history_text: Nullable::Null,
+            input_text: Nullable::Null
+        })
+    }
+
+    pub(crate) fn receive_message(&mut self, _messenger: &mut ClientMessenger, _renderer: Nullable<&mut Renderer>, _store: &mut DataStore, (sender, text): (ClientId, String)) {
+        // the first block of the id is enough to tell players apart
+        let sender = sender.to_string();
+        self.print(format!("<{}> {text}", sender.split('-').next().unwrap_or(&sender)));
+    }
+
+    pub(crate) fn receive_notice(&mut self, _messenger: &mut ClientMessenger, _renderer: Nullable<&mut Renderer>, _store: &mut DataStore, notice: String) {
+        self.print(notice);
+    }
+
+    fn print(&mut self, text: String) {
+        for line in text.lines() {
+            if self.history.len() == Self::MAX_HISTORY {
+                self.history.pop_front();
+            }
+            self.history.push_back(line.to_string());
+        }
+        self.scroll = 0;
+        self.visible_for = Self::DISPLAY_SECONDS;
+        self.dirty = true;
+    }
+
+    fn submit(&mut self, messenger: &mut ClientMessenger, store: &mut DataStore) {
+        let Some(input) = self.input.take() else {
+            return
+        };
+        if let Some(command) = input.strip_prefix('/') {
+            self.print(input.clone());
+            match run_command(command, store) {
+                Ok(output) if output.is_empty() => (),
+                Ok(output) => self.print(output),
+                Err(message) => self.print(format!("error: {message}"))
+            }
+        }
+        else if let Some(text) = sanitize(&input) {
+            // shows up in the history once the server broadcasts it back
+            messenger.call_server_fn(Chat::receive_message, text, SendMode::Safe);
+        }
+    }
+
+    /// The newest messages that fit, joined by newlines
+    fn visible_history(&self) -> String {
+        let mut chars = 0;
+        let mut lines = self.history.iter().rev()
+            .skip(self.scroll)
+            .take(Self::VISIBLE_MESSAGES)
+            .take_while(|line| {
+                chars += line.chars().count() + 1;
+                chars <= DynTextArea::MAX_CAPACITY
+            })
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        lines.reverse();
+        lines.join("\n")
+    }
+
+    fn update_text(&mut self, renderer: &mut Renderer) {
+        let history = self.visible_history();
+        // grow upwards from the input line
+        let lines = self.font.layout(&history, Self::FONT_SIZE, Self::SPACING, Some(Self::WRAP_WIDTH))
+            .last()
+            .map_or(0.0, |glyph| (glyph.position.y / Self::FONT_SIZE).round() + 1.0);
+        self.history_text.set_position(Vector2::new(Self::LEFT, Self::INPUT_Y - lines * Self::FONT_SIZE));
+        self.history_text.set_string(renderer, history);
+
+        let input = self.input.as_ref().map(|input| format!("> {input}_")).unwrap_or_default();
+        self.input_text.set_string(renderer, input);
+    }
+}
+
+impl ClientEntity for ChatHandle {}
+
+impl ClientHandle for ChatHandle {
+    fn owning_layer(&self) -> TypeId {
+        type_to_id::<ChatLayer>()
+    }
+
+    fn start(&mut self, messenger: &mut ClientMessenger, _renderer: Nullable<&mut Renderer>, store: &mut DataStore) {
+        messenger.register_receiver(ChatHandle::receive_message);
+        messenger.register_receiver(ChatHandle::receive_notice);
+
+        let font = store.get_store::<ChatFont>().0.clone();
+        let text_area = |y| DynTextArea::with_string(Vector2::new(Self::LEFT, y), Self::Z_INDEX, Self::FONT_SIZE, Self::SPACING, font.clone(), FlatTexture::get(), "")
+            .with_wrap_width(Self::WRAP_WIDTH);
+        self.history_text = Nullable::Value(text_area(Self::INPUT_Y - Self::FONT_SIZE));
+        self.input_text = Nullable::Value(text_area(Self::INPUT_Y));
+        self.font = Nullable::Value(font);
+    }
+
+    fn update(&mut self, _messenger: &mut ClientMessenger, renderer: &mut Renderer, _store: &mut DataStore, time: Time) {
+        self.visible_for = (self.visible_for - time.real_delta()).max(0.0);
+        if self.input.is_none() && self.visible_for == 0.0 {
+            if self.shown {
+                renderer.remove(&mut *self.history_text);
+                renderer.remove(&mut *self.input_text);
+                self.shown = false;
+            }
+            return
+        }
+
+        if self.dirty {
+            self.update_text(renderer);
+            self.dirty = false;
+        }
+        let _ = renderer.draw(&mut *self.history_text);
+        let _ = renderer.draw(&mut *self.input_text);
+        self.shown = true;
+    }
+
+    fn event(&mut self, event: &Event, messenger: &mut ClientMessenger, _renderer: &mut Renderer, store: &mut DataStore) -> bool {
+        if ConsoleState::is_open_in(store) {
+            return false
+        }
+        let Some(input) = &mut self.input else {
+            if let Event::KeyPressed(KeyCode::Enter) = event {
+                self.input = Some(String::new());
+                self.dirty = true;
+                return true
+            }
+            return false
+        };
+
+        match event {
+            Event::KeyPressed(KeyCode::Enter) => self.submit(messenger, store),
+            Event::KeyPressed(KeyCode::Escape) => self.input = None,
+            Event::KeyPressed(KeyCode::Backspace) => { input.pop(); }
+            Event::CharTyped(c) => if !c.is_control() && input.chars().count() < MAX_MESSAGE_LEN {
+                input.push(*c);
+            }
+            Event::MouseScrolled(offset) => {
+                let max_scroll = self.history.len().saturating_sub(1);
+                self.scroll = (self.scroll as isize + offset.y.signum() as isize).clamp(0, max_scroll as isize) as usize;
+            }
+            // typing must not trigger gameplay
+            Event::KeyPressed(_) | Event::MouseButtonPressed(_) | Event::MouseButtonReleased(_) => (),
+            _ => return false
+        }
+        self.dirty = true;
+        true
+    }
+
+    fn remove(&mut self, _messenger: &mut ClientMessenger, mut renderer: Nullable<&mut Renderer>, _store: &mut DataStore) {
+        if self.shown {
+            renderer.remove(&mut *self.history_text);
+            renderer.remove(&mut *self.input_text);
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use aeonetica_engine::networking::MAX_PACKET_SIZE;
+
+/// Longest chat message in characters
+pub(crate) const MAX_MESSAGE_LEN: usize = 200;
+/// Longest chat message in bytes, so a message with the sender's id always fits into a single packet
+pub(crate) const MAX_MESSAGE_BYTES: usize = 512;
+
+#[allow(clippy::assertions_on_constants)]
+const _: () = assert!(MAX_MESSAGE_BYTES + 128 < MAX_PACKET_SIZE);
+
+/// Trims `text`, turns tabs and newlines into spaces, strips other control characters and cuts it to [`MAX_MESSAGE_LEN`] characters and [`MAX_MESSAGE_BYTES`] bytes.
+/// Returns `None` if nothing is left to send.
+///
+/// Clients sanitize before sending and the server again before broadcasting, since it can't trust clients.
+pub(crate) fn sanitize(text: &str) -> Option<String> {
+    let mut message = String::new();
+    let chars = text.trim().chars()
+        .map(|c| if c.is_whitespace() { ' ' } else { c })
+        .filter(|c| !c.is_control());
+    for c in chars.take(MAX_MESSAGE_LEN) {
+        if message.len() + c.len_utf8() > MAX_MESSAGE_BYTES {
+            break
+        }
+        message.push(c);
+    }
+    let message = message.trim_end();
+    (!message.is_empty()).then(|| message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_are_sanitized_and_limited() {
+        assert_eq!(sanitize("  hello\tworld \n").as_deref(), Some("hello world"));
+        assert_eq!(sanitize(" \n\t "), None);
+        assert_eq!(sanitize("\u{7}"), None);
+
+        let long = "a".repeat(MAX_MESSAGE_LEN * 2);
+        assert_eq!(sanitize(&long).unwrap().len(), MAX_MESSAGE_LEN);
+
+        let wide = "€".repeat(MAX_MESSAGE_LEN);
+        let message = sanitize(&wide).unwrap();
+        assert!(message.len() <= MAX_MESSAGE_BYTES);
+        assert!(message.chars().all(|c| c == '€'), "multibyte characters must not be cut in half");
+    }
+}
@@ -0,0 +1,7 @@
+use aeonetica_engine::register;
+
+pub(crate) mod client;
+pub(crate) mod common;
+pub(crate) mod server;
+
+register!(client::ChatModClient::new(), server::ChatModServer::new());
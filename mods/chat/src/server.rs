@@ -0,0 +1,136 @@
+use std::time::Instant;
+
+use aeonetica_engine::{ClientId, EntityId, log};
+use aeonetica_engine::networking::SendMode;
+use aeonetica_engine::util::id_map::IdMap;
+use aeonetica_server::ServerMod;
+use aeonetica_server::ecs::Engine;
+use aeonetica_server::ecs::events::ConnectionListener;
+use aeonetica_server::ecs::messaging::Messenger;
+use aeonetica_server::ecs::module::Module;
+
+use crate::client::ChatHandle;
+use crate::common::sanitize;
+
+pub const CHAT: &str = "CHAT";
+
+/// Messages a client can send at once before being rate limited
+const BURST: f32 = 5.0;
+/// Messages per second a client can send in the long run
+const MESSAGES_PER_SECOND: f32 = 0.5;
+
+pub struct ChatModServer {
+
+}
+
+impl ChatModServer {
+    pub(crate) fn new() -> Self {
+        Self {}
+    }
+}
+
+impl ServerMod for ChatModServer {
+    fn start(&mut self, engine: &mut Engine) {
+        let eid = engine.new_entity();
+        engine.tag_entity(eid, CHAT);
+        let mut chat = engine.mut_entity(&eid);
+        chat.add_module(Chat { limiter: RateLimiter::new(BURST, MESSAGES_PER_SECOND) });
+        chat.add_module(Messenger::new::<ChatHandle>());
+        chat.mut_module::<Messenger>().register_receiver(Chat::receive_message);
+        chat.add_module(ConnectionListener::new(
+            |id, engine, client| {
+                engine.mut_module_of::<Messenger>(id).add_client(*client);
+            },
+            |id, engine, client| {
+                engine.mut_module_of::<Messenger>(id).remove_client(client);
+                engine.mut_module_of::<Chat>(id).limiter.forget(client);
+            }
+        ));
+    }
+}
+
+/// Broadcasts chat messages to all clients.
+pub struct Chat {
+    limiter: RateLimiter
+}
+
+impl Module for Chat {
+
+}
+
+impl Chat {
+    pub(crate) fn receive_message(id: &EntityId, engine: &mut Engine, client: &ClientId, text: String) {
+        let Some(text) = sanitize(&text) else {
+            return
+        };
+        let (mut chat, mut messenger) = engine.two_mut_modules_of::<Chat, Messenger>(id);
+        if !chat.limiter.allow(client, Instant::now()) {
+            messenger.call_client_fn_for(ChatHandle::receive_notice, client, "you are sending messages too quickly".to_string(), SendMode::Safe);
+            return
+        }
+        log!("chat message from {client}: {text}");
+        messenger.call_client_fn(ChatHandle::receive_message, (*client, text), SendMode::Safe);
+    }
+}
+
+/// Token bucket per client: up to `burst` messages at once, refilled by `per_second` messages every second.
+struct RateLimiter {
+    burst: f32,
+    per_second: f32,
+    /// remaining messages and when they were last refilled
+    buckets: IdMap<(f32, Instant)>
+}
+
+impl RateLimiter {
+    fn new(burst: f32, per_second: f32) -> Self {
+        Self { burst, per_second, buckets: Default::default() }
+    }
+
+    /// Takes a message from `client`'s bucket, returns `false` if it's empty.
+    fn allow(&mut self, client: &ClientId, now: Instant) -> bool {
+        let (messages, refilled) = self.buckets.entry(*client).or_insert((self.burst, now));
+        *messages = (*messages + now.saturating_duration_since(*refilled).as_secs_f32() * self.per_second).min(self.burst);
+        *refilled = now;
+        if *messages >= 1.0 {
+            *messages -= 1.0;
+            true
+        }
+        else {
+            false
+        }
+    }
+
+    fn forget(&mut self, client: &ClientId) {
+        self.buckets.remove(client);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use aeonetica_engine::Id;
+    use super::*;
+
+    #[test]
+    fn clients_are_rate_limited_separately() {
+        let mut limiter = RateLimiter::new(3.0, 1.0);
+        let (spammer, other) = (Id::new(), Id::new());
+        let start = Instant::now();
+
+        assert!((0..3).all(|_| limiter.allow(&spammer, start)));
+        assert!(!limiter.allow(&spammer, start));
+        assert!(limiter.allow(&other, start));
+
+        assert!(!limiter.allow(&spammer, start + Duration::from_millis(500)));
+        assert!(limiter.allow(&spammer, start + Duration::from_millis(1500)));
+        assert!(!limiter.allow(&spammer, start + Duration::from_millis(1600)));
+
+        // the bucket never holds more than the burst
+        let later = start + Duration::from_secs(60);
+        assert!((0..3).all(|_| limiter.allow(&spammer, later)));
+        assert!(!limiter.allow(&spammer, later));
+
+        limiter.forget(&spammer);
+        assert!(limiter.allow(&spammer, later));
+    }
+}
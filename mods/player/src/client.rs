@@ -4,7 +4,8 @@ use aeonetica_client::ClientMod;
 use aeonetica_client::data_store::DataStore;
 use aeonetica_client::networking::messaging::{ClientHandle, ClientMessenger};
 use aeonetica_client::renderer::material::{FlatTexture};
-use aeonetica_client::renderer::window::events::{Event, KeyCode};
+use aeonetica_client::actions::{Action, ActionMap};
+use aeonetica_client::renderer::window::events::Event;
 use aeonetica_client::renderer::{Renderer, builtin::Quad};
 use aeonetica_client::renderer::builtin::Line;
 use aeonetica_client::renderer::context::RenderContext;
@@ -33,6 +34,10 @@ pub struct PlayerModClient {
 }
 
 impl ClientMod for PlayerModClient {
+    fn dependencies(&self) -> Vec<&str> {
+        vec!["world"]
+    }
+
     fn register_handlers(&self, handlers: &mut IdMap<fn() -> Box<dyn ClientHandle>>, _store: &mut DataStore) {
         handlers.insert(type_to_id::<PlayerHandle>(), || Box::new(PlayerHandle::new()));
         log!("registered  client player mod stuffs");
@@ -192,7 +197,7 @@ impl ClientHandle for PlayerHandle {
                 let world = store.get_store::<ClientWorld>();
                 let p = self.position;
                 let mov_delta = v * time.delta as f32;
-                world.calc_move(&mut self.position, Vector2::new(PLAYER_SIZE, PLAYER_SIZE), mov_delta);
+                world.calc_move_in_water(&mut self.position, Vector2::new(PLAYER_SIZE, PLAYER_SIZE), mov_delta);
                 let delta = self.position - p;
                 if delta.x.abs() < 0.01 * time.delta as f32 {
                     self.velocity.x = 0.0;
@@ -228,35 +233,16 @@ impl ClientHandle for PlayerHandle {
         let _ = renderer.draw(quad);
     }
 
-    fn event(&mut self, event: &Event, _messenger: &mut ClientMessenger, _renderer: &mut Renderer, _store: &mut DataStore) -> bool {
+    fn event(&mut self, event: &Event, _messenger: &mut ClientMessenger, _renderer: &mut Renderer, store: &mut DataStore) -> bool {
         if !self.is_controlling { return false }
-        match event {
-            Event::KeyPressed(KeyCode::Space) => {
-                self.key_hover = true;
-                true
-            }
-            Event::KeyPressed(KeyCode::A) => {
-                self.key_left = true;
-                true
-            }
-            Event::KeyPressed(KeyCode::D) => {
-                self.key_right = true;
-                true
-            }
-            Event::KeyReleased(KeyCode::Space) => {
-                self.key_hover = false;
-                true
-            }
-            Event::KeyReleased(KeyCode::A) => {
-                self.key_left = false;
-                true
-            }
-            Event::KeyReleased(KeyCode::D) => {
-                self.key_right = false;
-                true
+        let actions = store.mut_or_default::<ActionMap>();
+        for (action, held) in [(Action::Hover, &mut self.key_hover), (Action::MoveLeft, &mut self.key_left), (Action::MoveRight, &mut self.key_right)] {
+            if let Some(pressed) = actions.state(event, action) {
+                *held = pressed;
+                return true
             }
-            _ => false
         }
+        false
     }
 }
 
@@ -18,6 +18,10 @@ pub struct PlayerModServer {
 }
 
 impl ServerMod for PlayerModServer {
+    fn dependencies(&self) -> Vec<&str> {
+        vec!["world"]
+    }
+
     fn start(&mut self, engine: &mut Engine) {
         log!("starting player mod server...");
         let eid = engine.new_entity();
@@ -98,10 +102,10 @@ pub struct Player {
 }
 
 impl Player {
-    pub(crate) fn client_position_update(id: &EntityId, engine: &mut Engine, _client_id: &ClientId, (position, teleporting): (Vector2<f32>, bool)) {
+    pub(crate) fn client_position_update(id: &EntityId, engine: &mut Engine, client_id: &ClientId, (position, teleporting): (Vector2<f32>, bool)) {
         let mut player = engine.mut_entity(id);
         player.mut_module::<Player>().position = position;
-        player.mut_module::<Messenger>().call_client_fn(PlayerHandle::receive_position, (position, teleporting), SendMode::Safe);
+        player.mut_module::<Messenger>().call_client_fn_except(PlayerHandle::receive_position, client_id, (position, teleporting), SendMode::Safe);
     }
 }
 
@@ -0,0 +1,38 @@
+#[description]
+Instanced vertex stage for tiles, every instance is one tile. The fragment stage is shared with terrain-shader.glsl
+
+#[vertex]
+#version 450 core
+
+#define MAX_LIGHT_SOURCE_COUNT 30
+
+layout (location = 0) in vec2 a_Corner;
+layout (location = 1) in vec2 i_Position;
+layout (location = 2) in vec2 i_Size;
+layout (location = 3) in vec2 i_TexOffset;
+layout (location = 4) in vec2 i_TexSize;
+layout (location = 5) in int i_TexIdx;
+
+uniform mat4 u_ViewProjection;
+uniform vec2 u_LightPositions[MAX_LIGHT_SOURCE_COUNT];
+uniform uint u_NumLights = MAX_LIGHT_SOURCE_COUNT;
+
+out vec2 v_TexCoord;
+out vec2 v_Position;
+out float v_LightDistances[MAX_LIGHT_SOURCE_COUNT];
+flat out int v_TexIdx;
+flat out float v_NormalMapped;
+
+void main() {
+    vec2 position = i_Position + a_Corner * i_Size;
+    v_TexCoord = i_TexOffset + a_Corner * i_TexSize;
+    v_TexIdx = i_TexIdx;
+    v_Position = position;
+    v_NormalMapped = 0.0;
+    gl_Position = u_ViewProjection * vec4(position, 0.0, 1.0);
+
+    for(uint i = 0; i < u_NumLights; i++) {
+        v_LightDistances[i] = length(vec3(u_LightPositions[i], 1.0) - vec3(position, 0.0));
+    }
+}
+
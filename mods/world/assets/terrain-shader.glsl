@@ -9,18 +9,24 @@ Shader for normal world terrain
 layout (location = 0) in vec2 a_Position;
 layout (location = 1) in vec2 a_TexCoord;
 layout (location = 2) in int a_TexIdx;
+// 1.0 for quads using the normal map, layouts without this attribute read it as 0.0
+layout (location = 3) in float a_NormalMapped;
 
 uniform mat4 u_ViewProjection;
 uniform vec2 u_LightPositions[MAX_LIGHT_SOURCE_COUNT];
 uniform uint u_NumLights = MAX_LIGHT_SOURCE_COUNT;
 
 out vec2 v_TexCoord;
+out vec2 v_Position;
 out float v_LightDistances[MAX_LIGHT_SOURCE_COUNT];
 flat out int v_TexIdx;
+flat out float v_NormalMapped;
 
 void main() {
     v_TexCoord = a_TexCoord;
     v_TexIdx = a_TexIdx;
+    v_Position = a_Position;
+    v_NormalMapped = a_NormalMapped;
     gl_Position = u_ViewProjection * vec4(a_Position, 0.0, 1.0);
 
     for(uint i = 0; i < u_NumLights; i++) {
@@ -34,26 +40,83 @@ void main() {
 #define MAX_LIGHT_SOURCE_COUNT 30
 
 in vec2 v_TexCoord;
+in vec2 v_Position;
 in float v_LightDistances[MAX_LIGHT_SOURCE_COUNT];
 flat in int v_TexIdx;
+flat in float v_NormalMapped;
 
 uniform sampler2D u_Textures[16];
+uniform sampler2D u_NormalMap;
+uniform int u_HasNormalMap = 0;
 
 uniform uint u_NumLights = MAX_LIGHT_SOURCE_COUNT;
+uniform vec2 u_LightPositions[MAX_LIGHT_SOURCE_COUNT];
 uniform vec3 u_LightColors[MAX_LIGHT_SOURCE_COUNT];
 uniform float u_LightIntensities[MAX_LIGHT_SOURCE_COUNT];
+uniform int u_LightCastsShadows[MAX_LIGHT_SOURCE_COUNT];
 uniform float u_AmbientLightStrength;
 
+// one texel per tile, red is set for solid tiles
+uniform sampler2D u_OcclusionMap;
+uniform vec2 u_OcclusionOrigin;
+uniform int u_HasOcclusionMap = 0;
+
 layout (location = 0) out vec4 r_Color;
 layout (location = 1) out vec4 r_WaterDepthMap;
 
+const vec3 FLAT_NORMAL = vec3(0.0, 0.0, 1.0);
+
+vec3 surface_normal() {
+    if(u_HasNormalMap == 0 || v_NormalMapped < 0.5) {
+        return FLAT_NORMAL;
+    }
+    vec3 normal = texture(u_NormalMap, v_TexCoord).rgb * 2.0 - 1.0;
+    // normal maps point green up, world y points down
+    normal.y = -normal.y;
+    return normalize(normal);
+}
+
+#define SHADOW_STEP 0.25
+
+bool is_solid(ivec2 tile) {
+    ivec2 texel = tile - ivec2(u_OcclusionOrigin);
+    ivec2 size = textureSize(u_OcclusionMap, 0);
+    if(any(lessThan(texel, ivec2(0))) || any(greaterThanEqual(texel, size))) {
+        return false;
+    }
+    return texelFetch(u_OcclusionMap, texel, 0).r > 0.5;
+}
+
+// marches from the fragment towards the light, the tile the fragment is on does not occlude itself
+bool in_shadow(vec2 light_position, float max_distance) {
+    vec2 delta = light_position - v_Position;
+    float distance = min(length(delta), max_distance);
+    vec2 dir = delta / max(length(delta), 0.0001);
+    ivec2 own_tile = ivec2(floor(v_Position));
+
+    for(float t = SHADOW_STEP; t < distance; t += SHADOW_STEP) {
+        ivec2 tile = ivec2(floor(v_Position + dir * t));
+        if(tile != own_tile && is_solid(tile)) {
+            return true;
+        }
+    }
+    return false;
+}
+
 void main() {
+    vec3 normal = surface_normal();
     vec3 total_diffuse = vec3(u_AmbientLightStrength);
 
     float intensity;
     for(uint i = 0; i < u_NumLights; i++) {
         intensity = u_LightIntensities[i];
-        total_diffuse += u_LightColors[i] * (intensity - min(v_LightDistances[i], intensity)) / intensity;
+        if(v_LightDistances[i] >= intensity || (u_HasOcclusionMap != 0 && u_LightCastsShadows[i] != 0 && in_shadow(u_LightPositions[i], intensity))) {
+            continue;
+        }
+        vec3 light_dir = normalize(vec3(u_LightPositions[i], 1.0) - vec3(v_Position, 0.0));
+        // shading relative to a flat surface, so tiles without a normal map stay unchanged
+        float shading = clamp(max(dot(normal, light_dir), 0.0) / max(light_dir.z, 0.001), 0.0, 2.0);
+        total_diffuse += u_LightColors[i] * (intensity - min(v_LightDistances[i], intensity)) / intensity * shading;
     }
 
     r_Color = texture(u_Textures[v_TexIdx], v_TexCoord) * vec4(total_diffuse, 1.0);
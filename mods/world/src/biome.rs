@@ -0,0 +1,104 @@
+use crate::tiles::{Tile, FgTile};
+
+/// Everything the world generator needs to know about a biome.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BiomeProperties {
+    /// background tile of open areas
+    pub background: Tile,
+    /// background tile of dense areas
+    pub stone: Tile,
+    /// background tile of rare accents and the space caves
+    pub accent: Tile,
+    /// decoration placed on cave floors
+    pub decoration: FgTile,
+    /// one in how many floor tiles gets a decoration, 0 for none
+    pub decoration_rarity: u32,
+    /// cave noise threshold above which terrain is open; higher values make the biome more closed off
+    pub cave_threshold: f64
+}
+
+/// Biomes in the order they appear along the biome noise, so neighbouring biomes can be blended.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Biome {
+    Laboratory,
+    Caverns,
+    Ruins
+}
+
+const BIOME_PROPERTIES: [BiomeProperties; Biome::ALL.len()] = [
+    BiomeProperties {
+        background: Tile::LabBrickWall,
+        stone: Tile::LabWall,
+        accent: Tile::HardStone,
+        decoration: FgTile::FluorecentLampM,
+        decoration_rarity: 24,
+        cave_threshold: 0.05
+    },
+    BiomeProperties {
+        background: Tile::StoneBrick,
+        stone: Tile::Stone,
+        accent: Tile::HardStone,
+        decoration: FgTile::Empty,
+        decoration_rarity: 0,
+        cave_threshold: 0.0
+    },
+    BiomeProperties {
+        background: Tile::MossyStoneBrick,
+        stone: Tile::StoneBrick,
+        accent: Tile::Stone,
+        decoration: FgTile::ChainV,
+        decoration_rarity: 40,
+        cave_threshold: -0.1
+    }
+];
+
+impl Biome {
+    pub const ALL: [Biome; 3] = [Biome::Laboratory, Biome::Caverns, Biome::Ruins];
+    /// Biome noise values separating neighbouring biomes in [`Biome::ALL`].
+    pub(crate) const BOUNDARIES: [f64; Biome::ALL.len() - 1] = [-0.3, 0.3];
+    /// Half the width (in biome noise units) of the band around a boundary in which two biomes are blended.
+    pub(crate) const BLEND_WIDTH: f64 = 0.08;
+
+    pub fn properties(&self) -> &'static BiomeProperties {
+        &BIOME_PROPERTIES[*self as usize]
+    }
+
+    /// The biome owning the biome noise value `value`.
+    pub(crate) fn from_noise(value: f64) -> Biome {
+        let index = Biome::BOUNDARIES.iter().take_while(|b| value >= **b).count();
+        Biome::ALL[index]
+    }
+
+    /// The cave threshold at `value`, interpolated linearly across boundaries so caves don't end in a hard seam.
+    pub(crate) fn blended_cave_threshold(value: f64) -> f64 {
+        for (i, boundary) in Biome::BOUNDARIES.iter().enumerate() {
+            let t = (value - boundary + Biome::BLEND_WIDTH) / (2.0 * Biome::BLEND_WIDTH);
+            if (0.0..1.0).contains(&t) {
+                let (a, b) = (Biome::ALL[i].properties(), Biome::ALL[i + 1].properties());
+                return a.cave_threshold + (b.cave_threshold - a.cave_threshold) * t
+            }
+        }
+        Biome::from_noise(value).properties().cave_threshold
+    }
+
+    /// The biome whose tiles are used at `value`. Inside a blend band, tiles of both biomes are dithered
+    /// using `jitter` in `[0, 1)`, which should be derived from the tile position.
+    pub(crate) fn dithered(value: f64, jitter: f64) -> Biome {
+        Biome::from_noise(value + (jitter * 2.0 - 1.0) * Biome::BLEND_WIDTH)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blending_is_continuous() {
+        for boundary in Biome::BOUNDARIES {
+            let inside = Biome::blended_cave_threshold(boundary - Biome::BLEND_WIDTH - 0.001);
+            let edge = Biome::blended_cave_threshold(boundary - Biome::BLEND_WIDTH + 0.001);
+            assert!((inside - edge).abs() < 0.01);
+            assert_ne!(Biome::from_noise(boundary - 0.001), Biome::from_noise(boundary + 0.001));
+        }
+    }
+}
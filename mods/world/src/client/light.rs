@@ -1,8 +1,10 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 
-use aeonetica_client::{data_store::DataStore, renderer::shader};
+use aeonetica_client::{data_store::DataStore, renderer::{shader, texture::{Texture, Format}, util::max_texture_units}};
 use aeonetica_engine::math::vector::*;
 use crate::client::light::shader::*;
+use crate::common::{Chunk, CHUNK_SIZE, WorldView};
+use crate::tiles::{FgTile, Tile};
 
 pub type LightId = u32;
 
@@ -10,16 +12,40 @@ pub struct LightStore {
     lights: BTreeMap<LightId, Light>,
     ambient_light: f32,
     is_dirty: bool,
-    light_id: u32
+    light_id: u32,
+    /// the lights uploaded to the shaders, see [`LightStore::cull`]
+    active: Vec<LightId>,
+    /// lights of glowing tiles, see [`LightStore::bulk_add`]
+    chunk_lights: HashMap<Vector2<i32>, Vec<LightId>>,
+    occlusion: OcclusionMap
 }
 
-const MAX_LIGHT_SOURCE_COUNT: usize = 30;
+/// Solid tiles in a square around the camera, used by the terrain shader to cast shadows.
+struct OcclusionMap {
+    texture: Texture,
+    origin: Vector2<i32>,
+    is_dirty: bool
+}
+
+/// Side length of the occlusion map in tiles.
+const OCCLUSION_MAP_SIZE: u32 = 128;
+/// How far the camera may move away from the center of the occlusion map before it gets rebuilt.
+const OCCLUSION_MAP_REBUILD_DISTANCE: i32 = 16;
+/// Texture unit of the occlusion map, after the 16 batch units and the normal map.
+const OCCLUSION_MAP_SLOT: u32 = 17;
+
+/// Size of the light arrays in the terrain shaders. Only this many lights, the ones nearest to the camera, are active at a time.
+pub const MAX_ACTIVE_LIGHTS: usize = 30;
 
 const LIGHT_POSITIONS_USTR: UniformStr = uniform_str!("u_LightPositions");
 pub(super) const AMBIENT_LIGHT_STRENGTH_USTR: UniformStr = uniform_str!("u_AmbientLightStrength");
 const INTENSITIES_USTR: UniformStr = uniform_str!("u_LightIntensities");
 const LIGHT_COLORS_USTR: UniformStr = uniform_str!("u_LightColors");
 const NUM_LIGHTS_USTR: UniformStr = uniform_str!("u_NumLights");
+const CASTS_SHADOWS_USTR: UniformStr = uniform_str!("u_LightCastsShadows");
+const OCCLUSION_MAP_USTR: UniformStr = uniform_str!("u_OcclusionMap");
+const OCCLUSION_ORIGIN_USTR: UniformStr = uniform_str!("u_OcclusionOrigin");
+const HAS_OCCLUSION_MAP_USTR: UniformStr = uniform_str!("u_HasOcclusionMap");
 
 impl LightStore {
     pub fn init(store: &mut DataStore) {
@@ -27,10 +53,35 @@ impl LightStore {
             lights: BTreeMap::new(),
             ambient_light: 0.2,
             is_dirty: true,
-            light_id: 0
+            light_id: 0,
+            active: vec![],
+            chunk_lights: HashMap::new(),
+            occlusion: OcclusionMap {
+                texture: Texture::create(Vector2::new(OCCLUSION_MAP_SIZE, OCCLUSION_MAP_SIZE), Format::RgbaU8),
+                origin: Vector2::default(),
+                is_dirty: true
+            }
         });
     }
 
+    /// Makes the next [`LightStore::update_occlusion`] rebuild the occlusion map, call this whenever solid tiles change.
+    pub fn invalidate_occlusion(&mut self) {
+        self.occlusion.is_dirty = true;
+    }
+
+    /// Rebuilds the occlusion map around `center` if tiles changed or `center` moved too far away from the last one.
+    pub fn update_occlusion(&mut self, world: &impl WorldView, center: Vector2<i32>) {
+        let half_size = OCCLUSION_MAP_SIZE as i32 / 2;
+        let offset = center - self.occlusion.origin - Vector2::new(half_size, half_size);
+        if !self.occlusion.is_dirty && offset.x.abs() <= OCCLUSION_MAP_REBUILD_DISTANCE && offset.y.abs() <= OCCLUSION_MAP_REBUILD_DISTANCE {
+            return;
+        }
+
+        self.occlusion.origin = center - Vector2::new(half_size, half_size);
+        self.occlusion.texture.set_data(&occlusion_data(world, self.occlusion.origin, OCCLUSION_MAP_SIZE));
+        self.occlusion.is_dirty = false;
+    }
+
     pub fn add(&mut self, light: Light) -> LightId {
         let id = self.light_id;
         self.light_id += 1;
@@ -46,10 +97,35 @@ impl LightStore {
         self.is_dirty = true;
     }
 
+    /// Adds lights belonging to a chunk, e.g. its [`chunk_lights`]. [`LightStore::bulk_remove`] removes them all at once.
+    pub fn bulk_add(&mut self, chunk: Vector2<i32>, lights: impl IntoIterator<Item = Light>) {
+        let ids = lights.into_iter().map(|light| self.add(light)).collect::<Vec<_>>();
+        self.chunk_lights.entry(chunk).or_default().extend(ids);
+    }
+
+    /// Removes every light added for the chunk with [`LightStore::bulk_add`], call this when it unloads.
+    pub fn bulk_remove(&mut self, chunk: &Vector2<i32>) {
+        for id in self.chunk_lights.remove(chunk).unwrap_or_default() {
+            self.remove(&id);
+        }
+    }
+
     pub fn update(&mut self, id: &LightId, light: Light) {
         *self.lights.get_mut(id).unwrap() = light;
     }
 
+    /// Picks the [`MAX_ACTIVE_LIGHTS`] lights nearest to `center`, usually the camera, to be uploaded.
+    /// Call it every frame before [`LightStore::upload_uniforms`], lights beyond the cap are not drawn.
+    pub fn cull(&mut self, center: Vector2<f32>) {
+        self.active = nearest_lights(&self.lights, center, MAX_ACTIVE_LIGHTS);
+        self.is_dirty = true;
+    }
+
+    /// Number of lights uploaded to the shaders, at most [`MAX_ACTIVE_LIGHTS`].
+    pub fn active_light_count(&self) -> usize {
+        self.active.len()
+    }
+
     pub fn ambient_light(&self) -> f32 {
         self.ambient_light
     }
@@ -62,35 +138,75 @@ impl LightStore {
     }
 
     pub fn upload_uniforms(&self, shader: &shader::Program) {
+        shader.bind();
+        if max_texture_units() > OCCLUSION_MAP_SLOT {
+            self.occlusion.texture.bind(OCCLUSION_MAP_SLOT);
+            shader.upload_uniform(&OCCLUSION_MAP_USTR, &(OCCLUSION_MAP_SLOT as i32));
+            shader.upload_uniform(&OCCLUSION_ORIGIN_USTR, &self.occlusion.origin.to_f32());
+            shader.upload_uniform(&HAS_OCCLUSION_MAP_USTR, &1);
+        }
+        else {
+            shader.upload_uniform(&HAS_OCCLUSION_MAP_USTR, &0);
+        }
+
         if !self.is_dirty {
             return;
         }
-        
-        shader.bind();
 
         let light_positions_location = shader.uniform_location(&LIGHT_POSITIONS_USTR);
         let light_intensities_location = shader.uniform_location(&INTENSITIES_USTR);
         let light_colors_location = shader.uniform_location(&LIGHT_COLORS_USTR);
+        let casts_shadows_location = shader.uniform_location(&CASTS_SHADOWS_USTR);
         
-        shader.upload_uniform(&NUM_LIGHTS_USTR, &(self.lights.len().min(MAX_LIGHT_SOURCE_COUNT) as u32));
+        let active = self.active.iter().filter_map(|id| self.lights.get(id)).collect::<Vec<_>>();
+        shader.upload_uniform(&NUM_LIGHTS_USTR, &(active.len() as u32));
         shader.upload_uniform(&AMBIENT_LIGHT_STRENGTH_USTR, &self.ambient_light);
 
-        for (i, light) in self.lights.values().enumerate() {
-            if i >= MAX_LIGHT_SOURCE_COUNT {
-                break;
-            }
-
+        for (i, light) in active.into_iter().enumerate() {
             light.position.upload(light_positions_location + i as i32);
             light.intensity.upload(light_intensities_location + i as i32);
-            light.color.upload(light_colors_location + i as i32);
+            light.clamped_color().upload(light_colors_location + i as i32);
+            (light.casts_shadows as i32).upload(casts_shadows_location + i as i32);
         }
     }
 }
 
+/// The ids of at most `max` lights, nearest to `center` first.
+fn nearest_lights(lights: &BTreeMap<LightId, Light>, center: Vector2<f32>, max: usize) -> Vec<LightId> {
+    let mut by_distance = lights.iter()
+        .map(|(id, light)| ((light.position - center).mag_sq(), *id))
+        .collect::<Vec<_>>();
+    by_distance.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+    by_distance.into_iter().take(max).map(|(_, id)| id).collect()
+}
+
+/// One RGBA texel per tile, red is 255 for solid tiles. Rows go along the x axis.
+fn occlusion_data(world: &impl WorldView, origin: Vector2<i32>, size: u32) -> Vec<u8> {
+    (0..size as i32)
+        .flat_map(|y| (0..size as i32).map(move |x| origin + Vector2::new(x, y)))
+        .flat_map(|pos| if world.get_tile(pos).is_solid() { [255, 0, 0, 255] } else { [0, 0, 0, 255] })
+        .collect()
+}
+
+/// A light for every tile of the chunk whose [`TileProperties`](crate::tiles::TileProperties) have a glow color.
+pub fn chunk_lights(chunk: &Chunk) -> Vec<Light> {
+    let tiles = chunk.tiles.iter().map(Tile::properties).enumerate();
+    let fg_tiles = chunk.fg_tiles.iter().map(FgTile::properties).enumerate();
+    tiles.chain(fg_tiles)
+        .filter_map(|(i, properties)| {
+            let color = properties.glow_color?;
+            let x = (i % CHUNK_SIZE) as i32 + chunk.chunk_pos.x() * CHUNK_SIZE as i32;
+            let y = (i / CHUNK_SIZE) as i32 + chunk.chunk_pos.y() * CHUNK_SIZE as i32;
+            Some(Light::new(Vector2::new(x as f32 + 0.5, y as f32 + 0.5), properties.light_radius, Vector3::new(color[0], color[1], color[2])))
+        })
+        .collect()
+}
+
 pub struct Light {
     position: Vector2<f32>,
     intensity: f32,
-    color: Vector3<f32>
+    color: Vector3<f32>,
+    casts_shadows: bool
 }
 
 impl Light {
@@ -98,7 +214,102 @@ impl Light {
         Self {
             position,
             intensity,
-            color
+            color,
+            casts_shadows: true
         }
     }
+
+    /// Lights cast shadows behind solid tiles by default, decorative glows can turn that off.
+    pub fn with_shadows(mut self, casts_shadows: bool) -> Self {
+        self.casts_shadows = casts_shadows;
+        self
+    }
+
+    pub fn casts_shadows(&self) -> bool {
+        self.casts_shadows
+    }
+
+    pub fn color(&self) -> Vector3<f32> {
+        self.color
+    }
+
+    /// Multiplies the color component-wise, e.g. by a biome's tint.
+    pub fn tinted(mut self, tint: Vector3<f32>) -> Self {
+        self.color *= tint;
+        self
+    }
+
+    /// The color as uploaded to the shader. Blended or tinted colors may leave the `0..=1` range,
+    /// which would oversaturate or darken everything the light touches.
+    pub fn clamped_color(&self) -> Vector3<f32> {
+        self.color.max(Vector3::new(0.0, 0.0, 0.0)).min(Vector3::new(1.0, 1.0, 1.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aeonetica_engine::util::nullable::Nullable;
+
+    use super::*;
+
+    struct SingleWall(Vector2<i32>);
+
+    impl WorldView for SingleWall {
+        fn get_tile_or_null(&self, pos: Vector2<i32>) -> Nullable<Tile> {
+            Nullable::Value(if pos == self.0 { Tile::Wall } else { Tile::StoneBrick })
+        }
+
+        fn get_fg_tile_or_null(&self, _pos: Vector2<i32>) -> Nullable<FgTile> {
+            Nullable::Value(FgTile::Empty)
+        }
+
+        fn get_water_tile_or_null(&self, _pos: Vector2<i32>) -> Nullable<u8> {
+            Nullable::Value(0)
+        }
+
+        fn is_loaded(&self, _pos: Vector2<i32>) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn occlusion_data_marks_solid_tiles() {
+        let data = occlusion_data(&SingleWall(Vector2::new(-1, 2)), Vector2::new(-2, 0), 4);
+        assert_eq!(data.len(), 4 * 4 * 4);
+        let solid = data.chunks(4).enumerate().filter(|(_, texel)| texel[0] == 255).map(|(i, _)| i).collect::<Vec<_>>();
+        // (-1, 2) relative to (-2, 0) is (1, 2)
+        assert_eq!(solid, vec![2 * 4 + 1]);
+    }
+
+    #[test]
+    fn glowing_tiles_emit_lights() {
+        let mut chunk = Chunk::new(Vector2::new(-1, 2));
+        chunk.set_tile(Vector2::new(3, 0), Tile::Lamp);
+        chunk.set_fg_tile(Vector2::new(0, 1), FgTile::FluorecentLampM);
+        let lights = chunk_lights(&chunk);
+        assert_eq!(lights.len(), 2);
+        let origin = Vector2::new(-(CHUNK_SIZE as f32), 2.0 * CHUNK_SIZE as f32);
+        assert_eq!(lights[0].position, origin + Vector2::new(3.5, 0.5));
+        assert_eq!(lights[0].intensity, Tile::Lamp.light_radius());
+        assert_eq!(lights[1].position, origin + Vector2::new(0.5, 1.5));
+    }
+
+    #[test]
+    fn culling_keeps_the_closest_lights() {
+        let lights = [8.0, -1.0, 3.0, -20.0, 0.5].into_iter()
+            .map(|x| Light::new(Vector2::new(x, 2.0), 7.5, Vector3::new(1.0, 1.0, 1.0)))
+            .enumerate()
+            .map(|(id, light)| (id as LightId, light))
+            .collect::<BTreeMap<_, _>>();
+        assert_eq!(nearest_lights(&lights, Vector2::new(0.0, 2.0), 3), vec![4, 1, 2]);
+        assert_eq!(nearest_lights(&lights, Vector2::new(-30.0, 0.0), 2), vec![3, 1]);
+        assert_eq!(nearest_lights(&lights, Vector2::default(), 10).len(), 5);
+    }
+
+    #[test]
+    fn light_colors_are_clamped() {
+        let light = Light::new(Vector2::default(), 4.0, Vector3::new(0.8, 0.5, 0.2)).tinted(Vector3::new(2.0, 1.0, -1.0));
+        assert_eq!(light.color(), Vector3::new(1.6, 0.5, -0.2));
+        assert_eq!(light.clamped_color(), Vector3::new(1.0, 0.5, 0.0));
+    }
 }
\ No newline at end of file
@@ -1,18 +1,30 @@
 use std::{rc::Rc, char::MAX};
 
-use aeonetica_client::{renderer::{buffer::*, shader, material::{Material, FlatTexture}, RenderID, texture::{Sampler2D, Sprite}, builtin::Quad}, vertex, data_store::DataStore};
+use aeonetica_client::{renderer::{buffer::*, shader::{self, UniformStr}, material::{Material, FlatTexture}, RenderID, texture::{Sampler2D, Sprite, Texture}, builtin::Quad, util::max_texture_units}, vertex, uniform_str, data_store::DataStore};
 use aeonetica_engine::math::vector::Vector2;
 use aeonetica_engine::error::ExpectLog;
 
 struct TerrainMaterial(Rc<FlatTexture>);
 struct TerrainShader(Rc<shader::Program>);
+struct InstancedTerrainMaterial(Rc<FlatTexture>);
+struct InstancedTerrainShader(Rc<shader::Program>);
+
+const TERRAIN_SHADER_SRC: &str = include_str!("../../assets/terrain-shader.glsl");
 
 thread_local! {
+    static NORMAL_MAPPED_TERRAIN_LAYOUT: Rc<BufferLayout> = Rc::new(<NormalMappedTerrain as Material>::Layout::build());
     static GLOW_TEXTURE_LAYOUT: Rc<BufferLayout> = Rc::new(<GlowTexture as Material>::Layout::build());
 }
 
 fn create_terrain_shader() -> TerrainShader {
-    TerrainShader(Rc::new(shader::Program::from_source(include_str!("../../assets/terrain-shader.glsl")).expect_log()))
+    TerrainShader(Rc::new(shader::Program::from_source(TERRAIN_SHADER_SRC).expect_log()))
+}
+
+fn create_instanced_terrain_shader() -> InstancedTerrainShader {
+    // only the vertex stage differs, the lighting in the fragment stage is shared with the terrain shader
+    let fragment = &TERRAIN_SHADER_SRC[TERRAIN_SHADER_SRC.find("#[fragment]").expect("terrain shader has no fragment stage")..];
+    let source = format!("{}\n{fragment}", include_str!("../../assets/terrain-instanced-vertex.glsl"));
+    InstancedTerrainShader(Rc::new(shader::Program::from_source(&source).expect_log()))
 }
 
 pub fn terrain_material(store: &mut DataStore) -> Rc<FlatTexture> {
@@ -24,18 +36,117 @@ pub fn terrain_shader(store: &mut DataStore) -> Rc<shader::Program> {
     store.get_or_create(create_terrain_shader).0.clone()
 }
 
+/// Terrain material drawing its quads instanced, for the tiles of whole chunks.
+/// Lit like [`terrain_material`], but without normal mapping.
+pub fn instanced_terrain_material(store: &mut DataStore) -> Rc<FlatTexture> {
+    let shader = instanced_terrain_shader(store);
+    store.get_or_create(|| InstancedTerrainMaterial(Rc::new(FlatTexture::instanced_with_shader(shader)))).0.clone()
+}
+
+/// Shader of [`instanced_terrain_material`], it needs the same lighting uniforms as the [`terrain_shader`].
+pub fn instanced_terrain_shader(store: &mut DataStore) -> Rc<shader::Program> {
+    store.get_or_create(create_instanced_terrain_shader).0.clone()
+}
+
+/// Texture unit the terrain normal map is bound to, right after the 16 units used by batches.
+/// OpenGL only guarantees 16 units per stage, so normal mapping is turned off on hardware without a 17th.
+const NORMAL_MAP_SLOT: u32 = 16;
+
+const NORMAL_MAP_USTR: UniformStr = uniform_str!("u_NormalMap");
+const HAS_NORMAL_MAP_USTR: UniformStr = uniform_str!("u_HasNormalMap");
+
+/// Normal map matching the layout of the terrain sprite sheet.
+struct TerrainNormalMap(Texture);
+
+/// Sets the normal sheet used by quads created with [`WithTerrain::with_terrain_sprite_normal`].
+/// It has to share the layout of the terrain sprite sheet, since the same texture coordinates are used for both.
+pub fn set_terrain_normal_map(store: &mut DataStore, normal_sheet: Texture) {
+    store.add_store(TerrainNormalMap(normal_sheet));
+}
+
+/// Binds the terrain normal map for the next draw calls of `shader`.
+/// Without a normal map every tile is lit as if its surface were flat.
+pub fn bind_terrain_normal_map(store: &DataStore, shader: &shader::Program) {
+    shader.bind();
+    match store.try_get_store::<TerrainNormalMap>() {
+        Some(TerrainNormalMap(normal_map)) if max_texture_units() > NORMAL_MAP_SLOT => {
+            normal_map.bind(NORMAL_MAP_SLOT);
+            shader.upload_uniform(&NORMAL_MAP_USTR, &(NORMAL_MAP_SLOT as i32));
+            shader.upload_uniform(&HAS_NORMAL_MAP_USTR, &1);
+        }
+        _ => shader.upload_uniform(&HAS_NORMAL_MAP_USTR, &0)
+    }
+}
+
+struct NormalMappedTerrainMaterial(Rc<NormalMappedTerrain>);
+
+pub fn terrain_normal_material(store: &mut DataStore) -> Rc<NormalMappedTerrain> {
+    let shader = store.get_or_create(create_terrain_shader).0.clone();
+    store.get_or_create(|| NormalMappedTerrainMaterial(Rc::new(NormalMappedTerrain { shader }))).0.clone()
+}
+
+/// Terrain material whose quads are shaded by the terrain normal map, see [`set_terrain_normal_map`].
+pub struct NormalMappedTerrain {
+    shader: Rc<shader::Program>
+}
+
+impl Material for NormalMappedTerrain {
+    type Layout = BufferLayoutBuilder<(Vertex, TexCoord, TextureID, Float)>;
+    type Data<const N: usize> = ([[f32; 2]; N], RenderID);
+    type VertexTuple = VertexTuple4<[f32; 2], [f32; 2], Sampler2D, f32>;
+
+    fn shader(&self) -> &Rc<shader::Program> {
+        &self.shader
+    }
+
+    fn texture_id<const N: usize>(data: &Self::Data<N>) -> Option<RenderID> {
+        Some(data.1)
+    }
+
+    fn layout<'a>() -> &'a Rc<BufferLayout> {
+        unsafe {
+            let x: *const Rc<BufferLayout> = NORMAL_MAPPED_TERRAIN_LAYOUT.with(|l| l as *const _);
+            x.as_ref().unwrap_unchecked()
+        }
+    }
+
+    fn vertices<const N: usize>(&self, vertices: [[f32; 2]; N], data: &Self::Data<N>) -> [Self::VertexTuple; N] {
+        // the last attribute tells the terrain shader to sample the normal map
+        Self::Layout::array(std::array::from_fn(|i| vertex!(vertices[i], data.0[i], Sampler2D(0), 1.0)))
+    }
+
+    fn data_slice<const N: usize, const NN: usize>(&self, data: &Self::Data<N>, offset: usize) -> Self::Data<NN> {
+        (std::array::from_fn(|i| data.0[offset + i]), data.1)
+    }
+
+    fn default_data<const N: usize>(&self) -> Self::Data<N> {
+        (std::array::from_fn(|_| [0.0; 2]), 0)
+    }
+}
+
 pub trait WithTerrain {
     fn with_terrain_texture(position: Vector2<f32>, size: Vector2<f32>, z_index: u8, texture: RenderID, material: Rc<FlatTexture>) -> Self;
     fn with_terrain_sprite(position: Vector2<f32>, size: Vector2<f32>, z_index: u8, sprite: Sprite, material: Rc<FlatTexture>) -> Self;
+    /// Like `with_terrain_sprite`, but the quad is lit using the terrain normal map at the same sprite location.
+    fn with_terrain_sprite_normal(position: Vector2<f32>, size: Vector2<f32>, z_index: u8, sprite: Sprite, material: Rc<NormalMappedTerrain>) -> Quad<NormalMappedTerrain>;
 }
 
 impl WithTerrain for Quad<FlatTexture> {
     fn with_terrain_texture(position: Vector2<f32>, size: Vector2<f32>, z_index: u8, texture: RenderID, material: Rc<FlatTexture>) -> Self {
-        Self::new(position, size, z_index, material, ([[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]], texture))
+        Self::with_material(position, size, z_index, material, ([[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]], texture))
     }
 
     fn with_terrain_sprite(position: Vector2<f32>, size: Vector2<f32>, z_index: u8, sprite: Sprite, material: Rc<FlatTexture>) -> Self {
-        Self::new(position, size, z_index, material, ([
+        Self::with_material(position, size, z_index, material, ([
+            [sprite.left(),  sprite.top()   ],
+            [sprite.right(), sprite.top()   ],
+            [sprite.right(), sprite.bottom()],
+            [sprite.left(),  sprite.bottom()]
+        ], sprite.texture()))
+    }
+
+    fn with_terrain_sprite_normal(position: Vector2<f32>, size: Vector2<f32>, z_index: u8, sprite: Sprite, material: Rc<NormalMappedTerrain>) -> Quad<NormalMappedTerrain> {
+        Quad::with_material(position, size, z_index, material, ([
             [sprite.left(),  sprite.top()   ],
             [sprite.right(), sprite.top()   ],
             [sprite.right(), sprite.bottom()],
@@ -99,11 +210,11 @@ pub trait WithGlow {
 
 impl WithGlow for Quad<GlowTexture> {
     fn with_glow_texture(position: Vector2<f32>, size: Vector2<f32>, z_index: u8, texture: RenderID, glow_color: [f32; 4], material: Rc<GlowTexture>) -> Self {
-        Self::new(position, size, z_index, material, ([[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]], texture, glow_color))
+        Self::with_material(position, size, z_index, material, ([[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]], texture, glow_color))
     }
 
     fn with_glow_sprite(position: Vector2<f32>, size: Vector2<f32>, z_index: u8, sprite: Sprite, glow_color: [f32; 4], material: Rc<GlowTexture>) -> Self {
-        Self::new(position, size, z_index, material, ([
+        Self::with_material(position, size, z_index, material, ([
             [sprite.left(),  sprite.top()   ],
             [sprite.right(), sprite.top()   ],
             [sprite.right(), sprite.bottom()],
@@ -177,17 +288,123 @@ impl WithWater for Quad<WaterMaterial> {
     fn with_water_texture(position: Vector2<f32>, size: Vector2<f32>, z_index: u8, texture: RenderID, material: Rc<WaterMaterial>, distance_to_surface: f32) -> Self {
         let d0 = position.y - distance_to_surface * 2.0 + size.y;
         let d1 = d0 + size.y;
-        Self::new(position, size, z_index, material, ([[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]], texture,  [d1, d1, d0, d0]))
+        Self::with_material(position, size, z_index, material, ([[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]], texture,  [d1, d1, d0, d0]))
     }
 
     fn with_water_sprite(position: Vector2<f32>, size: Vector2<f32>, z_index: u8, sprite: Sprite, material: Rc<WaterMaterial>, distance_to_surface: f32) -> Self {
         let d0 = position.y - distance_to_surface * 2.0 + size.y;
         let d1 = d0 + size.y;
-        Self::new(position, size, z_index, material, ([
+        Self::with_material(position, size, z_index, material, ([
             [sprite.left(),  sprite.top()   ],
             [sprite.right(), sprite.top()   ],
             [sprite.right(), sprite.bottom()],
             [sprite.left(),  sprite.bottom()]
         ], sprite.texture(), [d1, d1, d0, d0]))
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::OnceCell;
+    use aeonetica_client::renderer::Renderable;
+    use super::*;
+
+    const BANNER_SHADER_SRC: &str = "
+#[description]
+scrolls the texture of a ScrollingBanner
+
+#[vertex]
+#version 450 core
+
+layout (location = 0) in vec2 a_Position;
+layout (location = 1) in vec2 a_TexCoord;
+layout (location = 2) in int  a_TexIdx;
+layout (location = 3) in float a_Speed;
+
+uniform mat4 u_ViewProjection;
+uniform float u_Time;
+
+out vec2 v_TexCoord;
+flat out int v_TexIdx;
+
+void main() {
+    v_TexCoord = a_TexCoord + vec2(u_Time * a_Speed, 0.0);
+    v_TexIdx = a_TexIdx;
+    gl_Position = u_ViewProjection * vec4(a_Position, 0.0, 1.0);
+}
+
+#[fragment]
+#version 450 core
+
+in vec2 v_TexCoord;
+flat in int v_TexIdx;
+
+uniform sampler2D u_Textures[16];
+
+layout (location = 0) out vec4 r_Color;
+
+void main() {
+    r_Color = texture(u_Textures[v_TexIdx], fract(v_TexCoord));
+}
+";
+
+    thread_local! {
+        static BANNER_LAYOUT: Rc<BufferLayout> = Rc::new(<ScrollingBanner as Material>::Layout::build());
+    }
+
+    /// A material defined outside of the client, like a mod would.
+    #[derive(Default)]
+    struct ScrollingBanner {
+        // compiled on the first draw, creating the material needs no GL context
+        shader: OnceCell<Rc<shader::Program>>
+    }
+
+    impl Material for ScrollingBanner {
+        type Layout = BufferLayoutBuilder<(Vertex, TexCoord, TextureID, Float)>;
+        /// texture coordinates, texture and scroll speed
+        type Data<const N: usize> = ([[f32; 2]; N], RenderID, f32);
+        type VertexTuple = VertexTuple4<[f32; 2], [f32; 2], Sampler2D, f32>;
+
+        fn shader(&self) -> &Rc<shader::Program> {
+            self.shader.get_or_init(|| Rc::new(shader::Program::from_source(BANNER_SHADER_SRC).expect_log()))
+        }
+
+        fn texture_id<const N: usize>(data: &Self::Data<N>) -> Option<RenderID> {
+            Some(data.1)
+        }
+
+        fn layout<'a>() -> &'a Rc<BufferLayout> {
+            unsafe {
+                let x: *const Rc<BufferLayout> = BANNER_LAYOUT.with(|l| l as *const _);
+                x.as_ref().unwrap_unchecked()
+            }
+        }
+
+        fn vertices<const N: usize>(&self, vertices: [[f32; 2]; N], data: &Self::Data<N>) -> [Self::VertexTuple; N] {
+            Self::Layout::array(std::array::from_fn(|i| vertex!(vertices[i], data.0[i], Sampler2D(0), data.2)))
+        }
+
+        fn data_slice<const N: usize, const NN: usize>(&self, data: &Self::Data<N>, offset: usize) -> Self::Data<NN> {
+            (std::array::from_fn(|i| data.0[offset + i]), data.1, data.2)
+        }
+
+        fn default_data<const N: usize>(&self) -> Self::Data<N> {
+            (std::array::from_fn(|_| [0.0; 2]), 0, 0.0)
+        }
+    }
+
+    #[test]
+    fn mods_can_build_quads_of_their_own_material() {
+        let banner = Rc::new(ScrollingBanner::default());
+        let quad = Quad::with_material(Vector2::new(1.0, 2.0), Vector2::new(4.0, 1.0), 5, banner.clone(),
+            ([[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]], 3, 0.25));
+        assert_eq!(quad.texture_id(), Some(3));
+        assert_eq!(quad.bounds(), Some((Vector2::new(1.0, 2.0), Vector2::new(5.0, 3.0))));
+
+        let vertices = banner.vertices([[1.0, 2.0], [5.0, 2.0], [5.0, 3.0], [1.0, 3.0]], quad.params());
+        assert_eq!(vertices[2].1, [1.0, 1.0]);
+        assert!(vertices.iter().all(|vertex| vertex.3 == 0.25));
+        assert_eq!(banner.data_slice::<4, 2>(quad.params(), 2), ([[1.0, 1.0], [0.0, 1.0]], 3, 0.25));
+        assert_eq!(<ScrollingBanner as Material>::layout().stride() as usize, std::mem::size_of::<<ScrollingBanner as Material>::VertexTuple>());
+    }
+}
@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Receiver, Sender};
+
+use aeonetica_client::renderer::texture::{Sprite, SpriteSheet};
+use aeonetica_engine::math::vector::Vector2;
+use aeonetica_engine::util::pool::{Pool, Pooled};
+
+use crate::common::{Chunk, CHUNK_SIZE};
+
+use super::parallax::PARALLAX_Z_INDICES;
+
+/// z-index of the tiles, foreground tiles are drawn 3 above. Lower ones are left to the [`ParallaxBackground`](super::parallax::ParallaxBackground).
+pub(crate) const TERRAIN_Z_INDEX: u8 = PARALLAX_Z_INDICES.end;
+
+/// What to draw for one tile, everything but the GL resources.
+#[derive(Debug, Clone)]
+pub(crate) struct TileQuad {
+    pub(crate) position: Vector2<f32>,
+    pub(crate) z_index: u8,
+    pub(crate) kind: TileQuadKind
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum TileQuadKind {
+    Terrain(Sprite),
+    Glowing(Sprite, [f32; 4]),
+    /// water depth
+    Water(u8)
+}
+
+/// Buffers the chunk meshes are built into, kept in the [`DataStore`](aeonetica_client::data_store::DataStore).
+pub(crate) type QuadPool = Pool<Vec<TileQuad>>;
+
+/// The quads of a chunk, built by the [`MeshBuilder`] from the chunk's `revision`.
+/// The buffer goes back to its [`QuadPool`] once the mesh is dropped.
+pub(crate) struct ChunkMesh {
+    pub(crate) chunk_pos: Vector2<i32>,
+    revision: u32,
+    pub(crate) quads: Pooled<Vec<TileQuad>>
+}
+
+/// All sprites of the sheet, so they can be looked up without the texture.
+pub(crate) fn sprite_table(sheet: &SpriteSheet) -> Vec<Sprite> {
+    (0..sheet.num_sprites()).filter_map(|i| sheet.get(i)).collect()
+}
+
+/// Appends the chunk's quads to `quads`.
+pub(crate) fn build_mesh(chunk: &Chunk, tile_sprites: &[Sprite], fg_tile_sprites: &[Sprite], quads: &mut Vec<TileQuad>) {
+    let position = |i: usize| Vector2::new(
+        ((i % CHUNK_SIZE) as i32 + chunk.chunk_pos.x() * CHUNK_SIZE as i32) as f32,
+        ((i / CHUNK_SIZE) as i32 + chunk.chunk_pos.y() * CHUNK_SIZE as i32) as f32
+    );
+    // index 0 is air, so sprites are shifted by one
+    let tile_quad = |i: usize, index: u16, glow_color: Option<[f32; 4]>, sprites: &[Sprite], z_index: u8| {
+        let sprite = sprites.get(index as usize - 1)?.clone();
+        Some(match glow_color {
+            Some(color) => TileQuad { position: position(i), z_index: z_index + 1, kind: TileQuadKind::Glowing(sprite, color) },
+            None => TileQuad { position: position(i), z_index, kind: TileQuadKind::Terrain(sprite) }
+        })
+    };
+
+    let tiles = chunk.tiles.iter().enumerate()
+        .filter(|(_, tile)| tile.sprite_sheet_index() != 0)
+        .filter_map(|(i, tile)| tile_quad(i, tile.sprite_sheet_index(), tile.glow_color(), tile_sprites, TERRAIN_Z_INDEX));
+    let fg_tiles = chunk.fg_tiles.iter().enumerate()
+        .filter(|(_, tile)| tile.sprite_sheet_index() != 0)
+        .filter_map(|(i, tile)| tile_quad(i, tile.sprite_sheet_index(), tile.glow_color(), fg_tile_sprites, TERRAIN_Z_INDEX + 3));
+    let water = chunk.water_mask.iter().enumerate()
+        .filter(|(_, depth)| **depth > 0)
+        .map(|(i, depth)| TileQuad { position: position(i), z_index: 20, kind: TileQuadKind::Water(*depth) });
+    quads.extend(tiles.chain(fg_tiles).chain(water));
+}
+
+/// Builds chunk meshes on a worker thread, the main thread only has to add the finished quads to the renderer.
+/// Until then the chunk keeps showing its previous mesh.
+pub(crate) struct MeshBuilder {
+    requests: Sender<(Chunk, u32, Pooled<Vec<TileQuad>>)>,
+    finished: Receiver<ChunkMesh>,
+    /// the latest requested revision of every chunk whose mesh is not done yet
+    revisions: HashMap<Vector2<i32>, u32>,
+    next_revision: u32
+}
+
+impl MeshBuilder {
+    pub(crate) fn new(tile_sprites: Vec<Sprite>, fg_tile_sprites: Vec<Sprite>) -> Self {
+        let (requests, pending) = mpsc::channel::<(Chunk, u32, Pooled<Vec<TileQuad>>)>();
+        let (done, finished) = mpsc::channel();
+        // stops once the builder is dropped
+        std::thread::spawn(move || for (chunk, revision, mut quads) in pending {
+            build_mesh(&chunk, &tile_sprites, &fg_tile_sprites, &mut quads);
+            if done.send(ChunkMesh { chunk_pos: chunk.chunk_pos, revision, quads }).is_err() {
+                break
+            }
+        });
+        Self {
+            requests,
+            finished,
+            revisions: HashMap::new(),
+            next_revision: 0
+        }
+    }
+
+    /// Builds the chunk's mesh into a buffer of the pool, replacing any older request for the same chunk.
+    pub(crate) fn request(&mut self, chunk: Chunk, pool: &QuadPool) {
+        let revision = self.next_revision;
+        self.next_revision = self.next_revision.wrapping_add(1);
+        self.revisions.insert(chunk.chunk_pos, revision);
+        let _ = self.requests.send((chunk, revision, pool.get()));
+    }
+
+    /// Discards the mesh of a chunk that unloaded before it was done.
+    pub(crate) fn cancel(&mut self, chunk_pos: &Vector2<i32>) {
+        self.revisions.remove(chunk_pos);
+    }
+
+    /// The meshes finished since the last call, without outdated and cancelled ones.
+    pub(crate) fn finished(&mut self) -> Vec<ChunkMesh> {
+        let revisions = &mut self.revisions;
+        self.finished.try_iter()
+            .filter(|mesh| {
+                let latest = revisions.get(&mesh.chunk_pos) == Some(&mesh.revision);
+                if latest {
+                    revisions.remove(&mesh.chunk_pos);
+                }
+                latest
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+    use crate::tiles::{FgTile, Tile};
+    use super::*;
+
+    fn sprites(count: usize) -> Vec<Sprite> {
+        (0..count).map(|i| Sprite::new(i as u32, 0.0, 1.0, 0.0, 1.0)).collect()
+    }
+
+    fn chunk(chunk_pos: Vector2<i32>, lamp: Vector2<i32>) -> Chunk {
+        let mut chunk = Chunk::new(chunk_pos);
+        chunk.tiles.fill(Tile::StoneBrick);
+        chunk.set_tile(lamp, Tile::Lamp);
+        chunk
+    }
+
+    #[test]
+    fn meshes_are_built_for_visible_tiles() {
+        let mut chunk = chunk(Vector2::new(1, -1), Vector2::new(2, 3));
+        chunk.set_fg_tile(Vector2::new(0, 0), FgTile::ChainV);
+        chunk.water_mask[CHUNK_SIZE] = 4;
+        let mut quads = vec![];
+        build_mesh(&chunk, &sprites(64), &sprites(64), &mut quads);
+        assert_eq!(quads.len(), CHUNK_SIZE * CHUNK_SIZE + 2);
+
+        let origin = Vector2::new(CHUNK_SIZE as f32, -(CHUNK_SIZE as f32));
+        let lamp = quads.iter().find(|quad| matches!(quad.kind, TileQuadKind::Glowing(..))).unwrap();
+        assert_eq!((lamp.position, lamp.z_index), (origin + Vector2::new(2.0, 3.0), TERRAIN_Z_INDEX + 1));
+        let water = quads.last().unwrap();
+        assert_eq!(water.position, origin + Vector2::new(0.0, 1.0));
+        assert!(matches!(water.kind, TileQuadKind::Water(4)));
+        assert!(quads.iter().any(|quad| quad.z_index == TERRAIN_Z_INDEX + 3 && quad.position == origin));
+    }
+
+    #[test]
+    fn outdated_and_cancelled_meshes_are_discarded() {
+        let (a, b) = (Vector2::new(0, 0), Vector2::new(5, 5));
+        let pool = QuadPool::new();
+        let mut builder = MeshBuilder::new(sprites(64), sprites(64));
+        builder.request(chunk(a, Vector2::new(0, 0)), &pool);
+        builder.request(chunk(a, Vector2::new(7, 7)), &pool);
+        builder.request(chunk(b, Vector2::new(0, 0)), &pool);
+        builder.cancel(&b);
+
+        let mut meshes = vec![];
+        let started = Instant::now();
+        while !builder.revisions.is_empty() {
+            assert!(started.elapsed() < Duration::from_secs(5), "mesh was not built");
+            meshes.extend(builder.finished());
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(meshes.len(), 1);
+        assert_eq!(meshes[0].chunk_pos, a);
+        assert!(meshes[0].quads.iter().any(|quad| quad.position == Vector2::new(7.0, 7.0) && quad.z_index == TERRAIN_Z_INDEX + 1));
+        // the outdated mesh's buffer is back in the pool, the cancelled one may still be building
+        assert!(pool.free() >= 1);
+    }
+}
@@ -0,0 +1,213 @@
+use std::collections::HashSet;
+
+use aeonetica_client::data_store::DataStore;
+use aeonetica_client::renderer::Renderer;
+use aeonetica_client::renderer::builtin::Quad;
+use aeonetica_client::renderer::layer::Layer;
+use aeonetica_client::renderer::material::{FlatColor, FlatTexture};
+use aeonetica_client::renderer::texture::{Format, Texture};
+use aeonetica_engine::log;
+use aeonetica_engine::math::camera::Camera;
+use aeonetica_engine::math::vector::Vector2;
+use aeonetica_engine::time::Time;
+
+use crate::common::{Chunk, CHUNK_SIZE, WorldView};
+
+use super::{CameraData, ClientChunk, ClientWorld};
+
+/// Chunks shown around the camera chunk, per direction
+const RADIUS: i32 = 2;
+/// Chunks along each side of the minimap
+const CHUNKS: i32 = RADIUS * 2 + 1;
+const TEXTURE_SIZE: u32 = CHUNKS as u32 * CHUNK_SIZE as u32;
+const UNLOADED_COLOR: [u8; 4] = [0, 0, 0, 160];
+const WATER_COLOR: [u8; 3] = [40, 90, 200];
+
+/// Chunks whose tiles changed since the [`MinimapLayer`] last drew them, filled by the world handle while the layer exists.
+#[derive(Debug, Default)]
+pub(crate) struct MinimapChanges(pub(crate) HashSet<Vector2<i32>>);
+
+/// Where a chunk is drawn in the minimap texture. The texture wraps around, every chunk
+/// of the visible window has its own slot, so moving to another chunk only draws the newly visible ones.
+fn slot(chunk_pos: Vector2<i32>) -> Vector2<i32> {
+    Vector2::new(chunk_pos.x.rem_euclid(CHUNKS), chunk_pos.y.rem_euclid(CHUNKS))
+}
+
+/// Texture coordinates showing the window around `center` in the order of the quad's corners.
+fn window_uv_coords(center: Vector2<i32>) -> [[f32; 2]; 4] {
+    let min = slot(center - Vector2::new(RADIUS, RADIUS)).to_f32() / CHUNKS as f32;
+    let max = min + Vector2::new(1.0, 1.0);
+    [[min.x, min.y], [max.x, min.y], [max.x, max.y], [min.x, max.y]]
+}
+
+/// RGBA pixels of a chunk, one per tile, rows ordered top to bottom
+fn chunk_pixels(chunk: &Chunk) -> Vec<u8> {
+    chunk.tiles.iter().zip(chunk.water_mask.iter())
+        .flat_map(|(tile, water)| {
+            let [r, g, b, a] = tile.map_color();
+            if *water == 0 {
+                return [r, g, b, a]
+            }
+            let mix = |tile: u8, water: u8| ((tile as u16 + water as u16) / 2) as u8;
+            [mix(r, WATER_COLOR[0]), mix(g, WATER_COLOR[1]), mix(b, WATER_COLOR[2]), 255]
+        })
+        .collect()
+}
+
+/// Draws the window's chunks that changed or newly came into view into their slots, `slots` holds the chunk each slot shows.
+fn update_texture(slots: &mut [Option<Vector2<i32>>], texture: &Texture, world: &ClientWorld, center: Vector2<i32>, changes: &HashSet<Vector2<i32>>) {
+    let unloaded = UNLOADED_COLOR.repeat(CHUNK_SIZE * CHUNK_SIZE);
+    for x in -RADIUS..=RADIUS {
+        for y in -RADIUS..=RADIUS {
+            let chunk_pos = center + Vector2::new(x, y);
+            let slot_pos = slot(chunk_pos);
+            let shown = &mut slots[(slot_pos.y * CHUNKS + slot_pos.x) as usize];
+            if *shown == Some(chunk_pos) && !changes.contains(&chunk_pos) {
+                continue
+            }
+            let pixels = match world.chunks.get(&chunk_pos) {
+                Some(ClientChunk::Chunk(chunk, _)) => chunk_pixels(chunk),
+                _ => unloaded.clone()
+            };
+            let origin = slot_pos * CHUNK_SIZE as i32;
+            if let Err(e) = texture.sub_update(origin.x as u32, origin.y as u32, CHUNK_SIZE as u32, CHUNK_SIZE as u32, &pixels) {
+                log!(ERROR, "could not draw chunk {chunk_pos} on the minimap: {e}");
+            }
+            *shown = Some(chunk_pos);
+        }
+    }
+}
+
+/// Top down map of the chunks around the camera in the top right corner, drawn with the ui camera.
+pub(crate) struct MinimapLayer {
+    texture: Option<Texture>,
+    map: Option<Quad<FlatTexture>>,
+    marker: Option<Quad<FlatColor>>,
+    /// the chunk each slot shows, see [`slot`]
+    slots: Vec<Option<Vector2<i32>>>,
+    center: Option<Vector2<i32>>,
+    /// width of the ui camera, the map sticks to its right edge
+    width: f32
+}
+
+impl MinimapLayer {
+    const SIZE: f32 = 24.0;
+    const MARGIN: f32 = 2.0;
+    const MARKER_SIZE: f32 = 1.0;
+    const MARKER_COLOR: [f32; 4] = [1.0, 0.2, 0.2, 1.0];
+    const Z_INDEX: u8 = 150;
+
+    pub(crate) fn new() -> Self {
+        Self {
+            texture: None,
+            map: None,
+            marker: None,
+            slots: vec![None; (CHUNKS * CHUNKS) as usize],
+            center: None,
+            width: 160.0
+        }
+    }
+
+    fn position(&self) -> Vector2<f32> {
+        Vector2::new(self.width - Self::SIZE - Self::MARGIN, Self::MARGIN)
+    }
+}
+
+impl Layer for MinimapLayer {
+    fn instantiate_camera(&self) -> Camera {
+        Camera::new(0.0, 160.0, 90.0, 0.0, 1.0, -1.0)
+    }
+
+    fn resize_camera(&mut self, camera: &mut Camera, aspect_ratio: f32) {
+        self.width = 90.0 * aspect_ratio;
+        camera.set_projection(0.0, self.width, 90.0, 0.0, 1.0, -1.0);
+        let position = self.position();
+        if let Some(map) = &mut self.map {
+            map.set_position(position);
+        }
+    }
+
+    fn attach(&mut self, _renderer: &mut Renderer, store: &mut DataStore) {
+        let texture = Texture::create(Vector2::new(TEXTURE_SIZE, TEXTURE_SIZE), Format::RgbaU8);
+        texture.set_data(&UNLOADED_COLOR.repeat((TEXTURE_SIZE * TEXTURE_SIZE) as usize));
+        self.map = Some(Quad::with_texture(self.position(), Vector2::new(Self::SIZE, Self::SIZE), Self::Z_INDEX, texture.id()));
+        self.marker = Some(Quad::with_color(self.position(), Vector2::new(Self::MARKER_SIZE, Self::MARKER_SIZE), Self::Z_INDEX + 1, Self::MARKER_COLOR));
+        self.texture = Some(texture);
+        store.add_default::<MinimapChanges>();
+    }
+
+    fn quit(&mut self, renderer: &mut Renderer, store: &mut DataStore) {
+        if let Some(mut map) = self.map.take() {
+            renderer.remove(&mut map);
+        }
+        if let Some(mut marker) = self.marker.take() {
+            renderer.remove(&mut marker);
+        }
+        self.texture = None;
+        store.remove_store::<MinimapChanges>();
+    }
+
+    fn post_handles_update(&mut self, store: &mut DataStore, renderer: &mut Renderer, _time: Time) {
+        let Some(camera) = store.try_get_store::<CameraData>().map(|cam| cam.position) else {
+            return
+        };
+        let center = ClientWorld::chunk(camera.floor().to_i32());
+        // chunks outside of the window are drawn once they come into view anyway
+        let changes = std::mem::take(&mut store.mut_or_default::<MinimapChanges>().0);
+        if let (Some(texture), Some(world)) = (&self.texture, store.try_get_store::<ClientWorld>()) {
+            update_texture(&mut self.slots, texture, world, center, &changes);
+        }
+
+        let position = self.position();
+        if let Some(map) = &mut self.map {
+            if self.center != Some(center) {
+                map.set_uv_coords(window_uv_coords(center));
+                self.center = Some(center);
+            }
+            let _ = renderer.draw(map);
+        }
+        if let Some(marker) = &mut self.marker {
+            let window_min = (center - Vector2::new(RADIUS, RADIUS)) * CHUNK_SIZE as i32;
+            let in_window = (camera - window_min.to_f32()) / TEXTURE_SIZE as f32;
+            marker.set_position(position + in_window * Self::SIZE - Vector2::new(Self::MARKER_SIZE, Self::MARKER_SIZE).half());
+            let _ = renderer.draw(marker);
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "Minimap"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tiles::Tile;
+    use super::*;
+
+    #[test]
+    fn every_chunk_of_the_window_has_its_own_slot() {
+        for center in [(0, 0), (-7, 3), (12, -40)].map(Vector2::from) {
+            let slots: HashSet<_> = (-RADIUS..=RADIUS)
+                .flat_map(|x| (-RADIUS..=RADIUS).map(move |y| slot(center + Vector2::new(x, y))))
+                .collect();
+            assert_eq!(slots.len(), (CHUNKS * CHUNKS) as usize);
+            assert!(slots.iter().all(|slot| (0..CHUNKS).contains(&slot.x) && (0..CHUNKS).contains(&slot.y)));
+        }
+        // the window starts at the slot of its top left chunk
+        assert_eq!(window_uv_coords(Vector2::new(RADIUS, RADIUS))[0], [0.0, 0.0]);
+        assert_eq!(window_uv_coords(Vector2::new(RADIUS + 1, RADIUS))[2], [1.0 + 1.0 / CHUNKS as f32, 1.0]);
+    }
+
+    #[test]
+    fn tiles_are_drawn_in_their_map_color() {
+        let mut chunk = Chunk::new(Vector2::new(0, 0));
+        chunk.set_tile(Vector2::new(3, 1), Tile::Lamp);
+        chunk.water_mask[0] = 5;
+        let pixels = chunk_pixels(&chunk);
+        assert_eq!(pixels.len(), CHUNK_SIZE * CHUNK_SIZE * 4);
+        let pixel = |x: usize, y: usize| &pixels[(y * CHUNK_SIZE + x) * 4..][..4];
+        assert_eq!(pixel(3, 1), Tile::Lamp.map_color());
+        assert_eq!(pixel(4, 1), Tile::Wall.map_color());
+        assert_ne!(pixel(0, 0), Tile::Wall.map_color());
+    }
+}
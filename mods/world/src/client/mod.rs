@@ -1,12 +1,15 @@
 use std::collections::HashMap;
 use std::rc::Rc;
-use aeonetica_client::renderer::builtin::TextArea;
+use aeonetica_client::renderer::builtin::{DynTextArea, LineCap, Polyline};
 use aeonetica_client::renderer::texture::font::BitmapFont;
 use noise::{Fbm, NoiseFn, Perlin};
 use aeonetica_client::renderer::material::FlatTexture;
-use aeonetica_client::{ClientMod, networking::messaging::{ClientHandle, ClientMessenger}, data_store::DataStore, renderer::{layer::Layer, context::RenderContext, Renderer, texture::{SpriteSheet, Texture}, builtin::Quad}};
-use aeonetica_client::renderer::window::events::{Event, KeyCode};
+use aeonetica_client::{ClientMod, networking::messaging::{ClientHandle, ClientMessenger}, data_store::DataStore, renderer::{layer::Layer, context::RenderContext, Renderer, texture::{SpriteSheet, Texture, TextureConfig, Filter, Wrap}, builtin::Quad}};
+use aeonetica_client::renderer::window::events::{Event, InputState};
+use aeonetica_client::actions::{Action, ActionMap};
 use aeonetica_client::renderer::window::OpenGlRenderContextProvider;
+use aeonetica_client::console::{ConsoleCommand, ConsoleCommands, ConsoleLayer};
+use aeonetica_client::config::ClientConfig;
 use aeonetica_engine::{log, TypeId};
 use aeonetica_engine::math::camera::Camera;
 use aeonetica_engine::math::vector::*;
@@ -21,18 +24,26 @@ use aeonetica_engine::time::Time;
 use crate::client::pipeline::WorldRenderPipeline;
 use crate::client::materials::{WithGlow, WithTerrain};
 
-use crate::common::{Chunk, CHUNK_SIZE, WorldView};
+use crate::common::{Chunk, CHUNK_SIZE, CompressedChunk, DayNightCurve, WorldView};
 use crate::server::world::World;
 use crate::tiles::{Tile, FgTile};
 
 use debug_mod::Debug;
 
-use self::materials::{GlowTexture, terrain_material, WaterMaterial, WithWater};
-use self::light::{LightStore, Light, LightId};
+use self::materials::{GlowTexture, instanced_terrain_material, terrain_material, WaterMaterial, WithWater};
+use self::mesh::{MeshBuilder, QuadPool, sprite_table, TileQuad, TileQuadKind};
+use self::light::{chunk_lights, LightStore, MAX_ACTIVE_LIGHTS};
+use self::minimap::{MinimapChanges, MinimapLayer};
+use self::parallax::ParallaxBackground;
+use self::time_of_day::{TimeOfDayHandle, ClientTimeOfDay};
 
+mod mesh;
+mod minimap;
 mod pipeline;
 pub mod light;
 pub mod materials;
+pub mod parallax;
+pub mod time_of_day;
 
 #[allow(clippy::large_enum_variant)]
 pub enum ClientChunk {
@@ -43,11 +54,45 @@ pub enum ClientChunk {
 #[derive(PartialEq)]
 pub struct CameraData {
     pub position: Vector2<f32>,
+    /// Current magnification, smoothly following the target set with [`CameraData::zoom_by`]. Above 1 zooms in.
+    pub zoom: f32,
+    target_zoom: f32,
     trauma: f32
 }
 
 
 impl CameraData {
+    pub const MIN_ZOOM: f32 = 0.5;
+    pub const MAX_ZOOM: f32 = 4.0;
+    /// Zoom factor per scroll step
+    const ZOOM_STEP: f32 = 1.1;
+    /// How fast the zoom approaches its target, in 1/s
+    const ZOOM_SMOOTHING: f32 = 12.0;
+    /// World units visible vertically at a zoom of 1
+    const VIEW_HEIGHT: f32 = 27.0;
+
+    pub fn target_zoom(&self) -> f32 {
+        self.target_zoom
+    }
+
+    /// Changes the target zoom by `steps` scroll steps, positive zooming in, within [`CameraData::MIN_ZOOM`] and [`CameraData::MAX_ZOOM`].
+    pub fn zoom_by(&mut self, steps: f32) {
+        self.target_zoom = (self.target_zoom * Self::ZOOM_STEP.powf(steps)).clamp(Self::MIN_ZOOM, Self::MAX_ZOOM);
+    }
+
+    /// Moves the zoom towards the target, framerate independent.
+    fn smooth_zoom(&mut self, delta: f32) {
+        self.zoom += (self.target_zoom - self.zoom) * (1.0 - (-Self::ZOOM_SMOOTHING * delta).exp());
+        if (self.target_zoom - self.zoom).abs() < 1e-4 {
+            self.zoom = self.target_zoom;
+        }
+    }
+
+    /// World units visible vertically at the current zoom
+    pub fn view_height(&self) -> f32 {
+        Self::VIEW_HEIGHT / self.zoom
+    }
+
     pub fn add_trauma(&mut self, trauma: f32) {
         self.trauma = (self.trauma + trauma).clamp(0.0, 1.0);
     }
@@ -68,28 +113,117 @@ impl ClientMod for WorldModClient {
     fn register_handlers(&self, handlers: &mut IdMap<fn() -> Box<dyn ClientHandle>>, _store: &mut DataStore) {
         log!("handles registered");
         handlers.insert(type_to_id::<WorldHandle>(), || Box::new(WorldHandle::new()));
+        handlers.insert(type_to_id::<TimeOfDayHandle>(), || Box::new(TimeOfDayHandle::new()));
     }
 
     fn start<'a>(&self, store: &mut DataStore, provider: OpenGlRenderContextProvider<'a>) -> &'a mut RenderContext {
         let context = provider.make_context();
         println!("started worldmodclient");
+        let view_distance = store.try_get_store::<ClientConfig>()
+            .map(ViewDistance::from_config)
+            .unwrap_or_default();
         store.add_store(ClientWorld {
             chunks: Default::default(),
+            tile_requests: vec![],
+            predicted_tiles: Default::default(),
+            view_distance
         });
 
+        store.add_store(ParallaxBackground::rock_ridges());
         context.push(WorldLayer::new(), store).expect("duplicate layer");
-        context.push(UILayer::new().expect("error instanciating layer"), store).expect("duplicate layer");
+        let font = default_font().expect("error loading font");
+        context.push(UILayer::new(font.clone()), store).expect("duplicate layer");
+        context.push(MinimapLayer::new(), store).expect("duplicate layer");
+        context.push(ConsoleLayer::new(font), store).expect("duplicate layer");
+        store.mut_or_default::<ConsoleCommands>().register(ChunksCommand);
         store.add_default::<Debug<WorldLayer>>();
         store.add_store(CameraData {
             position: Vector2::new(0.0, 0.0),
+            zoom: 1.0,
+            target_zoom: 1.0,
             trauma: 0.0,
         });
         context
     }
 }
 
+/// Chunk radii around the camera chunk, per axis. Chunks are requested within `load_radius`
+/// and only dropped once they are further away than `unload_radius`, so a camera jittering
+/// across a chunk border does not repeatedly load and unload the same chunks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ViewDistance {
+    pub load_radius: Vector2<i32>,
+    pub unload_radius: Vector2<i32>
+}
+
+impl Default for ViewDistance {
+    fn default() -> Self {
+        Self {
+            load_radius: Vector2::new(2, 1),
+            unload_radius: Vector2::new(3, 2)
+        }
+    }
+}
+
+impl ViewDistance {
+    pub fn new(load_radius: Vector2<i32>, unload_radius: Vector2<i32>) -> Self {
+        debug_assert!(unload_radius.x > load_radius.x && unload_radius.y > load_radius.y, "unload radius has to be larger than load radius");
+        Self { load_radius, unload_radius }
+    }
+
+    /// Loads the configured radii, unloading chunks one further out.
+    pub fn from_config(config: &ClientConfig) -> Self {
+        let load_radius = Vector2::new(config.view_distance_x, config.view_distance_y);
+        Self::new(load_radius, load_radius + Vector2::new(1, 1))
+    }
+
+    /// Grows both radii for a view `factor` times as large, e.g. when zoomed out. Factors below 1 keep the radii.
+    pub fn scaled(&self, factor: f32) -> Self {
+        let scale = |r: i32| ((r as f32 + 1.0) * factor.max(1.0)).ceil() as i32 - 1;
+        let load_radius = Vector2::new(scale(self.load_radius.x), scale(self.load_radius.y));
+        Self::new(load_radius, load_radius + self.unload_radius - self.load_radius)
+    }
+
+    pub fn chunks_to_load(&self, center: Vector2<i32>) -> impl Iterator<Item = Vector2<i32>> {
+        let r = self.load_radius;
+        ((center.x - r.x)..=(center.x + r.x)).flat_map(move |x| ((center.y - r.y)..=(center.y + r.y)).map(move |y| Vector2::new(x, y)))
+    }
+
+    pub fn keeps(&self, center: Vector2<i32>, chunk: Vector2<i32>) -> bool {
+        let d = chunk - center;
+        d.x.abs() <= self.unload_radius.x && d.y.abs() <= self.unload_radius.y
+    }
+}
+
 pub struct ClientWorld {
-    chunks: HashMap<Vector2<i32>, ClientChunk>
+    chunks: HashMap<Vector2<i32>, ClientChunk>,
+    tile_requests: Vec<(Vector2<i32>, Tile)>,
+    predicted_tiles: HashMap<Vector2<i32>, Tile>,
+    view_distance: ViewDistance
+}
+
+impl ClientWorld {
+    pub fn view_distance(&self) -> ViewDistance {
+        self.view_distance
+    }
+
+    /// Takes effect on the next update, which requests newly visible chunks and drops those out of range.
+    pub fn set_view_distance(&mut self, view_distance: ViewDistance) {
+        self.view_distance = view_distance;
+    }
+
+    /// Requests the server to set the tile at `pos`.
+    /// The change is applied immediately and rolled back if the server answers with a different tile.
+    /// Returns false if the position is not loaded.
+    pub fn request_tile(&mut self, pos: Vector2<i32>, tile: Tile) -> bool {
+        let Some(ClientChunk::Chunk(chunk, _)) = self.chunks.get_mut(&Self::chunk(pos)) else {
+            return false
+        };
+        chunk.set_tile(Self::pos_in_chunk(pos), tile);
+        self.predicted_tiles.insert(pos, tile);
+        self.tile_requests.push((pos, tile));
+        true
+    }
 }
 
 impl WorldView for ClientWorld {
@@ -120,110 +254,138 @@ impl WorldView for ClientWorld {
 }
 
 pub(crate) struct WorldHandle {
-    tile_sprites: SpriteSheet,
-    fg_tile_sprites: SpriteSheet,
-    water_texture: Texture
+    /// own the textures the meshes' sprites refer to
+    _sprite_sheets: [SpriteSheet; 2],
+    water_texture: Texture,
+    meshes: MeshBuilder
 }
 
 impl WorldHandle {
+    /// tiles are drawn edge to edge, so they must never sample past their sprite
+    const TILE_TEXTURE_CONFIG: TextureConfig = TextureConfig {
+        min_filter: Filter::Linear,
+        mag_filter: Filter::Nearest,
+        wrap_s: Wrap::ClampToEdge,
+        wrap_t: Wrap::ClampToEdge,
+        mip_levels: 1
+    };
+
     fn new() -> Self {
+        let tile_sprites = SpriteSheet::from_texture(
+            Texture::from_bytes_with_config(include_bytes!("../../assets/include/tilemap.png"), Self::TILE_TEXTURE_CONFIG).unwrap(),
+            Vector2::new(16, 16)
+        ).expect("error loading world spritesheet").with_inset(SpriteSheet::HALF_TEXEL);
+        let fg_tile_sprites = SpriteSheet::from_texture(
+            Texture::from_bytes_with_config(include_bytes!("../../assets/include/overlaymap.png"), Self::TILE_TEXTURE_CONFIG).unwrap(),
+            Vector2::new(16, 16)
+        ).expect("error loading world spritesheet").with_inset(SpriteSheet::HALF_TEXEL);
         Self {
-            tile_sprites: SpriteSheet::from_texture(
-                Texture::from_bytes(include_bytes!("../../assets/include/tilemap.png")).unwrap(),
-                Vector2::new(16, 16)
-            ).expect("error loading world spritesheet"),
-            fg_tile_sprites: SpriteSheet::from_texture(
-                Texture::from_bytes(include_bytes!("../../assets/include/overlaymap.png")).unwrap(),
-                Vector2::new(16, 16)
-            ).expect("error loading world spritesheet"),
+            meshes: MeshBuilder::new(sprite_table(&tile_sprites), sprite_table(&fg_tile_sprites)),
+            _sprite_sheets: [tile_sprites, fg_tile_sprites],
             water_texture: Texture::from_bytes(include_bytes!("../../assets/include/water.png")).unwrap()
         }
     }
 
-    pub(crate) fn receive_chunk_data(&mut self, _messenger: &mut ClientMessenger, mut renderer: Nullable<&mut Renderer>, store: &mut DataStore, chunk: Chunk) {
-        let mut quads = vec![];
-        for (i, tile) in chunk.tiles.iter().enumerate() {
-            let index = tile.sprite_sheet_index();
-            if index == 0 {
-                continue;
-            }
+    pub(crate) fn receive_chunk_data(&mut self, _messenger: &mut ClientMessenger, _renderer: Nullable<&mut Renderer>, store: &mut DataStore, CompressedChunk(chunk): CompressedChunk) {
+        let chunk_pos = chunk.chunk_pos;
+        self.meshes.request(chunk.clone(), store.mut_or_default::<QuadPool>());
+        let mut world = store.mut_store::<ClientWorld>();
+        // the server's copy is authoritative, predictions inside of it are rolled back
+        world.predicted_tiles.retain(|pos, _| ClientWorld::chunk(*pos) != chunk_pos);
+        // a chunk that is sent again keeps showing its old blocks until the new mesh is done
+        let blocks = match world.chunks.remove(&chunk.chunk_pos) {
+            Some(ClientChunk::Chunk(_, blocks)) => blocks,
+            _ => vec![]
+        };
+        world.chunks.insert(chunk_pos, ClientChunk::Chunk(chunk, blocks));
+        if let Some(lights) = store.try_mut_store::<LightStore>() {
+            lights.invalidate_occlusion();
+        }
+        if let Some(changes) = store.try_mut_store::<MinimapChanges>() {
+            changes.0.insert(chunk_pos);
+        }
+    }
 
-            let x = (i % CHUNK_SIZE) as i32 + chunk.chunk_pos.x() * CHUNK_SIZE as i32;
-            let y = (i / CHUNK_SIZE) as i32 + chunk.chunk_pos.y() * CHUNK_SIZE as i32;
-            let sprite = self.tile_sprites.get(index as u32 - 1).unwrap();
-
-            if let Some(glow_color) = tile.glow_color() {
-                let quad = Quad::with_glow_sprite(
-                    Vector2::new(x as f32, y as f32), 
-                    Vector2::new(1.0, 1.0), 
-                    1, 
-                    sprite,
-                    glow_color,
-                    GlowTexture::get(store)
-                );
-                quads.push(Block::add_glowing(quad, *renderer, store));
-            }
-            else {
-                let mut quad = Quad::with_terrain_sprite(
-                    Vector2::new(x as f32, y as f32), 
-                    Vector2::new(1.0, 1.0), 
-                    0, 
-                    sprite,
-                    terrain_material(store)
-                );
-                renderer.add(&mut quad);
-                quads.push(Block::Default(quad));
-            }
+    pub(crate) fn receive_tile_update(&mut self, _messenger: &mut ClientMessenger, _renderer: Nullable<&mut Renderer>, store: &mut DataStore, (pos, tile): (Vector2<i32>, Tile)) {
+        let mut world = store.mut_store::<ClientWorld>();
+        let predicted = world.predicted_tiles.remove(&pos);
+        if predicted.is_some_and(|predicted| predicted != tile) {
+            log!(DEBUG, "rolling back predicted tile at {pos}");
         }
-        for (i, tile) in chunk.fg_tiles.iter().enumerate() {
-            let index = tile.sprite_sheet_index();
-            if index == 0 {
-                continue;
-            }
 
-            let x = (i % CHUNK_SIZE) as i32 + chunk.chunk_pos.x() * CHUNK_SIZE as i32;
-            let y = (i / CHUNK_SIZE) as i32 + chunk.chunk_pos.y() * CHUNK_SIZE as i32;
-            let sprite = self.fg_tile_sprites.get(index as u32 - 1).unwrap();
-
-            if let Some(glow_color) = tile.glow_color() {
-                let quad = Quad::with_glow_sprite(
-                    Vector2::new(x as f32, y as f32), 
-                    Vector2::new(1.0, 1.0), 
-                    4, 
-                    sprite,
-                    glow_color,
-                    GlowTexture::get(store)
-                );
-                quads.push(Block::add_glowing(quad, *renderer, store));
+        let Some(ClientChunk::Chunk(chunk, _)) = world.chunks.get_mut(&ClientWorld::chunk(pos)) else {
+            return
+        };
+        if chunk.get_tile(ClientWorld::pos_in_chunk(pos)) != tile {
+            chunk.set_tile(ClientWorld::pos_in_chunk(pos), tile);
+            self.rebuild_chunk(ClientWorld::chunk(pos), store);
+        }
+    }
+
+    pub(crate) fn receive_water_update(&mut self, _messenger: &mut ClientMessenger, _renderer: Nullable<&mut Renderer>, store: &mut DataStore, (chunk_pos, water_mask): (Vector2<i32>, [u8; CHUNK_SIZE*CHUNK_SIZE])) {
+        let mut world = store.mut_store::<ClientWorld>();
+        let Some(ClientChunk::Chunk(chunk, _)) = world.chunks.get_mut(&chunk_pos) else {
+            return
+        };
+        if chunk.water_mask != water_mask {
+            chunk.water_mask = water_mask;
+            self.rebuild_chunk(chunk_pos, store);
+        }
+    }
+
+    /// Requests a new mesh for the chunk, it keeps its blocks until [`WorldHandle::upload_meshes`] replaces them.
+    fn rebuild_chunk(&mut self, chunk_pos: Vector2<i32>, store: &mut DataStore) {
+        let chunk = match store.get_store::<ClientWorld>().chunks.get(&chunk_pos) {
+            Some(ClientChunk::Chunk(chunk, _)) => chunk.clone(),
+            _ => return
+        };
+        self.meshes.request(chunk, store.mut_or_default::<QuadPool>());
+        if let Some(lights) = store.try_mut_store::<LightStore>() {
+            lights.invalidate_occlusion();
+        }
+        if let Some(changes) = store.try_mut_store::<MinimapChanges>() {
+            changes.0.insert(chunk_pos);
+        }
+    }
+
+    /// Swaps the blocks of every chunk whose mesh finished building, together with the lights of its glowing tiles.
+    fn upload_meshes(&mut self, renderer: &mut Renderer, store: &mut DataStore) {
+        for mut mesh in self.meshes.finished() {
+            let mut world = store.mut_store::<ClientWorld>();
+            let Some(ClientChunk::Chunk(chunk, blocks)) = world.chunks.get_mut(&mesh.chunk_pos) else {
+                continue
+            };
+            let lights = chunk_lights(chunk);
+            let mut old = std::mem::take(blocks);
+            old.iter_mut().for_each(|block| block.remove_from(renderer));
+            let new: Vec<Block> = mesh.quads.drain(..).map(|quad| self.add_block(quad, renderer, store)).collect();
+            if let Some(ClientChunk::Chunk(_, blocks)) = store.mut_store::<ClientWorld>().chunks.get_mut(&mesh.chunk_pos) {
+                *blocks = new;
             }
-            else {
-                let mut quad = Quad::with_terrain_sprite(
-                    Vector2::new(x as f32, y as f32), 
-                    Vector2::new(1.0, 1.0), 
-                    3, 
-                    sprite,
-                    terrain_material(store)
-                );
-                renderer.add(&mut quad);
-                quads.push(Block::Default(quad));
+            if let Some(light_store) = store.try_mut_store::<LightStore>() {
+                light_store.bulk_remove(&mesh.chunk_pos);
+                light_store.bulk_add(mesh.chunk_pos, lights);
             }
         }
-        for (i, tile) in chunk.water_mask.iter().enumerate() {
-            if *tile > 0 {
-                let x = (i % CHUNK_SIZE) as i32 + chunk.chunk_pos.x() * CHUNK_SIZE as i32;
-                let y = (i / CHUNK_SIZE) as i32 + chunk.chunk_pos.y() * CHUNK_SIZE as i32;
-                let position = Vector2::new(x, y).to_f32();
-                Block::add_water(Quad::with_water_texture(
-                    position, 
-                    Vector2::new(1.0, 1.0), 
-                    20, 
-                    self.water_texture.id(), 
-                    WaterMaterial::get(store),
-                    *tile as f32
-                ), *renderer);
+    }
+
+    fn add_block(&self, quad: TileQuad, renderer: &mut Renderer, store: &mut DataStore) -> Block {
+        let size = Vector2::new(1.0, 1.0);
+        match quad.kind {
+            TileQuadKind::Terrain(sprite) => {
+                let mut quad = Quad::with_terrain_sprite(quad.position, size, quad.z_index, sprite, instanced_terrain_material(store));
+                renderer.add(&mut quad);
+                Block::Default(quad)
             }
+            TileQuadKind::Glowing(sprite, glow_color) => Block::add_glowing(
+                Quad::with_glow_sprite(quad.position, size, quad.z_index, sprite, glow_color, GlowTexture::get(store)),
+                renderer
+            ),
+            TileQuadKind::Water(depth) => Block::add_water(
+                Quad::with_water_texture(quad.position, size, quad.z_index, self.water_texture.id(), WaterMaterial::get(store), depth as f32),
+                renderer
+            )
         }
-        store.mut_store::<ClientWorld>().chunks.insert(chunk.chunk_pos, ClientChunk::Chunk(chunk, quads));
     }
 }
 
@@ -233,18 +395,15 @@ impl ClientEntity for WorldHandle {
 
 pub enum Block {
     Default(Quad<FlatTexture>),
-    Glowing(Quad<GlowTexture>, LightId),
+    /// its light belongs to the chunk, see [`LightStore::bulk_add`]
+    Glowing(Quad<GlowTexture>),
     Water(Quad<WaterMaterial>)
 }
 
 impl Block {
-    fn add_glowing(mut quad: Quad<GlowTexture>, renderer: &mut Renderer, store: &mut DataStore) -> Self {
+    fn add_glowing(mut quad: Quad<GlowTexture>, renderer: &mut Renderer) -> Self {
         renderer.add(&mut quad);
-        let light_color = quad.light_color();
-        let light_pos = *quad.position() + quad.size().half();
-        let light = Light::new(light_pos, 7.5, Vector3::new(light_color[0], light_color[1], light_color[2]));
-        let light_id = store.mut_store::<LightStore>().add(light);
-        Self::Glowing(quad, light_id)
+        Self::Glowing(quad)
     }
 
     fn add_water(mut quad: Quad<WaterMaterial>, renderer: &mut Renderer) -> Self {
@@ -252,13 +411,19 @@ impl Block {
         Self::Water(quad)
     }
 
-    fn remove_from(&mut self, renderer: &mut Renderer, store: &mut DataStore) {
+    /// Adds the quad once it scrolled into view and removes it once it left, see [`Renderer::draw`].
+    fn draw_to(&mut self, renderer: &mut Renderer) {
+        let _ = match self {
+            Self::Default(quad) => renderer.draw(quad),
+            Self::Glowing(quad) => renderer.draw(quad),
+            Self::Water(quad) => renderer.draw(quad)
+        };
+    }
+
+    fn remove_from(&mut self, renderer: &mut Renderer) {
         match self {
             Self::Default(quad) => renderer.remove(quad),
-            Self::Glowing(quad, light_pos) => {
-                renderer.remove(quad);
-                store.mut_store::<LightStore>().remove(light_pos);
-            },
+            Self::Glowing(quad) => renderer.remove(quad),
             Self::Water(quad) => {
                 // todo
                 renderer.remove(quad)
@@ -270,6 +435,8 @@ impl Block {
 impl ClientHandle for WorldHandle {
     fn start(&mut self, messenger: &mut ClientMessenger, _renderer: Nullable<&mut Renderer>, _store: &mut DataStore) {
         messenger.register_receiver(Self::receive_chunk_data);
+        messenger.register_receiver(Self::receive_tile_update);
+        messenger.register_receiver(Self::receive_water_update);
     }
 
     fn owning_layer(&self) -> TypeId {
@@ -277,47 +444,107 @@ impl ClientHandle for WorldHandle {
     }
 
     fn update(&mut self, messenger: &mut ClientMessenger, renderer: &mut Renderer, store: &mut DataStore, _time: Time) {
-        let cam = store.get_store::<CameraData>().position;
-        let mut_ref_ptr = store as *mut _;
+        let tile_requests = std::mem::take(&mut store.mut_store::<ClientWorld>().tile_requests);
+        for (pos, tile) in tile_requests {
+            messenger.call_server_fn(World::set_tile_requested, (pos, tile), SendMode::Safe);
+            self.rebuild_chunk(ClientWorld::chunk(pos), store);
+        }
+        self.upload_meshes(renderer, store);
+
+        let (cam, zoom) = { let cam = store.get_store::<CameraData>(); (cam.position, cam.zoom) };
         let mut client_world = store.mut_store::<ClientWorld>();
-        let chunks = &mut client_world.chunks;
         let center_chunk: Vector2<_> = (cam / Vector2::from((CHUNK_SIZE as f32, CHUNK_SIZE as f32))).floor().to_i32();
-        for x in (center_chunk.x-2)..=(center_chunk.x+2) {
-            for y in (center_chunk.y-1)..=(center_chunk.y+1) {
-                let k = Vector2::from((x, y));
-                chunks.entry(k).or_insert_with(|| {
-                    messenger.call_server_fn(World::request_world_chunk, k, SendMode::Safe);
-                    ClientChunk::Requested
-                });
-            }
+        // more of the world is visible when zoomed out
+        let view_distance = client_world.view_distance.scaled(1.0 / zoom);
+        let chunks = &mut client_world.chunks;
+        for k in view_distance.chunks_to_load(center_chunk) {
+            chunks.entry(k).or_insert_with(|| {
+                messenger.call_server_fn(World::request_world_chunk, k, SendMode::Safe);
+                ClientChunk::Requested
+            });
         }
 
+        let mut unloaded = vec![];
         chunks.retain(|k, v|{
-            let d = *k - center_chunk;
-            if d.x.abs() > 2 || d.y.abs() > 2 {
+            if !view_distance.keeps(center_chunk, *k) {
                 if let ClientChunk::Chunk(_, quads) = v {
                     for quad in quads {
-                        quad.remove_from(renderer, unsafe { &mut *mut_ref_ptr });
+                        quad.remove_from(renderer);
                     }
                 }
+                unloaded.push(*k);
                 false
             } else { true }
         });
+        for chunk in chunks.values_mut() {
+            if let ClientChunk::Chunk(_, blocks) = chunk {
+                blocks.iter_mut().for_each(|block| block.draw_to(renderer));
+            }
+        }
+        unloaded.iter().for_each(|chunk| self.meshes.cancel(chunk));
+        unloaded.iter().for_each(|chunk| messenger.call_server_fn(World::release_world_chunk, *chunk, SendMode::Safe));
+        if let Some(lights) = store.try_mut_store::<LightStore>() {
+            unloaded.iter().for_each(|chunk| lights.bulk_remove(chunk));
+        }
     }
 }
 
+const DAY_SKY_COLOR: [f32; 4] = [0.45, 0.65, 0.9, 1.0];
+
+fn sky_color(ambient_light: f32) -> [f32; 4] {
+    let brightness = ambient_light.clamp(0.0, 1.0);
+    [DAY_SKY_COLOR[0] * brightness, DAY_SKY_COLOR[1] * brightness, DAY_SKY_COLOR[2] * brightness, DAY_SKY_COLOR[3]]
+}
+
+/// The tile under the mouse cursor, `None` if its chunk isn't loaded. Updated every frame by the [`WorldLayer`].
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct HoveredTile(pub Option<Vector2<i32>>);
+
 pub struct WorldLayer {
     shake_noise: Box<dyn NoiseFn<f64, 2>>,
-    manual_shake_queued: bool
+    manual_shake_queued: bool,
+    /// outline around the hovered tile
+    highlight: Option<(Vector2<i32>, Polyline)>,
+    /// what this frame's camera sees, covered by the [`ParallaxBackground`]
+    view: Option<(Vector2<f32>, Vector2<f32>)>
 }
 
 impl WorldLayer {
+    const HIGHLIGHT_WEIGHT: f32 = 0.06;
+    const HIGHLIGHT_Z_INDEX: u8 = 10;
+    const HIGHLIGHT_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 0.8];
+
     fn new() -> Self {
         Self {
             shake_noise: Box::new(Fbm::<Perlin>::new(0)),
-            manual_shake_queued: false
+            manual_shake_queued: false,
+            highlight: None,
+            view: None
         }
     }
+
+    /// Picks the tile under the mouse with this frame's camera, so the pick follows the camera even if the mouse doesn't move.
+    fn update_hovered_tile(store: &mut DataStore, camera: &Camera) {
+        let input = store.mut_or_default::<InputState>();
+        let tile = pick_tile(camera, input.mouse_position(), input.viewport_size());
+        let loaded = tile.filter(|tile| store.try_get_store::<ClientWorld>()
+            .is_some_and(|world| world.get_tile_or_null(*tile).ref_option().is_some()));
+        *store.mut_or_default::<HoveredTile>() = HoveredTile(loaded);
+    }
+
+    fn outline(tile: Vector2<i32>) -> Polyline {
+        let (min, max) = (tile.to_f32(), tile.to_f32() + Vector2::new(1.0, 1.0));
+        let corners = vec![min, Vector2::new(max.x, min.y), max, Vector2::new(min.x, max.y), min];
+        Polyline::new(corners, Self::HIGHLIGHT_WEIGHT, Self::HIGHLIGHT_Z_INDEX, Self::HIGHLIGHT_COLOR).with_cap(LineCap::Square)
+    }
+}
+
+/// The tile at a screen position (in pixels, origin top left), `None` for an empty viewport.
+pub fn pick_tile(camera: &Camera, screen_pos: Vector2<f32>, viewport: Vector2<u32>) -> Option<Vector2<i32>> {
+    if viewport.x == 0 || viewport.y == 0 {
+        return None
+    }
+    Some(camera.screen_to_world(screen_pos, viewport.to_f32()).floor().to_i32())
 }
 
 impl Layer for WorldLayer {
@@ -325,12 +552,29 @@ impl Layer for WorldLayer {
         renderer.set_pipeline(WorldRenderPipeline::new(store).expect_log());
     }
 
+    fn quit(&mut self, renderer: &mut Renderer, store: &mut DataStore) {
+        if let Some((_, mut outline)) = self.highlight.take() {
+            renderer.remove(&mut outline);
+        }
+        if let Some(background) = store.try_mut_store::<ParallaxBackground>() {
+            background.remove_from(renderer);
+        }
+        store.remove_store::<ParallaxBackground>();
+        store.remove_store::<HoveredTile>();
+        store.remove_store::<ClientWorld>();
+        store.remove_store::<CameraData>();
+        store.remove_store::<LightStore>();
+    }
+
     fn instantiate_camera(&self) -> Camera {
         Camera::new(-24.0, 24.0, 13.5, -13.5, -1.0, 1.0)
     }
 
     fn update_camera(&mut self, store: &mut DataStore, camera: &mut Camera, time: Time) {
         let mut cam = store.mut_store::<CameraData>();
+        // zooming stays responsive while the game is paused
+        cam.smooth_zoom(time.real_delta());
+        camera.set_view_height(cam.view_height());
         if self.manual_shake_queued {
             cam.add_trauma(0.2);
             self.manual_shake_queued = false;
@@ -343,22 +587,47 @@ impl Layer for WorldLayer {
         cam.trauma = (cam.trauma - time.delta as f32 / 3.0).clamp(0.0, 1.0);
         camera.set_rotation(self.shake_noise.get([time.time as f64 * 5.0, 732.183]) as f32 * shake * 0.0);
         cam.trauma = (cam.trauma - time.delta as f32 / 3.0).clamp(0.0, 1.0);
+        self.view = Some(camera.visible_bounds());
+        Self::update_hovered_tile(store, camera);
     }
 
-    fn pre_handles_update(&mut self, store: &mut DataStore, renderer: &mut Renderer, _time: Time) {
+    fn pre_handles_update(&mut self, store: &mut DataStore, renderer: &mut Renderer, time: Time) {
+        // the sky gets darker with the ambient light, takes effect from the next frame on
+        let ambient_light = store.get_store::<LightStore>().ambient_light();
+        renderer.set_clear_color(sky_color(ambient_light));
+        if let Some(view) = self.view {
+            let material = terrain_material(store);
+            if let Some(background) = store.try_mut_store::<ParallaxBackground>() {
+                background.draw(renderer, view, time.time, &material);
+            }
+        }
         store.mut_store::<Debug<WorldLayer>>().renderer().start_render(renderer);
     }
 
     fn post_handles_update(&mut self, store: &mut DataStore, renderer: &mut Renderer, _time: Time) {
+        let hovered = store.mut_or_default::<HoveredTile>().0;
+        if self.highlight.as_ref().map(|(tile, _)| *tile) != hovered {
+            if let Some((_, mut outline)) = self.highlight.take() {
+                renderer.remove(&mut outline);
+            }
+            self.highlight = hovered.map(|tile| (tile, Self::outline(tile)));
+        }
+        if let Some((_, outline)) = &mut self.highlight {
+            let _ = renderer.draw(outline);
+        }
         store.mut_store::<Debug<WorldLayer>>().renderer().finish_render(renderer);
     }
 
-    fn event(&mut self, event: &Event, _store: &mut DataStore) -> bool {
+    fn event(&mut self, event: &Event, store: &mut DataStore) -> bool {
         match event {
-            Event::KeyPressed(KeyCode::Enter) => {
+            _ if store.mut_or_default::<ActionMap>().matches(event, Action::ShakeCamera) => {
                 self.manual_shake_queued = true;
                 true
             }
+            Event::MouseScrolled(offset) => {
+                store.mut_store::<CameraData>().zoom_by(offset.y);
+                true
+            }
             Event::MouseMoved(position) => {
                 log!(PACK, "mouse moved to: {position}");
                 true
@@ -370,7 +639,7 @@ impl Layer for WorldLayer {
 
 struct UILayer {
     font: Rc<BitmapFont>, 
-    fps_display: Nullable<TextArea<48, 12>>
+    fps_display: Nullable<DynTextArea>
 }
 
 impl Layer for UILayer {
@@ -378,44 +647,137 @@ impl Layer for UILayer {
         Camera::new(0.0, 160.0, 90.0, 0.0, 1.0, -1.0)
     }
 
+    fn resize_camera(&mut self, camera: &mut Camera, aspect_ratio: f32) {
+        // keep the ui anchored to the top left corner
+        camera.set_projection(0.0, 90.0 * aspect_ratio, 90.0, 0.0, 1.0, -1.0);
+    }
+
     fn attach(&mut self, renderer: &mut Renderer, _store: &mut DataStore) {
-        self.fps_display = Nullable::Value(TextArea::<48, 12>::with_string(Vector2::new(2.0, 2.0), 3, 3.0, 0.5, self.font.clone(), FlatTexture::get(), "FPS: "));
+        self.fps_display = Nullable::Value(DynTextArea::with_string(Vector2::new(2.0, 2.0), 3, 3.0, 0.5, self.font.clone(), FlatTexture::get(), "FPS: "));
         renderer.add(&mut *self.fps_display);
     }
 
-    fn post_handles_update(&mut self, _store: &mut DataStore, renderer: &mut Renderer, time: Time) {
-        let fps = 1.0 / time.delta;
-        (*self.fps_display).set_string(format!("FPS: {}", fps as i32));
-        let _ = renderer.modify(&mut *self.fps_display);
+    fn post_handles_update(&mut self, store: &mut DataStore, renderer: &mut Renderer, time: Time) {
+        let fps = 1.0 / time.real_delta();
+        let lights = store.try_get_store::<LightStore>().map_or(0, LightStore::active_light_count);
+        (*self.fps_display).set_string(renderer, format!("FPS: {}  lights: {lights}/{MAX_ACTIVE_LIGHTS}", fps as i32));
+        let _ = renderer.draw(&mut *self.fps_display);
     }
 
     fn event(&mut self, event: &Event, store: &mut DataStore) -> bool {
-        match event {
-            Event::KeyPressed(KeyCode::M) => {
-                let mut light_store = store.mut_store::<LightStore>();
-                let ambient = light_store.ambient_light();
-                (*light_store).set_ambient_light((ambient + 0.05).min(1.0));
-                true
-            },
-            Event::KeyPressed(KeyCode::N) => {
-                let mut light_store = store.mut_store::<LightStore>();
-                let ambient = light_store.ambient_light();
-                (*light_store).set_ambient_light((ambient - 0.05).max(0.0));
-                true
-            }
-            _ => false
+        let actions = store.mut_or_default::<ActionMap>();
+        let delta = if actions.matches(event, Action::LightUp) {
+            0.05
         }
+        else if actions.matches(event, Action::LightDown) {
+            -0.05
+        }
+        else {
+            return false
+        };
+        Self::adjust_brightness(store, delta);
+        true
     }
 }
 
 impl UILayer {
-    fn new() -> ErrorResult<Self> {
-        Ok(Self {
-            font: Rc::new(BitmapFont::from_texture_and_fontdata(
-                Texture::from_bytes(include_bytes!("../../assets/fonts/default/default.png"))?, 
-                include_str!("../../assets/fonts/default/default.bmf")
-            )?),
+    /// Shifts the whole day/night curve, since the ambient light itself follows the time of day.
+    fn adjust_brightness(store: &mut DataStore, delta: f32) {
+        let time_of_day = store.mut_or_default::<ClientTimeOfDay>();
+        let curve = time_of_day.curve();
+        time_of_day.set_curve(DayNightCurve {
+            night_ambient: (curve.night_ambient + delta).clamp(0.0, 1.0),
+            day_ambient: (curve.day_ambient + delta).clamp(0.0, 1.0)
+        });
+    }
+
+    fn new(font: Rc<BitmapFont>) -> Self {
+        Self {
+            font,
             fps_display: Nullable::Null
-        })
+        }
+    }
+}
+
+/// The font of the world mod's ui and console, for other mods to reuse.
+pub fn default_font() -> ErrorResult<Rc<BitmapFont>> {
+    Ok(Rc::new(BitmapFont::from_texture_and_fontdata(
+        Texture::from_bytes(include_bytes!("../../assets/fonts/default/default.png"))?, 
+        include_str!("../../assets/fonts/default/default.bmf")
+    )?))
+}
+
+/// Console command reporting how many chunks are loaded on the client.
+struct ChunksCommand;
+
+impl ConsoleCommand for ChunksCommand {
+    fn name(&self) -> &'static str {
+        "chunks"
     }
-}
\ No newline at end of file
+
+    fn description(&self) -> &'static str {
+        "shows the number of loaded chunks"
+    }
+
+    fn run(&self, _args: &[&str], store: &mut DataStore) -> Result<String, String> {
+        let world = store.try_get_store::<ClientWorld>().ok_or("world not loaded")?;
+        Ok(format!("{} chunks loaded", world.chunks.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use super::*;
+
+    #[test]
+    fn view_distance_hysteresis() {
+        let view_distance = ViewDistance::default();
+        let mut loaded = HashSet::new();
+        let mut unloaded = HashSet::new();
+        // camera shaking back and forth across a chunk border
+        for center in [(0, 0), (1, 0), (0, 0), (1, 1), (0, 0), (1, 0)].map(Vector2::from) {
+            loaded.extend(view_distance.chunks_to_load(center));
+            loaded.retain(|chunk| {
+                let keep = view_distance.keeps(center, *chunk);
+                if !keep {
+                    unloaded.insert(*chunk);
+                }
+                keep
+            });
+        }
+        assert!(unloaded.is_empty(), "chunks were unloaded during camera shake: {unloaded:?}");
+        assert!(view_distance.chunks_to_load(Vector2::new(0, 0)).all(|chunk| view_distance.keeps(Vector2::new(0, 0), chunk)));
+        assert!(!view_distance.keeps(Vector2::new(0, 0), Vector2::new(4, 0)));
+    }
+
+    #[test]
+    fn zoom_follows_target_and_widens_view_distance() {
+        let mut cam = CameraData { position: Vector2::default(), zoom: 1.0, target_zoom: 1.0, trauma: 0.0 };
+        cam.zoom_by(-100.0);
+        assert_eq!(cam.target_zoom(), CameraData::MIN_ZOOM);
+        for _ in 0..120 {
+            cam.smooth_zoom(1.0 / 60.0);
+        }
+        assert_eq!(cam.zoom, CameraData::MIN_ZOOM);
+        assert_eq!(cam.view_height(), 54.0);
+
+        let view_distance = ViewDistance::default();
+        assert_eq!(view_distance.scaled(1.0 / CameraData::MAX_ZOOM), view_distance);
+        let zoomed_out = view_distance.scaled(1.0 / cam.zoom);
+        assert_eq!(zoomed_out.load_radius, Vector2::new(5, 3));
+        assert_eq!(zoomed_out.unload_radius - zoomed_out.load_radius, view_distance.unload_radius - view_distance.load_radius);
+    }
+
+    #[test]
+    fn picked_tile_follows_the_camera() {
+        let mut camera = WorldLayer::new().instantiate_camera();
+        camera.set_position(Vector2::new(10.5, 5.5));
+        let viewport = Vector2::new(1280, 720);
+        assert_eq!(pick_tile(&camera, Vector2::new(640.0, 360.0), viewport), Some(Vector2::new(10, 5)));
+        // the world's y axis points down like the screen's, negative coordinates round down
+        assert_eq!(pick_tile(&camera, Vector2::new(0.0, 0.0), viewport), Some(Vector2::new(-14, -8)));
+        assert_eq!(pick_tile(&camera, Vector2::new(1279.0, 719.0), viewport), Some(Vector2::new(34, 18)));
+        assert_eq!(pick_tile(&camera, Vector2::default(), Vector2::new(0, 0)), None);
+    }
+}
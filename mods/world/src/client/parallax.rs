@@ -0,0 +1,210 @@
+use std::ops::Range;
+use std::rc::Rc;
+
+use aeonetica_client::renderer::Renderer;
+use aeonetica_client::renderer::builtin::Quad;
+use aeonetica_client::renderer::material::FlatTexture;
+use aeonetica_client::renderer::texture::{Format, Texture};
+use aeonetica_engine::math::vector::Vector2;
+use rand::{Rng, SeedableRng};
+
+/// z-indices below the terrain, one per [`ParallaxLayer`]
+pub const PARALLAX_Z_INDICES: Range<u8> = 0..4;
+
+/// How a [`ParallaxLayer`]'s texture moves relative to the world.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Parallax {
+    /// 0 moves with the world, 1 stays fixed on screen
+    depth: f32,
+    /// world units one repetition of the texture covers
+    texture_size: Vector2<f32>,
+    /// where the texture's origin is while the camera is at the world origin
+    offset: Vector2<f32>,
+    /// in world units per second
+    scroll_speed: Vector2<f32>
+}
+
+impl Parallax {
+    /// Texture coordinates of the quad covering `view` at `time`, in the order of the quad's corners.
+    fn uv_coords(&self, (view_min, view_max): (Vector2<f32>, Vector2<f32>), time: f32) -> [[f32; 2]; 4] {
+        let camera = (view_min + view_max).half();
+        let origin = self.offset + camera * self.depth + self.scroll_speed * time;
+        let uv = |corner: Vector2<f32>| -> [f32; 2] { ((corner - origin) / self.texture_size).into() };
+        [
+            uv(view_min),
+            uv(Vector2::new(view_max.x, view_min.y)),
+            uv(view_max),
+            uv(Vector2::new(view_min.x, view_max.y))
+        ]
+    }
+}
+
+/// A repeating texture behind the world that follows the camera by its depth, so it seems further away the larger it is.
+pub struct ParallaxLayer {
+    texture: Texture,
+    parallax: Parallax,
+    quad: Option<Quad<FlatTexture>>
+}
+
+impl ParallaxLayer {
+    /// The texture has to repeat, `depth` is clamped to `0..=1` with 0 moving along with the world and 1 staying fixed on screen.
+    /// `texture_size` is the size of one repetition of the texture in world units.
+    pub fn new(texture: Texture, depth: f32, texture_size: Vector2<f32>) -> Self {
+        Self {
+            texture,
+            parallax: Parallax {
+                depth: depth.clamp(0.0, 1.0),
+                texture_size,
+                offset: Vector2::default(),
+                scroll_speed: Vector2::default()
+            },
+            quad: None
+        }
+    }
+
+    /// Where the texture's origin is while the camera is at the world origin.
+    pub fn with_offset(mut self, offset: Vector2<f32>) -> Self {
+        self.parallax.offset = offset;
+        self
+    }
+
+    /// Moves the texture on its own by `scroll_speed` world units per second, like drifting clouds.
+    pub fn with_scroll_speed(mut self, scroll_speed: Vector2<f32>) -> Self {
+        self.parallax.scroll_speed = scroll_speed;
+        self
+    }
+
+    pub fn depth(&self) -> f32 {
+        self.parallax.depth
+    }
+
+    fn draw(&mut self, renderer: &mut Renderer, view: (Vector2<f32>, Vector2<f32>), time: f32, z_index: u8, material: &Rc<FlatTexture>) {
+        let uv_coords = self.parallax.uv_coords(view, time);
+        let (position, size) = (view.0, view.1 - view.0);
+        let quad = self.quad.get_or_insert_with(|| Quad::with_material(
+            position, size, z_index, material.clone(), (uv_coords, self.texture.id())
+        ));
+        quad.set_position(position);
+        quad.set_size(size);
+        quad.set_uv_coords(uv_coords);
+        if quad.z_index() != z_index {
+            quad.set_z_index(z_index);
+        }
+        let _ = renderer.draw(quad);
+    }
+
+    fn remove_from(&mut self, renderer: &mut Renderer) {
+        if let Some(mut quad) = self.quad.take() {
+            renderer.remove(&mut quad);
+        }
+    }
+}
+
+/// The [`ParallaxLayer`]s behind the world, drawn by the world layer within [`PARALLAX_Z_INDICES`].
+/// Mods can add their own to the store.
+#[derive(Default)]
+pub struct ParallaxBackground {
+    /// farthest first
+    layers: Vec<ParallaxLayer>
+}
+
+impl ParallaxBackground {
+    pub const MAX_LAYERS: usize = (PARALLAX_Z_INDICES.end - PARALLAX_Z_INDICES.start) as usize;
+
+    /// Distant rock ridges, a darker and nearer one in front of a lighter one.
+    pub fn rock_ridges() -> Self {
+        let mut background = Self::default();
+        for (seed, depth, color) in [(1, 0.85, [46, 50, 66, 255]), (2, 0.6, [31, 33, 45, 255])] {
+            let size = Vector2::new(256, 64);
+            let texture = Texture::create(size, Format::RgbaU8);
+            texture.set_data(&ridge_pixels(size, seed, color));
+            background.add(ParallaxLayer::new(texture, depth, size.to_f32() / 8.0));
+        }
+        background
+    }
+
+    /// Adds a layer in front of the farther and behind the nearer ones.
+    /// Returns `false` and drops the layer if there already are [`ParallaxBackground::MAX_LAYERS`].
+    pub fn add(&mut self, layer: ParallaxLayer) -> bool {
+        if self.layers.len() >= Self::MAX_LAYERS {
+            return false
+        }
+        let index = self.layers.partition_point(|other| other.depth() >= layer.depth());
+        self.layers.insert(index, layer);
+        true
+    }
+
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    /// Covers `view` with every layer.
+    pub(crate) fn draw(&mut self, renderer: &mut Renderer, view: (Vector2<f32>, Vector2<f32>), time: f32, material: &Rc<FlatTexture>) {
+        for (layer, z_index) in self.layers.iter_mut().zip(PARALLAX_Z_INDICES) {
+            layer.draw(renderer, view, time, z_index, material);
+        }
+    }
+
+    pub(crate) fn remove_from(&mut self, renderer: &mut Renderer) {
+        self.layers.iter_mut().for_each(|layer| layer.remove_from(renderer));
+    }
+}
+
+/// RGBA pixels of a ridge line that repeats horizontally, transparent above it and `color` below.
+fn ridge_pixels(size: Vector2<u32>, seed: u64, color: [u8; 4]) -> Vec<u8> {
+    let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+    // whole periods per texture width, so both edges meet
+    let waves: Vec<(f32, f32)> = (1..=4).map(|period| (period as f32, rng.gen_range(0.0..std::f32::consts::TAU))).collect();
+    let ridge = |x: u32| {
+        let t = x as f32 / size.x as f32 * std::f32::consts::TAU;
+        let wave: f32 = waves.iter().map(|(period, phase)| (t * period + phase).sin() / period).sum();
+        // the waves add up to at most about ±2.1
+        (0.5 + wave * 0.15) * size.y as f32
+    };
+    let ridges: Vec<f32> = (0..size.x).map(ridge).collect();
+    (0..size.y)
+        .flat_map(|y| ridges.iter().map(move |ridge| y as f32 >= *ridge))
+        .flat_map(|solid| if solid { color } else { [0; 4] })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn farther_layers_follow_the_camera_more() {
+        let parallax = |depth| Parallax { depth, texture_size: Vector2::new(4.0, 2.0), offset: Vector2::new(1.0, 0.0), scroll_speed: Vector2::default() };
+        let view = |camera: Vector2<f32>| (camera - Vector2::new(8.0, 4.0), camera + Vector2::new(8.0, 4.0));
+        let (near, far) = (parallax(0.0), parallax(0.75));
+
+        let at_origin = near.uv_coords(view(Vector2::default()), 0.0);
+        assert_eq!(at_origin, [[-2.25, -2.0], [1.75, -2.0], [1.75, 2.0], [-2.25, 2.0]]);
+        assert_eq!(far.uv_coords(view(Vector2::default()), 0.0), at_origin);
+
+        // moving the camera by a whole texture width scrolls the near layer by one repetition, the far one by a quarter
+        let moved = view(Vector2::new(4.0, 0.0));
+        assert_eq!(near.uv_coords(moved, 0.0)[0], [-1.25, -2.0]);
+        assert_eq!(far.uv_coords(moved, 0.0)[0], [-2.0, -2.0]);
+
+        let drifting = Parallax { scroll_speed: Vector2::new(2.0, 0.0), ..near };
+        assert_eq!(drifting.uv_coords(view(Vector2::default()), 2.0)[0], [-3.25, -2.0]);
+    }
+
+    #[test]
+    fn ridges_repeat_horizontally_above_solid_ground() {
+        let size = Vector2::new(64, 32);
+        let pixels = ridge_pixels(size, 7, [1, 2, 3, 255]);
+        assert_eq!(pixels.len(), (size.x * size.y * 4) as usize);
+        let solid = |x: u32, y: u32| pixels[((y * size.x + x) * 4 + 3) as usize] == 255;
+        let ridge = |x: u32| (0..size.y).find(|y| solid(x, *y)).unwrap();
+        for x in 0..size.x {
+            assert!(!solid(x, 0) && solid(x, size.y - 1));
+            assert!((ridge(x) as i32 - ridge((x + 1) % size.x) as i32).abs() <= 2, "ridge jumps at {x}");
+        }
+    }
+}
@@ -1,16 +1,21 @@
-use aeonetica_client::{renderer::{pipeline::Pipeline, Renderer, layer::LayerUpdater, buffer::framebuffer::*, texture::*, util::*, shader::{self, UniformStr}, material::Material}, uniform_str, data_store::DataStore};
-use aeonetica_engine::{time::Time, math::{camera::Camera, vector::Vector2}, error::ErrorResult};
+use aeonetica_client::{renderer::{pipeline::Pipeline, builtin::BloomPass, Renderer, layer::LayerUpdater, buffer::framebuffer::*, texture::*, util::*, shader::{self, UniformStr}, material::Material}, uniform_str, data_store::DataStore};
+use aeonetica_engine::{log, time::Time, math::{camera::Camera, vector::Vector2}, error::ErrorResult, util::nullable::Nullable};
 
-use super::{light::{LightStore, AMBIENT_LIGHT_STRENGTH_USTR}, materials::{terrain_shader, WaterMaterial}};
+use super::{ClientWorld, CameraData, light::{LightStore, AMBIENT_LIGHT_STRENGTH_USTR}, materials::{terrain_shader, instanced_terrain_shader, bind_terrain_normal_map, WaterMaterial}};
 
 pub(super) struct WorldRenderPipeline {
     intermediate_fb: FrameBuffer,
-    shader: shader::Program
+    shader: shader::Program,
+    bloom: Option<BloomPass>
 }
 
 impl WorldRenderPipeline {
-    const FB_SIZE: Vector2<u32> = Vector2::new(1920, 1080);
+    /// size until the first frame tells the actual target size
+    const INITIAL_FB_SIZE: Vector2<u32> = Vector2::new(1920, 1080);
     const FRAME_CCOL: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+    /// set to false to skip the bloom pass on low-end machines
+    const BLOOM: bool = true;
+    const BLOOM_THRESHOLD: f32 = 0.8;
 
     const FRAME_USTR: UniformStr = uniform_str!("u_Frame");
     const WATER_DEPTH_USTR: UniformStr = uniform_str!("u_WaterDepthMap");
@@ -19,31 +24,66 @@ impl WorldRenderPipeline {
 
     pub fn new(store: &mut DataStore) -> ErrorResult<Self> {
         LightStore::init(store);
-        
-        scissor(Vector2::new(0, 0), Vector2::new(1920, 1080));
 
         Ok(Self {
-            intermediate_fb: FrameBuffer::new([
-                    Attachment::Color(Texture::create(Self::FB_SIZE, Format::RgbaF16)), // main scene colors
-                    Attachment::Color(Texture::create(Self::FB_SIZE, Format::RgbaF16)) // water depth buffer
-                ], true)?,
-            shader: shader::Program::from_source(include_str!("../../assets/world-shader.glsl"))?
+            intermediate_fb: Self::create_intermediate_fb(Self::INITIAL_FB_SIZE)?,
+            shader: shader::Program::from_source(include_str!("../../assets/world-shader.glsl"))?,
+            bloom: if Self::BLOOM {
+                let mut bloom = BloomPass::new(Self::INITIAL_FB_SIZE)?;
+                bloom.set_threshold(Self::BLOOM_THRESHOLD);
+                Some(bloom)
+            } else { None }
         })
     }
+
+    fn create_intermediate_fb(size: Vector2<u32>) -> ErrorResult<FrameBuffer> {
+        FrameBuffer::new([
+            Attachment::Color(Texture::create(size, Format::RgbaF16)), // main scene colors
+            Attachment::Color(Texture::create(size, Format::RgbaF16)) // water depth buffer
+        ], true)
+    }
+
+    /// Recreates all intermediate framebuffers if `size` differs from their current size.
+    fn resize(&mut self, size: Vector2<u32>) -> ErrorResult<()> {
+        if self.intermediate_fb.size() == Some(size) {
+            return Ok(())
+        }
+        self.intermediate_fb = Self::create_intermediate_fb(size)?;
+        if let Some(bloom) = &mut self.bloom {
+            bloom.resize(size)?;
+        }
+        Ok(())
+    }
 }
 
 impl Pipeline for WorldRenderPipeline {
     fn pipeline(&mut self, renderer: &mut Renderer, camera: &Camera, target: &Target, mut updater: LayerUpdater, time: Time) {
+        if let Target::FrameBuffer(fb) = target {
+            if let Some(size) = fb.size() {
+                if let Err(err) = self.resize(size) {
+                    log!(ERROR, "could not resize world framebuffer: {err}");
+                }
+            }
+        }
+        let size = self.intermediate_fb.size().unwrap();
+
         self.intermediate_fb.bind();
-        self.intermediate_fb.clear(Self::FRAME_CCOL);
+        self.intermediate_fb.clear(renderer.clear_color().unwrap_or(Self::FRAME_CCOL));
         renderer.begin_scene(camera);
 
+        scissor(Vector2::new(0, 0), size.map(|i| i as i32));
         enable_scissor_test();
 
-        let shader = terrain_shader(updater.store());
-        let lights = updater.store().mut_store::<LightStore>();
-        lights.upload_uniforms(&shader);
+        let shaders = [terrain_shader(updater.store()), instanced_terrain_shader(updater.store())];
+        let camera_tile = updater.store().get_store::<CameraData>().option().map(|cam| cam.position.floor().to_i32());
+        if let ((Nullable::Value(lights), Nullable::Value(world)), Some(camera_tile)) = (updater.store().two_mut_stores::<LightStore, ClientWorld>(), camera_tile) {
+            lights.update_occlusion(world, camera_tile);
+        }
+        let mut lights = updater.store().mut_store::<LightStore>();
+        lights.cull(*camera.position());
+        shaders.iter().for_each(|shader| lights.upload_uniforms(shader));
         let ambient_light = lights.ambient_light();
+        shaders.iter().for_each(|shader| bind_terrain_normal_map(updater.store(), shader));
 
         let water_material = WaterMaterial::get(updater.store());
         let water_shader = water_material.shader();
@@ -57,6 +97,10 @@ impl Pipeline for WorldRenderPipeline {
         
         disable_scissor_test();
 
+        if let Some(bloom) = &self.bloom {
+            bloom.apply(&self.intermediate_fb, 0);
+        }
+
         self.shader.bind();
         self.shader.upload_uniform(&Self::TIME_USTR, &time.time);
         
@@ -0,0 +1,121 @@
+use aeonetica_client::{data_store::DataStore, networking::messaging::{ClientHandle, ClientMessenger}, renderer::Renderer};
+use aeonetica_engine::{TypeId, networking::messaging::ClientEntity, time::Time, util::{nullable::Nullable, type_to_id}};
+
+use crate::common::{DayNightCurve, DayPhase, is_night, phase_delta};
+use super::{WorldLayer, light::LightStore};
+
+/// Client side copy of the server's day/night cycle, advanced every frame between network updates.
+pub struct ClientTimeOfDay {
+    phase: DayPhase,
+    day_length: f32,
+    correction: f32,
+    curve: DayNightCurve
+}
+
+impl Default for ClientTimeOfDay {
+    fn default() -> Self {
+        Self {
+            phase: 0.5,
+            day_length: f32::INFINITY,
+            correction: 0.0,
+            curve: DayNightCurve::default()
+        }
+    }
+}
+
+impl ClientTimeOfDay {
+    /// Fraction of the remaining correction towards the server phase applied per second.
+    const CORRECTION_RATE: f32 = 2.0;
+    /// Differences to the server larger than this are jumped to immediately.
+    const MAX_SMOOTH_CORRECTION: f32 = 0.05;
+
+    pub fn phase(&self) -> DayPhase {
+        self.phase
+    }
+
+    pub fn is_night(&self) -> bool {
+        is_night(self.phase)
+    }
+
+    pub fn ambient_light(&self) -> f32 {
+        self.curve.ambient_light(self.phase)
+    }
+
+    pub fn curve(&self) -> DayNightCurve {
+        self.curve
+    }
+
+    pub fn set_curve(&mut self, curve: DayNightCurve) {
+        self.curve = curve;
+    }
+
+    fn synchronize(&mut self, phase: DayPhase, day_length: f32) {
+        self.day_length = day_length;
+        let delta = phase_delta(self.phase, phase);
+        if delta.abs() > Self::MAX_SMOOTH_CORRECTION {
+            self.phase = phase;
+            self.correction = 0.0;
+        } else {
+            self.correction = delta;
+        }
+    }
+
+    fn advance(&mut self, delta: f32) {
+        let correction = self.correction * (delta * Self::CORRECTION_RATE).min(1.0);
+        self.correction -= correction;
+        self.phase = (self.phase + delta / self.day_length + correction).rem_euclid(1.0);
+    }
+}
+
+pub(crate) struct TimeOfDayHandle;
+
+impl TimeOfDayHandle {
+    pub(crate) fn new() -> Self {
+        Self
+    }
+
+    pub(crate) fn receive_time_of_day(&mut self, _messenger: &mut ClientMessenger, _renderer: Nullable<&mut Renderer>, store: &mut DataStore, (phase, day_length): (DayPhase, f32)) {
+        store.mut_or_default::<ClientTimeOfDay>().synchronize(phase, day_length);
+    }
+}
+
+impl ClientEntity for TimeOfDayHandle {}
+
+impl ClientHandle for TimeOfDayHandle {
+    fn start(&mut self, messenger: &mut ClientMessenger, _renderer: Nullable<&mut Renderer>, _store: &mut DataStore) {
+        messenger.register_receiver(Self::receive_time_of_day);
+    }
+
+    fn owning_layer(&self) -> TypeId {
+        type_to_id::<WorldLayer>()
+    }
+
+    fn update(&mut self, _messenger: &mut ClientMessenger, _renderer: &mut Renderer, store: &mut DataStore, time: Time) {
+        let time_of_day = store.mut_or_default::<ClientTimeOfDay>();
+        time_of_day.advance(time.delta);
+        let ambient_light = time_of_day.ambient_light();
+        if let Some(lights) = store.try_mut_store::<LightStore>() {
+            lights.set_ambient_light(ambient_light);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converges_to_server_phase() {
+        let mut time_of_day = ClientTimeOfDay::default();
+        time_of_day.synchronize(0.2, 100.0);
+        assert_eq!(time_of_day.phase(), 0.2, "large differences are jumped to");
+
+        time_of_day.synchronize(0.23, 100.0);
+        assert!(time_of_day.phase() < 0.23);
+        for _ in 0..200 {
+            time_of_day.advance(0.01);
+        }
+        // 2s at 100s per day on top of the corrected phase
+        assert!((time_of_day.phase() - 0.25).abs() < 1e-3, "{}", time_of_day.phase());
+    }
+}
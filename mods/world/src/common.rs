@@ -1,5 +1,4 @@
 use std::fmt::Display;
-use std::ops::Mul;
 
 use aeonetica_engine::nanoserde::{SerBin, DeBin};
 use aeonetica_engine::nanoserde;
@@ -10,7 +9,43 @@ use crate::tiles::{Tile, FgTile};
 pub const CHUNK_SIZE: usize = 16;
 pub const GRAVITY: f32 = -20.0;
 
-#[derive(Debug, Copy, Clone)]
+/// Fraction of a day in `0.0..1.0`. `0.0` is midnight, `0.25` dawn, `0.5` noon and `0.75` dusk.
+pub type DayPhase = f32;
+
+pub fn is_night(phase: DayPhase) -> bool {
+    !(0.25..0.75).contains(&phase)
+}
+
+/// Shortest signed distance from phase `from` to phase `to`, wrapping around midnight.
+pub fn phase_delta(from: DayPhase, to: DayPhase) -> f32 {
+    (to - from + 0.5).rem_euclid(1.0) - 0.5
+}
+
+/// Maps the time of day to ambient light strength, following a sine from `night_ambient` at midnight to `day_ambient` at noon.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DayNightCurve {
+    pub night_ambient: f32,
+    pub day_ambient: f32
+}
+
+impl Default for DayNightCurve {
+    fn default() -> Self {
+        Self {
+            night_ambient: 0.05,
+            day_ambient: 0.8
+        }
+    }
+}
+
+impl DayNightCurve {
+    pub fn ambient_light(&self, phase: DayPhase) -> f32 {
+        let daylight = (1.0 - (phase * std::f32::consts::TAU).cos()) / 2.0;
+        self.night_ambient + (self.day_ambient - self.night_ambient) * daylight
+    }
+}
+
+/// Generation stages of a chunk, in the order they are run.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[repr(u8)]
 pub enum Population {
     Uninit,
@@ -21,6 +56,33 @@ pub enum Population {
     Finished
 }
 
+impl Population {
+    /// The stage following this one, `None` for [`Population::Finished`].
+    pub fn next(self) -> Option<Population> {
+        Some(match self {
+            Self::Uninit => Self::TerrainRaw,
+            Self::TerrainRaw => Self::TerrainPostProcess,
+            Self::TerrainPostProcess => Self::TerrainWatered,
+            Self::TerrainWatered => Self::Structures,
+            Self::Structures => Self::Finished,
+            Self::Finished => return None
+        })
+    }
+
+    /// The stage all directly adjacent chunks (including diagonals) have to reach before a chunk may enter this stage.
+    /// Stages reading or writing across chunk borders require their neighbours to be at least one stage behind,
+    /// and a chunk is only finished once no neighbouring structure can spill into it anymore.
+    pub fn neighbor_requirement(self) -> Option<Population> {
+        match self {
+            Self::Uninit | Self::TerrainRaw => None,
+            Self::TerrainPostProcess => Some(Self::TerrainRaw),
+            Self::TerrainWatered => Some(Self::TerrainPostProcess),
+            Self::Structures => Some(Self::TerrainWatered),
+            Self::Finished => Some(Self::Structures)
+        }
+    }
+}
+
 impl SerBin for Population {
     fn ser_bin(&self, output: &mut Vec<u8>) {
         (*self as u8).ser_bin(output)
@@ -29,11 +91,20 @@ impl SerBin for Population {
 
 impl DeBin for Population {
     fn de_bin(offset: &mut usize, bytes: &[u8]) -> Result<Self, nanoserde::DeBinErr> {
-        Ok(unsafe { std::mem::transmute(u8::de_bin(offset, bytes)?) })
+        let start = *offset;
+        Ok(match u8::de_bin(offset, bytes)? {
+            0 => Self::Uninit,
+            1 => Self::TerrainRaw,
+            2 => Self::TerrainPostProcess,
+            3 => Self::TerrainWatered,
+            4 => Self::Structures,
+            5 => Self::Finished,
+            _ => return Err(nanoserde::DeBinErr { o: start, l: 1, s: bytes.len() })
+        })
     }
 }
 
-#[derive(SerBin, DeBin, Debug, Clone)]
+#[derive(SerBin, DeBin, Debug, Clone, PartialEq)]
 pub struct Chunk {
     pub population: Population,
     pub chunk_pos: Vector2<i32>,
@@ -79,6 +150,83 @@ impl Chunk {
     }
 }
 
+/// Network representation of a [`Chunk`].
+/// Each tile layer is run-length encoded if that is smaller than the raw layer, which it is
+/// for most chunks, as they are dominated by [`Tile::Wall`], [`FgTile::Empty`] and dry tiles.
+/// A uniform chunk takes 28 bytes instead of the raw 1289, while a layer that does not compress
+/// is sent raw, costing 1 extra byte per layer (3 in total).
+#[derive(Debug, Clone)]
+pub struct CompressedChunk(pub Chunk);
+
+impl From<Chunk> for CompressedChunk {
+    fn from(chunk: Chunk) -> Self {
+        Self(chunk)
+    }
+}
+
+const LAYER_RAW: u8 = 0;
+const LAYER_RLE: u8 = 1;
+
+fn ser_layer<T: SerBin + PartialEq>(layer: &[T], output: &mut Vec<u8>) {
+    let mut rle = vec![LAYER_RLE];
+    let mut i = 0;
+    while i < layer.len() {
+        let run = layer[i..].iter().take(u8::MAX as usize).take_while(|t| **t == layer[i]).count();
+        (run as u8).ser_bin(&mut rle);
+        layer[i].ser_bin(&mut rle);
+        i += run;
+    }
+
+    let mut raw = vec![LAYER_RAW];
+    layer.iter().for_each(|t| t.ser_bin(&mut raw));
+
+    output.extend(if rle.len() < raw.len() { rle } else { raw })
+}
+
+fn de_layer<T: DeBin + Copy, const N: usize>(layer: &mut [T; N], offset: &mut usize, bytes: &[u8]) -> Result<(), nanoserde::DeBinErr> {
+    match u8::de_bin(offset, bytes)? {
+        LAYER_RLE => {
+            let mut i = 0;
+            while i < N {
+                let run = u8::de_bin(offset, bytes)? as usize;
+                let t = T::de_bin(offset, bytes)?;
+                if run == 0 || i + run > N {
+                    return Err(nanoserde::DeBinErr { o: *offset, l: run, s: bytes.len() })
+                }
+                layer[i..i + run].fill(t);
+                i += run;
+            }
+        }
+        LAYER_RAW => for t in layer.iter_mut() {
+            *t = T::de_bin(offset, bytes)?;
+        }
+        _ => return Err(nanoserde::DeBinErr { o: *offset - 1, l: 1, s: bytes.len() })
+    }
+    Ok(())
+}
+
+impl SerBin for CompressedChunk {
+    fn ser_bin(&self, output: &mut Vec<u8>) {
+        self.0.population.ser_bin(output);
+        self.0.chunk_pos.ser_bin(output);
+        ser_layer(&self.0.tiles, output);
+        ser_layer(&self.0.fg_tiles, output);
+        ser_layer(&self.0.water_mask, output);
+    }
+}
+
+impl DeBin for CompressedChunk {
+    fn de_bin(offset: &mut usize, bytes: &[u8]) -> Result<Self, nanoserde::DeBinErr> {
+        let population = Population::de_bin(offset, bytes)?;
+        let mut chunk = Chunk::new(Vector2::de_bin(offset, bytes)?);
+        chunk.population = population;
+        de_layer(&mut chunk.tiles, offset, bytes)?;
+        de_layer(&mut chunk.fg_tiles, offset, bytes)?;
+        de_layer(&mut chunk.water_mask, offset, bytes)?;
+        Ok(Self(chunk))
+    }
+}
+
 impl Display for Chunk {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "Chunk {} (base @{})", self.chunk_pos, self.chunk_pos * CHUNK_SIZE as i32)?;
@@ -94,6 +242,19 @@ impl Display for Chunk {
 }
 
 
+/// Lower bound for the step length of [`WorldView::calc_move`], so degenerate colliders don't take forever.
+const MIN_MOVE_STEP: f32 = 0.01;
+
+/// Movement is multiplied by this while the collider overlaps water, see [`WorldView::movement_factor`].
+pub const WATER_DRAG: f32 = 0.5;
+
+/// The tiles an aabb bounding box touches, sampled at least once per tile.
+fn aabb_tiles(pos: Vector2<f32>, size: Vector2<f32>) -> impl Iterator<Item = Vector2<i32>> {
+    let max_size = size.ceil().to_i32();
+    (0..=max_size.x).flat_map(move |x| (0..=max_size.y)
+        .map(move |y| Vector2::new(pos.x + (x as f32).min(size.x), pos.y + (y as f32).min(size.y)).floor().to_i32()))
+}
+
 /// This trait is used for both client and server and
 /// is read/viewing only, as the name implies.
 ///
@@ -128,25 +289,41 @@ pub trait WorldView {
 
     fn is_loaded(&self, pos: Vector2<i32>) -> bool;
 
-    /// Returns [`true`] if the aabb bounding box collides with a tile.
+    /// Returns [`true`] if the tile or the foreground tile at `pos` collides.
+    fn is_solid(&self, pos: Vector2<i32>) -> bool {
+        self.get_tile(pos).is_solid() || self.get_fg_tile(pos).is_solid()
+    }
+
+    /// Returns [`true`] if the aabb bounding box collides with a tile, see [`WorldView::is_solid`].
     fn overlap_aabb(&self, pos: Vector2<f32>, size: Vector2<f32>) -> bool {
-        let max_size = size.ceil().to_i32();
-        for x in 0..=max_size.x {
-            for y in 0..=max_size.y {
-                if self.get_tile(Vector2::new(pos.x + (x as f32).min(size.x), pos.y + (y as f32).min(size.y)).floor().to_i32()).is_solid() { return true }
-            }
-        }
-        false
+        aabb_tiles(pos, size).any(|tile| self.is_solid(tile))
+    }
+
+    /// Returns [`true`] if the aabb bounding box touches any water.
+    fn overlap_water(&self, pos: Vector2<f32>, size: Vector2<f32>) -> bool {
+        aabb_tiles(pos, size).any(|tile| self.get_water_tile(tile) > 0)
+    }
+
+    /// How much of its speed a collider keeps here, [`WATER_DRAG`] in water and `1.0` otherwise.
+    fn movement_factor(&self, pos: Vector2<f32>, size: Vector2<f32>) -> f32 {
+        if self.overlap_water(pos, size) { WATER_DRAG } else { 1.0 }
     }
 
     /// Tries to slide along walls instead of stopping movement alltogether.
+    /// The movement is split into steps no longer than half the collider's smallest side (and half a tile),
+    /// so fast movement can't tunnel through walls.
     fn calc_move(&self, pos: &mut Vector2<f32>, size: Vector2<f32>, delta: Vector2<f32>) {
-        let i = delta.mag().mul(25.0).ceil();
-        let delta_x = (delta.x / i, 0.0).into();
-        let delta_y = (0.0, delta.y / i).into();
+        let max_step = size.x.min(size.y).min(1.0).max(MIN_MOVE_STEP) * 0.5;
+        let i = (delta.mag() / max_step).ceil();
+        if i < 1.0 {
+            return
+        }
+        let step = delta / i;
+        let delta_x = (step.x, 0.0).into();
+        let delta_y = (0.0, step.y).into();
         for _ in 0..i as i32 {
-            if !self.overlap_aabb(*pos + delta, size) {
-                *pos += delta / i;
+            if !self.overlap_aabb(*pos + step, size) {
+                *pos += step;
             } else if !self.overlap_aabb(*pos + delta_x, size) {
                 *pos += delta_x;
             } else if !self.overlap_aabb(*pos + delta_y, size) {
@@ -154,4 +331,242 @@ pub trait WorldView {
             }
         }
     }
-}
\ No newline at end of file
+
+    /// [`WorldView::calc_move`], slowed down by the [`WorldView::movement_factor`] at the start position.
+    fn calc_move_in_water(&self, pos: &mut Vector2<f32>, size: Vector2<f32>, delta: Vector2<f32>) {
+        let delta = delta * self.movement_factor(*pos, size);
+        self.calc_move(pos, size, delta)
+    }
+}
+
+/// Returns [`true`] if the two boxes share any area. Boxes that only touch along an edge do not overlap.
+pub fn aabb_overlap(a_pos: Vector2<f32>, a_size: Vector2<f32>, b_pos: Vector2<f32>, b_size: Vector2<f32>) -> bool {
+    a_pos.x < b_pos.x + b_size.x && b_pos.x < a_pos.x + a_size.x &&
+    a_pos.y < b_pos.y + b_size.y && b_pos.y < a_pos.y + a_size.y
+}
+
+/// Sweeps the box at `pos` along `delta` against a static obstacle and returns the fraction of `delta`
+/// at which they first touch, together with the contact normal pointing away from the obstacle.
+/// Boxes overlapping from the start collide at `0.0` with a zero normal, while boxes that only slide along
+/// an edge of the obstacle don't collide at all. Unlike stepping, this can't skip thin obstacles.
+pub fn swept_aabb(pos: Vector2<f32>, size: Vector2<f32>, delta: Vector2<f32>, obstacle_pos: Vector2<f32>, obstacle_size: Vector2<f32>) -> Option<(f32, Vector2<f32>)> {
+    if aabb_overlap(pos, size, obstacle_pos, obstacle_size) {
+        return Some((0.0, Vector2::default()))
+    }
+
+    // entry and exit time of the overlap of each axis
+    let axis = |pos: f32, size: f32, delta: f32, obstacle_pos: f32, obstacle_size: f32| {
+        if delta == 0.0 {
+            if pos < obstacle_pos + obstacle_size && obstacle_pos < pos + size {
+                Some((f32::NEG_INFINITY, f32::INFINITY))
+            } else {
+                None
+            }
+        } else if delta > 0.0 {
+            Some(((obstacle_pos - (pos + size)) / delta, (obstacle_pos + obstacle_size - pos) / delta))
+        } else {
+            Some(((obstacle_pos + obstacle_size - pos) / delta, (obstacle_pos - (pos + size)) / delta))
+        }
+    };
+    let (entry_x, exit_x) = axis(pos.x, size.x, delta.x, obstacle_pos.x, obstacle_size.x)?;
+    let (entry_y, exit_y) = axis(pos.y, size.y, delta.y, obstacle_pos.y, obstacle_size.y)?;
+
+    let entry = entry_x.max(entry_y);
+    let exit = exit_x.min(exit_y);
+    if entry >= exit || !(0.0..=1.0).contains(&entry) {
+        return None
+    }
+
+    let normal = if entry_x > entry_y {
+        Vector2::new(-delta.x.signum(), 0.0)
+    } else {
+        Vector2::new(0.0, -delta.y.signum())
+    };
+    Some((entry, normal))
+}
+
+#[cfg(test)]
+mod tests {
+    use aeonetica_engine::util::assert_ser_bin_roundtrip;
+
+    use super::*;
+    use crate::tiles::{Tile, FgTile};
+
+    fn roundtrip(chunk: Chunk) -> (usize, Chunk) {
+        let bytes = CompressedChunk(chunk).serialize_bin();
+        (bytes.len(), CompressedChunk::deserialize_bin(&bytes).unwrap().0)
+    }
+
+    #[test]
+    fn ser_bin_roundtrip() {
+        for population in [Population::Uninit, Population::TerrainRaw, Population::TerrainPostProcess, Population::TerrainWatered, Population::Structures, Population::Finished] {
+            assert_ser_bin_roundtrip(&population);
+        }
+
+        let mut chunk = Chunk::new((-7, 12).into());
+        chunk.population = Population::Structures;
+        chunk.tiles[3] = Tile::Lamp;
+        chunk.fg_tiles[17] = FgTile::ChainV;
+        chunk.water_mask[200] = 4;
+        assert_ser_bin_roundtrip(&chunk);
+    }
+
+    #[test]
+    fn invalid_discriminants_are_rejected() {
+        assert!(Population::deserialize_bin(&[6]).is_err());
+        assert!(Tile::deserialize_bin(&u16::MAX.to_le_bytes()).is_err());
+        assert!(FgTile::deserialize_bin(&(FgTile::ALL.len() as u16).to_le_bytes()).is_err());
+
+        let mut layer = [0u8; 4];
+        assert!(de_layer(&mut layer, &mut 0, &[LAYER_RAW, 1, 2, 3, 4]).is_ok());
+        assert!(de_layer(&mut layer, &mut 0, &[LAYER_RLE + 1, 1, 2, 3, 4]).is_err());
+    }
+
+    #[test]
+    fn day_night_curve() {
+        let curve = DayNightCurve { night_ambient: 0.1, day_ambient: 0.9 };
+        assert!((curve.ambient_light(0.0) - 0.1).abs() < 1e-5);
+        assert!((curve.ambient_light(0.5) - 0.9).abs() < 1e-5);
+        assert!((curve.ambient_light(0.25) - 0.5).abs() < 1e-5);
+        assert!(is_night(0.1) && !is_night(0.5) && is_night(0.8));
+        assert!((phase_delta(0.95, 0.05) - 0.1).abs() < 1e-5);
+        assert!((phase_delta(0.05, 0.95) + 0.1).abs() < 1e-5);
+    }
+
+    #[test]
+    fn compressed_uniform_chunk() {
+        let chunk = Chunk::new((3, -4).into());
+        let raw_len = chunk.serialize_bin().len();
+        let (len, decoded) = roundtrip(chunk.clone());
+        assert!(len <= 28, "{len} > 28");
+        assert!(len < raw_len);
+        assert_eq!(decoded.chunk_pos, chunk.chunk_pos);
+        assert_eq!(decoded.tiles, chunk.tiles);
+    }
+
+    #[test]
+    fn compressed_mixed_chunk() {
+        let mut chunk = Chunk::new((0, 0).into());
+        for (i, tile) in chunk.tiles.iter_mut().enumerate() {
+            *tile = if i % 2 == 0 { Tile::Stone } else { Tile::Wall };
+        }
+        chunk.water_mask.iter_mut().enumerate().for_each(|(i, w)| *w = i as u8);
+        let raw_len = chunk.serialize_bin().len();
+        let (len, decoded) = roundtrip(chunk.clone());
+        assert!(len <= raw_len + 3);
+        assert_eq!(decoded.tiles, chunk.tiles);
+        assert_eq!(decoded.fg_tiles, chunk.fg_tiles);
+        assert_eq!(decoded.water_mask, chunk.water_mask);
+    }
+
+    #[test]
+    fn compressed_generated_chunks() {
+        let mut world = crate::server::world::World::new(1234);
+        let (mut total, mut raw_total) = (0, 0);
+        for x in -2..=2 {
+            for y in -2..=2 {
+                let chunk_pos = Vector2::new(x * 7, y * 5);
+                world.ensure_population(chunk_pos, Population::Finished);
+                let chunk = world.try_get_chunk_no_gen(chunk_pos).unwrap().clone();
+                let raw_len = chunk.serialize_bin().len();
+                let (len, decoded) = roundtrip(chunk.clone());
+                assert!(len < raw_len, "chunk {chunk_pos}: {len} >= {raw_len}");
+                assert_eq!(decoded.tiles, chunk.tiles);
+                assert_eq!(decoded.fg_tiles, chunk.fg_tiles);
+                assert_eq!(decoded.water_mask, chunk.water_mask);
+                total += len;
+                raw_total += raw_len;
+            }
+        }
+        // real terrain is mostly long runs of the same tile
+        assert!(total * 2 < raw_total, "generated chunks compress to {total} of {raw_total} bytes");
+    }
+
+    #[test]
+    fn aabb_collision_corner_cases() {
+        let one = Vector2::new(1.0, 1.0);
+        assert!(aabb_overlap((0.0, 0.0).into(), one, (0.5, 0.5).into(), one));
+        assert!(!aabb_overlap((0.0, 0.0).into(), one, (1.0, 0.0).into(), one));
+
+        // already overlapping
+        assert_eq!(swept_aabb((0.0, 0.0).into(), one, (1.0, 0.0).into(), (0.5, 0.0).into(), one), Some((0.0, Vector2::default())));
+        // grazing along the top edge
+        assert_eq!(swept_aabb((0.0, -1.0).into(), one, (3.0, 0.0).into(), (1.5, 0.0).into(), one), None);
+        // passing fully through a thin obstacle in one step
+        assert_eq!(swept_aabb((0.0, 0.0).into(), one, (4.0, 0.0).into(), (2.0, 0.0).into(), (0.1, 1.0).into()), Some((0.25, Vector2::new(-1.0, 0.0))));
+        // hitting from above, and stopping short
+        assert_eq!(swept_aabb((0.0, -2.0).into(), one, (0.0, 2.0).into(), (0.0, 0.0).into(), one), Some((0.5, Vector2::new(0.0, -1.0))));
+        assert_eq!(swept_aabb((0.0, -3.0).into(), one, (0.0, 1.0).into(), (0.0, 0.0).into(), one), None);
+    }
+
+    struct SingleWall(Vector2<i32>);
+
+    impl WorldView for SingleWall {
+        fn get_tile_or_null(&self, pos: Vector2<i32>) -> Nullable<Tile> {
+            Nullable::Value(if pos == self.0 { Tile::Wall } else { Tile::StoneBrick })
+        }
+
+        fn get_fg_tile_or_null(&self, _pos: Vector2<i32>) -> Nullable<FgTile> {
+            Nullable::Value(FgTile::Empty)
+        }
+
+        fn get_water_tile_or_null(&self, _pos: Vector2<i32>) -> Nullable<u8> {
+            Nullable::Value(0)
+        }
+
+        fn is_loaded(&self, _pos: Vector2<i32>) -> bool {
+            true
+        }
+    }
+
+    /// Platforms along row `platform`, water from column `water` on
+    struct PlatformAndWater {
+        platform: i32,
+        water: i32
+    }
+
+    impl WorldView for PlatformAndWater {
+        fn get_tile_or_null(&self, _pos: Vector2<i32>) -> Nullable<Tile> {
+            Nullable::Value(Tile::StoneBrick)
+        }
+
+        fn get_fg_tile_or_null(&self, pos: Vector2<i32>) -> Nullable<FgTile> {
+            Nullable::Value(if pos.y == self.platform { FgTile::MetalFrameFloorM } else { FgTile::ChainV })
+        }
+
+        fn get_water_tile_or_null(&self, pos: Vector2<i32>) -> Nullable<u8> {
+            Nullable::Value(if pos.x >= self.water { 3 } else { 0 })
+        }
+
+        fn is_loaded(&self, _pos: Vector2<i32>) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn platforms_stop_falls_and_water_slows_down() {
+        let world = PlatformAndWater { platform: 4, water: 10 };
+        let size = Vector2::new(0.8, 0.8);
+        let mut pos = Vector2::new(0.0, 0.0);
+        world.calc_move_in_water(&mut pos, size, Vector2::new(0.0, 10.0));
+        assert!(pos.y + size.y <= 4.0 && pos.y + size.y > 3.5, "collider fell through the platform to {pos}");
+
+        let mut dry = Vector2::new(0.0, 0.0);
+        world.calc_move_in_water(&mut dry, size, Vector2::new(2.0, 0.0));
+        let mut wet = Vector2::new(12.0, 0.0);
+        world.calc_move_in_water(&mut wet, size, Vector2::new(2.0, 0.0));
+        assert!((dry.x - 2.0).abs() < 1e-4);
+        assert!((wet.x - 12.0 - 2.0 * WATER_DRAG).abs() < 1e-4);
+    }
+
+    #[test]
+    fn calc_move_does_not_tunnel() {
+        let world = SingleWall(Vector2::new(5, 0));
+        let size = Vector2::new(0.2, 0.2);
+        let mut pos = Vector2::new(0.0, 0.4);
+        world.calc_move(&mut pos, size, Vector2::new(40.0, 0.0));
+        assert!(pos.x + size.x <= 5.0, "collider passed through the wall to {pos}");
+        assert!(pos.x > 4.5);
+        assert!(!world.overlap_aabb(pos, size));
+    }
+}
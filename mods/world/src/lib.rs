@@ -1,8 +1,11 @@
+#![feature(coroutines)]
+
 use aeonetica_engine::register;
 
 pub mod client;
 pub mod server;
 pub mod common;
 pub mod tiles;
+pub mod biome;
 
 register!(client::WorldModClient{}, server::WorldModServer::new());
@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+
+use aeonetica_engine::{ClientId, log};
+use aeonetica_engine::math::vector::Vector2;
+use crate::common::Chunk;
+use crate::server::world::World;
+
+/// Upper bound for the number of generator threads, regardless of the available parallelism.
+pub const MAX_GENERATOR_THREADS: usize = 4;
+/// Side length (in chunks) of the square regions that are always generated by the same worker.
+/// Keeping neighbouring chunks on one worker lets structures spill across chunk borders consistently.
+const REGION_SIZE: i32 = 8;
+/// Number of chunks a worker generates before starting over with an empty [`World`],
+/// which bounds the memory held by chunks that were already sent.
+const CHUNKS_PER_WORKER_WORLD: usize = 64;
+
+struct Worker {
+    jobs: Sender<Vector2<i32>>,
+    _thread: JoinHandle<()>
+}
+
+/// Generates chunks on background threads, so that requests for ungenerated chunks don't stall the server tick.
+/// Every worker owns its own generation-only [`World`], seeded like the real one, so finished chunks are identical
+/// to synchronously generated ones. The worker worlds are replaced every [`CHUNKS_PER_WORKER_WORLD`] chunks,
+/// so they don't keep every chunk forever. Requests for a chunk that is already being generated are coalesced.
+pub(crate) struct ChunkGenerator {
+    workers: Vec<Worker>,
+    finished: Receiver<Chunk>,
+    pending: HashMap<Vector2<i32>, Vec<ClientId>>
+}
+
+impl ChunkGenerator {
+    pub(crate) fn new(seed: u64) -> Self {
+        let threads = thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+            .clamp(1, MAX_GENERATOR_THREADS);
+        Self::with_threads(seed, threads)
+    }
+
+    pub(crate) fn with_threads(seed: u64, threads: usize) -> Self {
+        let (finished_sender, finished) = mpsc::channel();
+        let workers = (0..threads.max(1)).map(|i| {
+            let (jobs, job_receiver) = mpsc::channel::<Vector2<i32>>();
+            let finished_sender = finished_sender.clone();
+            let thread = thread::Builder::new()
+                .name(format!("chunk-gen-{i}"))
+                .spawn(move || {
+                    let mut world = World::new(seed);
+                    for (generated, chunk_pos) in job_receiver.into_iter().enumerate() {
+                        if generated > 0 && generated % CHUNKS_PER_WORKER_WORLD == 0 {
+                            world = World::new(seed);
+                        }
+                        let chunk = world.get_chunk_at(chunk_pos).clone();
+                        if finished_sender.send(chunk).is_err() {
+                            break
+                        }
+                    }
+                })
+                .expect("unable to spawn chunk generator thread");
+            Worker { jobs, _thread: thread }
+        }).collect();
+        Self {
+            workers,
+            finished,
+            pending: HashMap::new()
+        }
+    }
+
+    /// Queues `chunk_pos` for generation on behalf of `client`.
+    /// Returns `false` if the chunk was already in flight, in which case `client` is only added to its recipients.
+    pub(crate) fn request(&mut self, chunk_pos: Vector2<i32>, client: ClientId) -> bool {
+        if let Some(clients) = self.pending.get_mut(&chunk_pos) {
+            if !clients.contains(&client) {
+                clients.push(client);
+            }
+            return false
+        }
+        self.pending.insert(chunk_pos, vec![client]);
+        let worker = &self.workers[self.worker_index(chunk_pos)];
+        if worker.jobs.send(chunk_pos).is_err() {
+            log!(ERROR, "chunk generator worker died, chunk {chunk_pos} will never be generated");
+        }
+        true
+    }
+
+    /// Drops all pending sends to `client`, e.g. because it disconnected. Generation itself continues.
+    pub(crate) fn cancel_client(&mut self, client: &ClientId) {
+        self.pending.values_mut().for_each(|clients| clients.retain(|c| c != client));
+    }
+
+    /// Returns the next finished chunk together with the clients still waiting for it, without blocking.
+    pub(crate) fn try_next(&mut self) -> Option<(Chunk, Vec<ClientId>)> {
+        match self.finished.try_recv() {
+            Ok(chunk) => {
+                let clients = self.pending.remove(&chunk.chunk_pos).unwrap_or_default();
+                Some((chunk, clients))
+            }
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                log!(ERROR, "all chunk generator workers died");
+                None
+            }
+        }
+    }
+
+    fn worker_index(&self, chunk_pos: Vector2<i32>) -> usize {
+        let region = Vector2::new(chunk_pos.x.div_euclid(REGION_SIZE), chunk_pos.y.div_euclid(REGION_SIZE));
+        (region.x.wrapping_mul(31).wrapping_add(region.y)).rem_euclid(self.workers.len() as i32) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+    use aeonetica_engine::Id;
+    use super::*;
+
+    fn wait_for(generator: &mut ChunkGenerator) -> (Chunk, Vec<ClientId>) {
+        let start = Instant::now();
+        loop {
+            if let Some(result) = generator.try_next() {
+                return result
+            }
+            assert!(start.elapsed() < Duration::from_secs(30), "chunk generation timed out");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn background_chunks_match_synchronous_generation() {
+        let pos = Vector2::new(3, -2);
+        let mut generator = ChunkGenerator::with_threads(42, 2);
+        let (a, b) = (Id::new(), Id::new());
+        assert!(generator.request(pos, a));
+        assert!(!generator.request(pos, b));
+        generator.cancel_client(&a);
+
+        let (chunk, clients) = wait_for(&mut generator);
+        assert_eq!(chunk, *World::new(42).get_chunk_at(pos));
+        assert_eq!(clients, vec![b]);
+        assert!(generator.pending.is_empty());
+    }
+}
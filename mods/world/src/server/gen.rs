@@ -2,17 +2,33 @@ use std::collections::{HashMap, HashSet};
 use std::collections::hash_map::DefaultHasher;
 use std::hash::Hasher;
 
-use noise::{Fbm, NoiseFn, OpenSimplex, RidgedMulti, Terrace, Worley};
+use noise::{Fbm, MultiFractal, NoiseFn, OpenSimplex, Perlin, RidgedMulti, Terrace, Worley};
+use aeonetica_engine::log;
 use aeonetica_engine::math::vector::Vector2;
 use rand::{SeedableRng, Rng};
+use crate::biome::Biome;
 use crate::common::{CHUNK_SIZE, Population, Chunk, WorldView};
+use crate::server::structure::{Structure, StructureTile};
 use crate::server::world::World;
 use crate::tiles::{Tile, FgTile};
 
+// 64 bit FNV-1a, used instead of `DefaultHasher` because that one may change between Rust releases
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+fn fnv1a_step(hash: u64, byte: u8) -> u64 {
+    (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+}
+
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().copied().fold(FNV_OFFSET_BASIS, fnv1a_step)
+}
+
 pub(crate) struct GenProvider {
     pub(crate) seed: u64,
     pub(crate) cave_noise: Box<dyn NoiseFn<f64, 2>>,
     pub(crate) space_cave_noise: Box<dyn NoiseFn<f64, 2>>,
+    pub(crate) biome_noise: Box<dyn NoiseFn<f64, 2>>,
 }
 
 impl GenProvider {
@@ -25,26 +41,84 @@ impl GenProvider {
                     .add_control_point(0.0)
                     .add_control_point(0.1))
             },
-            space_cave_noise: Box::new(Worley::new(seed as u32 + 1))
+            space_cave_noise: Box::new(Worley::new(seed as u32 + 1)),
+            biome_noise: Box::new(Fbm::<Perlin>::new(seed as u32 + 2).set_octaves(3))
         }
     }
+
+    /// Size of biomes, in chunks.
+    const BIOME_SCALE: f64 = 12.0;
+
+    /// Raw biome noise at a tile position, see [`Biome::from_noise`].
+    /// Only depends on the absolute position, so biomes continue seamlessly across chunk borders.
+    pub(crate) fn biome_noise_at(&self, pos: Vector2<i32>) -> f64 {
+        let p = pos.to_f64() / (CHUNK_SIZE as f64 * Self::BIOME_SCALE);
+        self.biome_noise.get(p.into_array())
+    }
+
+    pub(crate) fn biome_at(&self, pos: Vector2<i32>) -> Biome {
+        Biome::from_noise(self.biome_noise_at(pos))
+    }
 }
 
 impl World {
+    /// Hashes the fully generated tiles, foreground tiles and water of a chunk in a fresh world with `seed`.
+    /// The hash is stable across platforms and compiler versions, so it can be used to check that two builds
+    /// generate identical worlds.
+    pub fn generation_fingerprint(seed: u64, chunk_pos: Vector2<i32>) -> u64 {
+        let mut world = World::new(seed);
+        let chunk = world.get_chunk_at(chunk_pos);
+        chunk.tiles.iter().map(|t| *t as u16)
+            .chain(chunk.fg_tiles.iter().map(|t| *t as u16))
+            .chain(chunk.water_mask.iter().map(|w| *w as u16))
+            .flat_map(u16::to_le_bytes)
+            .fold(FNV_OFFSET_BASIS, fnv1a_step)
+    }
+
     pub(crate) fn mut_init_chunk_at(&mut self, chunk_pos: Vector2<i32>, stage: Population) -> &mut Chunk{
-        let mut p = self.mut_chunk_at_raw(chunk_pos).population;
-        while (p as u8) < stage as u8 {
-            match p {
-                Population::Uninit => self.populate_terrain(chunk_pos, p),
-                Population::TerrainRaw => self.post_process_terrain(chunk_pos, p),
-                Population::TerrainPostProcess => self.lakeify_chunk(chunk_pos, p),
-                Population::TerrainWatered => self.structurize_chunk(chunk_pos, p),
-                Population::Structures => self.mut_chunk_at_raw(chunk_pos).population = Population::Finished,
-                Population::Finished => unreachable!("finished should always be last population stage"),
+        self.ensure_population(chunk_pos, stage);
+        self.mut_chunk_at_raw(chunk_pos)
+    }
+
+    /// Advances the chunk at `chunk_pos` stage by stage until it reaches `target`.
+    /// Before each stage, all neighbours are driven to that stage's [`Population::neighbor_requirement`],
+    /// so features crossing chunk borders never see half generated terrain.
+    /// A chunk whose stage is already being run further up the call stack is left alone, which breaks
+    /// cycles between neighbours that read each other while generating.
+    pub fn ensure_population(&mut self, chunk_pos: Vector2<i32>, target: Population) {
+        loop {
+            let current = self.mut_chunk_at_raw(chunk_pos).population;
+            let Some(next) = current.next() else { return };
+            if current >= target {
+                return
+            }
+            if self.populating.contains(&(chunk_pos, next)) {
+                log!(WARN, "cyclic population of chunk {chunk_pos} at stage {next:?}");
+                return
             }
-            p = self.mut_chunk_at_raw(chunk_pos).population;
+            self.populating.push((chunk_pos, next));
+            if let Some(requirement) = next.neighbor_requirement() {
+                for x in -1..=1 {
+                    for y in -1..=1 {
+                        if x != 0 || y != 0 {
+                            self.ensure_population(chunk_pos + Vector2::new(x, y), requirement);
+                        }
+                    }
+                }
+            }
+            // a neighbour may have advanced this chunk while it was being prepared
+            if self.mut_chunk_at_raw(chunk_pos).population == current {
+                match current {
+                    Population::Uninit => self.populate_terrain(chunk_pos, current),
+                    Population::TerrainRaw => self.post_process_terrain(chunk_pos, current),
+                    Population::TerrainPostProcess => self.lakeify_chunk(chunk_pos, current),
+                    Population::TerrainWatered => self.structurize_chunk(chunk_pos, current),
+                    Population::Structures => self.mut_chunk_at_raw(chunk_pos).population = Population::Finished,
+                    Population::Finished => unreachable!("finished should always be last population stage"),
+                }
+            }
+            self.populating.pop();
         }
-        self.mut_chunk_at_raw(chunk_pos)
     }
 
     pub fn get_init_tile_at(&mut self, pos: Vector2<i32>, stage: Population) -> Tile {
@@ -71,39 +145,47 @@ impl World {
         self.mut_init_chunk_at(World::chunk(pos), stage).set_water_tile(World::pos_in_chunk(pos), t)
     }
 
+    pub fn biome_at(&self, pos: Vector2<i32>) -> Biome {
+        self.generator.biome_at(pos)
+    }
+
     fn get_initial_terrain_tile(&self, pos: Vector2<i32>, can_be_wall: bool) -> Tile{
         let chunk_pos = Self::chunk(pos);
         let (x, y) = Self::pos_in_chunk(pos).into();
         let gen = self.generator.clone();
+        let biome_noise = gen.biome_noise_at(pos);
+        let threshold = Biome::blended_cave_threshold(biome_noise);
+        let jitter = self.chunk_hash_with_seed_and_salt(pos, 4711) as f64 / u64::MAX as f64;
+        let biome = Biome::dithered(biome_noise, jitter).properties();
         let scale = 0.75;
         let scale2 = 1.6;
         let p = Vector2::new(x, y).to_f64() / 16.0 * scale + chunk_pos.to_f64() * scale;
         let ps2 = Vector2::new(x, y).to_f64() / 16.0 * scale2 + chunk_pos.to_f64() * scale2;
         let accent_2 = gen.space_cave_noise.get(ps2.into_array()) < -0.865;
-        let current = gen.cave_noise.get(p.into_array()) > 0.0 || accent_2;
+        let current = gen.cave_noise.get(p.into_array()) > threshold || accent_2;
         let around =
-            (gen.cave_noise.get((p + Vector2::new(1.0/16.0 * scale, 0.0/16.0 * scale)).into_array()) > 0.0) as i32 +
-            (gen.cave_noise.get((p + Vector2::new(-1.0/16.0 * scale, 0.0/16.0 * scale)).into_array()) > 0.0) as i32 +
-            (gen.cave_noise.get((p + Vector2::new(0.0/16.0 * scale, 1.0/16.0 * scale)).into_array()) > 0.0) as i32 +
-            (gen.cave_noise.get((p + Vector2::new(0.0/16.0 * scale, -1.0/16.0 * scale)).into_array()) > 0.0) as i32;
+            (gen.cave_noise.get((p + Vector2::new(1.0/16.0 * scale, 0.0/16.0 * scale)).into_array()) > threshold) as i32 +
+            (gen.cave_noise.get((p + Vector2::new(-1.0/16.0 * scale, 0.0/16.0 * scale)).into_array()) > threshold) as i32 +
+            (gen.cave_noise.get((p + Vector2::new(0.0/16.0 * scale, 1.0/16.0 * scale)).into_array()) > threshold) as i32 +
+            (gen.cave_noise.get((p + Vector2::new(0.0/16.0 * scale, -1.0/16.0 * scale)).into_array()) > threshold) as i32;
         // a bit of a random approach - found accidentally
         let accent =
-            (gen.cave_noise.get((p + Vector2::new(scale, 0.0)).into_array()) > 0.0) as i32 +
-            (gen.cave_noise.get((p + Vector2::new(-scale, 0.0)).into_array()) > 0.0) as i32 +
-            (gen.cave_noise.get((p + Vector2::new(0.0, scale)).into_array()) > 0.0) as i32 +
-            (gen.cave_noise.get((p + Vector2::new(0.0, -scale)).into_array()) > 0.0) as i32;
+            (gen.cave_noise.get((p + Vector2::new(scale, 0.0)).into_array()) > threshold) as i32 +
+            (gen.cave_noise.get((p + Vector2::new(-scale, 0.0)).into_array()) > threshold) as i32 +
+            (gen.cave_noise.get((p + Vector2::new(0.0, scale)).into_array()) > threshold) as i32 +
+            (gen.cave_noise.get((p + Vector2::new(0.0, -scale)).into_array()) > threshold) as i32;
         if accent_2 {
             if accent > 1 {
-                Tile::HardStone
+                biome.accent
             } else {
-                Tile::StoneBrick
+                biome.background
             }
         }
         else if (current && around > 1) || around > 2 {
             if accent > 1 {
-                Tile::Stone
+                biome.stone
             } else {
-                Tile::StoneBrick
+                biome.background
             }
         } else {
             if can_be_wall {
@@ -111,15 +193,15 @@ impl World {
             } else {
                 if around > 1 {
                     if accent > 1 {
-                        Tile::Stone
+                        biome.stone
                     } else {
-                        Tile::StoneBrick
+                        biome.background
                     }
                 } else {
                     if accent > 1 {
-                        Tile::HardStone
+                        biome.accent
                     } else {
-                        Tile::StoneBrick
+                        biome.background
                     }
                 }
             }
@@ -221,8 +303,29 @@ impl World {
         }
     }
 
+    /// Places the decoration of each tile's biome on cave floors.
+    fn decorate_chunk(&mut self, chunk_pos: Vector2<i32>, population: Population) {
+        let base_pos = chunk_pos * 16;
+        for x in 0..CHUNK_SIZE as i32 {
+            for y in 0..CHUNK_SIZE as i32 {
+                let pos = base_pos + Vector2::new(x, y);
+                let biome = self.biome_at(pos).properties();
+                if biome.decoration_rarity == 0 || self.chunk_hash_with_seed_and_salt(pos, 815) % biome.decoration_rarity as u64 != 0 {
+                    continue;
+                }
+                if self.get_init_tile_at(pos, population) != Tile::Wall
+                    && self.get_init_tile_at(pos + Vector2::new(0, 1), population) == Tile::Wall
+                    && self.get_init_fg_tile_at(pos, population) == FgTile::Empty {
+                    self.set_init_fg_tile_at(pos, population, biome.decoration);
+                }
+            }
+        }
+    }
+
     fn structurize_chunk(&mut self, chunk_pos: Vector2<i32>, population: Population) {
         self.mut_chunk_at_raw(chunk_pos).population = Population::Structures;
+        self.flush_deferred_edits(chunk_pos);
+        self.decorate_chunk(chunk_pos, population);
         if chunk_pos.mag_sq() <= 2 { return }
         let mut pos = chunk_pos * 16;
         let mut rng = rand::rngs::StdRng::seed_from_u64(self.chunk_hash_with_seed_and_salt(pos, 0));
@@ -251,6 +354,7 @@ impl World {
             pipes.insert(pos);
             gen_pipe(&mut rng, &mut pipes, self, pos, Vector2::new(0, 0), 16);
 
+            let mut structure = Structure::new();
             for pipe in &pipes {
                 let (wl, wr, wu, wd) = (
                     self.get_init_tile_at(*pipe + Vector2::new(-1, 0), population) == Tile::Wall,
@@ -258,8 +362,7 @@ impl World {
                     self.get_init_tile_at(*pipe + Vector2::new(0, -1), population) == Tile::Wall,
                     self.get_init_tile_at(*pipe + Vector2::new( 0, 1), population) == Tile::Wall
                 );
-                self.set_init_fg_tile_at(*pipe, population, 
-                match (
+                structure.add(*pipe, StructureTile::FgTile(match (
                     pipes.contains(&(*pipe + Vector2::new(-1, 0))) || wl, 
                     pipes.contains(&(*pipe + Vector2::new(1, 0))) || wr, 
                     pipes.contains(&(*pipe + Vector2::new(0, -1))) || wu, 
@@ -291,8 +394,9 @@ impl World {
                     (false, false, false, true) => FgTile::PipeEndU,
 
                     (false, false, false, false) => FgTile::Empty
-                });
+                }));
             }
+            self.place_structure(Vector2::default(), &structure);
         }
         if rng.gen_ratio(1, 12) || both {
             pos += Vector2::new(rng.gen_range(0..CHUNK_SIZE as i32), rng.gen_range(0..CHUNK_SIZE as i32));
@@ -425,4 +529,57 @@ impl World {
         hasher.write_u64(salt);
         hasher.finish()
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FINGERPRINT_SEED: u64 = 1234;
+    const FINGERPRINT_CHUNK: Vector2<i32> = Vector2 { x: 5, y: -3 };
+    /// Fingerprint of [`FINGERPRINT_CHUNK`] generated with [`FINGERPRINT_SEED`].
+    /// Changing the generator on purpose changes every world, so this has to be updated along with it;
+    /// `pinned_generation_fingerprint` prints the new value when it fails.
+    const GENERATION_FINGERPRINT: u64 = 0x5011b3b361c0c843;
+
+    #[test]
+    fn generation_is_reproducible() {
+        let a = World::generation_fingerprint(FINGERPRINT_SEED, FINGERPRINT_CHUNK);
+        assert_eq!(a, World::generation_fingerprint(FINGERPRINT_SEED, FINGERPRINT_CHUNK));
+        assert_ne!(a, World::generation_fingerprint(FINGERPRINT_SEED + 1, FINGERPRINT_CHUNK));
+    }
+
+    #[test]
+    fn finished_chunks_have_structured_neighbours() {
+        let mut world = World::new(FINGERPRINT_SEED);
+        world.ensure_population(FINGERPRINT_CHUNK, Population::Finished);
+        assert!(world.populating.is_empty());
+        for x in -1..=1 {
+            for y in -1..=1 {
+                let population = world.try_get_chunk_no_gen(FINGERPRINT_CHUNK + Vector2::new(x, y)).unwrap().population;
+                assert!(population >= Population::Structures);
+            }
+        }
+        assert!(world.try_get_chunk_no_gen(FINGERPRINT_CHUNK + Vector2::new(2, 2)).unwrap().population >= Population::TerrainWatered);
+    }
+
+    #[test]
+    fn biome_selection_is_stable() {
+        let (a, b) = (GenProvider::new(FINGERPRINT_SEED), GenProvider::new(FINGERPRINT_SEED));
+        let mut seen = HashSet::new();
+        for x in (-2000..2000).step_by(37) {
+            for y in (-2000..2000).step_by(41) {
+                let pos = Vector2::new(x, y);
+                assert_eq!(a.biome_at(pos), b.biome_at(pos));
+                seen.insert(a.biome_at(pos));
+            }
+        }
+        assert!(seen.len() > 1, "biome noise should select more than one biome");
+    }
+
+    #[test]
+    fn pinned_generation_fingerprint() {
+        let fingerprint = World::generation_fingerprint(FINGERPRINT_SEED, FINGERPRINT_CHUNK);
+        assert_eq!(fingerprint, GENERATION_FINGERPRINT, "world generation changed, new fingerprint is {fingerprint:#x}");
+    }
+}
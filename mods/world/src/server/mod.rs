@@ -1,20 +1,34 @@
 pub mod world;
+pub mod time_of_day;
+pub mod structure;
+pub mod physics;
 pub(crate) mod gen;
+pub(crate) mod chunk_generator;
+pub(crate) mod persistence;
+
+use std::path::Path;
 
 use aeonetica_server::ServerMod;
 
 use aeonetica_engine::log;
 use aeonetica_server::ecs::Engine;
-use crate::server::world::World;
+use crate::server::persistence::LEVEL_FILE;
+use crate::server::world::{World, WORLD};
+use crate::server::time_of_day::TimeOfDay;
+
+/// Where the world is saved unless another directory is given as the second flag
+pub const DEFAULT_SAVE_DIR: &str = "saves/world";
 
 pub struct WorldModServer {
-    seed: u64
+    seed: u64,
+    save_dir: String
 }
 
 impl WorldModServer {
     pub(crate) fn new() -> Self {
         Self {
-            seed: 0
+            seed: 0,
+            save_dir: DEFAULT_SAVE_DIR.to_string()
         }
     }
 }
@@ -28,9 +42,28 @@ impl ServerMod for WorldModServer {
             self.seed = rand::random();
             log!(DEBUG, "No seed found. Generated {}", self.seed);
         }
+        if let Some(save_dir) = flags.get(1) {
+            self.save_dir = save_dir.clone();
+        }
     }
 
     fn start(&mut self, engine: &mut Engine) {
-        World::new_wold_entity(engine, self.seed);
+        // a saved world keeps its own seed
+        let world = if Path::new(&self.save_dir).join(LEVEL_FILE).exists() {
+            World::load_from(&self.save_dir).unwrap_or_else(|e| {
+                log!(ERROR, "could not load world from {}, generating a new one: {e}", self.save_dir);
+                World::new(self.seed)
+            })
+        } else {
+            World::new(self.seed)
+        };
+        World::new_wold_entity(engine, world);
+        TimeOfDay::new_time_of_day_entity(engine, TimeOfDay::DEFAULT_DAY_LENGTH, 0.3);
     }
-}
\ No newline at end of file
+
+    fn shutdown(&mut self, engine: &mut Engine) {
+        if let Err(e) = engine.mut_module_by_tag::<World>(WORLD).save_to(&self.save_dir) {
+            log!(ERROR, "could not save world to {}: {e}", self.save_dir);
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use aeonetica_engine::error::{ErrorResult, ResultExt};
+use aeonetica_engine::log;
+use aeonetica_engine::math::vector::Vector2;
+use aeonetica_engine::nanoserde::{self, DeBin, DeRon, SerRon};
+use crate::common::{Chunk, Population};
+use crate::server::gen::fnv1a;
+
+/// Side length (in chunks) of the square regions stored together in one file.
+pub const REGION_SIZE: i32 = 16;
+/// Name of the file next to the regions that stores the seed.
+pub const LEVEL_FILE: &str = "level.ron";
+
+const REGION_MAGIC: &[u8; 4] = b"AEWR";
+const REGION_VERSION: u32 = 1;
+pub(crate) const LEVEL_VERSION: u32 = 1;
+/// chunk x and y, data length and checksum
+const ENTRY_HEADER_SIZE: usize = 4 + 4 + 4 + 8;
+
+// nanoserde's derives don't support restricted visibility
+#[derive(SerRon, DeRon)]
+pub struct Level {
+    pub version: u32,
+    pub seed: u64
+}
+
+/// Region file layout, all numbers little endian:
+/// ```text
+/// "AEWR" version: u32
+/// { chunk_x: i32, chunk_y: i32, len: u32, checksum: u64, chunk: [u8; len] (SerBin of Chunk) }*
+/// ```
+/// Chunks that fail their checksum or can't be read are skipped, so a corrupt or partially written file
+/// only costs the affected chunks, which are then generated again.
+pub(crate) struct RegionStore {
+    dir: PathBuf,
+    /// serialized chunks of every region read so far, removed once they are loaded into the world
+    regions: HashMap<Vector2<i32>, HashMap<Vector2<i32>, Vec<u8>>>
+}
+
+impl RegionStore {
+    pub(crate) fn new<P: AsRef<Path>>(dir: P) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+            regions: HashMap::new()
+        }
+    }
+
+    pub(crate) fn region_of(chunk_pos: Vector2<i32>) -> Vector2<i32> {
+        Vector2::new(chunk_pos.x.div_euclid(REGION_SIZE), chunk_pos.y.div_euclid(REGION_SIZE))
+    }
+
+    fn region_path(dir: &Path, region: Vector2<i32>) -> PathBuf {
+        dir.join(format!("r.{}.{}.region", region.x, region.y))
+    }
+
+    fn region(&mut self, region: Vector2<i32>) -> &mut HashMap<Vector2<i32>, Vec<u8>> {
+        let dir = &self.dir;
+        self.regions.entry(region).or_insert_with(|| {
+            let path = Self::region_path(dir, region);
+            match fs::read(&path) {
+                Ok(bytes) => read_region(&path, &bytes),
+                Err(_) => HashMap::new()
+            }
+        })
+    }
+
+    /// Whether the chunk is stored and hasn't been loaded yet.
+    pub(crate) fn contains(&mut self, chunk_pos: Vector2<i32>) -> bool {
+        self.region(Self::region_of(chunk_pos)).contains_key(&chunk_pos)
+    }
+
+    /// Removes the chunk from the store. Returns `None` if it was never saved or can't be read.
+    pub(crate) fn take(&mut self, chunk_pos: Vector2<i32>) -> Option<Chunk> {
+        let data = self.region(Self::region_of(chunk_pos)).remove(&chunk_pos)?;
+        match Chunk::deserialize_bin(&data) {
+            Ok(chunk) if chunk.chunk_pos == chunk_pos && chunk.population == Population::Finished => Some(chunk),
+            Ok(_) => {
+                log!(WARN, "saved chunk {chunk_pos} is invalid, generating it again");
+                None
+            }
+            Err(e) => {
+                log!(WARN, "could not read saved chunk {chunk_pos}, generating it again: {e}");
+                None
+            }
+        }
+    }
+
+    /// Reads every region file in the store's directory and returns all chunks that haven't been loaded,
+    /// so saving doesn't lose chunks that weren't needed this session.
+    pub(crate) fn take_remaining(&mut self) -> HashMap<Vector2<i32>, Vec<u8>> {
+        let regions = fs::read_dir(&self.dir).into_iter().flatten().flatten()
+            .filter_map(|entry| parse_region_name(&entry.file_name().to_string_lossy()))
+            .collect::<Vec<_>>();
+        for region in regions {
+            self.region(region);
+        }
+        self.regions.values_mut().flat_map(std::mem::take).collect()
+    }
+}
+
+fn parse_region_name(name: &str) -> Option<Vector2<i32>> {
+    let (x, y) = name.strip_prefix("r.")?.strip_suffix(".region")?.split_once('.')?;
+    Some(Vector2::new(x.parse().ok()?, y.parse().ok()?))
+}
+
+fn read_region(path: &Path, bytes: &[u8]) -> HashMap<Vector2<i32>, Vec<u8>> {
+    let mut chunks = HashMap::new();
+    if bytes.len() < 8 || &bytes[..4] != REGION_MAGIC {
+        log!(WARN, "{} is not a region file, generating its chunks again", path.display());
+        return chunks
+    }
+    let version = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+    if version != REGION_VERSION {
+        log!(WARN, "region file {} has unsupported version {version}, generating its chunks again", path.display());
+        return chunks
+    }
+
+    let mut rest = &bytes[8..];
+    let int = |bytes: &[u8], at: usize| i32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+    while !rest.is_empty() {
+        if rest.len() < ENTRY_HEADER_SIZE {
+            log!(WARN, "region file {} is truncated", path.display());
+            break
+        }
+        let chunk_pos = Vector2::new(int(rest, 0), int(rest, 4));
+        let len = u32::from_le_bytes(rest[8..12].try_into().unwrap()) as usize;
+        let checksum = u64::from_le_bytes(rest[12..20].try_into().unwrap());
+        let Some(data) = rest.get(ENTRY_HEADER_SIZE..ENTRY_HEADER_SIZE + len) else {
+            log!(WARN, "region file {} is truncated at chunk {chunk_pos}", path.display());
+            break
+        };
+        if fnv1a(data) == checksum {
+            chunks.insert(chunk_pos, data.to_vec());
+        }
+        else {
+            log!(WARN, "chunk {chunk_pos} in region file {} is corrupt, generating it again", path.display());
+        }
+        rest = &rest[ENTRY_HEADER_SIZE + len..];
+    }
+    chunks
+}
+
+/// Writes all chunks into their region files in `dir`, replacing existing ones.
+pub(crate) fn write_regions(dir: &Path, chunks: HashMap<Vector2<i32>, Vec<u8>>) -> ErrorResult<()> {
+    let mut regions: HashMap<Vector2<i32>, Vec<u8>> = HashMap::new();
+    for (chunk_pos, data) in chunks {
+        let region = regions.entry(RegionStore::region_of(chunk_pos)).or_insert_with(|| {
+            let mut header = REGION_MAGIC.to_vec();
+            header.extend(REGION_VERSION.to_le_bytes());
+            header
+        });
+        region.extend(chunk_pos.x.to_le_bytes());
+        region.extend(chunk_pos.y.to_le_bytes());
+        region.extend((data.len() as u32).to_le_bytes());
+        region.extend(fnv1a(&data).to_le_bytes());
+        region.extend(data);
+    }
+    for (region, bytes) in regions {
+        // written next to the old file first, so a crash while saving never leaves a half written region
+        let path = RegionStore::region_path(dir, region);
+        let temp = path.with_extension("region.tmp");
+        fs::write(&temp, bytes).with_context(|| format!("writing region {}", path.display()))?;
+        fs::rename(&temp, &path).with_context(|| format!("writing region {}", path.display()))?;
+    }
+    Ok(())
+}
+
+pub(crate) fn read_level(dir: &Path) -> ErrorResult<Level> {
+    let path = dir.join(LEVEL_FILE);
+    let data = fs::read_to_string(&path).with_context(|| format!("reading {}", path.display()))?;
+    Level::deserialize_ron(&data).with_context(|| format!("parsing {}", path.display()))
+}
+
+pub(crate) fn write_level(dir: &Path, seed: u64) -> ErrorResult<()> {
+    fs::write(dir.join(LEVEL_FILE), Level { version: LEVEL_VERSION, seed }.serialize_ron())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aeonetica_engine::nanoserde::SerBin;
+
+    #[test]
+    fn corrupt_and_truncated_entries_are_skipped() {
+        let good = Chunk { population: Population::Finished, ..Chunk::new(Vector2::new(1, 2)) };
+        let mut bytes = REGION_MAGIC.to_vec();
+        bytes.extend(REGION_VERSION.to_le_bytes());
+        let mut entry = |chunk_pos: Vector2<i32>, data: &[u8], checksum: u64| {
+            bytes.extend(chunk_pos.x.to_le_bytes());
+            bytes.extend(chunk_pos.y.to_le_bytes());
+            bytes.extend((data.len() as u32).to_le_bytes());
+            bytes.extend(checksum.to_le_bytes());
+            bytes.extend(data);
+        };
+        let data = good.serialize_bin();
+        entry(good.chunk_pos, &data, fnv1a(&data));
+        entry(Vector2::new(3, 4), &data, fnv1a(&data) ^ 1);
+        entry(Vector2::new(5, 6), &data, fnv1a(&data));
+        bytes.truncate(bytes.len() - 10);
+
+        let chunks = read_region(Path::new("test.region"), &bytes);
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(Chunk::deserialize_bin(&chunks[&good.chunk_pos]).unwrap(), good);
+        assert!(read_region(Path::new("test.region"), b"garbage").is_empty());
+
+        assert_eq!(parse_region_name("r.-1.20.region"), Some(Vector2::new(-1, 20)));
+        assert_eq!(parse_region_name("r.1.region"), None);
+        assert_eq!(RegionStore::region_of(Vector2::new(-1, REGION_SIZE)), Vector2::new(-1, 1));
+    }
+}
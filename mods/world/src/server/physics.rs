@@ -0,0 +1,164 @@
+use aeonetica_engine::{EntityId, time::Time};
+use aeonetica_engine::math::vector::Vector2;
+use aeonetica_engine::util::nullable::Nullable;
+use aeonetica_server::ecs::Engine;
+use aeonetica_server::ecs::module::{HasPosition, Module};
+use crate::common::{GRAVITY, WorldView};
+use crate::server::world::{WORLD, World};
+
+/// Moves its entity through the world every tick, under gravity and colliding with solid tiles.
+/// Positive y points down, like everywhere else in the world.
+pub struct Physics {
+    position: Vector2<f32>,
+    size: Vector2<f32>,
+    velocity: Vector2<f32>,
+    gravity_scale: f32,
+    grounded: bool
+}
+
+impl Physics {
+    pub fn new(position: Vector2<f32>, size: Vector2<f32>) -> Self {
+        Self {
+            position,
+            size,
+            velocity: Vector2::default(),
+            gravity_scale: 1.0,
+            grounded: false
+        }
+    }
+
+    pub fn with_gravity_scale(mut self, gravity_scale: f32) -> Self {
+        self.gravity_scale = gravity_scale;
+        self
+    }
+
+    pub fn position(&self) -> Vector2<f32> {
+        self.position
+    }
+
+    pub fn set_position(&mut self, position: Vector2<f32>) {
+        self.position = position;
+    }
+
+    pub fn size(&self) -> Vector2<f32> {
+        self.size
+    }
+
+    pub fn velocity(&self) -> Vector2<f32> {
+        self.velocity
+    }
+
+    pub fn set_velocity(&mut self, velocity: Vector2<f32>) {
+        self.velocity = velocity;
+    }
+
+    pub fn apply_impulse(&mut self, impulse: Vector2<f32>) {
+        self.velocity += impulse;
+    }
+
+    /// Whether downward movement was blocked by a solid tile during the last step.
+    pub fn is_grounded(&self) -> bool {
+        self.grounded
+    }
+
+    /// Launches the entity upwards with `speed` if it is grounded. Returns whether it jumped.
+    pub fn jump(&mut self, speed: f32) -> bool {
+        if self.grounded {
+            self.velocity.y = -speed;
+            self.grounded = false;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Integrates velocity and position over `delta` seconds.
+    /// Velocity along an axis is cleared when movement along it is blocked, movement in water is slowed down by [`WATER_DRAG`](crate::common::WATER_DRAG).
+    pub fn step(&mut self, world: &impl WorldView, delta: f32) {
+        self.velocity.y -= GRAVITY * self.gravity_scale * delta;
+        let wanted = self.velocity * delta * world.movement_factor(self.position, self.size);
+        let before = self.position;
+        world.calc_move(&mut self.position, self.size, wanted);
+        let moved = self.position - before;
+
+        let blocked_x = (moved.x - wanted.x).abs() > BLOCKED_EPSILON;
+        let blocked_y = (moved.y - wanted.y).abs() > BLOCKED_EPSILON;
+        self.grounded = blocked_y && wanted.y > 0.0;
+        if blocked_x {
+            self.velocity.x = 0.0;
+        }
+        if blocked_y {
+            self.velocity.y = 0.0;
+        }
+    }
+}
+
+const BLOCKED_EPSILON: f32 = 1e-4;
+
+impl HasPosition for Physics {
+    fn position(&self) -> Vector2<f32> {
+        self.position
+    }
+}
+
+impl Module for Physics {
+    fn tick(id: &EntityId, engine: &mut Engine, time: Time) {
+        let Nullable::Value(&wid) = engine.get_entity_id_by_tag(WORLD) else { return };
+        let (physics, world): (Nullable<&mut Physics>, Nullable<&mut World>) = engine.many_mut_modules_of_entities::<2, (Physics, World)>([*id, wid]);
+        if let (Nullable::Value(physics), Nullable::Value(world)) = (physics, world) {
+            physics.step(&*world, time.delta);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tiles::{Tile, FgTile};
+    use super::*;
+
+    /// Solid from `self.0` downwards.
+    struct Floor(i32);
+
+    impl WorldView for Floor {
+        fn get_tile_or_null(&self, pos: Vector2<i32>) -> Nullable<Tile> {
+            Nullable::Value(if pos.y >= self.0 { Tile::Wall } else { Tile::StoneBrick })
+        }
+
+        fn get_fg_tile_or_null(&self, _pos: Vector2<i32>) -> Nullable<FgTile> {
+            Nullable::Value(FgTile::Empty)
+        }
+
+        fn get_water_tile_or_null(&self, _pos: Vector2<i32>) -> Nullable<u8> {
+            Nullable::Value(0)
+        }
+
+        fn is_loaded(&self, _pos: Vector2<i32>) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn falls_lands_and_jumps() {
+        let world = Floor(10);
+        let mut physics = Physics::new(Vector2::new(0.0, 0.0), Vector2::new(0.8, 0.8));
+        assert!(!physics.jump(5.0));
+
+        physics.step(&world, 0.05);
+        assert!(physics.position().y > 0.0);
+        assert!(!physics.is_grounded());
+
+        for _ in 0..100 {
+            physics.step(&world, 0.05);
+        }
+        assert!(physics.is_grounded());
+        assert!(physics.position().y + physics.size().y <= 10.0);
+        assert!(physics.position().y + physics.size().y > 9.5);
+        assert_eq!(physics.velocity().y, 0.0);
+
+        assert!(physics.jump(5.0));
+        assert!(!physics.jump(5.0));
+        physics.step(&world, 0.05);
+        assert!(physics.velocity().y < 0.0);
+        assert!(!physics.is_grounded());
+    }
+}
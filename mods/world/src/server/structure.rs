@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+
+use aeonetica_engine::math::vector::Vector2;
+use crate::common::{Population, WorldView};
+use crate::server::world::World;
+use crate::tiles::{Tile, FgTile};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum StructureTile {
+    Tile(Tile),
+    FgTile(FgTile),
+    Water(u8)
+}
+
+/// A set of tiles placed relative to an origin by [`World::place_structure`].
+#[derive(Debug, Clone, Default)]
+pub struct Structure {
+    tiles: Vec<(Vector2<i32>, StructureTile)>
+}
+
+impl Structure {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, offset: Vector2<i32>, tile: StructureTile) -> &mut Self {
+        self.tiles.push((offset, tile));
+        self
+    }
+
+    pub fn tiles(&self) -> &[(Vector2<i32>, StructureTile)] {
+        &self.tiles
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty()
+    }
+}
+
+/// Structure tiles waiting for their chunk to reach [`Population::Structures`], keyed by chunk position.
+pub(crate) type DeferredEdits = HashMap<Vector2<i32>, Vec<(Vector2<i32>, StructureTile)>>;
+
+impl World {
+    /// Places `structure` with its offsets relative to `origin`.
+    /// Tiles in chunks that already reached [`Population::Structures`] are written immediately, all others
+    /// are queued and written once their chunk gets there, so structures may cross chunk borders
+    /// without forcing the neighbours to generate and regardless of the order chunks are generated in.
+    pub fn place_structure(&mut self, origin: Vector2<i32>, structure: &Structure) {
+        for (offset, tile) in structure.tiles() {
+            let pos = origin + *offset;
+            let chunk_pos = World::chunk(pos);
+            let ready = self.try_get_chunk_no_gen(chunk_pos).option().is_some_and(|c| c.population >= Population::Structures);
+            if ready {
+                self.apply_structure_tile(pos, *tile);
+            } else {
+                self.deferred_edits.entry(chunk_pos).or_default().push((pos, *tile));
+            }
+        }
+    }
+
+    /// Writes all queued structure tiles of a chunk, called when it enters [`Population::Structures`].
+    pub(crate) fn flush_deferred_edits(&mut self, chunk_pos: Vector2<i32>) {
+        for (pos, tile) in self.deferred_edits.remove(&chunk_pos).unwrap_or_default() {
+            self.apply_structure_tile(pos, tile);
+        }
+    }
+
+    fn apply_structure_tile(&mut self, pos: Vector2<i32>, tile: StructureTile) {
+        let p = World::pos_in_chunk(pos);
+        let chunk_pos = World::chunk(pos);
+        let chunk = self.mut_chunk_at_raw(chunk_pos);
+        let finished = chunk.population == Population::Finished;
+        match tile {
+            StructureTile::Tile(t) => chunk.set_tile(p, t),
+            StructureTile::FgTile(t) => chunk.set_fg_tile(p, t),
+            StructureTile::Water(depth) => chunk.set_water_tile(p, depth)
+        }
+        // placed after generation, so regenerating the chunk would lose it
+        if finished {
+            self.mark_modified(chunk_pos);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn structures_cross_quadrant_borders() {
+        let mut world = World::new(7);
+        let mut structure = Structure::new();
+        structure
+            .add(Vector2::new(-1, 0), StructureTile::FgTile(FgTile::MetalFrameBlock))
+            .add(Vector2::new(0, 0), StructureTile::FgTile(FgTile::MetalFrameBlock));
+        world.place_structure(Vector2::new(0, 5), &structure);
+        assert!(world.deferred_edits.contains_key(&Vector2::new(-1, 0)));
+        assert!(world.deferred_edits.contains_key(&Vector2::new(0, 0)));
+
+        world.ensure_population(Vector2::new(0, 0), Population::Finished);
+        world.ensure_population(Vector2::new(-1, 0), Population::Finished);
+        assert!(!world.deferred_edits.contains_key(&Vector2::new(-1, 0)));
+        assert!(!world.deferred_edits.contains_key(&Vector2::new(0, 0)));
+        assert_eq!(world.try_get_fg_tile_no_gen(Vector2::new(-1, 5)).unwrap(), FgTile::MetalFrameBlock);
+        assert_eq!(world.try_get_fg_tile_no_gen(Vector2::new(0, 5)).unwrap(), FgTile::MetalFrameBlock);
+
+        // chunks past the structure stage are written right away
+        world.place_structure(Vector2::new(-3, 5), &structure);
+        assert_eq!(world.try_get_fg_tile_no_gen(Vector2::new(-4, 5)).unwrap(), FgTile::MetalFrameBlock);
+    }
+}
@@ -0,0 +1,87 @@
+use aeonetica_engine::{EntityId, log};
+use aeonetica_engine::networking::SendMode;
+use aeonetica_engine::time::Time;
+use aeonetica_server::ecs::Engine;
+use aeonetica_server::ecs::events::ConnectionListener;
+use aeonetica_server::ecs::messaging::Messenger;
+use aeonetica_server::ecs::module::Module;
+
+use crate::client::time_of_day::TimeOfDayHandle;
+use crate::common::{DayPhase, is_night};
+
+pub const TIME_OF_DAY: &str = "TIME_OF_DAY";
+
+/// Drives the day/night cycle and replicates it to all clients.
+/// Look it up with `engine.get_module_by_tag::<TimeOfDay>(TIME_OF_DAY)`.
+pub struct TimeOfDay {
+    phase: DayPhase,
+    /// length of a full day in seconds
+    day_length: f32,
+    since_sync: f32
+}
+
+impl TimeOfDay {
+    pub const DEFAULT_DAY_LENGTH: f32 = 600.0;
+    /// Seconds between phase updates sent to clients, they interpolate in between.
+    const SYNC_INTERVAL: f32 = 2.0;
+
+    pub(crate) fn new_time_of_day_entity(engine: &mut Engine, day_length: f32, phase: DayPhase) -> EntityId {
+        let eid = engine.new_entity();
+        engine.tag_entity(eid, TIME_OF_DAY);
+        let mut entity = engine.mut_entity(&eid);
+        entity.add_module(Messenger::new::<TimeOfDayHandle>());
+        entity.add_module(ConnectionListener::new(
+            |id, engine, client| {
+                let (mut messenger, time_of_day) = engine.two_mut_modules_of::<Messenger, TimeOfDay>(id);
+                messenger.add_client(*client);
+                messenger.call_client_fn_for(TimeOfDayHandle::receive_time_of_day, client, (time_of_day.phase, time_of_day.day_length), SendMode::Safe);
+            },
+            |id, engine, client| {
+                engine.mut_module_of::<Messenger>(id).remove_client(client);
+            }));
+        entity.add_module(TimeOfDay {
+            phase: phase.rem_euclid(1.0),
+            day_length,
+            since_sync: 0.0
+        });
+        log!("day/night cycle started with a day length of {day_length}s");
+        eid
+    }
+
+    pub fn phase(&self) -> DayPhase {
+        self.phase
+    }
+
+    pub fn is_night(&self) -> bool {
+        is_night(self.phase)
+    }
+
+    pub fn day_length(&self) -> f32 {
+        self.day_length
+    }
+
+    /// Jumps to `phase`, clients are updated on the next tick.
+    pub fn set_phase(&mut self, phase: DayPhase) {
+        self.phase = phase.rem_euclid(1.0);
+        self.since_sync = Self::SYNC_INTERVAL;
+    }
+
+    /// Returns whether clients should be sent the new phase.
+    fn advance(&mut self, delta: f32) -> bool {
+        self.phase = (self.phase + delta / self.day_length).rem_euclid(1.0);
+        self.since_sync += delta;
+        if self.since_sync >= Self::SYNC_INTERVAL {
+            self.since_sync = 0.0;
+            true
+        } else { false }
+    }
+}
+
+impl Module for TimeOfDay {
+    fn tick(id: &EntityId, engine: &mut Engine, time: Time) {
+        let (mut messenger, mut time_of_day) = engine.two_mut_modules_of::<Messenger, TimeOfDay>(id);
+        if time_of_day.advance(time.delta) {
+            messenger.call_client_fn(TimeOfDayHandle::receive_time_of_day, (time_of_day.phase, time_of_day.day_length), SendMode::Quick);
+        }
+    }
+}
@@ -1,23 +1,49 @@
 
 
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
 use std::rc::Rc;
 use aeonetica_engine::{ClientId, EntityId, log};
+use aeonetica_engine::error::{Error, ErrorResult, Fatality};
+use aeonetica_engine::error::builtin::DataError;
+use aeonetica_engine::nanoserde::SerBin;
 use aeonetica_engine::networking::SendMode;
 use aeonetica_engine::math::vector::Vector2;
 use aeonetica_engine::util::id_map::{IdSet};
 use aeonetica_engine::util::nullable::Nullable;
 use aeonetica_server::ecs::Engine;
+use aeonetica_server::ecs::scheduling::{Event, WaitFor};
 use aeonetica_server::ecs::entity::Entity;
 use aeonetica_server::ecs::events::ConnectionListener;
 use aeonetica_server::ecs::messaging::Messenger;
 use aeonetica_server::ecs::module::Module;
+use aeonetica_server::yield_task;
 use crate::client::WorldHandle;
-use crate::common::{Chunk, Population, WorldView};
+use crate::common::{Chunk, CHUNK_SIZE, CompressedChunk, Population, WorldView};
+use crate::server::chunk_generator::ChunkGenerator;
 use crate::server::gen::GenProvider;
+use crate::server::persistence::{self, RegionStore};
+use crate::server::structure::DeferredEdits;
 use crate::tiles::{Tile, FgTile};
 
 pub const WORLD: &str = "WORLD";
 
+/// Maximum number of water tiles a single call to [`World::step_water`] may update.
+pub const MAX_WATER_UPDATES_PER_CHUNK: usize = 64;
+/// Maximum number of chunks whose water is stepped per tick.
+pub const MAX_WATER_CHUNKS_PER_TICK: usize = 16;
+
+/// Emitted on the server [`Engine`] whenever a client changes a tile, for modules that want to react to it.
+pub struct TileChanged {
+    pub pos: Vector2<i32>,
+    pub old: Tile,
+    pub new: Tile,
+    pub client: ClientId
+}
+
+impl Event for TileChanged {}
+
 pub(crate) struct ChunkHolder {
     further_x: Option<Box<ChunkHolder>>,
     further_y: Option<Box<ChunkHolder>>,
@@ -27,13 +53,29 @@ pub(crate) struct ChunkHolder {
 
 impl ChunkHolder {
     pub(crate) fn new(chunk_pos: Vector2<i32>) -> ChunkHolder {
+        Self::with_chunk(Chunk::new(chunk_pos))
+    }
+
+    fn with_chunk(chunk: Chunk) -> ChunkHolder {
         ChunkHolder {
             further_x: None,
             further_y: None,
-            chunk: Chunk::new(chunk_pos),
+            chunk,
             subscribed_players: Default::default()
         }
     }
+
+    /// The saved chunk if there is one, otherwise an ungenerated one.
+    /// Loaded chunks count as modified, so they are saved again.
+    fn load(saved: &mut Option<RegionStore>, modified: &mut HashSet<Vector2<i32>>, chunk_pos: Vector2<i32>) -> ChunkHolder {
+        match saved.as_mut().and_then(|saved| saved.take(chunk_pos)) {
+            Some(chunk) => {
+                modified.insert(chunk_pos);
+                Self::with_chunk(chunk)
+            }
+            None => Self::new(chunk_pos)
+        }
+    }
 }
 
 pub struct World {
@@ -43,16 +85,31 @@ pub struct World {
     origin_nw: ChunkHolder,
     origin_sw: ChunkHolder,
     cached_chunk_pos: Vector2<i32>,
-    cached_chunk_raw_ptr: usize
+    cached_chunk_raw_ptr: usize,
+    /// chunks whose water may still flow, stepped by [`World::tick_water`]
+    water_chunks: Vec<Vector2<i32>>,
+    water_cursor: usize,
+    /// clients that have each chunk loaded, see [`World::release_world_chunk`]
+    loaded_by: HashMap<Vector2<i32>, IdSet>,
+    chunk_generator: Option<ChunkGenerator>,
+    /// chunks and the stage they are currently being advanced to, see [`World::ensure_population`]
+    pub(crate) populating: Vec<(Vector2<i32>, Population)>,
+    pub(crate) deferred_edits: DeferredEdits,
+    /// chunks changed since they were generated, the only ones that are saved
+    modified: HashSet<Vector2<i32>>,
+    /// chunks saved by a previous session that haven't been loaded yet, see [`World::load_from`]
+    saved: Option<RegionStore>
 }
 
 impl World {
-    pub(crate) fn new_wold_entity(engine: &mut Engine, seed: u64) -> EntityId {
+    pub(crate) fn new_wold_entity(engine: &mut Engine, mut world: World) -> EntityId {
         let eid = engine.new_entity();
         engine.tag_entity(eid, WORLD);
         let entity: &mut Entity = &mut engine.mut_entity(&eid);
         entity.add_module(Messenger::new::<WorldHandle>());
         entity.mut_module::<Messenger>().register_receiver(World::request_world_chunk);
+        entity.mut_module::<Messenger>().register_receiver(World::release_world_chunk);
+        entity.mut_module::<Messenger>().register_receiver(World::set_tile_requested);
 
         entity.add_module(ConnectionListener::new(
             |id, engine, client| {
@@ -60,21 +117,97 @@ impl World {
                 let messenger: &mut Messenger = &mut engine.mut_module_of(id);
                 messenger.add_client(*client);
             },
-            |_id, _engine, client| {
+            |id, engine, client| {
                 log!("user said bye bye to world: {client}");
-
+                let world: &mut World = &mut engine.mut_module_of(id);
+                world.loaded_by.retain(|_, clients| {
+                    clients.remove(client);
+                    !clients.is_empty()
+                });
+                if let Some(generator) = world.chunk_generator.as_mut() {
+                    generator.cancel_client(client);
+                }
             }));
-        let chunk_zero = ChunkHolder::new((0, 0).into());
-        entity.add_module(World {
+        world.chunk_generator = Some(ChunkGenerator::new(world.seed()));
+        entity.add_module(world);
+        engine.queue_task(move |mut e: &mut Engine| {
+            while e.entity_exists(&eid) {
+                World::send_generated_chunks(&eid, e);
+                World::tick_water(&eid, e);
+                yield_task!(e, WaitFor::ticks(1));
+            }
+        });
+        eid
+    }
+
+    /// Creates a world without any networking, with chunks being generated synchronously on access.
+    pub(crate) fn new(seed: u64) -> Self {
+        Self {
             generator: Rc::new(GenProvider::new(seed)),
-            cached_chunk_pos: (0, 0).into(),
-            cached_chunk_raw_ptr: &chunk_zero.chunk as *const Chunk as usize,
-            origin_ne: chunk_zero,
+            // never a valid chunk position, so the cache starts out empty
+            // instead of pointing into a chunk holder that is about to be moved
+            cached_chunk_pos: (i32::MAX, i32::MAX).into(),
+            cached_chunk_raw_ptr: 0,
+            origin_ne: ChunkHolder::new((0, 0).into()),
             origin_se: ChunkHolder::new((0, -1).into()),
             origin_nw: ChunkHolder::new((-1, 0).into()),
             origin_sw: ChunkHolder::new((-1, -1).into()),
-        });
-        eid
+            water_chunks: vec![],
+            water_cursor: 0,
+            loaded_by: HashMap::new(),
+            chunk_generator: None,
+            populating: vec![],
+            deferred_edits: Default::default(),
+            modified: HashSet::new(),
+            saved: None
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.generator.seed
+    }
+
+    /// Opens a world saved with [`World::save_to`]. Chunks are read from disk when they are first accessed,
+    /// chunks that were never saved are generated from the saved seed.
+    pub fn load_from<P: AsRef<Path>>(path: P) -> ErrorResult<Self> {
+        let path = path.as_ref();
+        let level = persistence::read_level(path)?;
+        if level.version != persistence::LEVEL_VERSION {
+            return Err(Error::new(DataError(format!("world save {} has unsupported version {}", path.display(), level.version)), Fatality::DEFAULT, true))
+        }
+        let mut world = World::new(level.seed);
+        world.saved = Some(RegionStore::new(path));
+        for origin in [&mut world.origin_ne, &mut world.origin_se, &mut world.origin_nw, &mut world.origin_sw] {
+            let chunk_pos = origin.chunk.chunk_pos;
+            *origin = ChunkHolder::load(&mut world.saved, &mut world.modified, chunk_pos);
+        }
+        log!("loaded world with seed {} from {}", level.seed, path.display());
+        Ok(world)
+    }
+
+    /// Saves the seed and every chunk that changed since it was generated, all other chunks are generated again
+    /// when the world is loaded. Saved chunks of a world opened with [`World::load_from`] that weren't needed
+    /// this session are kept.
+    pub fn save_to<P: AsRef<Path>>(&mut self, path: P) -> ErrorResult<()> {
+        let path = path.as_ref();
+        fs::create_dir_all(path)?;
+        let mut chunks = self.saved.as_mut().map(RegionStore::take_remaining).unwrap_or_default();
+        for chunk_pos in &self.modified {
+            if let Nullable::Value(chunk) = self.try_get_chunk_no_gen(*chunk_pos) {
+                chunks.insert(*chunk_pos, chunk.serialize_bin());
+            }
+        }
+        let count = chunks.len();
+        persistence::write_regions(path, chunks)?;
+        persistence::write_level(path, self.seed())?;
+        log!("saved {count} chunks to {}", path.display());
+        Ok(())
+    }
+
+    /// Marks a chunk as changed, so it is saved by [`World::save_to`].
+    /// Only needed when the chunk is modified directly, the tile setters of the world do this on their own.
+    pub fn mark_modified(&mut self, chunk_pos: Vector2<i32>) {
+        self.modified.insert(chunk_pos);
     }
 
     pub fn get_tile_at(&mut self, pos: Vector2<i32>) -> Tile {
@@ -82,6 +215,7 @@ impl World {
     }
 
     pub fn set_tile_at(&mut self, pos: Vector2<i32>, t: Tile) {
+        self.mark_modified(World::chunk(pos));
         self.mut_chunk_at(World::chunk(pos)).set_tile(World::pos_in_chunk(pos), t)
     }
 
@@ -90,9 +224,19 @@ impl World {
     }
 
     pub fn set_fg_tile_at(&mut self, pos: Vector2<i32>, t: FgTile) {
+        self.mark_modified(World::chunk(pos));
         self.mut_chunk_at(World::chunk(pos)).set_fg_tile(World::pos_in_chunk(pos), t)
     }
 
+    pub fn get_water_tile_at(&mut self, pos: Vector2<i32>) -> u8 {
+        self.get_chunk_at(World::chunk(pos)).get_water_tile(World::pos_in_chunk(pos))
+    }
+
+    pub fn set_water_tile_at(&mut self, pos: Vector2<i32>, depth: u8) {
+        self.mark_modified(World::chunk(pos));
+        self.mut_chunk_at(World::chunk(pos)).set_water_tile(World::pos_in_chunk(pos), depth)
+    }
+
     pub fn mut_chunk_at(&mut self, chunk_pos: Vector2<i32>) -> &mut Chunk {
         self.mut_init_chunk_at(chunk_pos, Population::Finished)
     }
@@ -128,7 +272,7 @@ impl World {
                 let mut pos = chunk_ref.chunk.chunk_pos;
                 if chunk_pos.x < 0 { pos.x -= 1 }
                 else { pos.x += 1 }
-                chunk_ref.further_x = Some(Box::new(ChunkHolder::new(pos)))
+                chunk_ref.further_x = Some(Box::new(ChunkHolder::load(&mut self.saved, &mut self.modified, pos)))
             }
             chunk_ref = chunk_ref.further_x.as_mut().unwrap();
         }
@@ -138,7 +282,7 @@ impl World {
                 let mut pos = chunk_ref.chunk.chunk_pos;
                 if chunk_pos.y < 0 { pos.y -= 1 }
                 else { pos.y += 1 }
-                chunk_ref.further_y = Some(Box::new(ChunkHolder::new(pos)))
+                chunk_ref.further_y = Some(Box::new(ChunkHolder::load(&mut self.saved, &mut self.modified, pos)))
             }
             chunk_ref = chunk_ref.further_y.as_mut().unwrap();
         }
@@ -151,9 +295,198 @@ impl World {
         self.mut_chunk_at(chunk_pos)
     }
 
+    /// Advances the water in one chunk by a single step.
+    /// Water spreads into horizontally and vertically adjacent non-solid tiles, losing one depth per spread,
+    /// and may flow into neighbouring chunks. All spreads are computed from the state before the step,
+    /// so the result does not depend on iteration order. At most [`MAX_WATER_UPDATES_PER_CHUNK`] tiles are updated.
+    /// Chunks that are not fully generated are never generated by this, water does not flow into them.
+    /// Returns the positions of all chunks whose water mask changed.
+    pub fn step_water(&mut self, chunk_pos: Vector2<i32>) -> Vec<Vector2<i32>> {
+        let Nullable::Value(chunk) = self.try_get_finished_chunk(chunk_pos) else {
+            return vec![]
+        };
+        let water_mask = chunk.water_mask;
+        let origin = chunk_pos * CHUNK_SIZE as i32;
+        let mut spreads = vec![];
+        'tiles: for (i, depth) in water_mask.into_iter().enumerate() {
+            if depth < 2 {
+                continue;
+            }
+            let pos = origin + Vector2::new((i % CHUNK_SIZE) as i32, (i / CHUNK_SIZE) as i32);
+            for offset in [Vector2::new(0, 1), Vector2::new(-1, 0), Vector2::new(1, 0), Vector2::new(0, -1)] {
+                let target = pos + offset;
+                let Nullable::Value(target_chunk) = self.try_get_finished_chunk(World::chunk(target)) else {
+                    continue;
+                };
+                let in_chunk = World::pos_in_chunk(target);
+                if !target_chunk.get_tile(in_chunk).is_solid() && target_chunk.get_water_tile(in_chunk) < depth - 1 {
+                    spreads.push((target, depth - 1));
+                    if spreads.len() == MAX_WATER_UPDATES_PER_CHUNK {
+                        break 'tiles;
+                    }
+                }
+            }
+        }
+
+        // all targets are in finished chunks, so none of these generate
+        let mut changed = vec![];
+        for (pos, depth) in spreads {
+            if self.get_water_tile_at(pos) < depth {
+                self.set_water_tile_at(pos, depth);
+                if !changed.contains(&World::chunk(pos)) {
+                    changed.push(World::chunk(pos));
+                }
+            }
+        }
+        changed
+    }
+
+    /// Steps the water of up to [`MAX_WATER_CHUNKS_PER_TICK`] simulated chunks in round-robin order
+    /// and sends the updated water masks to the clients that have them loaded.
+    /// Chunks whose water settled or that no client has loaded stop being simulated,
+    /// until they are sent again or water flows into them, see [`World::wake_water`].
+    fn tick_water(id: &EntityId, engine: &mut Engine) {
+        let (mut messenger, mut world) = engine.two_mut_modules_of::<Messenger, World>(id);
+        let mut updated = vec![];
+        let mut resting = vec![];
+        for _ in 0..world.water_chunks.len().min(MAX_WATER_CHUNKS_PER_TICK) {
+            world.water_cursor = (world.water_cursor + 1) % world.water_chunks.len();
+            let chunk_pos = world.water_chunks[world.water_cursor];
+            let changed = if world.loaded_by.contains_key(&chunk_pos) { world.step_water(chunk_pos) } else { vec![] };
+            if changed.is_empty() {
+                resting.push(chunk_pos);
+            }
+            for changed in changed {
+                if !updated.contains(&changed) {
+                    updated.push(changed);
+                }
+            }
+        }
+        world.water_chunks.retain(|chunk_pos| !resting.contains(chunk_pos));
+        world.water_cursor = world.water_cursor.min(world.water_chunks.len().saturating_sub(1));
+        for chunk_pos in updated {
+            let Some(clients) = world.loaded_by.get(&chunk_pos) else {
+                continue;
+            };
+            let water_mask = world.try_get_chunk_no_gen(chunk_pos).unwrap().water_mask;
+            for client in clients {
+                if messenger.has_client(client) {
+                    messenger.call_client_fn_for(WorldHandle::receive_water_update, client, (chunk_pos, water_mask), SendMode::Safe);
+                }
+            }
+            if !world.water_chunks.contains(&chunk_pos) {
+                world.water_chunks.push(chunk_pos);
+            }
+        }
+    }
+
+    /// Simulates the water around `pos` again, e.g. after the tile there changed.
+    fn wake_water(&mut self, pos: Vector2<i32>) {
+        for offset in [Vector2::new(0, 0), Vector2::new(0, 1), Vector2::new(-1, 0), Vector2::new(1, 0), Vector2::new(0, -1)] {
+            let chunk_pos = World::chunk(pos + offset);
+            if !self.water_chunks.contains(&chunk_pos) {
+                self.water_chunks.push(chunk_pos);
+            }
+        }
+    }
+
+    /// The chunk if it is fully generated, never generates it.
+    fn try_get_finished_chunk(&self, chunk_pos: Vector2<i32>) -> Nullable<&Chunk> {
+        match self.try_get_chunk_no_gen(chunk_pos) {
+            Nullable::Value(chunk) if chunk.population == Population::Finished => Nullable::Value(chunk),
+            _ => Nullable::Null
+        }
+    }
+
+    /// Called by clients for chunks they unloaded, which they stop receiving water updates for.
+    pub(crate) fn release_world_chunk(id: &EntityId, engine: &mut Engine, client: &ClientId, chunk_pos: Vector2<i32>) {
+        let world: &mut World = &mut engine.mut_module_of(id);
+        if let Some(clients) = world.loaded_by.get_mut(&chunk_pos) {
+            clients.remove(client);
+            if clients.is_empty() {
+                world.loaded_by.remove(&chunk_pos);
+            }
+        }
+    }
+
+    /// Sends a chunk to the requesting client. Chunks that are not fully generated yet are handed to the
+    /// background [`ChunkGenerator`] and sent once they are ready, see [`World::send_generated_chunks`].
     pub(crate) fn request_world_chunk(id: &EntityId, engine: &mut Engine, client: &ClientId, chunk_pos: Vector2<i32>) {
-        let chunk = engine.mut_module_of::<Self>(id).get_chunk_at(chunk_pos).clone();
-        engine.mut_module_of::<Messenger>(id).call_client_fn_for(WorldHandle::receive_chunk_data, client, chunk, SendMode::Safe);
+        let (mut messenger, mut world) = engine.two_mut_modules_of::<Messenger, World>(id);
+        let generated = matches!(world.try_get_chunk_no_gen(chunk_pos), Nullable::Value(chunk) if chunk.population == Population::Finished)
+            || world.saved.as_mut().is_some_and(|saved| saved.contains(chunk_pos));
+        if let (false, Some(generator)) = (generated, world.chunk_generator.as_mut()) {
+            generator.request(chunk_pos, *client);
+        } else {
+            world.send_chunk(&mut messenger, client, chunk_pos);
+        }
+    }
+
+    /// Stores all chunks finished by the background generator and sends them to the clients that requested them.
+    /// Clients that disconnected in the meantime are skipped.
+    fn send_generated_chunks(id: &EntityId, engine: &mut Engine) {
+        let (mut messenger, mut world) = engine.two_mut_modules_of::<Messenger, World>(id);
+        while let Some((chunk, clients)) = world.chunk_generator.as_mut().and_then(|generator| generator.try_next()) {
+            let chunk_pos = chunk.chunk_pos;
+            world.store_generated_chunk(chunk);
+            for client in &clients {
+                if messenger.has_client(client) {
+                    world.send_chunk(&mut messenger, client, chunk_pos);
+                }
+            }
+        }
+    }
+
+    /// Replaces the chunk with one generated in the background, writing the structure tiles this world queued for it.
+    /// A chunk that got structures written into it here in the meantime is finished synchronously instead,
+    /// replacing it would lose them.
+    fn store_generated_chunk(&mut self, chunk: Chunk) {
+        let chunk_pos = chunk.chunk_pos;
+        // the chunk may have been generated synchronously in the meantime, e.g. by flowing water
+        let existing = self.mut_chunk_at_raw(chunk_pos);
+        if existing.population < Population::Structures {
+            *existing = chunk;
+            self.flush_deferred_edits(chunk_pos);
+        } else {
+            self.ensure_population(chunk_pos, Population::Finished);
+        }
+    }
+
+    fn send_chunk(&mut self, messenger: &mut Messenger, client: &ClientId, chunk_pos: Vector2<i32>) {
+        if !self.water_chunks.contains(&chunk_pos) {
+            self.water_chunks.push(chunk_pos);
+        }
+        self.loaded_by.entry(chunk_pos).or_default().insert(*client);
+        let chunk = self.get_chunk_at(chunk_pos).clone();
+        messenger.call_client_fn_for(WorldHandle::receive_chunk_data, client, CompressedChunk(chunk), SendMode::Safe);
+    }
+
+    /// Applies a tile change requested by a client and echoes the authoritative result to all subscribed clients.
+    /// Requests are applied in the order they arrive, so concurrent edits of the same tile resolve to the last write.
+    /// Invalid requests are answered with the current tile, so the requesting client can roll back its prediction.
+    /// Requests in chunks that are not generated are answered with the whole chunk, replacing the client's stale copy.
+    pub(crate) fn set_tile_requested(id: &EntityId, engine: &mut Engine, client: &ClientId, (pos, tile): (Vector2<i32>, Tile)) {
+        let (mut messenger, mut world) = engine.two_mut_modules_of::<Messenger, World>(id);
+        let Nullable::Value(current) = world.try_get_tile_no_gen(pos) else {
+            log!(WARN, "client {client} tried to modify tile {pos} in ungenerated chunk, sending the chunk again");
+            if messenger.has_client(client) {
+                World::request_world_chunk(id, engine, client, World::chunk(pos));
+            }
+            return
+        };
+
+        if !messenger.has_client(client) {
+            log!(WARN, "client {client} tried to modify tile {pos} without being subscribed to the world");
+            messenger.call_client_fn_for(WorldHandle::receive_tile_update, client, (pos, current), SendMode::Safe);
+            return
+        }
+
+        world.set_tile_at(pos, tile);
+        world.wake_water(pos);
+        messenger.call_client_fn(WorldHandle::receive_tile_update, (pos, tile), SendMode::Safe);
+        if current != tile {
+            engine.emit(TileChanged { pos, old: current, new: tile, client: *client });
+        }
     }
 
     pub fn try_get_tile_no_gen(&self, pos: Vector2<i32>) -> Nullable<Tile> {
@@ -234,4 +567,67 @@ impl WorldView for World {
 
 impl Module for World {
 
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::structure::{Structure, StructureTile};
+
+    #[test]
+    fn saved_worlds_keep_changes_and_regenerate_the_rest() {
+        let dir = std::env::temp_dir().join(format!("aeonetica-world-save-{}", std::process::id()));
+        let edited = Vector2::new(5, -40);
+        let mut world = World::new(42);
+        let tile = if world.get_tile_at(edited) == Tile::Wall { Tile::Stone } else { Tile::Wall };
+        world.set_tile_at(edited, tile);
+        let untouched = World::chunk(edited) + Vector2::new(3, 0);
+        let fresh = world.get_chunk_at(untouched).clone();
+        world.save_to(&dir).unwrap();
+        assert!(world.modified.contains(&World::chunk(edited)) && !world.modified.contains(&untouched));
+
+        let mut loaded = World::load_from(&dir).unwrap();
+        assert_eq!(loaded.seed(), 42);
+        assert!(loaded.try_get_chunk_no_gen(World::chunk(edited)).is_null(), "chunks are only read when they are needed");
+        assert_eq!(loaded.get_tile_at(edited), tile);
+        assert_eq!(*loaded.get_chunk_at(untouched), fresh);
+
+        // saving again keeps the edited chunk, even though it was loaded without being changed
+        loaded.save_to(&dir).unwrap();
+        assert_eq!(World::load_from(&dir).unwrap().get_tile_at(edited), tile);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn generated_chunks_keep_queued_structures() {
+        let chunk_pos = Vector2::new(6, 2);
+        let generated = World::new(3).get_chunk_at(chunk_pos).clone();
+        let mut world = World::new(3);
+        let pos = chunk_pos * CHUNK_SIZE as i32 + Vector2::new(4, 4);
+        world.place_structure(pos, Structure::new().add(Vector2::new(0, 0), StructureTile::FgTile(FgTile::MetalFrameBlock)));
+        assert!(world.deferred_edits.contains_key(&chunk_pos));
+
+        world.store_generated_chunk(generated);
+        assert!(!world.deferred_edits.contains_key(&chunk_pos));
+        assert_eq!(world.try_get_fg_tile_no_gen(pos).unwrap(), FgTile::MetalFrameBlock);
+        assert_eq!(world.try_get_chunk_no_gen(chunk_pos).unwrap().population, Population::Finished);
+    }
+
+    #[test]
+    fn water_does_not_flow_into_ungenerated_chunks() {
+        let mut world = World::new(7);
+        world.ensure_population((0, 0).into(), Population::Finished);
+        let neighbour = world.try_get_chunk_no_gen((1, 0).into()).unwrap().population;
+        assert!(neighbour < Population::Finished);
+
+        let edge = Vector2::new(CHUNK_SIZE as i32 - 1, 4);
+        // the tile right of the edge is in the neighbour, which would be generated by setting it
+        world.set_tile_at(edge, Tile::LabWall);
+        world.set_tile_at(edge + Vector2::new(-1, 0), Tile::LabWall);
+        world.set_water_tile_at(edge, 5);
+        assert_eq!(world.step_water((0, 0).into()), vec![Vector2::new(0, 0)]);
+        assert_eq!(world.try_get_water_tile_no_gen(edge + Vector2::new(-1, 0)).unwrap(), 4);
+        assert_eq!(world.try_get_chunk_no_gen((1, 0).into()).unwrap().population, neighbour);
+        assert_eq!(world.step_water((1, 0).into()), vec![]);
+    }
+}
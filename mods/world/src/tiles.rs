@@ -1,19 +1,100 @@
 use aeonetica_engine::nanoserde::{self, DeBin, SerBin};
 
-#[repr(u16)]
-#[derive(Debug, Copy, Clone, PartialEq)]
-pub enum Tile {
-    Wall,
-    StoneBrick,
-    MossyStoneBrick,
-    Stone,
-    HardStone,
-    Lamp,
-    QuarteredLamp,
-    LabWall,
-    LabBrickWall
+/// Declares a tile enum together with an `ALL` constant listing every variant in discriminant order.
+macro_rules! tile_enum {
+    ($(#[$meta:meta])* $vis:vis enum $name:ident { $($variant:ident),* $(,)? }) => {
+        $(#[$meta])*
+        #[repr(u16)]
+        #[derive(Debug, Copy, Clone, PartialEq)]
+        $vis enum $name {
+            $($variant),*
+        }
+
+        impl $name {
+            pub const ALL: [$name; [$(stringify!($variant)),*].len()] = [$($name::$variant),*];
+        }
+    };
 }
 
+/// Everything there is to know about a kind of tile. Each tile enum has a table of these,
+/// indexed by discriminant, so adding a tile means adding a row to its table.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TileProperties {
+    pub sprite_index: u16,
+    pub solid: bool,
+    /// part of the generated terrain rather than built
+    pub natural: bool,
+    pub glow_color: Option<[f32; 4]>,
+    /// radius of the light cast by glowing tiles
+    pub light_radius: f32,
+    pub friction: f32,
+    /// RGBA color on the minimap
+    pub map_color: [u8; 4]
+}
+
+impl TileProperties {
+    const DEFAULT_LIGHT_RADIUS: f32 = 7.5;
+
+    const fn new(sprite_index: u16) -> Self {
+        Self {
+            sprite_index,
+            solid: false,
+            natural: false,
+            glow_color: None,
+            light_radius: 0.0,
+            friction: 1.0,
+            map_color: [128, 128, 128, 255]
+        }
+    }
+
+    const fn solid(mut self) -> Self {
+        self.solid = true;
+        self
+    }
+
+    const fn natural(mut self) -> Self {
+        self.natural = true;
+        self
+    }
+
+    const fn map_color(mut self, r: u8, g: u8, b: u8) -> Self {
+        self.map_color = [r, g, b, 255];
+        self
+    }
+
+    const fn glowing(mut self, color: [f32; 4]) -> Self {
+        self.glow_color = Some(color);
+        self.light_radius = Self::DEFAULT_LIGHT_RADIUS;
+        self
+    }
+}
+
+tile_enum! {
+    pub enum Tile {
+        Wall,
+        StoneBrick,
+        MossyStoneBrick,
+        Stone,
+        HardStone,
+        Lamp,
+        QuarteredLamp,
+        LabWall,
+        LabBrickWall
+    }
+}
+
+const TILE_PROPERTIES: [TileProperties; Tile::ALL.len()] = [
+    TileProperties::new(Tile::Wall as u16).solid().natural().map_color(14, 14, 20),
+    TileProperties::new(Tile::StoneBrick as u16).natural().map_color(104, 104, 116),
+    TileProperties::new(Tile::MossyStoneBrick as u16).map_color(84, 116, 76),
+    TileProperties::new(Tile::Stone as u16).natural().map_color(124, 124, 124),
+    TileProperties::new(Tile::HardStone as u16).natural().map_color(76, 76, 88),
+    TileProperties::new(Tile::Lamp as u16).glowing([0.9, 0.9, 0.7, 1.0]).map_color(230, 230, 180),
+    TileProperties::new(Tile::QuarteredLamp as u16).glowing([1.0, 0.5, 0.5, 1.0]).map_color(250, 128, 128),
+    TileProperties::new(Tile::LabWall as u16).map_color(148, 158, 170),
+    TileProperties::new(Tile::LabBrickWall as u16).map_color(128, 138, 150)
+];
+
 impl SerBin for Tile {
     fn ser_bin(&self, output: &mut Vec<u8>) {
         (*self as u16).ser_bin(output)
@@ -22,67 +103,116 @@ impl SerBin for Tile {
 
 impl DeBin for Tile {
     fn de_bin(offset: &mut usize, bytes: &[u8]) -> Result<Self, nanoserde::DeBinErr> {
-        Ok(unsafe { std::mem::transmute(u16::de_bin(offset, bytes)?) })
+        let start = *offset;
+        let discriminant = u16::de_bin(offset, bytes)?;
+        Self::ALL.get(discriminant as usize).copied().ok_or(nanoserde::DeBinErr { o: start, l: 2, s: bytes.len() })
     }
 }
 
 impl Tile {
+    pub fn properties(&self) -> &'static TileProperties {
+        &TILE_PROPERTIES[*self as usize]
+    }
+
     pub fn sprite_sheet_index(&self) -> u16 {
-        *self as u16
+        self.properties().sprite_index
     }
 
     pub fn is_solid(&self) -> bool {
-        (*self as u16) == 0
+        self.properties().solid
     }
 
     pub fn is_natural(&self) -> bool {
-        matches!(self, Tile::Wall | Tile::StoneBrick | Tile::Stone | Tile::HardStone)
+        self.properties().natural
     }
 
     pub fn glow_color(&self) -> Option<[f32; 4]> {
-        match self {
-            Self::Lamp => Some([0.9, 0.9, 0.7, 1.0]),
-            Self::QuarteredLamp => Some([1.0, 0.5, 0.5, 1.0]),
-            _ => None
-        }
+        self.properties().glow_color
+    }
+
+    pub fn light_radius(&self) -> f32 {
+        self.properties().light_radius
+    }
+
+    pub fn friction(&self) -> f32 {
+        self.properties().friction
+    }
+
+    pub fn map_color(&self) -> [u8; 4] {
+        self.properties().map_color
     }
 }
 
-#[repr(u16)]
-#[derive(Debug, Copy, Clone, PartialEq)]
-pub enum FgTile {
-    Empty,
-    PipeEndL,
-    PipeLR,
-    PipeLRU,
-    PipeLRD,
-    PipeEndR,
-    PipeEndD,
-    PipeUD,
-    PipeEndU,
-    PipeRUD,
-    PipeLUD,
-    PipeLD,
-    PipeRD,
-    PipeLU,
-    PipeRU,
-    PipeLRUD,
-    ChainV,
-    ChainH,
-    FluorecentLampL,
-    FluorecentLampM,
-    FluorecentLampR,
-    MetalFrameBlock,
-    MetalFrameFloorL,
-    MetalFrameFloorM,
-    MetalFrameFloorR,
-    MetalFrameFloorMSupport,
-    MetalFrameFloorMItemSupport,
-    FramedPipeUD,
-    FramedPipeLR,
-    FramedPipeJunction
+tile_enum! {
+    pub enum FgTile {
+        Empty,
+        PipeEndL,
+        PipeLR,
+        PipeLRU,
+        PipeLRD,
+        PipeEndR,
+        PipeEndD,
+        PipeUD,
+        PipeEndU,
+        PipeRUD,
+        PipeLUD,
+        PipeLD,
+        PipeRD,
+        PipeLU,
+        PipeRU,
+        PipeLRUD,
+        ChainV,
+        ChainH,
+        FluorecentLampL,
+        FluorecentLampM,
+        FluorecentLampR,
+        MetalFrameBlock,
+        MetalFrameFloorL,
+        MetalFrameFloorM,
+        MetalFrameFloorR,
+        MetalFrameFloorMSupport,
+        MetalFrameFloorMItemSupport,
+        FramedPipeUD,
+        FramedPipeLR,
+        FramedPipeJunction
+    }
 }
 
+const FLUORECENT_LAMP_COLOR: [f32; 4] = [0.8, 0.8, 1.0, 1.0];
+
+const FG_TILE_PROPERTIES: [TileProperties; FgTile::ALL.len()] = [
+    TileProperties::new(FgTile::Empty as u16),
+    TileProperties::new(FgTile::PipeEndL as u16),
+    TileProperties::new(FgTile::PipeLR as u16),
+    TileProperties::new(FgTile::PipeLRU as u16),
+    TileProperties::new(FgTile::PipeLRD as u16),
+    TileProperties::new(FgTile::PipeEndR as u16),
+    TileProperties::new(FgTile::PipeEndD as u16),
+    TileProperties::new(FgTile::PipeUD as u16),
+    TileProperties::new(FgTile::PipeEndU as u16),
+    TileProperties::new(FgTile::PipeRUD as u16),
+    TileProperties::new(FgTile::PipeLUD as u16),
+    TileProperties::new(FgTile::PipeLD as u16),
+    TileProperties::new(FgTile::PipeRD as u16),
+    TileProperties::new(FgTile::PipeLU as u16),
+    TileProperties::new(FgTile::PipeRU as u16),
+    TileProperties::new(FgTile::PipeLRUD as u16),
+    TileProperties::new(FgTile::ChainV as u16),
+    TileProperties::new(FgTile::ChainH as u16),
+    TileProperties::new(FgTile::FluorecentLampL as u16).glowing(FLUORECENT_LAMP_COLOR),
+    TileProperties::new(FgTile::FluorecentLampM as u16).glowing(FLUORECENT_LAMP_COLOR),
+    TileProperties::new(FgTile::FluorecentLampR as u16).glowing(FLUORECENT_LAMP_COLOR),
+    TileProperties::new(FgTile::MetalFrameBlock as u16).solid(),
+    TileProperties::new(FgTile::MetalFrameFloorL as u16).solid(),
+    TileProperties::new(FgTile::MetalFrameFloorM as u16).solid(),
+    TileProperties::new(FgTile::MetalFrameFloorR as u16).solid(),
+    TileProperties::new(FgTile::MetalFrameFloorMSupport as u16),
+    TileProperties::new(FgTile::MetalFrameFloorMItemSupport as u16),
+    TileProperties::new(FgTile::FramedPipeUD as u16),
+    TileProperties::new(FgTile::FramedPipeLR as u16),
+    TileProperties::new(FgTile::FramedPipeJunction as u16)
+];
+
 impl SerBin for FgTile {
     fn ser_bin(&self, output: &mut Vec<u8>) {
         (*self as u16).ser_bin(output)
@@ -91,19 +221,53 @@ impl SerBin for FgTile {
 
 impl DeBin for FgTile {
     fn de_bin(offset: &mut usize, bytes: &[u8]) -> Result<Self, nanoserde::DeBinErr> {
-        Ok(unsafe { std::mem::transmute(u16::de_bin(offset, bytes)?) })
+        let start = *offset;
+        let discriminant = u16::de_bin(offset, bytes)?;
+        Self::ALL.get(discriminant as usize).copied().ok_or(nanoserde::DeBinErr { o: start, l: 2, s: bytes.len() })
     }
 }
 
 impl FgTile {
+    pub fn properties(&self) -> &'static TileProperties {
+        &FG_TILE_PROPERTIES[*self as usize]
+    }
+
     pub fn sprite_sheet_index(&self) -> u16 {
-        *self as u16
+        self.properties().sprite_index
+    }
+
+    /// Solid foreground tiles like platforms collide just like solid tiles, see [`WorldView::is_solid`](crate::common::WorldView::is_solid).
+    pub fn is_solid(&self) -> bool {
+        self.properties().solid
     }
 
     pub fn glow_color(&self) -> Option<[f32; 4]> {
-        match self {
-            Self::FluorecentLampL | Self::FluorecentLampM | Self::FluorecentLampR => Some([0.8, 0.8, 1.0, 1.0]),
-            _ => None
-        }   
+        self.properties().glow_color
+    }
+
+    pub fn light_radius(&self) -> f32 {
+        self.properties().light_radius
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_tile_has_properties() {
+        for tile in Tile::ALL {
+            assert_eq!(tile.properties().sprite_index, tile as u16, "row for {tile:?} is out of place");
+        }
+        for tile in FgTile::ALL {
+            assert_eq!(tile.properties().sprite_index, tile as u16, "row for {tile:?} is out of place");
+        }
+    }
+
+    #[test]
+    fn glowing_tiles_cast_light() {
+        for tile in Tile::ALL {
+            assert_eq!(tile.glow_color().is_some(), tile.light_radius() > 0.0, "{tile:?}");
+        }
     }
 }
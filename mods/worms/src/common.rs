@@ -0,0 +1,124 @@
+use aeonetica_engine::math::vector::Vector2;
+use aeonetica_engine::nanoserde::{SerBin, DeBin};
+use aeonetica_engine::nanoserde;
+
+/// Segment deltas are quantized to multiples of this, so the reconstructed positions are off by at most half of it.
+pub(crate) const DELTA_QUANTUM: f32 = 1.0 / 256.0;
+
+/// A full keyframe is sent after this many delta updates, even if nothing requires one.
+pub(crate) const KEYFRAME_INTERVAL: usize = 100;
+
+/// Worm segment positions sent to clients.
+#[derive(SerBin, DeBin, Debug, Clone, PartialEq)]
+pub enum WormUpdate {
+    /// absolute positions of all segments
+    Keyframe(Vec<Vector2<f32>>),
+    /// (segment index, x, y) offsets in [`DELTA_QUANTUM`]s to the previous update, for the segments that moved
+    Delta(Vec<(u16, i16, i16)>)
+}
+
+impl WormUpdate {
+    /// Reconstructs the segment positions from the ones of the previous update.
+    /// Returns `None` for a delta that doesn't fit `previous`, e.g. when no keyframe was received yet.
+    pub(crate) fn apply(self, previous: &[Vector2<f32>]) -> Option<Vec<Vector2<f32>>> {
+        match self {
+            WormUpdate::Keyframe(segments) => Some(segments),
+            WormUpdate::Delta(deltas) => {
+                if previous.is_empty() {
+                    return None
+                }
+                let mut segments = previous.to_vec();
+                for (i, x, y) in deltas {
+                    *segments.get_mut(i as usize)? += dequantize(x, y);
+                }
+                Some(segments)
+            }
+        }
+    }
+}
+
+/// Encodes the server side segments relative to what clients last reconstructed.
+///
+/// Updates are sent reliably and in order, so the last sent state is the one every receiving client has.
+/// The baseline tracks the quantized positions clients see, not the exact ones, so rounding errors don't add up.
+#[derive(Debug, Default)]
+pub(crate) struct DeltaEncoder {
+    sent: Vec<Vector2<f32>>,
+    updates_since_keyframe: usize
+}
+
+impl DeltaEncoder {
+    /// Encodes `segments` as a delta to the previous update, or as a keyframe if `force_keyframe` is set,
+    /// the keyframe interval passed or a segment moved too far to be encoded as a delta.
+    pub(crate) fn encode(&mut self, segments: &[Vector2<f32>], force_keyframe: bool) -> WormUpdate {
+        if !force_keyframe && self.updates_since_keyframe < KEYFRAME_INTERVAL && self.sent.len() == segments.len() {
+            let deltas = self.sent.iter().zip(segments)
+                .enumerate()
+                .map(|(i, (sent, segment))| quantize(*segment - *sent).map(|(x, y)| (i as u16, x, y)))
+                .collect::<Option<Vec<_>>>();
+            if let Some(mut deltas) = deltas {
+                deltas.retain(|(_, x, y)| *x != 0 || *y != 0);
+                for (i, x, y) in &deltas {
+                    self.sent[*i as usize] += dequantize(*x, *y);
+                }
+                self.updates_since_keyframe += 1;
+                return WormUpdate::Delta(deltas)
+            }
+        }
+        self.sent = segments.to_vec();
+        self.updates_since_keyframe = 0;
+        self.keyframe()
+    }
+
+    /// The state the deltas currently build on, for clients that join mid-stream.
+    pub(crate) fn keyframe(&self) -> WormUpdate {
+        WormUpdate::Keyframe(self.sent.clone())
+    }
+}
+
+fn quantize(delta: Vector2<f32>) -> Option<(i16, i16)> {
+    let q = |d: f32| {
+        let q = (d / DELTA_QUANTUM).round();
+        (q >= i16::MIN as f32 && q <= i16::MAX as f32).then_some(q as i16)
+    };
+    Some((q(delta.x)?, q(delta.y)?))
+}
+
+fn dequantize(x: i16, y: i16) -> Vector2<f32> {
+    Vector2::new(x as f32 * DELTA_QUANTUM, y as f32 * DELTA_QUANTUM)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deltas_reconstruct_positions() {
+        let mut encoder = DeltaEncoder::default();
+        let mut segments = vec![Vector2::new(0.0, 0.0), Vector2::new(0.8, 0.0), Vector2::new(1.6, 0.0)];
+        let mut client = encoder.encode(&segments, false).apply(&[]).unwrap();
+        assert_eq!(client, segments);
+
+        for step in 1..KEYFRAME_INTERVAL {
+            segments[0] += Vector2::new(0.0123, -0.0071);
+            if step % 2 == 0 {
+                segments[2] += Vector2::new(0.001, 0.0);
+            }
+            let update = encoder.encode(&segments, false);
+            assert!(matches!(&update, WormUpdate::Delta(deltas) if deltas.iter().all(|(i, _, _)| *i != 1)), "{update:?}");
+            client = update.apply(&client).unwrap();
+            for (client, server) in client.iter().zip(&segments) {
+                assert!((*client - *server).mag() <= DELTA_QUANTUM, "{client} != {server}");
+            }
+        }
+
+        assert!(matches!(encoder.encode(&segments, false), WormUpdate::Delta(_)));
+        assert_eq!(encoder.encode(&segments, false), WormUpdate::Keyframe(segments.clone()));
+        segments[1] += Vector2::new(500.0, 0.0);
+        assert_eq!(encoder.encode(&segments, false), WormUpdate::Keyframe(segments.clone()));
+        assert_eq!(encoder.encode(&segments, true), WormUpdate::Keyframe(segments.clone()));
+
+        assert_eq!(WormUpdate::Delta(vec![(0, 1, 1)]).apply(&[]), None);
+        assert_eq!(WormUpdate::Delta(vec![(3, 1, 1)]).apply(&segments), None);
+    }
+}
@@ -3,6 +3,7 @@
 use aeonetica_engine::register;
 
 pub(crate) mod client;
+pub(crate) mod common;
 pub(crate) mod server;
 
 register!(client::WormsModClient::new(), server::WormsModServer::new());
\ No newline at end of file
@@ -1,8 +1,9 @@
 use aeonetica_engine::{time::Time, math::vector::Vector2, EntityId, networking::SendMode, util::nullable::Nullable};
-use aeonetica_server::{ServerMod, ecs::{module::Module, Engine, messaging::Messenger}};
+use aeonetica_server::{ServerMod, ecs::{module::Module, Engine, messaging::{Messenger, ReplicationSchedule}}};
 use player_mod::server::{PLAYER_HANDLER, PlayerHandler, Player};
 use world_mod::{server::world::{WORLD, World}, common::WorldView};
 use crate::client::WormHandle;
+use crate::common::DeltaEncoder;
 
 
 pub struct WormsModServer {
@@ -16,6 +17,10 @@ impl WormsModServer {
 }
 
 impl ServerMod for WormsModServer {
+    fn dependencies(&self) -> Vec<&str> {
+        vec!["world", "player"]
+    }
+
     fn start(&mut self, engine: &mut Engine) {
         Worm::create(engine);
     }
@@ -23,11 +28,14 @@ impl ServerMod for WormsModServer {
 
 const SEG_LEN: f32 = 0.8;
 pub(crate) const WORM_SPEED: f32 = 5.0;
+/// position updates per second, the client interpolates between them
+const REPLICATION_RATE: f32 = 20.0;
 
 pub(crate) struct Worm {
     ppos: Vector2<f32>,
     looking_dir: Vector2<f32>,
     segments: Vec<Vector2<f32>>,
+    encoder: DeltaEncoder,
     attack_cooldown: f32
 }
 
@@ -37,22 +45,26 @@ impl Worm {
         
         let mut entity = engine.mut_entity(&eid);
         entity.add_module(Worm::new(Vector2::new(-15.0, 0.0), Vector2::new(1.0,  0.0), 10));
-        entity.add_module(Messenger::new::<WormHandle>());
+        entity.add_module(Messenger::new::<WormHandle>().with_replication(ReplicationSchedule::new(REPLICATION_RATE)));
         eid
     }
 
     fn new(pos: Vector2<f32>, dir: Vector2<f32>, segs: usize) -> Self {
+        let segments = {
+            let dir = dir.normalized();
+            let mut segments = vec![];
+            for i in 0..segs {
+                segments.push(pos + dir * SEG_LEN * i as f32);
+            }
+            segments
+        };
+        let mut encoder = DeltaEncoder::default();
+        encoder.encode(&segments, true);
         Self {
             ppos: Default::default(),
             looking_dir: Vector2::new(1.0, 0.0),
-            segments: {
-                let dir = dir.normalized();
-                let mut segments = vec![];
-                for i in 0..segs {
-                    segments.push(pos + dir * SEG_LEN * i as f32);
-                }
-                segments
-            },
+            segments,
+            encoder,
             attack_cooldown: 0.0
         }
     }
@@ -83,8 +95,9 @@ impl Module for Worm {
                     }
                     if engine.mut_module_of::<Messenger>(id).add_client(*pid) {
                         println!("ADDDD");
+                        // later deltas build on the last broadcast state, so that is what a joining client starts from
                         let (mut messenger, worm) = engine.two_mut_modules_of::<Messenger, Worm>(id);
-                        messenger.call_client_fn(WormHandle::receive_position, (worm.segments.clone(), worm.looking_dir, true), SendMode::Safe);
+                        messenger.call_client_fn_for(WormHandle::receive_position, pid, (worm.encoder.keyframe(), worm.looking_dir, true), SendMode::Safe);
                     }
                 }
             }
@@ -126,8 +139,14 @@ impl Module for Worm {
 
         if (ppos - self_pos).mag_sq() > 0.05 {
             let (mut messenger, mut worm) = engine.two_mut_modules_of::<Messenger, Worm>(id);
+            // the delta has to build on the last sent update, so it is only encoded when sending
+            if !messenger.should_replicate(time.time) {
+                return
+            }
+            let worm = &mut **worm;
             worm.ppos = self_pos;
-            messenger.call_client_fn(WormHandle::receive_position, (worm.segments.clone(), worm.looking_dir, false), SendMode::Safe);
+            let update = worm.encoder.encode(&worm.segments, false);
+            messenger.call_client_fn(WormHandle::receive_position, (update, worm.looking_dir, false), SendMode::Safe);
         }
     }
 }
\ No newline at end of file
@@ -1,14 +1,17 @@
 
 use aeonetica_engine::{EntityId, Id, TypeId};
+use aeonetica_engine::error::{Error, ErrorResult, Fatality};
+use aeonetica_engine::error::builtin::DataError;
 use aeonetica_engine::util::id_map::IdMap;
 use aeonetica_engine::util::nullable::Nullable;
 use aeonetica_engine::util::type_to_id;
 use crate::ecs::Engine;
-use crate::ecs::module::{Module, ModuleDyn};
+use crate::ecs::module::{Module, ModuleDyn, ModuleState};
 
 pub struct Entity {
     engine: *mut Engine, // Only use for add/removal events!!! DO NOT use for any other purpose!!!
     pub(crate) entity_id: EntityId,
+    pub(crate) parent: Option<EntityId>,
     pub(crate) modules: IdMap<Box<dyn ModuleDyn>>
 }
 
@@ -17,6 +20,7 @@ impl Entity {
         Self {
             engine: engine as *const Engine as *mut Engine,
             entity_id: Id::new(),
+            parent: None,
             modules: Default::default()
         }
     }
@@ -38,9 +42,20 @@ impl Entity {
         self.modules.keys().copied().collect()
     }
 
-    pub fn remove_module<T: Module + Sized + 'static>(&mut self) -> bool{
+    /// Calls the [`Module::remove`] hook of the `T` module and detaches it, returning the module.
+    pub fn remove_module<T: Module + Sized + 'static>(&mut self) -> Option<T> {
         if let Some(m) = self.modules.get(&type_to_id::<T>()) { m.remove_dyn(&self.entity_id, unsafe {&mut *self.engine}) }
-        self.modules.remove(&type_to_id::<T>()).is_some()
+        self.modules.remove(&type_to_id::<T>())
+            .and_then(|m| m.into_any().downcast::<T>().ok())
+            .map(|m| *m)
+    }
+
+    /// Swaps the `T` module for `module`, running the removal hook of the old and the init and start hooks of the new one.
+    /// Returns the old module, if there was one.
+    pub fn replace_module<T: Module + Sized + 'static>(&mut self, module: T) -> Option<T> {
+        let old = self.remove_module::<T>();
+        self.add_module(module);
+        old
     }
 
     pub fn get_module<T: Module + Sized + 'static>(&self) -> Nullable<&T> {
@@ -77,4 +92,44 @@ impl Entity {
     pub fn id(&self) -> EntityId {
         self.entity_id
     }
+
+    /// Makes positions of this entity relative to `parent`, see [`Engine::world_transform`].
+    pub fn set_parent(&mut self, parent: EntityId) {
+        self.parent = Some(parent);
+    }
+
+    pub fn clear_parent(&mut self) {
+        self.parent = None;
+    }
+
+    pub fn parent(&self) -> Option<EntityId> {
+        self.parent
+    }
+
+    /// State of every module that has any, see [`Module::serialize`].
+    pub fn snapshot(&self) -> Vec<ModuleState> {
+        self.modules.iter()
+            .filter_map(|(id, m)| Some(ModuleState { module: *id, version: m.state_version(), data: m.serialize()? }))
+            .collect()
+    }
+
+    /// Passes each state to [`Module::deserialize`] of the matching module.
+    /// States of modules the entity doesn't have or of another [`Module::state_version`] are rejected,
+    /// all other states are still restored. Returns the first error.
+    pub fn restore(&mut self, states: &[ModuleState]) -> ErrorResult<()> {
+        let mut result = Ok(());
+        for state in states {
+            let restored = match self.modules.get_mut(&state.module) {
+                None => Err(Error::new(DataError(format!("entity {} has no module {}", self.entity_id, state.module)), Fatality::DEFAULT, false)),
+                Some(m) if m.state_version() != state.version => Err(Error::new(DataError(format!(
+                    "state of module {} has version {}, expected {}", state.module, state.version, m.state_version()
+                )), Fatality::DEFAULT, false)),
+                Some(m) => m.deserialize(&state.data)
+            };
+            if result.is_ok() {
+                result = restored;
+            }
+        }
+        result
+    }
 }
\ No newline at end of file
@@ -0,0 +1,103 @@
+use std::any::Any;
+use std::rc::Rc;
+use aeonetica_engine::Id;
+use aeonetica_engine::util::id_map::IdMap;
+use aeonetica_engine::util::type_to_id;
+use crate::ecs::Engine;
+use crate::ecs::scheduling::{Event, EventId};
+
+pub type SubscriptionId = Id;
+
+type Handler = Rc<dyn Fn(&mut Engine, &dyn Any)>;
+
+/// Typed events passed between server modules, see [`Engine::emit`] and [`Engine::subscribe`].
+#[derive(Default)]
+pub(crate) struct EventBus {
+    handlers: IdMap<Vec<(SubscriptionId, Handler)>>,
+    queue: Vec<(EventId, Box<dyn Any>)>
+}
+
+impl Engine {
+    /// Queues `event` for dispatch at the end of the current tick, after all modules ticked and tasks ran.
+    /// Tasks waiting for `E` via [`WaitFor::event`](crate::ecs::scheduling::WaitFor::event) are resumed on dispatch as well.
+    pub fn emit<E: Event + 'static>(&mut self, event: E) {
+        self.events.queue.push((type_to_id::<E>(), Box::new(event)));
+    }
+
+    /// Registers `handler` to be called for every dispatched event of type `E`.
+    ///
+    /// Events are dispatched in the order they were emitted, and each event is passed to its handlers in
+    /// the order they subscribed. Events emitted by handlers are dispatched in the same pass, after all
+    /// events emitted before them. Subscriptions made by handlers apply from the next event on.
+    pub fn subscribe<E: Event + 'static, F: Fn(&mut Engine, &E) + 'static>(&mut self, handler: F) -> SubscriptionId {
+        let id = Id::new();
+        let handler: Handler = Rc::new(move |engine, event| handler(engine, event.downcast_ref::<E>().unwrap()));
+        self.events.handlers.entry(type_to_id::<E>()).or_default().push((id, handler));
+        id
+    }
+
+    pub fn unsubscribe(&mut self, subscription: &SubscriptionId) -> bool {
+        let mut found = false;
+        for handlers in self.events.handlers.values_mut() {
+            let len = handlers.len();
+            handlers.retain(|(id, _)| id != subscription);
+            found |= handlers.len() != len;
+        }
+        found
+    }
+
+    pub(crate) fn dispatch_events(&mut self) {
+        let mut i = 0;
+        while i < self.events.queue.len() {
+            let (event_id, event) = std::mem::replace(&mut self.events.queue[i], (EventId::default(), Box::new(())));
+            let handlers = self.events.handlers.get(&event_id)
+                .map(|handlers| handlers.iter().map(|(_, h)| h.clone()).collect::<Vec<_>>())
+                .unwrap_or_default();
+            for handler in handlers {
+                handler(self, event.as_ref());
+            }
+            self.fire_raw_event(&event_id);
+            i += 1;
+        }
+        self.events.queue.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use super::*;
+    use crate::ecs::tests::test_engine;
+
+    struct TileBroken(u32);
+    impl Event for TileBroken {}
+
+    struct DropSpawned(u32);
+    impl Event for DropSpawned {}
+
+    #[test]
+    fn events_dispatch_in_order() {
+        let mut engine = test_engine();
+        let log = Rc::new(RefCell::new(vec![]));
+        let (l1, l2, l3) = (log.clone(), log.clone(), log.clone());
+        engine.subscribe(move |engine: &mut Engine, e: &TileBroken| {
+            l1.borrow_mut().push(format!("particles {}", e.0));
+            engine.emit(DropSpawned(e.0));
+        });
+        let drops = engine.subscribe(move |_: &mut Engine, e: &TileBroken| l2.borrow_mut().push(format!("drops {}", e.0)));
+        engine.subscribe(move |_: &mut Engine, e: &DropSpawned| l3.borrow_mut().push(format!("spawned {}", e.0)));
+
+        engine.emit(TileBroken(1));
+        engine.emit(TileBroken(2));
+        assert!(log.borrow().is_empty());
+
+        engine.dispatch_events();
+        assert_eq!(*log.borrow(), ["particles 1", "drops 1", "particles 2", "drops 2", "spawned 1", "spawned 2"]);
+
+        assert!(engine.unsubscribe(&drops));
+        log.borrow_mut().clear();
+        engine.emit(TileBroken(3));
+        engine.dispatch_events();
+        assert_eq!(*log.borrow(), ["particles 3", "spawned 3"]);
+    }
+}
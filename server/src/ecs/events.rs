@@ -1,10 +1,16 @@
+use std::collections::HashSet;
 use aeonetica_engine::{ClientId, EntityId};
 use crate::ecs::Engine;
 use crate::ecs::module::Module;
 
+/// Decides whether a client is of interest to the entity owning a [`ConnectionListener`].
+pub type InterestFilter = fn(id: &EntityId, engine: &Engine, user: &ClientId) -> bool;
+
 pub struct ConnectionListener {
     pub(crate) on_join: fn(id: &EntityId, engine: &mut Engine, user: &ClientId),
     pub(crate) on_leave: fn(id: &EntityId, engine: &mut Engine, user: &ClientId),
+    pub(crate) filter: Option<InterestFilter>,
+    pub(crate) joined: HashSet<ClientId>
 }
 
 impl ConnectionListener {
@@ -12,8 +18,102 @@ impl ConnectionListener {
         Self {
             on_join,
             on_leave,
+            filter: None,
+            joined: Default::default()
+        }
+    }
+
+    /// Like [`ConnectionListener::new`], but `on_join` only fires for clients passing `filter`.
+    ///
+    /// The filter is evaluated once when a client logs in. It does not re-run on its own as clients
+    /// or entities move; call [`Engine::reevaluate_interest`] to run it again for all logged in clients,
+    /// which fires `on_join` for clients that became interesting and `on_leave` for clients that no longer are.
+    /// `on_leave` only ever fires for clients that `on_join` fired for before.
+    pub fn with_filter(on_join: fn(id: &EntityId, engine: &mut Engine, user: &ClientId), on_leave: fn(id: &EntityId, engine: &mut Engine, user: &ClientId), filter: InterestFilter) -> Self {
+        Self {
+            filter: Some(filter),
+            ..Self::new(on_join, on_leave)
+        }
+    }
+
+    pub fn has_joined(&self, user: &ClientId) -> bool {
+        self.joined.contains(user)
+    }
+
+    fn interested(&self, id: &EntityId, engine: &Engine, user: &ClientId) -> bool {
+        self.filter.map(|filter| filter(id, engine, user)).unwrap_or(true)
+    }
+}
+
+impl Module for ConnectionListener {}
+
+impl Engine {
+    pub(crate) fn fire_join(&mut self, user: &ClientId) {
+        self.for_each_module_of_type::<ConnectionListener, _>(|engine, id, m| {
+            if m.interested(id, engine, user) && m.joined.insert(*user) {
+                (m.on_join)(id, engine, user)
+            }
+        });
+    }
+
+    pub(crate) fn fire_leave(&mut self, user: &ClientId) {
+        self.for_each_module_of_type::<ConnectionListener, _>(|engine, id, m| {
+            if m.joined.remove(user) {
+                (m.on_leave)(id, engine, user)
+            }
+        });
+    }
+
+    /// Re-runs the interest filter of the entity's [`ConnectionListener`] for all logged in clients,
+    /// firing `on_join` and `on_leave` for clients whose interest changed.
+    pub fn reevaluate_interest(&mut self, id: &EntityId) {
+        let mut_self_ref_ptr = self as *mut Self;
+        let clients = self.clients.iter().copied().collect::<Vec<_>>();
+        let Some(listener) = self.mut_module_of::<ConnectionListener>(id).option() else { return };
+        let engine = unsafe { &mut *mut_self_ref_ptr };
+        for user in clients {
+            let interested = listener.interested(id, engine, &user);
+            if interested && listener.joined.insert(user) {
+                (listener.on_join)(id, engine, &user)
+            } else if !interested && listener.joined.remove(&user) {
+                (listener.on_leave)(id, engine, &user)
+            }
         }
     }
 }
 
-impl Module for ConnectionListener {}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::tests::test_engine;
+
+    const JOINED: &str = "JOINED";
+
+    #[test]
+    fn filtered_connection_listener() {
+        let mut engine = test_engine();
+        let listener = engine.new_entity();
+        engine.mut_entity(&listener).add_module(ConnectionListener::with_filter(
+            |id, engine, _| { engine.tag_entity(*id, JOINED); },
+            |_, engine, _| { engine.remove_tag(JOINED); },
+            |_, engine, _| engine.tag_exists("INTERESTED")
+        ));
+        let client = aeonetica_engine::Id::new();
+        engine.clients.insert(client);
+
+        engine.fire_join(&client);
+        assert!(!engine.tag_exists(JOINED));
+
+        let marker = engine.new_entity();
+        engine.tag_entity(marker, "INTERESTED");
+        engine.reevaluate_interest(&listener);
+        assert!(engine.tag_exists(JOINED));
+
+        engine.remove_tag("INTERESTED");
+        engine.reevaluate_interest(&listener);
+        assert!(!engine.tag_exists(JOINED));
+
+        engine.fire_leave(&client);
+        assert!(!engine.get_module_of::<ConnectionListener>(&listener).has_joined(&client));
+    }
+}
@@ -3,13 +3,17 @@ use std::cell::RefCell;
 use std::collections::{HashSet};
 use std::collections::hash_set::Iter;
 use std::fmt::Debug;
+use std::marker::PhantomData;
 use std::rc::Rc;
+use std::time::{Duration, Instant};
 use aeonetica_engine::{ClientId, EntityId, Id, TypeId};
 use aeonetica_engine::nanoserde::{DeBin, SerBin};
 use aeonetica_engine::networking::server_packets::{ServerMessage, ServerPacket};
 use aeonetica_engine::util::type_to_id;
 use crate::ecs::{Module, Engine};
-use crate::networking::NetworkServer;
+use crate::networking::{AwaitingReply, NetworkServer, ReplySlot};
+use aeonetica_engine::error::{Error, ErrorResult, Fatality};
+use aeonetica_engine::error::builtin::{DataError, NetworkError};
 use aeonetica_engine::networking::messaging::ClientEntity;
 use aeonetica_engine::networking::SendMode;
 use aeonetica_engine::util::id_map::IdMap;
@@ -30,10 +34,78 @@ pub unsafe trait Renderer {}
 /// This trait is for internal use only.
 pub unsafe trait DataStore {}
 
+/// Limits how often an entity replicates its state, independent of the tick rate.
+///
+/// The schedule of a [`Messenger`] is shared by all of its clients: an update either goes to every client or to none,
+/// which keeps broadcast deltas consistent. For separate rates per client, e.g. for updates sent with
+/// [`Messenger::call_client_fn_for`], keep one schedule per client, such as a `HashMap<ClientId, ReplicationSchedule>`.
+///
+/// Updates that are not due are dropped, the next one that goes out has to bring clients up to date on its own.
+/// With [`SendMode::Quick`] and [`SendMode::Ordered`] that already is the case, as every datagram may get lost.
+/// [`SendMode::Safe`] updates that are deltas have to be encoded against the last *sent* state,
+/// so only encode them once [`ReplicationSchedule::should_replicate`] returned `true`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplicationSchedule {
+    /// seconds between two updates, 0 sends every time
+    interval: f32,
+    next: f32,
+    track_dirty: bool,
+    dirty: bool
+}
+
+impl ReplicationSchedule {
+    /// Tick times are sums of floats, so updates within this many seconds of being due count as due.
+    const TOLERANCE: f32 = 1e-4;
+
+    /// Sends every time it is asked
+    pub fn unlimited() -> Self {
+        Self::new(0.0)
+    }
+
+    /// At most `rate` updates per second, unlimited if it isn't positive.
+    pub fn new(rate: f32) -> Self {
+        Self {
+            interval: if rate > 0.0 { 1.0 / rate } else { 0.0 },
+            next: f32::MIN,
+            track_dirty: false,
+            dirty: false
+        }
+    }
+
+    /// Only replicates after [`ReplicationSchedule::mark_dirty`] was called since the last update.
+    pub fn with_dirty_tracking(mut self) -> Self {
+        self.track_dirty = true;
+        self
+    }
+
+    /// The state changed and should be replicated once due.
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    /// Whether an update is due at `now`, the gameplay time in seconds. Returning `true` counts as sending it.
+    pub fn should_replicate(&mut self, now: f32) -> bool {
+        if now + Self::TOLERANCE < self.next || (self.track_dirty && !self.dirty) {
+            return false
+        }
+        // keeps the rate when ticks are a bit late, but doesn't catch up on updates that weren't asked for
+        self.next = if now - self.next < self.interval { self.next + self.interval } else { now + self.interval };
+        self.dirty = false;
+        true
+    }
+}
+
+impl Default for ReplicationSchedule {
+    fn default() -> Self {
+        Self::unlimited()
+    }
+}
+
 pub struct Messenger {
     ns: Option<Rc<RefCell<NetworkServer>>>,
     handle_type: TypeId,
     entity_id: EntityId,
+    replication: ReplicationSchedule,
     pub(crate) receivers: HashSet<ClientId>,
     pub(crate) receiver_functions: IdMap<Box<dyn Fn(&EntityId, &mut Engine, &ClientId, &Vec<u8>)>>
 }
@@ -54,10 +126,26 @@ impl Messenger {
             receivers: Default::default(),
             handle_type: type_to_id::<H>(),
             entity_id: Id::new(),
+            replication: Default::default(),
             receiver_functions: Default::default()
         }
     }
 
+    /// Limits [`Messenger::call_client_fn_throttled`] and [`Messenger::should_replicate`] to `schedule`, for all clients at once.
+    pub fn with_replication(mut self, schedule: ReplicationSchedule) -> Self {
+        self.replication = schedule;
+        self
+    }
+
+    pub fn replication(&mut self) -> &mut ReplicationSchedule {
+        &mut self.replication
+    }
+
+    /// Whether the entity's replication schedule allows an update at `now`, see [`ReplicationSchedule::should_replicate`].
+    pub fn should_replicate(&mut self, now: f32) -> bool {
+        self.replication.should_replicate(now)
+    }
+
     pub fn register_receiver<F: Fn(&EntityId, &mut Engine, &ClientId, M) + 'static, M: SerBin + DeBin>(&mut self, f: F) {
         let m = move |id: &Id, engine: &mut Engine, sender: &ClientId, data: &Vec<u8>|
             f(id, engine, sender, M::deserialize_bin(data).unwrap());
@@ -78,6 +166,27 @@ impl Messenger {
         }
     }
 
+    /// Like [`Messenger::call_client_fn`], but drops the message for all clients if the replication schedule
+    /// doesn't allow an update at `now`. Returns whether it was sent.
+    pub fn call_client_fn_throttled<F: Fn(&mut T, &mut TClientMessenger, Nullable<&mut TRenderer>, &mut TDataStore, M), T: ClientEntity, TClientMessenger: ClientMessenger, TRenderer: Renderer, TDataStore: DataStore, M: SerBin + DeBin>(&mut self, f: F, message: M, mode: SendMode, now: f32) -> bool {
+        if !self.should_replicate(now) {
+            return false
+        }
+        self.call_client_fn::<F, T, TClientMessenger, TRenderer, TDataStore, M>(f, message, mode);
+        true
+    }
+
+    /// Like [`Messenger::call_client_fn`], but skips `exclude`, e.g. the client that caused the message.
+    pub fn call_client_fn_except<F: Fn(&mut T, &mut TClientMessenger, Nullable<&mut TRenderer>, &mut TDataStore, M), T: ClientEntity, TClientMessenger: ClientMessenger, TRenderer: Renderer, TDataStore: DataStore, M: SerBin + DeBin>(&mut self, _: F, exclude: &ClientId, message: M, mode: SendMode) {
+        let id = type_to_id::<F>();
+        for client in self.receivers.iter().filter(|client| *client != exclude) {
+            let _ = self.ns.as_ref().unwrap().borrow().send(client, &ServerPacket {
+                conv_id: Id::new(),
+                message: ServerMessage::ModMessage(self.entity_id, id, message.serialize_bin()),
+            }, mode);
+        }
+    }
+
     pub fn call_client_fn_for<F: Fn(&mut T, &mut TClientMessenger, Nullable<&mut TRenderer>, &mut TDataStore, M), T: ClientEntity, TClientMessenger: ClientMessenger, TRenderer: Renderer, TDataStore: DataStore, M: SerBin + DeBin>(&mut self, _: F, client: &ClientId, message: M, mode: SendMode) {
         let id = type_to_id::<F>();
         let _ = self.ns.as_ref().unwrap().borrow().send(client, &ServerPacket {
@@ -86,6 +195,38 @@ impl Messenger {
         }, mode);
     }
 
+    /// Like [`Messenger::call_client_fn_for`], but `F` returns a value that is sent back to the server.
+    /// The returned [`ReplyHandle`] resolves once the reply arrives, or with an error if the client
+    /// doesn't answer within `timeout` or disconnects. Poll it each tick, e.g. from a task:
+    ///
+    /// ```ignore
+    /// let mut reply = messenger.call_client_fn_await(Handle::confirm, &client, offer, SendMode::Safe, Duration::from_secs(10));
+    /// while !reply.is_ready() {
+    ///     yield_task!(engine, WaitFor::ticks(1));
+    /// }
+    /// let accepted: bool = reply.try_take().unwrap()?;
+    /// ```
+    pub fn call_client_fn_await<F: Fn(&mut T, &mut TClientMessenger, Nullable<&mut TRenderer>, &mut TDataStore, M) -> R, T: ClientEntity, TClientMessenger: ClientMessenger, TRenderer: Renderer, TDataStore: DataStore, M: SerBin + DeBin, R: SerBin + DeBin>(&mut self, _: F, client: &ClientId, message: M, mode: SendMode, timeout: Duration) -> ReplyHandle<R> {
+        let id = type_to_id::<F>();
+        let conv_id = Id::new();
+        let slot = ReplySlot::default();
+        let mut ns = self.ns.as_ref().unwrap().borrow_mut();
+        let sent = ns.send(client, &ServerPacket {
+            conv_id,
+            message: ServerMessage::ModMessage(self.entity_id, id, message.serialize_bin()),
+        }, mode);
+        match sent {
+            Ok(()) => if let Some(handle) = ns.clients.get_mut(client) {
+                handle.awaiting_replies.insert(conv_id, AwaitingReply {
+                    deadline: Instant::now() + timeout,
+                    slot: slot.clone()
+                });
+            },
+            Err(e) => { slot.replace(Some(Err(e))); }
+        }
+        ReplyHandle::new(slot)
+    }
+
     pub fn clients(&self) -> Iter<ClientId> {
         self.receivers.iter()
     }
@@ -115,4 +256,77 @@ impl Messenger {
             true
         } else { false }
     }
-}
\ No newline at end of file
+}
+
+/// The pending return value of a [`Messenger::call_client_fn_await`] call.
+pub struct ReplyHandle<R: DeBin> {
+    slot: Option<ReplySlot>,
+    _reply: PhantomData<R>
+}
+
+impl<R: DeBin> ReplyHandle<R> {
+    pub(crate) fn new(slot: ReplySlot) -> Self {
+        Self { slot: Some(slot), _reply: PhantomData }
+    }
+
+    /// Whether [`ReplyHandle::try_take`] returns a result.
+    pub fn is_ready(&self) -> bool {
+        self.slot.as_ref().is_some_and(|slot| slot.borrow().is_some() || Self::disconnected(slot))
+    }
+
+    /// Takes the reply, or the error if the call failed or timed out. Returns `None` while the
+    /// reply is still outstanding and after the result was taken.
+    pub fn try_take(&mut self) -> Option<ErrorResult<R>> {
+        if !self.is_ready() {
+            return None
+        }
+        let slot = self.slot.take()?;
+        if Self::disconnected(&slot) {
+            return Some(Err(Error::new(NetworkError("client disconnected before replying".to_string()), Fatality::DEFAULT, false)))
+        }
+        let result = slot.take()?;
+        Some(result.and_then(|data| R::deserialize_bin(&data)
+            .map_err(|e| Error::new(DataError(format!("invalid reply: {e}")), Fatality::DEFAULT, false))))
+    }
+
+    /// The network server drops the waiting entry together with the client.
+    fn disconnected(slot: &ReplySlot) -> bool {
+        slot.borrow().is_none() && Rc::strong_count(slot) == 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn updates_are_dropped_above_the_replication_rate() {
+        let ticks = |schedule: &mut ReplicationSchedule| (0..60).filter(|tick| schedule.should_replicate(*tick as f32 / 60.0)).count();
+        assert_eq!(ticks(&mut ReplicationSchedule::new(20.0)), 20);
+        assert_eq!(ticks(&mut ReplicationSchedule::unlimited()), 60);
+
+        // a late tick doesn't make the next update wait longer
+        let mut schedule = ReplicationSchedule::new(10.0);
+        assert!(schedule.should_replicate(0.0));
+        assert!(!schedule.should_replicate(0.05));
+        assert!(schedule.should_replicate(0.13));
+        assert!(schedule.should_replicate(0.2));
+        // nor does it catch up after a pause
+        assert!(schedule.should_replicate(5.0));
+        assert!(!schedule.should_replicate(5.05));
+    }
+
+    #[test]
+    fn dirty_tracking_only_replicates_changes() {
+        let mut schedule = ReplicationSchedule::new(10.0).with_dirty_tracking();
+        assert!(!schedule.should_replicate(0.0));
+        schedule.mark_dirty();
+        assert!(schedule.should_replicate(0.0));
+        // changes within the interval are coalesced into the next update
+        schedule.mark_dirty();
+        schedule.mark_dirty();
+        assert!(!schedule.should_replicate(0.05));
+        assert!(schedule.should_replicate(0.1));
+        assert!(!schedule.should_replicate(0.2));
+    }
+}
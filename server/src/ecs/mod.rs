@@ -3,35 +3,46 @@ use std::any::type_name;
 use std::collections::{HashMap, HashSet};
 use std::collections::hash_set;
 use std::collections::hash_map::{Iter, IterMut, Keys};
+use std::time::Duration;
 
 
 use crate::ecs::entity::Entity;
 use aeonetica_engine::util::{type_to_id, Typle};
 use aeonetica_engine::{ClientId, EntityId, Id, log};
+use aeonetica_engine::math::vector::Vector2;
 use aeonetica_engine::networking::SendMode;
+use aeonetica_engine::networking::stats::NetworkStatsSnapshot;
 use aeonetica_engine::networking::server_packets::{ServerMessage, ServerPacket};
 use aeonetica_engine::util::id_map::IdMap;
 use aeonetica_engine::util::nullable::Nullable;
 use aeonetica_engine::util::nullable::Nullable::Value;
-use crate::ecs::events::ConnectionListener;
 
-use crate::ecs::module::{Module, ModuleDyn};
+use crate::ecs::module::{HasPosition, Module, ModuleDyn};
+use crate::ecs::event_bus::EventBus;
 use crate::ecs::scheduling::TaskQueue;
+use crate::server::DEFAULT_TICK_RATE;
 use crate::server_runtime::ServerRuntime;
 
 pub mod module;
 pub mod entity;
 pub mod events;
+pub mod event_bus;
 pub mod messaging;
 pub mod scheduling;
 
 pub struct Engine {
     entites: IdMap<Entity>,
-    tagged: HashMap<String, EntityId>,
+    /// entities by tag, kept in sync when entities are untagged or removed
+    tagged: HashMap<String, HashSet<EntityId>>,
     tasks: TaskQueue,
+    events: EventBus,
     pub(crate) clients: HashSet<ClientId>,
     pub(crate) runtime: ServerRuntime,
-    pub(crate) tick: usize
+    pub(crate) tick: usize,
+    pub(crate) tick_rate: u32,
+    pub(crate) tick_drift: Duration,
+    pub(crate) time_scale: f32,
+    pub(crate) paused: bool
 }
 
 impl Engine {
@@ -41,11 +52,35 @@ impl Engine {
             tagged: Default::default(),
             clients: Default::default(),
             tasks: TaskQueue::default(),
+            events: EventBus::default(),
             runtime,
-            tick: 0
+            tick: 0,
+            tick_rate: DEFAULT_TICK_RATE,
+            tick_drift: Duration::ZERO,
+            time_scale: 1.0,
+            paused: false
         }
     }
 
+    /// Number of fixed timestep ticks run since the server started.
+    #[inline]
+    pub fn tick(&self) -> usize {
+        self.tick
+    }
+
+    /// Ticks per second the server runs at, see [`run_with_tick_rate`](crate::server::run_with_tick_rate).
+    #[inline]
+    pub fn tick_rate(&self) -> u32 {
+        self.tick_rate
+    }
+
+    /// Time accumulated towards the next tick that has not been simulated yet.
+    /// Stays below one tick duration unless the server is falling behind.
+    #[inline]
+    pub fn tick_drift(&self) -> Duration {
+        self.tick_drift
+    }
+
     /// Obtain a second mutable handle to the Engine.
     /// This is highly unsafe and should only be used internally.
     #[inline]
@@ -73,9 +108,7 @@ impl Engine {
     pub fn kick_client(&mut self, id: &ClientId, reason: &str) -> bool {
         if self.clients.contains(id) {
             self.clients.remove(id);
-            self.for_each_module_of_type::<ConnectionListener, _>(|engine, eid,  m| {
-                (m.on_leave)(eid, engine, id);
-            });
+            self.fire_leave(id);
             let _ = self.runtime.ns.borrow().send(id, &ServerPacket {
                 conv_id: Id::new(),
                 message: ServerMessage::Kick(reason.to_string()),
@@ -95,6 +128,44 @@ impl Engine {
         self.clients.iter()
     }
 
+    /// Speed of gameplay time on the server and all clients, see [`Time::time_scale`](aeonetica_engine::time::Time::time_scale).
+    pub fn time_scale(&self) -> f32 {
+        self.time_scale
+    }
+
+    /// Slows down (below 1.0) or speeds up gameplay, e.g. for debugging. Negative scales are clamped to 0.
+    pub fn set_time_scale(&mut self, scale: f32) {
+        self.time_scale = scale.max(0.0);
+        self.clients.iter().for_each(|client| self.send_time_scale(client));
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Freezes gameplay time on the server and all clients. Ticks keep running with a delta of 0.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.clients.iter().for_each(|client| self.send_time_scale(client));
+    }
+
+    pub(crate) fn send_time_scale(&self, client: &ClientId) {
+        let _ = self.runtime.ns.borrow().send(client, &ServerPacket {
+            conv_id: Id::new(),
+            message: ServerMessage::TimeScale(self.time_scale, self.paused),
+        }, SendMode::Safe);
+    }
+
+    /// Packets and bytes the server sent and received since it started.
+    pub fn network_stats(&self) -> NetworkStatsSnapshot {
+        self.runtime.ns.borrow().stats()
+    }
+
+    /// Packets queued for the client that were not sent yet, grows while the client can't keep up.
+    pub fn send_queue_depth(&self, client: &ClientId) -> usize {
+        self.runtime.ns.borrow().queue_depth(client)
+    }
+
     pub(crate) fn for_each_module<F: Fn(&mut Self, &EntityId, &mut Box<dyn ModuleDyn>)>(&mut self, runner: F) {
         let mut_self_ref_ptr = self as *mut Self;
         for id in self.entites.keys().cloned().collect::<Vec<_>>() {
@@ -119,8 +190,15 @@ impl Engine {
         }
     }
 
+    /// Removes the entity after running the `remove` hooks of all its modules.
+    /// Children of the removed entity are reparented to its parent, or become roots if it had none.
+    /// Their local positions are kept as is.
     pub fn remove_entity(&mut self, id: &EntityId) -> bool {
         let mut_self_ref_ptr = self as *mut Self;
+        let parent = self.entites.get(id).and_then(|e| e.parent);
+        self.entites.values_mut()
+            .filter(|e| e.parent == Some(*id))
+            .for_each(|e| e.parent = parent);
         if let Some(e) = self.entites.get_mut(id) {
             for mid in e.modules.keys().cloned().collect::<Vec<_>>() {
                 if let Some(m) = e.modules.get(&mid) {
@@ -128,54 +206,82 @@ impl Engine {
                 }
             }
         };
+        self.tagged.retain(|_, entities| {
+            entities.remove(id);
+            !entities.is_empty()
+        });
         self.entites.remove(id).is_some()
     }
 
     /// Returns `true` if tagging is successful.
-    /// Tagging fails if `tag_exists(tag)` returns true or `entity_exists(id)` returns false.
+    /// Tagging fails if `has_tag(id, tag)` returns true or `entity_exists(id)` returns false.
+    /// Any number of entities can share a tag.
     #[inline]
     pub fn tag_entity<S: Into<String>>(&mut self, id: EntityId, tag: S) -> bool {
-        let tag = tag.into();
-        if !self.tag_exists(&tag) && self.entity_exists(&id) {
-            self.tagged.insert(tag, id);
-            true
-        } else {
-            false
+        self.entity_exists(&id) && self.tagged.entry(tag.into()).or_default().insert(id)
+    }
+
+    /// Returns `true` if the entity had the tag.
+    #[inline]
+    pub fn untag_entity(&mut self, id: &EntityId, tag: &str) -> bool {
+        let Some(entities) = self.tagged.get_mut(tag) else {
+            return false
+        };
+        let removed = entities.remove(id);
+        if entities.is_empty() {
+            self.tagged.remove(tag);
         }
+        removed
     }
 
-    /// Returns `true` if tag exists and `entity_exists(id)` returns true.
-    /// Tags whose entity got removed are treated as nonexistent and can be overridden
+    /// Returns `true` if at least one entity has the tag.
     #[inline]
     pub fn tag_exists(&self, tag: &str) -> bool {
-       self.tagged.get(tag).map(|id| self.entity_exists(id)).unwrap_or(false)
+       self.tagged.contains_key(tag)
+    }
+
+    #[inline]
+    pub fn has_tag(&self, id: &EntityId, tag: &str) -> bool {
+        self.tagged.get(tag).map(|entities| entities.contains(id)).unwrap_or(false)
     }
 
+    /// Removes the tag from all entities that have it.
     /// Returns `true` if tag existed.
-    /// Removal fails if `tag_exists(tag)` returns false.
     #[inline]
     pub fn remove_tag(&mut self, tag: &str) -> bool {
-        if self.tag_exists(tag) {
-            self.tagged.remove(tag);
-            true
-        } else {
-            false
-        }
+        self.tagged.remove(tag).is_some()
+    }
+
+    /// All entities with the tag, in no particular order.
+    #[inline]
+    pub fn entities_with_tag(&self, tag: &str) -> impl Iterator<Item = &EntityId> {
+        self.tagged.get(tag).into_iter().flatten()
     }
 
+    /// All tags of an entity, in no particular order.
+    pub fn tags_of<'a>(&'a self, id: &'a EntityId) -> impl Iterator<Item = &'a str> {
+        self.tagged.iter()
+            .filter(move |(_, entities)| entities.contains(id))
+            .map(|(tag, _)| tag.as_str())
+    }
+
+    /// If multiple entities share the tag, any one of them is returned.
     #[inline]
     pub fn get_entity_by_tag(&self, tag: &str) -> Nullable<&Entity> {
-        self.entites.get(self.tagged.get(tag)?).into()
+        self.entites.get(self.get_entity_id_by_tag(tag)?).into()
     }
 
+    /// If multiple entities share the tag, any one of them is returned.
     #[inline]
     pub fn get_entity_id_by_tag(&self, tag: &str) -> Nullable<&EntityId> {
-        self.tagged.get(tag).into()
+        self.entities_with_tag(tag).next().into()
     }
 
+    /// If multiple entities share the tag, any one of them is returned.
     #[inline]
     pub fn mut_entity_by_tag(&mut self, tag: &str) -> Nullable<&mut Entity> {
-        self.entites.get_mut(self.tagged.get(tag)?).into()
+        let id = *self.get_entity_id_by_tag(tag)?;
+        self.entites.get_mut(&id).into()
     }
 
     #[inline]
@@ -282,4 +388,204 @@ impl Engine {
     pub fn find_with<T: Module + Sized + 'static>(&self) -> impl Iterator<Item = (&EntityId, &T)>{
         self.entites.iter().filter_map(|(id, e)| if e.has_module::<T>() { Some((id, e.get_module::<T>().option()?))} else { None })
     }
+
+    /// Position of the entity in world space, composed of the local [`HasPosition`] of `T`
+    /// of the entity and all its ancestors. Ancestors without a `T` module contribute no offset.
+    /// Returns `Null` if the entity does not exist, or if the parent chain contains a cycle.
+    pub fn world_transform<T: Module + HasPosition + Sized + 'static>(&self, id: &EntityId) -> Nullable<Vector2<f32>> {
+        let mut visited = HashSet::new();
+        let mut position = Vector2::default();
+        let mut current = Some(*id);
+        while let Some(eid) = current {
+            if !visited.insert(eid) {
+                log!(ERROR, "cyclic entity hierarchy detected at entity {eid}");
+                return Nullable::Null;
+            }
+            let entity = self.get_entity(&eid)?;
+            if let Some(m) = entity.get_module::<T>().ref_option() {
+                position += m.position();
+            }
+            current = entity.parent;
+        }
+        Value(position)
+    }
+
+    /// Returns all entities whose `T` module position lies within the box spanned by `min` and `max` (inclusive).
+    #[inline]
+    pub fn entities_in_aabb<T: Module + HasPosition + Sized + 'static>(&self, min: Vector2<f32>, max: Vector2<f32>) -> impl Iterator<Item = &EntityId> {
+        self.find_with::<T>().filter_map(move |(id, m)| {
+            let pos = m.position();
+            (pos.x >= min.x && pos.x <= max.x && pos.y >= min.y && pos.y <= max.y).then_some(id)
+        })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use aeonetica_engine::error::{Error, ErrorResult, Fatality};
+    use aeonetica_engine::error::builtin::DataError;
+    use super::*;
+    use crate::ecs::module::ModuleState;
+    use crate::networking::NetworkServer;
+    use crate::server_runtime::ModProfile;
+
+    pub(crate) fn test_engine() -> Engine {
+        Engine::new(ServerRuntime {
+            mod_profile: ModProfile {
+                profile: "test".to_string(),
+                version: "0.0.0".to_string(),
+                mod_targets: None,
+                modstack: Default::default()
+            },
+            supported_mod_targets: Default::default(),
+            loaded_mods: vec![],
+            reload_requests: vec![],
+            reloads: 0,
+            ns: Rc::new(RefCell::new(NetworkServer::start("127.0.0.1:0").unwrap())),
+            shutdown: Default::default()
+        })
+    }
+
+    struct Positioned(Vector2<f32>);
+
+    impl Module for Positioned {}
+
+    impl HasPosition for Positioned {
+        fn position(&self) -> Vector2<f32> {
+            self.0
+        }
+    }
+
+    #[test]
+    fn entities_in_aabb() {
+        let mut engine = test_engine();
+        let mut spawn = |pos: (f32, f32)| {
+            let id = engine.new_entity();
+            engine.mut_entity(&id).add_module(Positioned(pos.into()));
+            id
+        };
+        let inside = [spawn((0.0, 0.0)), spawn((5.0, 5.0)), spawn((-2.5, 3.0))];
+        let outside = [spawn((10.0, 0.0)), spawn((0.0, -5.1)), spawn((-100.0, 100.0))];
+        let unpositioned = engine.new_entity();
+
+        let found = engine.entities_in_aabb::<Positioned>((-5.0, -5.0).into(), (5.0, 5.0).into()).copied().collect::<HashSet<_>>();
+        assert_eq!(found, HashSet::from(inside));
+        assert!(outside.iter().all(|id| !found.contains(id)));
+        assert!(!found.contains(&unpositioned));
+    }
+
+    #[test]
+    fn world_transform_hierarchy() {
+        let mut engine = test_engine();
+        let mut spawn = |pos: (f32, f32), parent: Option<EntityId>| {
+            let id = engine.new_entity();
+            let mut entity = engine.mut_entity(&id);
+            entity.add_module(Positioned(pos.into()));
+            if let Some(parent) = parent {
+                entity.set_parent(parent);
+            }
+            id
+        };
+        let platform = spawn((10.0, 0.0), None);
+        let turret = spawn((0.0, 2.0), Some(platform));
+        let barrel = spawn((1.0, 0.5), Some(turret));
+
+        assert_eq!(engine.world_transform::<Positioned>(&barrel).unwrap(), Vector2::new(11.0, 2.5));
+        assert_eq!(engine.world_transform::<Positioned>(&platform).unwrap(), Vector2::new(10.0, 0.0));
+
+        engine.remove_entity(&turret);
+        assert_eq!(engine.get_entity(&barrel).unwrap().parent(), Some(platform));
+        assert_eq!(engine.world_transform::<Positioned>(&barrel).unwrap(), Vector2::new(11.0, 0.5));
+
+        engine.mut_entity(&platform).set_parent(barrel);
+        assert!(engine.world_transform::<Positioned>(&barrel).is_null());
+    }
+
+    #[test]
+    fn remove_and_replace_modules() {
+        let mut engine = test_engine();
+        let id = engine.new_entity();
+        engine.mut_entity(&id).add_module(Positioned((1.0, 2.0).into()));
+        assert_eq!(engine.find_with::<Positioned>().count(), 1);
+
+        let old = engine.mut_entity(&id).replace_module(Positioned((3.0, 4.0).into()));
+        assert_eq!(old.map(|p| p.0), Some(Vector2::new(1.0, 2.0)));
+        assert_eq!(engine.get_module_of::<Positioned>(&id).unwrap().0, Vector2::new(3.0, 4.0));
+
+        assert!(engine.mut_entity(&id).remove_module::<Positioned>().is_some());
+        assert!(engine.mut_entity(&id).remove_module::<Positioned>().is_none());
+        assert_eq!(engine.find_with::<Positioned>().count(), 0);
+        assert_eq!(engine.id_find_with::<Positioned>().count(), 0);
+    }
+
+    #[test]
+    fn tags_are_shared_and_kept_in_sync() {
+        let mut engine = test_engine();
+        let (first, second, untagged) = (engine.new_entity(), engine.new_entity(), engine.new_entity());
+        assert!(engine.tag_entity(first, "SPAWNER"));
+        assert!(engine.tag_entity(second, "SPAWNER"));
+        assert!(!engine.tag_entity(second, "SPAWNER"));
+        assert!(engine.tag_entity(second, "BOSS"));
+        assert!(!engine.tag_entity(Id::new(), "SPAWNER"));
+
+        let spawners = engine.entities_with_tag("SPAWNER").copied().collect::<HashSet<_>>();
+        assert_eq!(spawners, HashSet::from([first, second]));
+        assert!(engine.has_tag(&second, "BOSS") && !engine.has_tag(&untagged, "SPAWNER"));
+        assert_eq!(engine.tags_of(&second).collect::<HashSet<_>>(), HashSet::from(["SPAWNER", "BOSS"]));
+
+        assert!(engine.untag_entity(&first, "SPAWNER"));
+        assert!(!engine.untag_entity(&first, "SPAWNER"));
+        assert_eq!(engine.entities_with_tag("SPAWNER").collect::<Vec<_>>(), vec![&second]);
+
+        engine.remove_entity(&second);
+        assert!(!engine.tag_exists("SPAWNER") && !engine.tag_exists("BOSS"));
+        assert_eq!(engine.entities_with_tag("SPAWNER").count(), 0);
+
+        engine.tag_entity(first, "BOSS");
+        assert!(engine.remove_tag("BOSS"));
+        assert!(!engine.has_tag(&first, "BOSS"));
+    }
+
+    struct Counter(u32);
+
+    impl Module for Counter {
+        fn serialize(&self) -> Option<Vec<u8>> {
+            Some(self.0.to_le_bytes().to_vec())
+        }
+
+        fn state_version(&self) -> u32 {
+            2
+        }
+
+        fn deserialize(&mut self, data: &[u8]) -> ErrorResult<()> {
+            let bytes = data.try_into().map_err(|_| Error::new(DataError(format!("expected 4 bytes, got {}", data.len())), Fatality::DEFAULT, false))?;
+            self.0 = u32::from_le_bytes(bytes);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn module_state_snapshots_restore_matching_versions() {
+        let mut engine = test_engine();
+        let id = engine.new_entity();
+        engine.mut_entity(&id).add_module(Counter(7));
+        engine.mut_entity(&id).add_module(Positioned((1.0, 2.0).into()));
+        let snapshot = engine.get_entity(&id).unwrap().snapshot();
+        assert_eq!(snapshot, vec![ModuleState { module: type_to_id::<Counter>(), version: 2, data: 7u32.to_le_bytes().to_vec() }]);
+
+        let copy = engine.new_entity();
+        engine.mut_entity(&copy).add_module(Counter(0));
+        engine.mut_entity(&copy).restore(&snapshot).unwrap();
+        assert_eq!(engine.get_module_of::<Counter>(&copy).unwrap().0, 7);
+
+        let outdated = ModuleState { version: 1, data: 9u32.to_le_bytes().to_vec(), ..snapshot[0].clone() };
+        let truncated = ModuleState { data: vec![1], ..snapshot[0].clone() };
+        let unknown = ModuleState { module: type_to_id::<Positioned>(), ..snapshot[0].clone() };
+        for state in [outdated, truncated, unknown] {
+            assert!(engine.mut_entity(&copy).restore(&[state]).is_err());
+        }
+        assert_eq!(engine.get_module_of::<Counter>(&copy).unwrap().0, 7, "rejected states must not change the module");
+    }
 }
@@ -1,4 +1,7 @@
-use aeonetica_engine::{EntityId, time::Time};
+use std::any::Any;
+use aeonetica_engine::{EntityId, TypeId, nanoserde, time::Time, math::vector::Vector2};
+use aeonetica_engine::error::ErrorResult;
+use aeonetica_engine::nanoserde::{SerBin, DeBin};
 use crate::ecs::Engine;
 
 pub trait Module {
@@ -9,16 +12,44 @@ pub trait Module {
     fn tick(id: &EntityId, engine: &mut Engine, time: Time) where Self: Sized {}
     #[allow(unused_variables)]
     fn remove(id: &EntityId, engine: &mut Engine) where Self: Sized {}
+
+    /// State of the module for saving it or sending it to a newly connected client, see [`Entity::snapshot`].
+    /// `None` for modules without state worth keeping, which is the default.
+    ///
+    /// [`Entity::snapshot`]: crate::ecs::entity::Entity::snapshot
+    fn serialize(&self) -> Option<Vec<u8>> { None }
+    /// Version of the data returned by [`Module::serialize`]. Bump it whenever its layout changes.
+    fn state_version(&self) -> u32 { 0 }
+    /// Restores state returned by [`Module::serialize`] of a module reporting the same [`Module::state_version`].
+    /// Data that can't be read has to be rejected with an error, leaving the module unchanged.
+    #[allow(unused_variables)]
+    fn deserialize(&mut self, data: &[u8]) -> ErrorResult<()> { Ok(()) }
+}
+
+/// Serialized state of a single module, see [`Module::serialize`].
+#[derive(Debug, Clone, PartialEq, SerBin, DeBin)]
+pub struct ModuleState {
+    pub module: TypeId,
+    pub version: u32,
+    pub data: Vec<u8>
+}
+
+/// Implemented by modules that place their entity in the world,
+/// making it discoverable by spatial queries like [`Engine::entities_in_aabb`].
+pub trait HasPosition {
+    fn position(&self) -> Vector2<f32>;
 }
 
 pub(crate) trait ModuleDyn: Module {
     fn start_dyn(&self, id: &EntityId, engine: &mut Engine);
     fn tick_dyn(&self, id: &EntityId, engine: &mut Engine, time: Time);
     fn remove_dyn(&self, id: &EntityId, engine: &mut Engine);
+    /// For downcasting a detached module back to its type
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
 }
 
 /// This trait is a helper trait to make the non-self methods of `Module` accessible via vtable
-impl<T: Module + Sized> ModuleDyn for T {
+impl<T: Module + Sized + 'static> ModuleDyn for T {
     fn start_dyn(&self, id: &EntityId, engine: &mut Engine) {
         T::start(id, engine)
     }
@@ -28,4 +59,7 @@ impl<T: Module + Sized> ModuleDyn for T {
     fn remove_dyn(&self, id: &EntityId, engine: &mut Engine) {
         T::remove(id, engine)
     }
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
 }
\ No newline at end of file
@@ -3,7 +3,7 @@ use std::collections::{BinaryHeap};
 use std::collections::hash_map::Entry;
 use std::marker::PhantomData;
 use std::ops::{Coroutine, CoroutineState};
-use aeonetica_engine::TypeId;
+use aeonetica_engine::{EntityId, TypeId};
 use aeonetica_engine::util::id_map::IdMap;
 use aeonetica_engine::util::type_to_id;
 use crate::ecs::Engine;
@@ -31,10 +31,14 @@ pub trait Event {}
 
 impl Eq for Task {}
 
+pub type Condition = Box<dyn Fn(&Engine) -> bool>;
+
 #[derive(Default)]
 pub(crate) struct TaskQueue {
     pub(crate) heap: BinaryHeap<Task>,
-    pub(crate) event_queue: IdMap<Vec<Box<dyn TaskFunc>>>
+    pub(crate) event_queue: IdMap<Vec<Box<dyn TaskFunc>>>,
+    /// tasks waiting for [`WaitFor::until`]
+    pub(crate) conditions: Vec<(Condition, Box<dyn TaskFunc>)>
 }
 
 pub type EventId = TypeId;
@@ -43,17 +47,37 @@ pub struct PrivateWaiter;
 
 pub enum WaitFor {
     Ticks(usize, PrivateWaiter),
-    Event(EventId, PrivateWaiter)
+    Seconds(f32, PrivateWaiter),
+    Event(EventId, PrivateWaiter),
+    Until(Condition, PrivateWaiter)
 }
 
 impl WaitFor {
     pub fn ticks(ticks: usize) -> Self {
         WaitFor::Ticks(ticks, PrivateWaiter)
     }
+
+    /// Waits for as many ticks as take at least `seconds` at the server's [`Engine::tick_rate`].
+    /// Ticks keep running while gameplay is paused or slowed down, so this wait does as well.
+    pub fn seconds(seconds: f32) -> Self {
+        WaitFor::Seconds(seconds, PrivateWaiter)
+    }
     
     pub fn event<T: Event>() -> Self {
         WaitFor::Event(type_to_id::<T>(), PrivateWaiter)
     }
+
+    /// Waits until `condition` holds, e.g. until a door is open.
+    /// Conditions are checked once per tick after the modules ticked, before the tasks due that tick run.
+    /// They only get to read the engine, a task waiting on one that already holds resumes on the next tick.
+    pub fn until(condition: impl Fn(&Engine) -> bool + 'static) -> Self {
+        WaitFor::Until(Box::new(condition), PrivateWaiter)
+    }
+
+    /// Waits until the entity was removed, see [`WaitFor::until`].
+    pub fn entity_removed(id: EntityId) -> Self {
+        Self::until(move |engine| !engine.entity_exists(&id))
+    }
 }
 
 pub struct Yielder<'a>(PrivateYielder, PhantomData<&'a ()>, WaitFor);
@@ -97,6 +121,15 @@ impl Engine {
     }
 
     pub(crate) fn run_tasks(&mut self) {
+        // taken out first, tasks that start waiting on a condition while running are checked from the next tick on
+        let conditions = std::mem::take(&mut self.tasks.conditions);
+        let engine: &Engine = self;
+        let (ready, waiting): (Vec<_>, Vec<_>) = conditions.into_iter().partition(|(condition, _)| condition(engine));
+        self.tasks.conditions.extend(waiting);
+        for (_, task) in ready {
+            self.run_task(task);
+        }
+
         while self.tasks.heap.peek().map(|t| t.timestamp <= self.tick).unwrap_or(false) {
             let task = self.tasks.heap.pop().unwrap();
             self.run_task(task.func);
@@ -111,6 +144,11 @@ impl Engine {
                     timestamp: { self.tick + t },
                     func: Box::from(fnpin),
                 }),
+                WaitFor::Seconds(seconds, _) => self.tasks.heap.push(Task {
+                    timestamp: self.tick + self.ticks_for(seconds),
+                    func: Box::from(fnpin),
+                }),
+                WaitFor::Until(condition, _) => self.tasks.conditions.push((condition, Box::from(fnpin))),
                 WaitFor::Event(event, _) => {
                     if let Entry::Vacant(e) = self.tasks.event_queue.entry(event) {
                         e.insert(vec![Box::from(fnpin)]);
@@ -122,4 +160,66 @@ impl Engine {
             CoroutineState::Complete(_) => (),
         }
     }
+
+    /// Whole ticks lasting at least `seconds`
+    fn ticks_for(&self, seconds: f32) -> usize {
+        // products like 0.1 * 60 end up slightly above the whole number
+        (seconds * self.tick_rate as f32 - 1e-3).ceil().max(0.0) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use crate::ecs::tests::test_engine;
+    use crate::yield_task;
+    use super::*;
+
+    /// Runs the tasks of `ticks` ticks like the server loop, calling `before_tasks` where the modules would tick.
+    fn run_ticks(engine: &mut Engine, ticks: usize, mut before_tasks: impl FnMut(&mut Engine)) {
+        for _ in 0..ticks {
+            before_tasks(engine);
+            engine.run_tasks();
+            engine.tick += 1;
+        }
+    }
+
+    #[test]
+    fn seconds_are_waited_for_in_ticks() {
+        let mut engine = test_engine();
+        engine.tick_rate = 60;
+        let resumed = Rc::new(Cell::new(None));
+        let resumed_at = resumed.clone();
+        engine.queue_task(move |mut e: &mut Engine| {
+            yield_task!(e, WaitFor::seconds(0.1));
+            resumed_at.set(Some(e.tick()));
+        });
+        run_ticks(&mut engine, 6, |_| ());
+        assert_eq!(resumed.get(), None);
+        run_ticks(&mut engine, 1, |_| ());
+        assert_eq!(resumed.get(), Some(6));
+    }
+
+    #[test]
+    fn conditions_are_checked_every_tick() {
+        let mut engine = test_engine();
+        let door = engine.new_entity();
+        let resumed = Rc::new(Cell::new(None));
+        let resumed_at = resumed.clone();
+        engine.queue_task(move |mut e: &mut Engine| {
+            yield_task!(e, WaitFor::entity_removed(door));
+            resumed_at.set(Some(e.tick()));
+            // already holds, so it resumes on the next tick instead of looping within this one
+            yield_task!(e, WaitFor::until(|_| true));
+            resumed_at.set(Some(e.tick()));
+        });
+        run_ticks(&mut engine, 4, |engine| if engine.tick() == 3 {
+            engine.remove_entity(&door);
+        });
+        assert_eq!(resumed.get(), Some(3));
+        run_ticks(&mut engine, 1, |_| ());
+        assert_eq!(resumed.get(), Some(4));
+        assert!(engine.tasks.conditions.is_empty());
+    }
 }
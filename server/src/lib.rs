@@ -14,15 +14,42 @@ mod server_runtime;
 pub mod server;
 
 pub trait ServerMod {
+    /// Mods (by `path:name` or just `name`) that have to be initialized and started before this one.
+    fn dependencies(&self) -> Vec<&str> { vec![] }
     #[allow(unused_variables)]
     fn init(&mut self, flags: &Vec<String>){}
     #[allow(unused_variables)]
     fn start(&mut self, engine: &mut Engine) {}
+
+    /// Whether the mod may be reloaded at runtime, see [`Engine::request_mod_reload`].
+    /// Everything a reloadable mod created (entities with its modules, tasks, subscriptions, client functions)
+    /// has to be removed in [`ServerMod::stop`], since its code is unloaded together with the library.
+    fn reloadable(&self) -> bool { false }
+    /// Version of the state returned by [`ServerMod::stop`]. Bump it whenever the layout of that state changes,
+    /// so a reloaded mod never receives state it can't read.
+    fn state_version(&self) -> u32 { 0 }
+    /// Called before a reloadable mod is unloaded. Returns state to pass to the new version of the mod.
+    #[allow(unused_variables)]
+    fn stop(&mut self, engine: &mut Engine) -> Vec<u8> { vec![] }
+    /// Called after [`ServerMod::start`] of a reloaded mod with the state its previous version returned from
+    /// [`ServerMod::stop`], if both report the same [`ServerMod::state_version`].
+    #[allow(unused_variables)]
+    fn restore(&mut self, engine: &mut Engine, state: &[u8]) {}
+    /// Called once when the server shuts down, after the last tick and after all clients were kicked.
+    /// The place to save state to disk.
+    #[allow(unused_variables)]
+    fn shutdown(&mut self, engine: &mut Engine) {}
 }
 
 pub struct ServerModBox {
+    // has to be dropped before the library it was loaded from
     server_mod: Box<dyn ServerMod>,
-    _library: Library
+    _library: Library,
+    pub(crate) name_path: String,
+    /// directory below `runtime/` the library was extracted to
+    pub(crate) runtime_path: String,
+    pub(crate) flags: Vec<String>,
+    pub(crate) modified: Option<std::time::SystemTime>
 }
 
 impl ServerModBox {
@@ -30,6 +57,10 @@ impl ServerModBox {
         Self {
             server_mod,
             _library: library,
+            name_path: String::new(),
+            runtime_path: String::new(),
+            flags: vec![],
+            modified: None
         }
     }
 }
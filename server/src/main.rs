@@ -5,7 +5,7 @@ use server::server;
 
 fn main() {
 	aeonetica_engine::enable_ansi_support::enable_ansi_support().unwrap_or_else(|_| eprintln!("ansi not supported in this console"));
-    // cargo run -- 0.0.0.0:6090
+    // cargo run -- 0.0.0.0:6090 [tick rate]
     let mut args: Vec<_> = std::env::args().skip(1).collect();
     log!("started server with args {args:?}");
     if args.is_empty() {
@@ -13,5 +13,6 @@ fn main() {
         //let e = AError::new(AET::ValueError(format!("expected command line arg ip:port>, got {}", args.len())));
         //e.log_exit();
     }
-    server::run(&args[0]);
+    let tick_rate = server::parse_tick_rate(args.get(1).map(String::as_str)).unwrap_or_else(|e| e.log_exit());
+    server::run_with_tick_rate(&args[0], tick_rate);
 }
\ No newline at end of file
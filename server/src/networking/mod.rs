@@ -1,51 +1,151 @@
 
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::io::{Read, Write};
-use std::net::{SocketAddr, TcpListener, UdpSocket};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::rc::Rc;
 
-use std::sync::{Arc, Mutex};
-use std::thread;
-use std::time::Instant;
+use std::sync::{mpsc, Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 use aeonetica_engine::error::{Error, Fatality, ErrorResult};
 use aeonetica_engine::error::builtin::NetworkError;
-use aeonetica_engine::{Id, log};
+use aeonetica_engine::{ClientId, Id, log, MAX_CLIENT_TIMEOUT};
 use aeonetica_engine::nanoserde::{SerBin, DeBin};
 use aeonetica_engine::networking::{MAX_PACKET_SIZE, SendMode};
-use aeonetica_engine::networking::client_packets::ClientPacket;
-use aeonetica_engine::networking::server_packets::ServerPacket;
+use aeonetica_engine::networking::datagram::{DatagramReceiver, DatagramSender, DEFAULT_FRAGMENT_TIMEOUT};
+use aeonetica_engine::networking::client_packets::{ClientMessage, ClientPacket};
+use aeonetica_engine::networking::server_packets::{ServerMessage, ServerPacket};
+use aeonetica_engine::networking::stats::{NetworkStats, NetworkStatsSnapshot};
 use aeonetica_engine::util::id_map::IdMap;
+use send_queue::{Priority, SendQueue};
 
 mod protocol;
+mod send_queue;
 
 pub(crate) struct NetworkServer {
     pub(crate) udp: UdpSocket,
     pub(crate) received: Arc<Mutex<Vec<(SocketAddr, ClientPacket)>>>,
     pub(crate) clients: IdMap<ClientHandle>,
-    pub(crate) tcp: Arc<Mutex<HashMap<SocketAddr, Arc<Mutex<Vec<Vec<u8>>>>>>>
+    /// writer of every tcp connection, dropping it closes the connection once everything sent is written
+    pub(crate) tcp: Arc<Mutex<HashMap<SocketAddr, mpsc::Sender<Vec<u8>>>>>,
+    pub(crate) datagrams: Mutex<HashMap<SocketAddr, DatagramSender>>,
+    /// reassembly and ordering state of every peer sending datagrams with the time of its last datagram, used by the udp thread
+    receivers: DatagramReceivers,
+    /// packets waiting for [`NetworkServer::flush`], by address
+    queues: Mutex<HashMap<SocketAddr, SendQueue>>,
+    stats: Arc<ServerStats>,
+    /// cleared by [`NetworkServer::shutdown`] to stop all network threads
+    running: Arc<AtomicBool>,
+    /// udp receiver and tcp listener
+    threads: Vec<JoinHandle<()>>,
+    /// one per tcp connection, joined on shutdown so queued packets are flushed
+    writers: Arc<Mutex<Vec<JoinHandle<()>>>>,
+    /// reading thread of every tcp connection with its stream, shut down after the writers are done
+    readers: TcpReaders
+}
+
+/// How often blocked network threads check whether the server is shutting down
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Bytes sent to one client per [`NetworkServer::flush`], the rest stays queued for the next tick
+pub(crate) const SEND_BUDGET_PER_TICK: usize = 256 * 1024;
+
+/// Time without datagrams from a peer after which its reassembly and ordering state is dropped.
+/// Connected clients send keep alive datagrams more often than that.
+pub(crate) const DATAGRAM_STATE_TIMEOUT: Duration = Duration::from_millis(MAX_CLIENT_TIMEOUT as u64);
+
+/// Milliseconds after which partially received udp messages are discarded, [`DEFAULT_FRAGMENT_TIMEOUT`] if not set.
+pub(crate) const FRAGMENT_TIMEOUT_ENVIRONMENT_VAR: &str = "AEONETICA_FRAGMENT_TIMEOUT_MS";
+
+type ServerStats = NetworkStats<ServerMessage, ClientMessage>;
+type TcpReaders = Arc<Mutex<Vec<(TcpStream, JoinHandle<()>)>>>;
+type DatagramReceivers = Arc<Mutex<HashMap<SocketAddr, (Instant, DatagramReceiver)>>>;
+
+/// Drops the datagram state of every peer that sent nothing for `timeout`,
+/// the udp source address of a client is not known when it disconnects.
+fn expire_receivers(receivers: &mut HashMap<SocketAddr, (Instant, DatagramReceiver)>, timeout: Duration) {
+    receivers.retain(|_, (last_received, _)| last_received.elapsed() < timeout);
+}
+
+fn fragment_timeout() -> Duration {
+    let Ok(value) = std::env::var(FRAGMENT_TIMEOUT_ENVIRONMENT_VAR) else {
+        return DEFAULT_FRAGMENT_TIMEOUT
+    };
+    match value.parse::<u64>() {
+        Ok(ms) if ms > 0 => Duration::from_millis(ms),
+        _ => {
+            log!(WARN, "invalid {FRAGMENT_TIMEOUT_ENVIRONMENT_VAR} {value:?}, using {DEFAULT_FRAGMENT_TIMEOUT:?}");
+            DEFAULT_FRAGMENT_TIMEOUT
+        }
+    }
 }
 
 pub(crate) struct ClientHandle {
     pub(crate) last_seen: Instant,
     pub(crate) client_addr: SocketAddr,
+    /// replies the server is waiting for from this client, by `conv_id`
+    pub(crate) awaiting_replies: IdMap<AwaitingReply>
+}
+
+/// Filled with the reply data or an error once the reply arrived or timed out.
+pub(crate) type ReplySlot = Rc<RefCell<Option<ErrorResult<Vec<u8>>>>>;
+
+pub(crate) struct AwaitingReply {
+    pub(crate) deadline: Instant,
+    pub(crate) slot: ReplySlot
 }
 
 impl NetworkServer {
     pub(crate) fn start(addr: &str) -> ErrorResult<Self>{
         let socket = UdpSocket::bind(addr)?;
         let sock = socket.try_clone()?;
+        sock.set_read_timeout(Some(SHUTDOWN_POLL_INTERVAL))?;
         let received = Arc::new(Mutex::new(vec![]));
         let tcp_sockets = Arc::new(Mutex::new(HashMap::new()));
         let recv = received.clone();
         let recv_tcp = received.clone();
         let tcp = tcp_sockets.clone();
-        std::thread::spawn(move || {
+        let stats = Arc::new(ServerStats::default());
+        let (udp_stats, tcp_stats) = (stats.clone(), stats.clone());
+        let running = Arc::new(AtomicBool::new(true));
+        let (udp_running, tcp_running) = (running.clone(), running.clone());
+        let writers: Arc<Mutex<Vec<JoinHandle<()>>>> = Default::default();
+        let tcp_writers = writers.clone();
+        let readers: TcpReaders = Default::default();
+        let tcp_readers = readers.clone();
+        let receivers: DatagramReceivers = Default::default();
+        let udp_receivers = receivers.clone();
+        let fragment_timeout = fragment_timeout();
+        let udp_thread = std::thread::spawn(move || {
             let mut buf = [0u8; MAX_PACKET_SIZE];
-            loop {
+            let mut last_expiry = Instant::now();
+            while udp_running.load(Ordering::Relaxed) {
+                if last_expiry.elapsed() >= SHUTDOWN_POLL_INTERVAL {
+                    expire_receivers(&mut udp_receivers.lock().unwrap(), DATAGRAM_STATE_TIMEOUT);
+                    last_expiry = Instant::now();
+                }
                 match sock.recv_from(&mut buf) {
                     Ok((len, src)) => {
-                        match DeBin::deserialize_bin(&buf[..len]) {
-                            Ok(packet) => recv.lock().unwrap().push((src, packet)),
-                            Err(e) => log!(ERROR, "invalid client packet from {src}: {e}")
+                        let packets = {
+                            let mut receivers = udp_receivers.lock().unwrap();
+                            let (last_received, receiver) = receivers.entry(src).or_insert_with(|| (Instant::now(), DatagramReceiver::new(fragment_timeout)));
+                            *last_received = Instant::now();
+                            receiver.receive(&buf[..len])
+                        };
+                        let Some(packets) = packets else {
+                            log!(ERROR, "invalid datagram from {src}");
+                            continue
+                        };
+                        for (data, mode) in packets {
+                            match ClientPacket::deserialize_bin(&data[..]) {
+                                Ok(packet) => {
+                                    udp_stats.received.record(&packet.message, mode, data.len());
+                                    // queued right on the receiving thread, so packets keep their arrival order
+                                    recv.lock().unwrap().push((src, packet))
+                                }
+                                Err(e) => log!(ERROR, "invalid client packet from {src}: {e}")
+                            }
                         }
                     },
                     Err(_e) => {}
@@ -53,16 +153,27 @@ impl NetworkServer {
             }
         });
         let listener = TcpListener::bind(addr)?;
-        listener.set_nonblocking(false).unwrap();
-        std::thread::spawn(move || {
-            for mut stream in listener.incoming().flatten() {
+        // polled, so the listener notices when the server shuts down
+        listener.set_nonblocking(true)?;
+        let tcp_thread = std::thread::spawn(move || {
+            while tcp_running.load(Ordering::Relaxed) {
+                let mut stream = match listener.accept() {
+                    Ok((stream, _)) => stream,
+                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                        thread::sleep(SHUTDOWN_POLL_INTERVAL);
+                        continue
+                    }
+                    Err(_) => continue
+                };
                 stream.set_nonblocking(false).unwrap();
                 let addr = stream.peer_addr().unwrap();
-                let queue = Arc::new(Mutex::new(vec![]));
-                tcp.lock().unwrap().insert(addr, queue.clone());
+                let (writer, outgoing) = mpsc::channel::<Vec<u8>>();
+                tcp.lock().unwrap().insert(addr, writer);
                 let recv_tcp_inner = recv_tcp.clone();
+                let stats = tcp_stats.clone();
                 let mut write_stream = stream.try_clone().unwrap();
-                thread::spawn(move || {
+                let read_stream = stream.try_clone().unwrap();
+                let reader = thread::spawn(move || {
                     loop {
                         let r = (||{
                             let mut size = [0u8; 4];
@@ -70,8 +181,11 @@ impl NetworkServer {
                             let size = u32::from_le_bytes(size);
                             let mut buffer: Vec<u8> = vec![0; size as usize];
                             stream.read_exact(&mut buffer[..])?;
-                            match DeBin::deserialize_bin(&buffer[..]) {
-                                Ok(packet) => recv_tcp_inner.lock().unwrap().push((addr, packet)),
+                            match ClientPacket::deserialize_bin(&buffer[..]) {
+                                Ok(packet) => {
+                                    stats.received.record(&packet.message, SendMode::Safe, buffer.len());
+                                    recv_tcp_inner.lock().unwrap().push((addr, packet))
+                                }
                                 Err(e) => log!(ERROR, "invalid client packet from {addr}: {e}")
                             }
                             Ok::<_, std::io::Error>(())
@@ -80,31 +194,66 @@ impl NetworkServer {
                     }
                     log!("terminated tcp connection with {}", addr)
                 });
-                thread::spawn(move || {
-                    loop {
-                        let mut queued: Vec<Vec<u8>> = vec![];
-                        {
-                            let mut q = queue.lock().unwrap();
-                            std::mem::swap(&mut queued, &mut q);
-                        }
-                        if queued.len() > 0 {
-                            for msg in queued {
-                                write_stream.write(&(msg.len() as u32).to_le_bytes()).unwrap();
-                                write_stream.write(&msg[..]).unwrap();
-                            }
-                        }
+                let mut readers = tcp_readers.lock().unwrap();
+                readers.retain(|(_, thread)| !thread.is_finished());
+                readers.push((read_stream, reader));
+                let mut writers = tcp_writers.lock().unwrap();
+                writers.retain(|thread| !thread.is_finished());
+                writers.push(thread::spawn(move || {
+                    // ends once the server dropped the sender, after writing everything sent before
+                    for msg in outgoing {
+                        let written = write_stream.write_all(&(msg.len() as u32).to_le_bytes())
+                            .and_then(|_| write_stream.write_all(&msg[..]));
+                        if written.is_err() { break }
                     }
-                });
+                    // the client reads everything sent so far before the connection closes
+                    let _ = write_stream.shutdown(Shutdown::Write);
+                }));
             }
         });
         Ok(Self {
             udp: socket,
             received,
             clients: Default::default(),
-            tcp: tcp_sockets
+            tcp: tcp_sockets,
+            datagrams: Default::default(),
+            receivers,
+            queues: Default::default(),
+            stats,
+            running,
+            threads: vec![udp_thread, tcp_thread],
+            writers,
+            readers
         })
     }
 
+    /// Stops accepting and receiving packets, flushes all packets queued for tcp connections and closes them.
+    /// Blocks until all network threads have stopped.
+    pub(crate) fn shutdown(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        // the listener has to stop first, so no writer is started after they are joined
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+        self.receivers.lock().unwrap().clear();
+        self.flush_with_budget(usize::MAX);
+        self.tcp.lock().unwrap().clear();
+        for writer in std::mem::take(&mut *self.writers.lock().unwrap()) {
+            let _ = writer.join();
+        }
+        // clients might keep their side open, which would block the readers forever
+        for (stream, reader) in std::mem::take(&mut *self.readers.lock().unwrap()) {
+            let _ = stream.shutdown(Shutdown::Both);
+            let _ = reader.join();
+        }
+        log!("stopped network threads");
+    }
+
+    /// Packets and bytes sent to and received from all clients since the server started.
+    pub(crate) fn stats(&self) -> NetworkStatsSnapshot {
+        self.stats.snapshot()
+    }
+
     pub(crate) fn queued_packets(&mut self) -> Vec<(SocketAddr, ClientPacket)> {
         std::mem::replace(&mut self.received.lock().unwrap() as &mut Vec<(SocketAddr, ClientPacket)>, vec![])
     }
@@ -117,23 +266,165 @@ impl NetworkServer {
         Ok(())
     }
 
+    /// Queues the packet, it is sent by the next [`NetworkServer::flush`] as its [`Priority`] allows.
     pub(crate) fn send_raw(&self, ip_addr: SocketAddr, packet: &ServerPacket, mode: SendMode) -> ErrorResult<()>{
         let data = SerBin::serialize_bin(packet);
+        self.stats.sent.record(&packet.message, mode, data.len());
+        self.queues.lock().unwrap().entry(ip_addr).or_default().push(Priority::of(&packet.message), data, mode);
+        Ok(())
+    }
+
+    /// Sends the queued packets of every client, ordered by [`Priority`] and up to [`SEND_BUDGET_PER_TICK`] bytes each.
+    pub(crate) fn flush(&self) {
+        self.flush_with_budget(SEND_BUDGET_PER_TICK)
+    }
+
+    fn flush_with_budget(&self, budget: usize) {
+        let mut queues = self.queues.lock().unwrap();
+        for (addr, queue) in queues.iter_mut() {
+            for (data, mode) in queue.take(budget) {
+                self.send_now(*addr, data, mode);
+            }
+        }
+        queues.retain(|_, queue| !queue.is_empty());
+    }
+
+    /// Sends everything still queued for `addr`, closes its tcp connection and forgets its outgoing datagram state.
+    /// The state of datagrams received from the client expires after [`DATAGRAM_STATE_TIMEOUT`].
+    pub(crate) fn disconnect(&self, addr: &SocketAddr) {
+        let queue = self.queues.lock().unwrap().remove(addr);
+        for (data, mode) in queue.map(|mut queue| queue.take(usize::MAX)).unwrap_or_default() {
+            self.send_now(*addr, data, mode);
+        }
+        self.tcp.lock().unwrap().remove(addr);
+        self.datagrams.lock().unwrap().remove(addr);
+    }
+
+    /// Number of packets queued for the client, `0` for unknown clients.
+    pub(crate) fn queue_depth(&self, client_id: &ClientId) -> usize {
+        self.clients.get(client_id)
+            .and_then(|client| self.queues.lock().unwrap().get(&client.client_addr).map(SendQueue::len))
+            .unwrap_or(0)
+    }
+
+    fn send_now(&self, addr: SocketAddr, data: Vec<u8>, mode: SendMode) {
         match mode {
-            SendMode::Quick => {
-                if data.len() > MAX_PACKET_SIZE {
-                    return Err(Error::new(NetworkError(format!("Packet is too large: {} > {}", data.len(), MAX_PACKET_SIZE)), Fatality::WARN, false))
+            SendMode::Quick | SendMode::Ordered => {
+                let datagrams = self.datagrams.lock().unwrap().entry(addr).or_default().wrap(&data, matches!(mode, SendMode::Ordered));
+                for datagram in datagrams {
+                    let _ = self.udp.send_to(&datagram[..], addr);
                 }
-                let sock = self.udp.try_clone()?;
-                std::thread::spawn(move || sock.send_to(&data[..], ip_addr));
             }
             SendMode::Safe => {
-                if let Some(tcp_queue) = self.tcp.lock().unwrap().get_mut(&ip_addr) {
-                    let mut queue = tcp_queue.lock().unwrap();
-                    queue.push(data);
+                // the writer is gone once the connection was closed
+                if let Some(writer) = self.tcp.lock().unwrap().get(&addr) {
+                    let _ = writer.send(data);
                 }
             }
         }
-        Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reliable_packets_arrive_complete_and_in_order() {
+        const PACKETS: u32 = 2000;
+        let port = UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let mut server = NetworkServer::start(&format!("127.0.0.1:{port}")).unwrap();
+        let mut clients = (0..4).map(|_| (Id::new(), TcpStream::connect(("127.0.0.1", port)).unwrap())).collect::<Vec<_>>();
+
+        // the clients' packets interleave on the server
+        for i in 0..PACKETS {
+            for (client_id, stream) in &mut clients {
+                let data = SerBin::serialize_bin(&ClientPacket { client_id: *client_id, conv_id: Id::new(), message: ClientMessage::Pong(i.to_string()) });
+                stream.write_all(&(data.len() as u32).to_le_bytes()).unwrap();
+                stream.write_all(&data).unwrap();
+            }
+        }
+
+        let mut received: HashMap<Id, Vec<u32>> = HashMap::new();
+        let started = Instant::now();
+        while received.values().map(Vec::len).sum::<usize>() < PACKETS as usize * clients.len() {
+            assert!(started.elapsed() < Duration::from_secs(10), "packets were dropped");
+            for (_, packet) in server.queued_packets() {
+                let ClientMessage::Pong(i) = packet.message else { panic!("unexpected packet {:?}", packet.message) };
+                received.entry(packet.client_id).or_default().push(i.parse().unwrap());
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        for (client_id, _) in &clients {
+            assert_eq!(received[client_id], (0..PACKETS).collect::<Vec<_>>());
+        }
+        server.shutdown();
+    }
+
+    #[test]
+    fn idle_datagram_state_expires() {
+        let mut server = NetworkServer::start("127.0.0.1:0").unwrap();
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let data = SerBin::serialize_bin(&ClientPacket { client_id: Id::new(), conv_id: Id::new(), message: ClientMessage::KeepAlive });
+        let datagram = DatagramSender::default().wrap(&data, false).remove(0);
+        client.send_to(&datagram, server.udp.local_addr().unwrap()).unwrap();
+
+        let addr = client.local_addr().unwrap();
+        let started = Instant::now();
+        while !server.receivers.lock().unwrap().contains_key(&addr) {
+            assert!(started.elapsed() < Duration::from_secs(10), "datagram was not received");
+            thread::sleep(Duration::from_millis(1));
+        }
+        expire_receivers(&mut server.receivers.lock().unwrap(), DATAGRAM_STATE_TIMEOUT);
+        assert!(server.receivers.lock().unwrap().contains_key(&addr));
+        expire_receivers(&mut server.receivers.lock().unwrap(), Duration::ZERO);
+        assert!(server.receivers.lock().unwrap().is_empty());
+        server.shutdown();
+    }
+
+    #[test]
+    fn small_safe_messages_do_not_overtake_large_ones() {
+        let port = UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let mut server = NetworkServer::start(&format!("127.0.0.1:{port}")).unwrap();
+        let mut client = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let addr = client.local_addr().unwrap();
+        let started = Instant::now();
+        while !server.tcp.lock().unwrap().contains_key(&addr) {
+            assert!(started.elapsed() < Duration::from_secs(10), "connection was not accepted");
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        let id = Id::new();
+        for data in [vec![1; send_queue::BULK_MESSAGE_SIZE * 4], vec![2; 8]] {
+            server.send_raw(addr, &ServerPacket { conv_id: Id::new(), message: ServerMessage::ModMessage(id, id, data) }, SendMode::Safe).unwrap();
+        }
+        server.flush();
+
+        for expected in [1, 2] {
+            let mut size = [0u8; 4];
+            client.read_exact(&mut size).unwrap();
+            let mut data = vec![0; u32::from_le_bytes(size) as usize];
+            client.read_exact(&mut data).unwrap();
+            let ServerMessage::ModMessage(_, _, data) = ServerPacket::deserialize_bin(&data).unwrap().message else { panic!("unexpected packet") };
+            assert_eq!(data[0], expected);
+        }
+        server.shutdown();
+    }
+
+    #[test]
+    fn shutdown_closes_connections_clients_keep_open() {
+        let port = UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let mut server = NetworkServer::start(&format!("127.0.0.1:{port}")).unwrap();
+        let mut client = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let started = Instant::now();
+        while server.readers.lock().unwrap().is_empty() {
+            assert!(started.elapsed() < Duration::from_secs(10), "connection was not accepted");
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        // returns although the client never closes its side
+        server.shutdown();
+        assert!(server.readers.lock().unwrap().is_empty());
+        assert_eq!(client.read(&mut [0u8; 1]).unwrap(), 0);
+    }
+}
@@ -1,17 +1,18 @@
 use std::fs::File;
 use std::io::{Read, Seek, SeekFrom};
 use std::net::SocketAddr;
+use std::time::{Duration, Instant};
 
-use aeonetica_engine::error::ErrorResult;
+use aeonetica_engine::error::{Error, ErrorResult, Fatality};
+use aeonetica_engine::error::builtin::NetworkError;
 use aeonetica_engine::networking::client_packets::{ClientMessage, ClientPacket};
 use aeonetica_engine::networking::server_packets::{ServerInfo, ServerMessage, ServerPacket};
 use aeonetica_engine::{ENGINE_VERSION, MAX_CLIENT_TIMEOUT};
-use aeonetica_engine::{log, Id};
-use aeonetica_engine::networking::{MOD_DOWNLOAD_CHUNK_SIZE, NetResult, SendMode};
+use aeonetica_engine::{log, ClientId, Id};
+use aeonetica_engine::networking::{mod_profile_hash, MOD_DOWNLOAD_CHUNK_SIZE, NetResult, SendMode};
 use aeonetica_engine::sha2;
 use aeonetica_engine::sha2::Digest;
 use crate::ecs::Engine;
-use crate::ecs::events::ConnectionListener;
 use crate::ecs::messaging::Messenger;
 use crate::networking::ClientHandle;
 use crate::server_runtime::mod_client_zip;
@@ -31,26 +32,46 @@ impl Engine {
     }
 
     pub(crate) fn timeout_inactive(&mut self) {
-        let mut_self_ref_ptr = self as *mut Self;
-        let clients = self.runtime.ns.borrow().clients.keys().cloned().collect::<Vec<_>>();
-        for id in clients {
-            let mut_self_ref = unsafe { &mut *mut_self_ref_ptr };
-            if self.runtime.ns.borrow().clients.get(&id).map(|client| {
-                if client.last_seen.elapsed().as_millis() < MAX_CLIENT_TIMEOUT {
-                    false
-                } else {
-                    mut_self_ref.kick_client(&id, "TIMEOUT");
-                    let _ = mut_self_ref.runtime.ns.borrow().send(&id, &ServerPacket {
-                        conv_id: Id::new(),
-                        message: ServerMessage::Unregister("TIMEOUT".to_string()),
-                    }, SendMode::Safe);
-                    log!("timed out client ip {}", client.client_addr);
-                    true
+        self.prune_inactive(Duration::from_millis(MAX_CLIENT_TIMEOUT as u64));
+        self.timeout_replies(Instant::now());
+    }
+
+    /// Fails all awaited replies whose deadline passed before `now` and forgets them.
+    pub(crate) fn timeout_replies(&mut self, now: Instant) {
+        for (id, client) in self.runtime.ns.borrow_mut().clients.iter_mut() {
+            client.awaiting_replies.retain(|_, awaiting| {
+                if awaiting.deadline > now {
+                    return true
                 }
-            }).unwrap_or(true) {
-                mut_self_ref.runtime.ns.borrow_mut().clients.remove(&id);
+                awaiting.slot.replace(Some(Err(Error::new(NetworkError(format!("client {id} did not reply in time")), Fatality::DEFAULT, false))));
+                false
+            });
+        }
+    }
+
+    /// Drops all clients that have not sent any packet within `timeout`.
+    /// Logged in clients are kicked, which runs the `ConnectionListener` leave callbacks,
+    /// and every client is removed from all messengers and forgotten by the network server.
+    /// Returns the ids of the pruned clients.
+    pub(crate) fn prune_inactive(&mut self, timeout: Duration) -> Vec<ClientId> {
+        let stale = self.runtime.ns.borrow().clients.iter()
+            .filter(|(_, client)| client.last_seen.elapsed() >= timeout)
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        for id in &stale {
+            self.kick_client(id, "TIMEOUT");
+            let _ = self.runtime.ns.borrow().send(id, &ServerPacket {
+                conv_id: Id::new(),
+                message: ServerMessage::Unregister("TIMEOUT".to_string()),
+            }, SendMode::Safe);
+            self.for_each_module_of_type::<Messenger, _>(|_, _, messenger| { messenger.receivers.remove(id); });
+            let mut ns = self.runtime.ns.borrow_mut();
+            if let Some(client) = ns.clients.remove(id) {
+                ns.disconnect(&client.client_addr);
+                log!("timed out client ip {}", client.client_addr);
             }
         }
+        stale
     }
 
     pub(crate) fn handle_packet(&mut self, addr: &SocketAddr, packet: &ClientPacket) -> ErrorResult<()> {
@@ -70,7 +91,8 @@ impl Engine {
                     } else {
                         self.runtime.ns.borrow_mut().clients.insert(packet.client_id, ClientHandle {
                             last_seen: std::time::Instant::now(),
-                            client_addr: *addr
+                            client_addr: *addr,
+                            awaiting_replies: Default::default()
                         });
                         self.runtime.ns.borrow().send(&packet.client_id, &ServerPacket{
                             conv_id: packet.conv_id,
@@ -117,33 +139,235 @@ impl Engine {
                     message: ServerMessage::RawData(buffer[..len].to_vec())
                 }, SendMode::Safe)?;
             },
-            ClientMessage::Login => {
+            ClientMessage::Login(login) => {
+                let profile = &self.runtime.mod_profile;
+                // a restarted server may have other mods than the ones the reconnecting client loaded
+                if let Some(mismatch) = login.mismatch(ENGINE_VERSION, &mod_profile_hash(&profile.profile, &profile.version, &profile.modstack)) {
+                    log!(WARN, "refused login of client {}: {mismatch}", packet.client_id);
+                    self.runtime.ns.borrow().send_raw(*addr, &ServerPacket {
+                        conv_id: packet.conv_id,
+                        message: ServerMessage::Kick(mismatch)
+                    }, SendMode::Safe)?;
+                    return Ok(())
+                }
+                // a client logging in again after reconnecting uses a new tcp connection
+                if let Some(client) = self.runtime.ns.borrow_mut().clients.get_mut(&packet.client_id) {
+                    client.client_addr = *addr;
+                }
                 if !self.clients.contains(&packet.client_id) {
                     log!("client logged in: {}", packet.client_id);
                     self.clients.insert(packet.client_id);
-                    self.for_each_module_of_type::<ConnectionListener, _>(|engine, id, m| (m.on_join)(id, engine, &packet.client_id))
+                    self.fire_join(&packet.client_id)
                 }
+                // reconnecting clients may have missed changes
+                self.send_time_scale(&packet.client_id);
             }
             ClientMessage::Logout => {
                 if self.clients.contains(&packet.client_id) {
                     log!("client logged out: {}", packet.client_id);
-                    self.for_each_module_of_type::<ConnectionListener, _>(|engine, id, m| (m.on_leave)(id, engine, &packet.client_id));
+                    self.fire_leave(&packet.client_id);
                     self.clients.remove(&packet.client_id);
                     let mut ns = self.runtime.ns.borrow_mut();
                     ns.clients.remove(&packet.client_id);
-                    ns.tcp.lock().unwrap().remove(addr);
+                    ns.disconnect(addr);
                 }
             }
             ClientMessage::ModMessage(eid, rid, data) => {
                 let mut_engine_ref = unsafe { &mut *(self as *mut Self) };
-                if let Some(m) = self.get_module_of::<Messenger>(eid).ref_option() {
-                    if let Some(f) = m.receiver_functions.get(rid) {
-                        f(eid, mut_engine_ref, &packet.client_id, data)
-                    }
+                let Some(m) = self.get_module_of::<Messenger>(eid).option() else {
+                    return Err(Error::new(NetworkError(format!("client {} called a server fn of entity {eid}, which has no messenger", packet.client_id)), Fatality::WARN, false))
+                };
+                let Some(f) = m.receiver_functions.get(rid) else {
+                    return Err(Error::new(NetworkError(format!("client {} called server fn {rid} of entity {eid}, which is not a registered receiver", packet.client_id)), Fatality::WARN, false))
+                };
+                f(eid, mut_engine_ref, &packet.client_id, data)
+            }
+            ClientMessage::ModReply(data) => {
+                // late replies to calls that already timed out are dropped
+                let awaiting = self.runtime.ns.borrow_mut().clients.get_mut(&packet.client_id)
+                    .and_then(|client| client.awaiting_replies.remove(&packet.conv_id));
+                if let Some(awaiting) = awaiting {
+                    awaiting.slot.replace(Some(Ok(data.clone())));
                 }
             }
             _ => ()
         }
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::io::Read;
+    use std::net::{TcpStream, UdpSocket};
+    use std::rc::Rc;
+    use std::time::{Duration, Instant};
+    use aeonetica_engine::{ClientId, Id, TypeId};
+    use aeonetica_engine::nanoserde::{DeBin, SerBin};
+    use aeonetica_engine::networking::server_packets::{ServerMessage, ServerPacket};
+    use aeonetica_engine::util::type_to_id;
+    use aeonetica_engine::networking::SendMode;
+    use aeonetica_engine::networking::client_packets::{ClientMessage, ClientPacket};
+    use aeonetica_engine::util::nullable::Nullable;
+    use crate::ecs::Engine;
+    use crate::ecs::events::ConnectionListener;
+    use crate::ecs::messaging::{self, Messenger};
+    use crate::ecs::tests::test_engine;
+    use crate::networking::{ClientHandle, NetworkServer};
+    use crate::server_runtime::SHUTDOWN_REASON;
+
+    const LEFT: &str = "LEFT";
+
+    struct TestHandle;
+
+    impl aeonetica_engine::networking::messaging::ClientEntity for TestHandle {}
+
+    struct TestMessenger;
+    unsafe impl messaging::ClientMessenger for TestMessenger {}
+    struct TestRenderer;
+    unsafe impl messaging::Renderer for TestRenderer {}
+    struct TestStore;
+    unsafe impl messaging::DataStore for TestStore {}
+
+    impl TestHandle {
+        fn confirm(&mut self, _: &mut TestMessenger, _: Nullable<&mut TestRenderer>, _: &mut TestStore, amount: u32) -> bool {
+            amount < 10
+        }
+    }
+
+    #[test]
+    fn prune_inactive_clients() {
+        let mut engine = test_engine();
+        let listener = engine.new_entity();
+        engine.mut_entity(&listener).add_module(ConnectionListener::new(
+            |_, _, _| {},
+            |id, engine, _| { engine.tag_entity(*id, LEFT); }
+        ));
+        engine.mut_entity(&listener).add_module(Messenger::new::<TestHandle>());
+
+        let now = Instant::now();
+        // an instant a minute ago doesn't exist shortly after the system booted
+        let Some(minute_ago) = now.checked_sub(Duration::from_secs(60)) else {
+            return
+        };
+        let stale = aeonetica_engine::Id::new();
+        let active = aeonetica_engine::Id::new();
+        for (id, last_seen, port) in [(stale, minute_ago, 1), (active, now, 2)] {
+            engine.runtime.ns.borrow_mut().clients.insert(id, ClientHandle {
+                last_seen,
+                client_addr: ([127, 0, 0, 1], port).into(),
+                awaiting_replies: Default::default()
+            });
+            engine.clients.insert(id);
+            engine.fire_join(&id);
+            engine.mut_module_of::<Messenger>(&listener).receivers.insert(id);
+        }
+
+        assert_eq!(engine.prune_inactive(Duration::from_secs(10)), vec![stale]);
+        assert!(!engine.is_client_logged_in(&stale));
+        assert!(engine.is_client_logged_in(&active));
+        assert!(!engine.runtime.ns.borrow().clients.contains_key(&stale));
+        assert!(!engine.get_module_of::<Messenger>(&listener).has_client(&stale));
+        assert!(engine.get_module_of::<Messenger>(&listener).has_client(&active));
+        assert!(engine.tag_exists(LEFT));
+    }
+
+    #[test]
+    fn awaited_replies_resolve_or_fail() {
+        let mut engine = test_engine();
+        let entity = engine.new_entity();
+        engine.mut_entity(&entity).add_module(Messenger::new::<TestHandle>());
+        let client = Id::new();
+        let addr = ([127, 0, 0, 1], 1).into();
+        engine.runtime.ns.borrow_mut().clients.insert(client, ClientHandle {
+            last_seen: Instant::now(),
+            client_addr: addr,
+            awaiting_replies: Default::default()
+        });
+        let call = |engine: &mut Engine, timeout: Duration| engine.mut_module_of::<Messenger>(&entity)
+            .call_client_fn_await(TestHandle::confirm, &client, 5, SendMode::Safe, timeout);
+        let awaiting = |engine: &Engine| engine.runtime.ns.borrow().clients.get(&client)
+            .map(|client| client.awaiting_replies.keys().copied().collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        let mut reply = call(&mut engine, Duration::from_secs(10));
+        assert!(!reply.is_ready());
+        let conv_id = awaiting(&engine)[0];
+        engine.handle_packet(&addr, &ClientPacket { client_id: client, conv_id, message: ClientMessage::ModReply(true.serialize_bin()) }).unwrap();
+        assert!(reply.try_take().unwrap().unwrap());
+        assert!(reply.try_take().is_none());
+        assert!(awaiting(&engine).is_empty());
+
+        let mut timed_out = call(&mut engine, Duration::from_secs(1));
+        engine.timeout_replies(Instant::now() + Duration::from_secs(2));
+        assert!(timed_out.try_take().unwrap().is_err());
+        assert!(awaiting(&engine).is_empty());
+
+        let mut disconnected = call(&mut engine, Duration::from_secs(10));
+        engine.runtime.ns.borrow_mut().clients.remove(&client);
+        assert!(disconnected.try_take().unwrap().is_err());
+        assert!(call(&mut engine, Duration::from_secs(10)).try_take().unwrap().is_err());
+    }
+
+    fn tag_caller(id: &Id, engine: &mut Engine, _: &ClientId, tag: String) {
+        engine.tag_entity(*id, tag);
+    }
+
+    #[test]
+    fn mod_messages_need_registered_receivers() {
+        let mut engine = test_engine();
+        let entity = engine.new_entity();
+        engine.mut_entity(&entity).add_module(Messenger::new::<TestHandle>());
+        engine.mut_module_of::<Messenger>(&entity).register_receiver(tag_caller);
+        let addr = ([127, 0, 0, 1], 1).into();
+        let call = |eid, rid| ClientPacket { client_id: Id::new(), conv_id: Id::new(), message: ClientMessage::ModMessage(eid, rid, "CALLED".to_string().serialize_bin()) };
+
+        engine.handle_packet(&addr, &call(entity, type_to_id::<fn(&Id, &mut Engine, &ClientId, String)>())).unwrap_err();
+        engine.handle_packet(&addr, &call(Id::new(), type_to_id_of(tag_caller))).unwrap_err();
+        assert!(!engine.tag_exists("CALLED"));
+        engine.handle_packet(&addr, &call(entity, type_to_id_of(tag_caller))).unwrap();
+        assert!(engine.tag_exists("CALLED"));
+    }
+
+    fn type_to_id_of<F>(_: F) -> TypeId {
+        type_to_id::<F>()
+    }
+
+    #[test]
+    fn clients_receive_kick_before_shutdown() {
+        let mut engine = test_engine();
+        // udp and tcp have to share a port, as in a real server
+        let port = UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let addr = format!("127.0.0.1:{port}");
+        engine.runtime.ns = Rc::new(RefCell::new(NetworkServer::start(&addr).unwrap()));
+
+        let mut stream = TcpStream::connect(&addr).unwrap();
+        let client_addr = stream.local_addr().unwrap();
+        let connected = Instant::now();
+        while !engine.runtime.ns.borrow().tcp.lock().unwrap().contains_key(&client_addr) {
+            assert!(connected.elapsed() < Duration::from_secs(5), "connection was not accepted");
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        let client = Id::new();
+        engine.runtime.ns.borrow_mut().clients.insert(client, ClientHandle {
+            last_seen: Instant::now(),
+            client_addr,
+            awaiting_replies: Default::default()
+        });
+        engine.clients.insert(client);
+
+        engine.request_shutdown();
+        assert!(engine.runtime.shutdown_requested());
+        engine.shutdown();
+        assert!(!engine.is_client_logged_in(&client));
+
+        let mut size = [0u8; 4];
+        stream.read_exact(&mut size).unwrap();
+        let mut packet = vec![0; u32::from_le_bytes(size) as usize];
+        stream.read_exact(&mut packet).unwrap();
+        let packet = ServerPacket::deserialize_bin(&packet).unwrap();
+        assert_eq!(packet.message, ServerMessage::Kick(SHUTDOWN_REASON.to_string()));
+        assert_eq!(stream.read(&mut [0]).unwrap(), 0, "connection should be closed after the kick");
+    }
+}
@@ -0,0 +1,123 @@
+use std::collections::VecDeque;
+use aeonetica_engine::networking::SendMode;
+use aeonetica_engine::networking::server_packets::ServerMessage;
+
+/// Mod messages at least this large are treated like chunk data, smaller ones like entity updates
+pub(crate) const BULK_MESSAGE_SIZE: usize = 512;
+
+/// Critical packets overtake all others queued for the same client. Otherwise only quick packets are
+/// reordered by priority: safe and ordered ones keep the order they were sent in, as their receivers
+/// may depend on it, e.g. for delta encoding.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum Priority {
+    /// connection management, keep alives and time scale changes
+    Critical,
+    /// client handles and small mod messages
+    Entity,
+    /// mod downloads and large mod messages such as chunks
+    Bulk
+}
+
+impl Priority {
+    const COUNT: usize = 3;
+
+    pub(crate) fn of(message: &ServerMessage) -> Self {
+        match message {
+            ServerMessage::RawData(..) => Self::Bulk,
+            ServerMessage::ModMessage(_, _, data) if data.len() >= BULK_MESSAGE_SIZE => Self::Bulk,
+            ServerMessage::ModMessage(..) | ServerMessage::AddClientHandle(..) | ServerMessage::RemoveClientHandle(..) => Self::Entity,
+            _ => Self::Critical
+        }
+    }
+}
+
+/// Serialized packets waiting to be sent to one client, see [`NetworkServer::flush`](super::NetworkServer::flush).
+#[derive(Debug, Default)]
+pub(crate) struct SendQueue {
+    /// critical packets of every mode and the other quick packets, by priority
+    tiers: [VecDeque<(Vec<u8>, SendMode)>; Priority::COUNT],
+    /// safe and ordered packets that aren't critical, sent after the quick entity updates
+    sequential: VecDeque<(Vec<u8>, SendMode)>
+}
+
+impl SendQueue {
+    pub(crate) fn push(&mut self, priority: Priority, data: Vec<u8>, mode: SendMode) {
+        match (priority, mode) {
+            (Priority::Critical, _) | (_, SendMode::Quick) => self.tiers[priority as usize].push_back((data, mode)),
+            _ => self.sequential.push_back((data, mode))
+        }
+    }
+
+    /// Takes packets by priority until they add up to `budget` bytes.
+    /// The first packet is always taken, so packets larger than the budget still go out.
+    pub(crate) fn take(&mut self, budget: usize) -> Vec<(Vec<u8>, SendMode)> {
+        let mut taken = vec![];
+        let mut spent = 0;
+        let [critical, entity, bulk] = &mut self.tiers;
+        for queue in [critical, entity, &mut self.sequential, bulk] {
+            while let Some((data, _)) = queue.front() {
+                if !taken.is_empty() && spent + data.len() > budget {
+                    return taken
+                }
+                spent += data.len();
+                taken.extend(queue.pop_front());
+            }
+        }
+        taken
+    }
+
+    /// Number of queued packets
+    pub(crate) fn len(&self) -> usize {
+        self.tiers.iter().map(VecDeque::len).sum::<usize>() + self.sequential.len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.tiers.iter().all(VecDeque::is_empty) && self.sequential.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aeonetica_engine::Id;
+    use super::*;
+
+    fn first(taken: Vec<(Vec<u8>, SendMode)>) -> Vec<u8> {
+        taken.into_iter().map(|(data, _)| data[0]).collect()
+    }
+
+    #[test]
+    fn critical_and_quick_packets_overtake_within_the_budget() {
+        let id = Id::new();
+        assert_eq!(Priority::of(&ServerMessage::KeepAlive), Priority::Critical);
+        assert_eq!(Priority::of(&ServerMessage::ModMessage(id, id, vec![0; 16])), Priority::Entity);
+        assert_eq!(Priority::of(&ServerMessage::ModMessage(id, id, vec![0; BULK_MESSAGE_SIZE])), Priority::Bulk);
+
+        let mut queue = SendQueue::default();
+        queue.push(Priority::Bulk, vec![1; 600], SendMode::Quick);
+        queue.push(Priority::Bulk, vec![2; 600], SendMode::Quick);
+        queue.push(Priority::Entity, vec![3; 10], SendMode::Quick);
+        queue.push(Priority::Critical, vec![4; 10], SendMode::Safe);
+        queue.push(Priority::Entity, vec![5; 10], SendMode::Ordered);
+        assert_eq!(queue.len(), 5);
+
+        assert_eq!(first(queue.take(700)), vec![4, 3, 5, 1]);
+        queue.push(Priority::Critical, vec![6; 10], SendMode::Safe);
+        assert_eq!(first(queue.take(100)), vec![6]);
+        // packets larger than the budget are not stuck
+        assert_eq!(first(queue.take(100)), vec![2]);
+        assert!(queue.is_empty());
+        assert!(queue.take(100).is_empty());
+    }
+
+    #[test]
+    fn safe_and_ordered_packets_keep_their_order() {
+        let mut queue = SendQueue::default();
+        queue.push(Priority::Bulk, vec![1; 600], SendMode::Safe);
+        queue.push(Priority::Entity, vec![2; 10], SendMode::Safe);
+        queue.push(Priority::Bulk, vec![3; 600], SendMode::Ordered);
+        queue.push(Priority::Entity, vec![4; 10], SendMode::Ordered);
+        queue.push(Priority::Entity, vec![5; 10], SendMode::Quick);
+
+        assert_eq!(first(queue.take(usize::MAX)), vec![5, 1, 2, 3, 4]);
+    }
+}
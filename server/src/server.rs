@@ -1,62 +1,181 @@
+use std::sync::atomic::Ordering;
 use std::time::{Duration, Instant};
 use aeonetica_engine::time::Time;
 use aeonetica_engine::{log};
+use aeonetica_engine::error::{Error, ErrorResult, Fatality};
+use aeonetica_engine::error::builtin::ValueError;
 use crate::ecs::Engine;
-use crate::server_runtime::ServerRuntime;
+use crate::server_runtime::{ServerRuntime, hot_reload_enabled};
 
-const TPS: usize = 20;
-const FULL_SEC: usize = 1_000_000_000;
+pub const DEFAULT_TICK_RATE: u32 = 20;
+/// Maximum number of ticks simulated in one go when the server falls behind.
+/// Any further backlog is dropped instead of piling up.
+const MAX_CATCH_UP_TICKS: u32 = 5;
+/// How often mod zip files are checked for changes when hot reloading is enabled.
+const HOT_RELOAD_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Accumulates elapsed wall-clock time and hands it out in ticks of constant length.
+pub(crate) struct FixedTimestep {
+    tick_duration: Duration,
+    accumulator: Duration,
+    max_catch_up: u32
+}
+
+impl FixedTimestep {
+    pub(crate) fn new(tick_rate: u32, max_catch_up: u32) -> Self {
+        Self {
+            tick_duration: Duration::from_secs(1) / tick_rate,
+            accumulator: Duration::ZERO,
+            max_catch_up
+        }
+    }
+
+    /// Adds `elapsed` to the accumulator and returns the number of ticks to run now.
+    pub(crate) fn advance(&mut self, elapsed: Duration) -> u32 {
+        self.accumulator += elapsed;
+        let mut ticks = 0;
+        while self.accumulator >= self.tick_duration {
+            if ticks == self.max_catch_up {
+                log!(WARN, "server is running behind, skipping {}ms", self.accumulator.as_millis());
+                self.accumulator = Duration::ZERO;
+                break;
+            }
+            self.accumulator -= self.tick_duration;
+            ticks += 1;
+        }
+        ticks
+    }
+
+    pub(crate) fn tick_duration(&self) -> Duration {
+        self.tick_duration
+    }
+
+    pub(crate) fn drift(&self) -> Duration {
+        self.accumulator
+    }
+
+    pub(crate) fn until_next_tick(&self) -> Duration {
+        self.tick_duration.saturating_sub(self.accumulator)
+    }
+}
+
+/// Reads the tick rate given on the command line, [`DEFAULT_TICK_RATE`] if there is none.
+pub fn parse_tick_rate(arg: Option<&str>) -> ErrorResult<u32> {
+    match arg.map(str::parse::<u32>) {
+        None => Ok(DEFAULT_TICK_RATE),
+        Some(Ok(tick_rate)) if tick_rate > 0 => Ok(tick_rate),
+        Some(_) => Err(Error::new(ValueError(format!("invalid tick rate {}, expected a positive number of ticks per second", arg.unwrap())), Fatality::FATAL, true))
+    }
+}
 
 pub fn run(ip: &str) {
+    run_with_tick_rate(ip, DEFAULT_TICK_RATE)
+}
+
+/// Exits with an error if `tick_rate` is 0.
+pub fn run_with_tick_rate(ip: &str, tick_rate: u32) {
+    if tick_rate == 0 {
+        Error::new(ValueError("tick rate must be at least 1".to_string()), Fatality::FATAL, true).log_exit();
+    }
     let runtime = ServerRuntime::create(ip).map_err(|e| {
         e.log_exit();
     }).unwrap();
 
     log!("running start for all mods");
     let mut engine = Engine::new(runtime);
+    engine.tick_rate = tick_rate;
 
     let mut_engine_ref = unsafe { &mut *(&mut engine as *mut Engine) };
     engine.runtime.loaded_mods.iter_mut().for_each(|m| {
         m.start(mut_engine_ref);
     });
 
-    let mut time_nanos = 0;
-    let mut time = Time {
-        time: 0.0,
-        delta: 0.0,
-		raw_delta: 0.0
-    };
+    let mut timestep = FixedTimestep::new(tick_rate, MAX_CATCH_UP_TICKS);
+    let delta = timestep.tick_duration().as_secs_f32();
+    let mut time = Time::default();
 
     println!("\x1b[38;5;200mServer successfully set up and ready for clients to connect\x1b[0m");
 
-    loop {
-        let t = Instant::now();
+    let hot_reload = hot_reload_enabled();
+    if hot_reload {
+        log!("hot reloading of mods enabled");
+    }
+    let mut last_reload_check = Instant::now();
 
+    let shutdown = engine.runtime.shutdown.clone();
+    let _ = ctrlc::set_handler(move || {
+        if shutdown.swap(true, Ordering::SeqCst) {
+            log!(WARN, "forcing shutdown");
+            std::process::exit(1);
+        }
+        log!("shutting down after the current tick, press Ctrl+C again to force")
+    }).map_err(|e| log!(WARN, "could not set Ctrl+C handler: {e}"));
+
+    let mut last = Instant::now();
+    while !engine.runtime.shutdown_requested() {
         let _ = engine.handle_queued().map_err(|e| {
             log!(ERROR, "{e}")
         });
 
-        engine.timeout_inactive();
+        let now = Instant::now();
+        let ticks = timestep.advance(now - last);
+        last = now;
 
-        engine.for_each_module(|engine, id, m| m.tick_dyn(id, engine, time));
-        engine.run_tasks();
+        for _ in 0..ticks {
+            time.time_scale = engine.time_scale;
+            time.paused = engine.paused;
+            time.advance(delta, delta);
+            engine.timeout_inactive();
 
-        let delta_time_nanos_intermediate = t.elapsed().as_nanos();
-        
+            engine.for_each_module(|engine, id, m| m.tick_dyn(id, engine, time));
+            engine.run_tasks();
+            engine.dispatch_events();
 
-        engine.tick += 1;
+            engine.tick += 1;
+        }
+        engine.tick_drift = timestep.drift();
+        engine.runtime.ns.borrow().flush();
 
-        if (delta_time_nanos_intermediate as usize) < 1_000_000_000 / TPS {
-            let to_wait = 1_000_000_000 / TPS - delta_time_nanos_intermediate as usize;
-            //println!("to_wait = {}", to_wait as f32 / 1_000_000_000.0);
-            std::thread::sleep(Duration::from_nanos(to_wait as u64));
+        if hot_reload && last_reload_check.elapsed() >= HOT_RELOAD_CHECK_INTERVAL {
+            engine.request_changed_mod_reloads();
+            last_reload_check = Instant::now();
         }
+        engine.reload_requested_mods();
+
+        std::thread::sleep(timestep.until_next_tick());
+    }
+
+    engine.shutdown();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_timestep_accumulates() {
+        let mut timestep = FixedTimestep::new(20, 5);
+        assert_eq!(timestep.advance(Duration::from_millis(30)), 0);
+        assert_eq!(timestep.advance(Duration::from_millis(30)), 1);
+        assert_eq!(timestep.drift(), Duration::from_millis(10));
+        assert_eq!(timestep.advance(Duration::from_millis(140)), 3);
+        assert_eq!(timestep.drift(), Duration::ZERO);
+        assert_eq!(timestep.until_next_tick(), Duration::from_millis(50));
+    }
+
+    #[test]
+    fn fixed_timestep_caps_catch_up() {
+        let mut timestep = FixedTimestep::new(20, 5);
+        assert_eq!(timestep.advance(Duration::from_secs(10)), 5);
+        assert_eq!(timestep.drift(), Duration::ZERO);
+        assert_eq!(timestep.advance(Duration::from_millis(50)), 1);
+    }
 
-        let delta_time_nanos = t.elapsed().as_nanos();
-        time_nanos += delta_time_nanos;
-        time.raw_delta = delta_time_nanos as f32 / FULL_SEC as f32;
-		time.delta = time.raw_delta.min(0.2);
-        time.time = time_nanos as f32 / FULL_SEC as f32;
-        //println!("time.delta = {}  percentage = {}", time.delta, time.delta / (1.0 / TPS as f32) * 100.0);
+    #[test]
+    fn tick_rate_must_be_positive() {
+        assert_eq!(parse_tick_rate(None).unwrap(), DEFAULT_TICK_RATE);
+        assert_eq!(parse_tick_rate(Some("60")).unwrap(), 60);
+        assert!(parse_tick_rate(Some("0")).is_err());
+        assert!(parse_tick_rate(Some("fast")).is_err());
     }
-}
\ No newline at end of file
+}
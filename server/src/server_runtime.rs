@@ -4,7 +4,11 @@ use std::fs::File;
 use std::io::Read;
 use std::path::Path;
 use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::SystemTime;
 use aeonetica_engine::error::builtin::ModError;
+use aeonetica_engine::util::load_order::load_order;
 use aeonetica_engine::libloading::{Library, Symbol};
 use aeonetica_engine::{log, nanoserde};
 use aeonetica_engine::error::*;
@@ -12,6 +16,7 @@ use aeonetica_engine::nanoserde::{DeBin, DeRon, SerBin, SerRon};
 use aeonetica_engine::util::unzip_archive;
 use crate::{ServerMod, ServerModBox};
 use crate::networking::NetworkServer;
+use crate::ecs::Engine;
 
 
 mod paths_util {
@@ -40,7 +45,12 @@ pub struct ServerRuntime {
     pub(crate) mod_profile: ModProfile,
     pub(crate) supported_mod_targets: HashSet<String>,
     pub(crate) loaded_mods: Vec<ServerModBox>,
-    pub(crate) ns: Rc<RefCell<NetworkServer>>
+    pub(crate) reload_requests: Vec<String>,
+    /// number of mod reloads so far, each one is extracted to its own directory
+    pub(crate) reloads: usize,
+    pub(crate) ns: Rc<RefCell<NetworkServer>>,
+    /// shared with the signal handler
+    pub(crate) shutdown: Arc<AtomicBool>
 }
 
 #[derive(SerRon, DeRon, SerBin, DeBin)]
@@ -57,47 +67,259 @@ impl ServerRuntime {
         File::open("mods/mods.ron")?.read_to_string(&mut data)?;
         let profile: ModProfile = DeRon::deserialize_ron(&data)?;
         let mod_targets = HashSet::from_iter(profile.mod_targets.clone().unwrap_or(vec![aeonetica_engine::MOD_TARGET.to_string()]).iter().cloned());
-        let mut mods = vec![];
+        let mut loaded = vec![];
         log!("loading mods for targets {mod_targets:?}");
-        for item in &profile.modstack {
-            log!("loading mod {} ...", item.0);
-            let mut m = load_mod(item.0, &mod_targets)
+        let mut modstack = profile.modstack.iter().collect::<Vec<_>>();
+        modstack.sort_by_key(|(name_path, _)| *name_path);
+        for (name_path, flags) in modstack {
+            log!("loading mod {} ...", name_path);
+            let m = load_mod(name_path, &mod_targets)
                 .map_err(|mut e| {
-                    e.add_info(format!("could not load mod {}", item.0));
+                    e.add_info(format!("could not load mod {}", name_path));
                     e
                 })?;
-            m.init(item.1);
+            loaded.push((name_path, flags, Some(m)));
+            log!("loaded mod {}", name_path)
+        }
+
+        let order = load_order(&loaded.iter().map(|(name_path, _, m)| (name_path.as_str(), m.as_ref().unwrap().dependencies())).collect::<Vec<_>>())?;
+        let mut mods = vec![];
+        for i in order {
+            let (name_path, flags, m) = &mut loaded[i];
+            let mut m = m.take().unwrap();
+            log!(DEBUG, "initializing mod {name_path}");
+            m.init(flags);
+            m.flags = flags.clone();
             mods.push(m);
-            log!("loaded mod {}", item.0)
         }
         log!("successfully loaded {} mods from profile {} v{}", mods.len(), profile.profile, profile.version);
         Ok(ServerRuntime {
             supported_mod_targets: mod_targets,
             mod_profile: profile,
             loaded_mods: mods,
-            ns: Rc::new(RefCell::new(NetworkServer::start(addr)?))
+            reload_requests: vec![],
+            reloads: 0,
+            ns: Rc::new(RefCell::new(NetworkServer::start(addr)?)),
+            shutdown: Default::default()
         })
     }
+
+    /// Shuts the server down after the current tick: connected clients are kicked, every mod's
+    /// [`ServerMod::shutdown`] is called and the network threads are stopped.
+    /// This is also what happens on Ctrl+C.
+    pub fn request_shutdown(&self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+    }
+
+    pub fn shutdown_requested(&self) -> bool {
+        self.shutdown.load(Ordering::SeqCst)
+    }
+}
+
+/// Splits a modstack entry into its path and name.
+pub(crate) fn split_name_path(name_path: &str) -> ErrorResult<(&str, &str)> {
+    name_path.split_once(':')
+        .ok_or_else(|| Error::new(ModError(format!("malformed mod entry {name_path}, expected path:name")), Fatality::FATAL, false))
 }
 
 pub(crate) fn load_mod(name_path: &str, supported_mod_targets: &HashSet<String>) -> ErrorResult<ServerModBox> {
-    let (path, name) = name_path.split_once(':').unwrap();
+    let (path, _) = split_name_path(name_path)?;
+    load_mod_to(name_path, path, supported_mod_targets)
+}
 
-    unzip_archive(File::open(mod_zip(path))?, format!("runtime/{path}"))?;
-    unzip_archive(File::open(mod_server_zip(path, name))?, format!("runtime/{path}/server"))?;
+/// Extracts the mod to `runtime/{runtime_path}` and loads its server library from there.
+fn load_mod_to(name_path: &str, runtime_path: &str, supported_mod_targets: &HashSet<String>) -> ErrorResult<ServerModBox> {
+    let (path, name) = split_name_path(name_path)?;
+
+    unzip_archive(File::open(mod_zip(path))?, format!("runtime/{runtime_path}"))?;
+    unzip_archive(File::open(mod_server_zip(runtime_path, name))?, format!("runtime/{runtime_path}/server"))?;
 
     for target in supported_mod_targets {
-        if !Path::new(&mod_client_zip(path, name, target)).exists() {
+        if !Path::new(&mod_client_zip(runtime_path, name, target)).exists() {
             Err(Error::new(ModError(format!("Mod {name_path} does not support target advertised in mods.ron: {target}\n(of {supported_mod_targets:?})")), Fatality::FATAL, false))?;
         }
     }
 
-    let server_lib_file = server_lib(path, name);
+    let server_lib_file = server_lib(runtime_path, name);
     log!(DEBUG, "loading lib: {}", server_lib_file);
     let server_lib = unsafe { Library::new(server_lib_file)
         .map_err(|e| Error::new(ModError(format!("could not load mod: {e}")), Fatality::FATAL, false))? };
     let _create_mod_server: Symbol<fn() -> Box<dyn ServerMod>> = unsafe { server_lib.get("_create_mod_server".as_ref())
         .map_err(|e| Error::new(ModError(format!("could not load mod: {e}")), Fatality::FATAL, false))? };
     let mod_server = _create_mod_server();
-    Ok(ServerModBox::new(mod_server, server_lib))
+    let mut mod_box = ServerModBox::new(mod_server, server_lib);
+    mod_box.name_path = name_path.to_string();
+    mod_box.runtime_path = runtime_path.to_string();
+    mod_box.modified = mod_zip_modified(path);
+    Ok(mod_box)
+}
+
+fn mod_zip_modified(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(mod_zip(path)).and_then(|m| m.modified()).ok()
+}
+
+/// Set to `1` or `true` to reload [reloadable](ServerMod::reloadable) mods whenever their zip file changes.
+pub(crate) const HOT_RELOAD_ENVIRONMENT_VAR: &str = "AEONETICA_HOT_RELOAD";
+
+pub(crate) fn hot_reload_enabled() -> bool {
+    std::env::var(HOT_RELOAD_ENVIRONMENT_VAR).is_ok_and(|value| matches!(value.to_uppercase().as_str(), "1" | "TRUE"))
+}
+
+/// Reason sent to all clients when the server shuts down
+pub(crate) const SHUTDOWN_REASON: &str = "server shutting down";
+
+impl Engine {
+    /// See [`ServerRuntime::request_shutdown`].
+    pub fn request_shutdown(&self) {
+        self.runtime.request_shutdown()
+    }
+
+    /// Kicks all clients, lets mods save their state and stops the network threads.
+    /// Clients receive the kick before their connection is closed.
+    pub(crate) fn shutdown(&mut self) {
+        log!("shutting down");
+        let clients = self.clients.iter().copied().collect::<Vec<_>>();
+        for client in &clients {
+            self.kick_client(client, SHUTDOWN_REASON);
+        }
+        // dependents first, they might still use the mods they depend on
+        let mut_engine_ref = unsafe { &mut *(self as *mut Engine) };
+        self.runtime.loaded_mods.iter_mut().rev().for_each(|m| {
+            m.shutdown(mut_engine_ref);
+        });
+        self.runtime.ns.borrow_mut().shutdown();
+    }
+
+    /// Reloads the library of the mod `name` (its `path:name` or just `name`) after the current tick.
+    /// This is a development tool: only mods reporting [`ServerMod::reloadable`] can be reloaded, and
+    /// the client side of the mod is not reloaded. Connected clients and everything owned by other mods are kept.
+    pub fn request_mod_reload(&mut self, name: &str) {
+        if !self.runtime.reload_requests.iter().any(|n| n == name) {
+            self.runtime.reload_requests.push(name.to_string());
+        }
+    }
+
+    /// Requests a reload of every reloadable mod whose zip file changed since it was loaded.
+    pub(crate) fn request_changed_mod_reloads(&mut self) {
+        let changed = self.runtime.loaded_mods.iter()
+            .filter(|m| m.reloadable() && m.modified.is_some())
+            .filter(|m| match split_name_path(&m.name_path) {
+                Ok((path, _)) => mod_zip_modified(path) != m.modified,
+                Err(err) => {
+                    log!(WARN, "not watching mod for changes: {err}");
+                    false
+                }
+            })
+            .map(|m| m.name_path.clone())
+            .collect::<Vec<_>>();
+        changed.iter().for_each(|name_path| self.request_mod_reload(name_path));
+    }
+
+    pub(crate) fn reload_requested_mods(&mut self) {
+        for name in std::mem::take(&mut self.runtime.reload_requests) {
+            match self.reload_mod(&name) {
+                Ok(()) => log!("reloaded mod {name}"),
+                Err(err) => log!(ERROR, "could not reload mod {name}: {err}")
+            }
+        }
+    }
+
+    fn reload_mod(&mut self, name: &str) -> ErrorResult<()> {
+        let index = self.runtime.loaded_mods.iter()
+            .position(|m| m.name_path == name || m.name_path.split_once(':').is_some_and(|(_, n)| n == name))
+            .ok_or_else(|| Error::new(ModError(format!("mod {name} is not loaded")), Fatality::WARN, false))?;
+        if !self.runtime.loaded_mods[index].reloadable() {
+            return Err(Error::new(ModError(format!("mod {name} is not reloadable")), Fatality::WARN, false));
+        }
+
+        let (name_path, flags) = (self.runtime.loaded_mods[index].name_path.clone(), self.runtime.loaded_mods[index].flags.clone());
+        let (path, _) = split_name_path(&name_path)?;
+        // loading the library from its old file would return the old handle, and overwriting a loaded library
+        // crashes the server, so the new version is extracted next to it. If that fails the old one keeps running.
+        self.runtime.reloads += 1;
+        let runtime_path = format!("{path}-reload{}", self.runtime.reloads);
+        let mut new = load_mod_to(&name_path, &runtime_path, &self.runtime.supported_mod_targets)
+            .map_err(|err| {
+                let _ = std::fs::remove_dir_all(format!("runtime/{runtime_path}"));
+                err
+            })?;
+
+        let mut old = self.runtime.loaded_mods.remove(index);
+        let state_version = old.state_version();
+        let state = old.stop(self);
+        let old_runtime_path = old.runtime_path.clone();
+        drop(old);
+        if old_runtime_path.starts_with(&format!("{path}-reload")) {
+            let _ = std::fs::remove_dir_all(format!("runtime/{old_runtime_path}"));
+        }
+        // clients connecting from now on get the new client side
+        let update_client_side = || -> ErrorResult<()> { unzip_archive(File::open(mod_zip(path))?, format!("runtime/{path}")) };
+        if let Err(err) = update_client_side() {
+            log!(WARN, "could not update the client side of mod {name_path}: {err}");
+        }
+
+        new.init(&flags);
+        new.flags = flags;
+        new.start(self);
+        if new.state_version() == state_version {
+            new.restore(self, &state);
+        } else {
+            log!(WARN, "state of mod {name_path} changed from version {state_version} to {}, discarding old state", new.state_version());
+        }
+        self.runtime.loaded_mods.insert(index, new);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use aeonetica_engine::libloading;
+    use super::*;
+    use crate::ecs::tests::test_engine;
+
+    struct Reloadable(Rc<Cell<bool>>);
+
+    impl ServerMod for Reloadable {
+        fn reloadable(&self) -> bool { true }
+
+        fn stop(&mut self, _engine: &mut Engine) -> Vec<u8> {
+            self.0.set(true);
+            vec![]
+        }
+    }
+
+    fn loaded_mod(name_path: &str, stopped: &Rc<Cell<bool>>) -> ServerModBox {
+        let library = libloading::os::unix::Library::this().into();
+        let mut m = ServerModBox::new(Box::new(Reloadable(stopped.clone())), library);
+        m.name_path = name_path.to_string();
+        m.modified = Some(SystemTime::now());
+        m
+    }
+
+    #[test]
+    fn failed_reloads_keep_the_old_mod() {
+        let mut engine = test_engine();
+        let stopped = Rc::new(Cell::new(false));
+        engine.runtime.loaded_mods.push(loaded_mod("no-such-mod:missing", &stopped));
+
+        assert!(engine.reload_mod("missing").is_err());
+        assert_eq!(engine.runtime.loaded_mods.len(), 1);
+        assert_eq!(engine.runtime.loaded_mods[0].name_path, "no-such-mod:missing");
+        assert!(!stopped.get());
+    }
+
+    #[test]
+    fn malformed_mod_entries_are_rejected() {
+        assert!(split_name_path("world").is_err());
+        assert!(load_mod("world", &HashSet::new()).is_err());
+
+        let mut engine = test_engine();
+        let stopped = Rc::new(Cell::new(false));
+        engine.runtime.loaded_mods.push(loaded_mod("malformed", &stopped));
+        engine.request_changed_mod_reloads();
+        assert!(engine.runtime.reload_requests.is_empty());
+        assert!(engine.reload_mod("malformed").is_err());
+        assert!(!stopped.get());
+    }
 }